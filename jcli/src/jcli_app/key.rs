@@ -7,6 +7,10 @@ use chain_crypto::{
 };
 use ed25519_bip32::{DerivationError, DerivationScheme};
 use hex::FromHexError;
+use jormungandr_lib::{
+    crypto::password_encryption::{self, DecryptionError, PASSPHRASE_ENV_VAR},
+    interfaces::NodeSecret,
+};
 use rand::{rngs::OsRng, SeedableRng};
 use rand_chacha::ChaChaRng;
 use std::{
@@ -65,6 +69,18 @@ pub enum Error {
         public_hrp: String,
         private_hrp: String,
     },
+    #[error("invalid node secret")]
+    InvalidNodeSecret(#[source] serde_yaml::Error),
+    #[error("the node secret is already encrypted")]
+    AlreadyEncrypted,
+    #[error("cannot read the passphrase")]
+    Passphrase(#[source] std::io::Error),
+    #[error("the passphrase cannot be empty")]
+    EmptyPassphrase,
+    #[error("the passphrases do not match")]
+    PassphraseMismatch,
+    #[error("cannot decrypt the node secret")]
+    Decryption(#[from] DecryptionError),
 }
 
 #[derive(StructOpt, Debug)]
@@ -84,6 +100,10 @@ pub enum Key {
     Verify(Verify),
     /// derive a child key from a ed25519bip32 parent key
     Derive(Derive),
+    /// encrypt a node secret file with a passphrase
+    EncryptSecret(EncryptSecret),
+    /// decrypt a node secret file encrypted with `encrypt-secret`
+    DecryptSecret(DecryptSecret),
 }
 
 #[derive(StructOpt, Debug)]
@@ -193,6 +213,36 @@ pub struct Derive {
     child_key: OutputFile,
 }
 
+/// the passphrase is read from the JORMUNGANDR_SECRET_PASSPHRASE environment
+/// variable, or prompted for if the variable is not set
+#[derive(StructOpt, Debug)]
+pub struct EncryptSecret {
+    /// the node secret file (in YAML format) to encrypt
+    ///
+    /// if no value passed, the node secret will be read from the
+    /// standard input
+    #[structopt(long = "input")]
+    input: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    output_file: OutputFile,
+}
+
+/// the passphrase is read from the JORMUNGANDR_SECRET_PASSPHRASE environment
+/// variable, or prompted for if the variable is not set
+#[derive(StructOpt, Debug)]
+pub struct DecryptSecret {
+    /// the encrypted node secret file to decrypt
+    ///
+    /// if no value passed, the encrypted node secret will be read from the
+    /// standard input
+    #[structopt(long = "input")]
+    input: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    output_file: OutputFile,
+}
+
 #[derive(StructOpt, Debug)]
 struct OutputFile {
     /// output the key to the given file or to stdout if not provided
@@ -230,6 +280,8 @@ impl Key {
             Key::Sign(args) => args.exec(),
             Key::Verify(args) => args.exec(),
             Key::Derive(args) => args.exec(),
+            Key::EncryptSecret(args) => args.exec(),
+            Key::DecryptSecret(args) => args.exec(),
         }
     }
 }
@@ -405,6 +457,62 @@ impl Derive {
     }
 }
 
+impl EncryptSecret {
+    fn exec(self) -> Result<(), Error> {
+        let mut data = Vec::new();
+        io::open_file_read(&self.input)?.read_to_end(&mut data)?;
+        if password_encryption::is_encrypted(&data) {
+            return Err(Error::AlreadyEncrypted);
+        }
+        // make sure the secret can be loaded by the node once decrypted
+        serde_yaml::from_slice::<NodeSecret>(&data).map_err(Error::InvalidNodeSecret)?;
+
+        let passphrase = read_new_passphrase()?;
+        let encrypted = password_encryption::encrypt(
+            passphrase.as_bytes(),
+            password_encryption::DEFAULT_ITERATIONS,
+            &data,
+            &mut OsRng,
+        );
+        self.output_file.open()?.write_all(&encrypted)?;
+        Ok(())
+    }
+}
+
+impl DecryptSecret {
+    fn exec(self) -> Result<(), Error> {
+        let mut data = Vec::new();
+        io::open_file_read(&self.input)?.read_to_end(&mut data)?;
+        let passphrase = read_passphrase("passphrase: ")?;
+        let decrypted = password_encryption::decrypt(passphrase.as_bytes(), &data)?;
+        self.output_file.open()?.write_all(&decrypted)?;
+        Ok(())
+    }
+}
+
+fn read_passphrase(prompt: &str) -> Result<String, Error> {
+    match std::env::var(PASSPHRASE_ENV_VAR) {
+        Ok(passphrase) => Ok(passphrase),
+        Err(_) => rpassword::read_password_from_tty(Some(prompt)).map_err(Error::Passphrase),
+    }
+}
+
+/// read the passphrase to encrypt with, the prompted passphrase has to
+/// be typed twice
+fn read_new_passphrase() -> Result<String, Error> {
+    let passphrase = read_passphrase("passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(Error::EmptyPassphrase);
+    }
+    if std::env::var_os(PASSPHRASE_ENV_VAR).is_none() {
+        let confirmation = read_passphrase("confirm passphrase: ")?;
+        if confirmation != passphrase {
+            return Err(Error::PassphraseMismatch);
+        }
+    }
+    Ok(passphrase)
+}
+
 fn read_hex<P: AsRef<Path>>(path: &Option<P>) -> Result<Vec<u8>, Error> {
     hex::decode(io::read_line(path)?).map_err(Into::into)
 }
@@ -28,6 +28,8 @@ pub enum Error {
     OutputFormatFailed(#[from] output_format::Error),
     #[error("could not read input file")]
     InputFileInvalid(#[from] std::io::Error),
+    #[error("could not write output file")]
+    OutputFileInvalid(#[source] std::io::Error),
     #[error("input yaml is not valid")]
     InputFileYamlMalformed(#[from] serde_yaml::Error),
     #[error("failed to serialize input")]
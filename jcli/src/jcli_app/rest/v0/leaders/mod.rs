@@ -36,6 +36,35 @@ pub enum Leaders {
         /// ID of deleted leader
         id: u32,
     },
+    /// Replace the secret of a leader from the given epoch
+    Rotate {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        /// ID of the leader
+        id: u32,
+        /// epoch from which the new secret is used
+        #[structopt(long)]
+        epoch: u32,
+        /// File containing YAML with the new leader secret.
+        /// It must have the same format as secret YAML passed to Jormungandr as --secret.
+        /// If not provided, YAML will be read from stdin.
+        #[structopt(short, long)]
+        file: Option<PathBuf>,
+    },
+    /// Get the slots of an epoch won by the leaders
+    Schedule {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        #[structopt(flatten)]
+        output_format: OutputFormat,
+        /// epoch of the schedule, the epoch of the tip if not given
+        #[structopt(long)]
+        epoch: Option<u32>,
+    },
 
     /// Leadership log operations
     Logs(GetLogs),
@@ -65,6 +94,19 @@ impl Leaders {
             } => get(addr, debug, output_format),
             Leaders::Post { addr, debug, file } => post(addr, debug, file),
             Leaders::Delete { id, addr, debug } => delete(addr, debug, id),
+            Leaders::Rotate {
+                addr,
+                debug,
+                id,
+                epoch,
+                file,
+            } => rotate(addr, debug, id, epoch, file),
+            Leaders::Schedule {
+                addr,
+                debug,
+                output_format,
+                epoch,
+            } => get_schedule(addr, debug, output_format, epoch),
             Leaders::Logs(GetLogs::Get {
                 addr,
                 debug,
@@ -108,6 +150,51 @@ fn delete(addr: HostAddr, debug: DebugFlag, id: u32) -> Result<(), Error> {
     Ok(())
 }
 
+fn rotate(
+    addr: HostAddr,
+    debug: DebugFlag,
+    id: u32,
+    epoch: u32,
+    file: Option<PathBuf>,
+) -> Result<(), Error> {
+    let url = addr
+        .with_segments(&["v0", "leaders", &id.to_string(), "rotate"])?
+        .into_url();
+    let builder = reqwest::Client::new().post(url);
+    let secret: serde_json::Value = io::read_yaml(&file)?;
+    let input = serde_json::json!({
+        "epoch": epoch,
+        "secret": secret,
+    });
+    let response = RestApiSender::new(builder, &debug)
+        .with_json_body(&input)?
+        .send()?;
+    response.ok_response()?;
+    println!("Success");
+    Ok(())
+}
+
+fn get_schedule(
+    addr: HostAddr,
+    debug: DebugFlag,
+    output_format: OutputFormat,
+    epoch: Option<u32>,
+) -> Result<(), Error> {
+    let url = addr
+        .with_segments(&["v0", "leaders", "schedule"])?
+        .into_url();
+    let mut builder = reqwest::Client::new().get(url);
+    if let Some(epoch) = epoch {
+        builder = builder.query(&[("epoch", epoch)]);
+    }
+    let response = RestApiSender::new(builder, &debug).send()?;
+    response.ok_response()?;
+    let schedule = response.body().json_value()?;
+    let formatted = output_format.format_json(schedule)?;
+    println!("{}", formatted);
+    Ok(())
+}
+
 fn get_logs(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Result<(), Error> {
     let url = addr.with_segments(&["v0", "leaders", "logs"])?.into_url();
     let builder = reqwest::Client::new().get(url);
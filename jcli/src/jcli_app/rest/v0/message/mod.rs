@@ -36,6 +36,20 @@ pub enum Message {
         #[structopt(flatten)]
         output_format: OutputFormat,
     },
+
+    /// get the status of the given messages from the node's logs. Messages
+    /// unknown to the node are reported as not found
+    Status {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        #[structopt(flatten)]
+        output_format: OutputFormat,
+        /// hex-encoded IDs of the messages
+        #[structopt(required = true)]
+        ids: Vec<String>,
+    },
 }
 
 impl Message {
@@ -47,6 +61,12 @@ impl Message {
                 debug,
                 output_format,
             } => get_logs(addr, debug, output_format),
+            Message::Status {
+                addr,
+                debug,
+                output_format,
+                ids,
+            } => get_status(addr, debug, output_format, ids),
         }
     }
 }
@@ -62,6 +82,26 @@ fn get_logs(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Re
     Ok(())
 }
 
+fn get_status(
+    addr: HostAddr,
+    debug: DebugFlag,
+    output_format: OutputFormat,
+    ids: Vec<String>,
+) -> Result<(), Error> {
+    let url = addr
+        .with_segments(&["v0", "fragment", "status"])?
+        .into_url();
+    let builder = reqwest::Client::new()
+        .get(url)
+        .query(&[("ids", ids.join(","))]);
+    let response = RestApiSender::new(builder, &debug).send()?;
+    response.ok_response()?;
+    let status = response.body().json_value()?;
+    let formatted = output_format.format_json(status)?;
+    println!("{}", formatted);
+    Ok(())
+}
+
 fn post_message(file: Option<PathBuf>, addr: HostAddr, debug: DebugFlag) -> Result<(), Error> {
     let msg_hex = io::read_line(&file)?;
     let msg_bin = hex::decode(&msg_hex)?;
@@ -7,6 +7,7 @@ mod network;
 mod node;
 mod settings;
 mod shutdown;
+mod snapshot;
 mod stake;
 mod stake_pool;
 mod stake_pools;
@@ -41,6 +42,8 @@ pub enum V0 {
     StakePools(stake_pools::StakePools),
     /// Shutdown node
     Shutdown(shutdown::Shutdown),
+    /// Ledger snapshot operations
+    Snapshot(snapshot::Snapshot),
     /// Blockchain tip information
     Tip(tip::Tip),
     /// UTXO information
@@ -63,6 +66,7 @@ impl V0 {
             V0::StakePool(stake_pool) => stake_pool.exec(),
             V0::StakePools(stake_pools) => stake_pools.exec(),
             V0::Shutdown(shutdown) => shutdown.exec(),
+            V0::Snapshot(snapshot) => snapshot.exec(),
             V0::Tip(tip) => tip.exec(),
             V0::Utxo(utxo) => utxo.exec(),
             V0::Diagnostic(diagnostic) => diagnostic.exec(),
@@ -0,0 +1,54 @@
+use crate::jcli_app::rest::Error;
+use crate::jcli_app::utils::{DebugFlag, HostAddr, RestApiSender};
+use chain_crypto::Blake2b256;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// response header with the hash of the block the ledger snapshot is taken at
+const SNAPSHOT_BLOCK_HEADER: &str = "x-snapshot-block";
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum Snapshot {
+    /// Get a ledger snapshot taken at the end of the most recent stable epoch,
+    /// the digest of the snapshot is printed
+    Get {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        /// file to write the snapshot to
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+    },
+}
+
+impl Snapshot {
+    pub fn exec(self) -> Result<(), Error> {
+        let (addr, debug, output) = match self {
+            Snapshot::Get {
+                addr,
+                debug,
+                output,
+            } => (addr, debug, output),
+        };
+        let url = addr.with_segments(&["v0", "snapshot"])?.into_url();
+        let builder = reqwest::Client::new().get(url);
+        let response = RestApiSender::new(builder, &debug).send()?;
+        response.ok_response()?;
+        let bytes = response.body().binary();
+        std::fs::write(&output, bytes).map_err(Error::OutputFileInvalid)?;
+        // the digest is computed from the received snapshot, it is the value
+        // of the `trusted_snapshot` of the node configuration
+        println!("{}", Blake2b256::new(bytes));
+        if let Some(block) = response
+            .response()
+            .headers()
+            .get(SNAPSHOT_BLOCK_HEADER)
+            .and_then(|block| block.to_str().ok())
+        {
+            eprintln!("the snapshot is taken at block {}", block);
+        }
+        Ok(())
+    }
+}
@@ -27,6 +27,9 @@ pub fn test_log_ttl() {
             log_max_entries: 100_000usize.into(),
             log_ttl: Duration::from_secs(log_ttl_timeout).into(),
             garbage_collection_interval: Duration::from_secs(garbage_collection_interval).into(),
+            fragment_selection: Default::default(),
+            network_limits: Default::default(),
+            rest_limits: Default::default(),
         })
         .build();
 
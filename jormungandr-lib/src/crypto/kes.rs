@@ -0,0 +1,229 @@
+//! evolution of the Genesis Praos KES signing keys.
+//!
+//! The period of a KES key follows the time elapsed since the start of the
+//! blockchain, one period every `kes_update_speed`. Once evolved, a key
+//! cannot sign for an earlier period. The period reached by every key is
+//! saved in a file (in the node's storage, or given to the remote signer)
+//! so the older periods of a key loaded again from its secret file are
+//! skipped after a restart.
+
+use chain_crypto::{bech32::Bech32 as _, SecretKey, SumEd25519_12};
+use chain_impl_mockchain::leadership::Leader;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write as _},
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+
+/// name of the file of the KES periods in the node's storage directory
+pub const KES_PERIODS_FILE_NAME: &str = "kes_periods.json";
+
+#[derive(Debug, Error)]
+pub enum KesError {
+    #[error("the KES key cannot be evolved past the period {period}")]
+    Exhausted { period: u32 },
+    #[error("cannot access the KES periods file")]
+    Io(#[from] io::Error),
+    #[error("invalid KES periods file")]
+    Encoding(#[from] serde_json::Error),
+}
+
+/// timing of the KES periods of the blockchain
+#[derive(Debug, Clone, Copy)]
+pub struct KesSchedule {
+    block0_time: SystemTime,
+    update_speed: Duration,
+}
+
+impl KesSchedule {
+    pub fn new(block0_time: SystemTime, update_speed: Duration) -> Self {
+        KesSchedule {
+            block0_time,
+            update_speed,
+        }
+    }
+
+    /// the KES period of the given time, the first period lasts until the
+    /// end of the first `kes_update_speed` after the start of the blockchain
+    pub fn period_at(&self, time: SystemTime) -> u32 {
+        let update_speed = self.update_speed.as_secs().max(1);
+        time.duration_since(self.block0_time)
+            .map(|elapsed| (elapsed.as_secs() / update_speed) as u32)
+            .unwrap_or(0)
+    }
+}
+
+/// the KES periods reached by the keys, identified by their public key
+/// which does not change when the key evolves
+pub struct KesPeriods {
+    path: Option<PathBuf>,
+    periods: BTreeMap<String, u32>,
+}
+
+fn key_id(key: &SecretKey<SumEd25519_12>) -> String {
+    key.to_public().to_bech32_str()
+}
+
+impl KesPeriods {
+    /// load the KES periods from the given file, without file the periods
+    /// are only kept in memory
+    pub fn load(path: Option<PathBuf>) -> Result<Self, KesError> {
+        let periods = match &path {
+            None => BTreeMap::new(),
+            Some(path) => match File::open(path) {
+                Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(error) => return Err(error.into()),
+            },
+        };
+        Ok(KesPeriods { path, periods })
+    }
+
+    /// the period the given key has already reached
+    pub fn get(&self, key: &SecretKey<SumEd25519_12>) -> u32 {
+        self.periods.get(&key_id(key)).copied().unwrap_or(0)
+    }
+
+    /// record the period reached by the key, it is saved before the key
+    /// is used to sign in this period
+    pub fn set(&mut self, key: &SecretKey<SumEd25519_12>, period: u32) -> Result<(), KesError> {
+        self.periods.insert(key_id(key), period);
+        self.save()
+    }
+
+    /// evolve the KES key of the Genesis Praos leader from the period
+    /// `current` it has reached up to `period`, or up to the period saved
+    /// for this key if it is already further. Return the period the key
+    /// has reached.
+    pub fn evolve_leader(
+        &mut self,
+        leader: &mut Leader,
+        current: u32,
+        period: u32,
+    ) -> Result<u32, KesError> {
+        let sig_key = match &mut leader.genesis_leader {
+            Some(genesis_leader) => &mut genesis_leader.sig_key,
+            None => return Ok(current),
+        };
+        let period = period.max(self.get(sig_key));
+        if period <= current {
+            return Ok(current);
+        }
+        // the period is saved first so the older periods of the key are
+        // never used again, even if the process stops now
+        self.set(sig_key, period)?;
+        evolve(sig_key, current, period)?;
+        Ok(period)
+    }
+
+    fn save(&self) -> Result<(), KesError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            serde_json::to_writer(&mut writer, &self.periods)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// evolve the key from the period `from` to the period `to`, the previous
+/// periods of the key are erased
+pub fn evolve(key: &mut SecretKey<SumEd25519_12>, from: u32, to: u32) -> Result<(), KesError> {
+    for period in from..to {
+        if !key.update() {
+            return Err(KesError::Exhausted { period });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hash::Hash;
+    use chain_crypto::{Blake2b256, Curve25519_2HashDH};
+    use chain_impl_mockchain::leadership::GenesisLeader;
+    use tempfile::tempdir;
+
+    #[test]
+    fn period_at() {
+        let block0_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let schedule = KesSchedule::new(block0_time, Duration::from_secs(10));
+        assert_eq!(schedule.period_at(SystemTime::UNIX_EPOCH), 0);
+        assert_eq!(schedule.period_at(block0_time), 0);
+        assert_eq!(schedule.period_at(block0_time + Duration::from_secs(9)), 0);
+        assert_eq!(schedule.period_at(block0_time + Duration::from_secs(10)), 1);
+        assert_eq!(schedule.period_at(block0_time + Duration::from_secs(25)), 2);
+    }
+
+    #[test]
+    fn periods_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(KES_PERIODS_FILE_NAME);
+        let key = SecretKey::<SumEd25519_12>::generate(rand::thread_rng());
+        let other = SecretKey::<SumEd25519_12>::generate(rand::thread_rng());
+
+        let mut periods = KesPeriods::load(Some(path.clone())).unwrap();
+        assert_eq!(periods.get(&key), 0);
+        periods.set(&key, 3).unwrap();
+
+        let periods = KesPeriods::load(Some(path.clone())).unwrap();
+        assert_eq!(periods.get(&key), 3);
+        assert_eq!(periods.get(&other), 0);
+    }
+
+    #[test]
+    fn periods_invalid_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(KES_PERIODS_FILE_NAME);
+        fs::write(&path, b"{\"truncated").unwrap();
+        match KesPeriods::load(Some(path.clone())) {
+            Err(KesError::Encoding(_)) => (),
+            _ => panic!("the invalid file should be rejected"),
+        }
+    }
+
+    #[test]
+    fn evolve_keeps_the_public_key() {
+        let mut key = SecretKey::<SumEd25519_12>::generate(rand::thread_rng());
+        let public = key.to_public();
+        evolve(&mut key, 0, 2).unwrap();
+        assert_eq!(key.to_public(), public);
+        // evolving to a reached period does nothing
+        evolve(&mut key, 2, 2).unwrap();
+        assert_eq!(key.to_public(), public);
+    }
+
+    #[test]
+    fn evolve_leader_skips_the_saved_periods() {
+        let sig_key = SecretKey::<SumEd25519_12>::generate(rand::thread_rng());
+        let mut periods = KesPeriods::load(None).unwrap();
+        periods.set(&sig_key, 2).unwrap();
+        let mut leader = Leader {
+            bft_leader: None,
+            genesis_leader: Some(GenesisLeader {
+                node_id: Blake2b256::from(Hash::from([1; 32])).into(),
+                sig_key,
+                vrf_key: SecretKey::<Curve25519_2HashDH>::generate(rand::thread_rng()),
+            }),
+        };
+
+        // the key loaded again from its secret file skips the saved periods
+        assert_eq!(periods.evolve_leader(&mut leader, 0, 1).unwrap(), 2);
+        assert_eq!(periods.evolve_leader(&mut leader, 2, 3).unwrap(), 3);
+        let sig_key = &leader.genesis_leader.as_ref().unwrap().sig_key;
+        assert_eq!(periods.get(sig_key), 3);
+        // the key is not evolved back to an earlier period
+        assert_eq!(periods.evolve_leader(&mut leader, 3, 1).unwrap(), 3);
+    }
+}
@@ -24,8 +24,20 @@
 //! It provides the same interfaces as for the identifier in the
 //! `key` module but limited to Account only.
 //!
+//! # Password encryption
+//!
+//! Encryption of the secret files with a key derived from a password,
+//! so the node's secrets do not have to be stored in plain text.
+//!
+//! # KES keys
+//!
+//! Evolution of the Genesis Praos KES signing keys, shared by the node and
+//! the remote signer so both skip the periods a key was already used in.
+//!
 
 pub mod account;
 pub mod hash;
+pub mod kes;
 pub mod key;
+pub mod password_encryption;
 pub(crate) mod serde;
@@ -0,0 +1,172 @@
+//! password based encryption of secret files.
+//!
+//! The encryption key is derived from the password with PBKDF2 (HMAC-SHA512)
+//! and the data is encrypted with ChaCha20Poly1305. The encrypted data has
+//! the following layout:
+//!
+//! ```text
+//! | magic (4) | version (1) | iterations (4, big endian) | salt (16) | nonce (12) | ciphertext | tag (16) |
+//! ```
+//!
+//! everything before the ciphertext is authenticated as additional data.
+
+use cryptoxide::{chacha20poly1305::ChaCha20Poly1305, hmac::Hmac, pbkdf2::pbkdf2, sha2::Sha512};
+use rand_core::{CryptoRng, RngCore};
+use thiserror::Error;
+
+const MAGIC: [u8; 4] = *b"jsec";
+const VERSION: u8 = 1;
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const KEY_SIZE: usize = 32;
+const TAG_SIZE: usize = 16;
+const HEADER_SIZE: usize = MAGIC.len() + 1 + 4 + SALT_SIZE + NONCE_SIZE;
+
+/// number of iterations of the key derivation function used by default
+pub const DEFAULT_ITERATIONS: u32 = 100_000;
+
+/// environment variable holding the passphrase of the encrypted node secret
+/// files, read by the node, the remote signer and jcli
+pub const PASSPHRASE_ENV_VAR: &str = "JORMUNGANDR_SECRET_PASSPHRASE";
+
+#[derive(Debug, Error)]
+pub enum DecryptionError {
+    #[error("the data is not encrypted with a password")]
+    NotEncrypted,
+    #[error("unsupported encryption version {0}")]
+    UnsupportedVersion(u8),
+    #[error("the encrypted data is truncated or malformed")]
+    Malformed,
+    #[error("wrong password or corrupted data")]
+    Authentication,
+}
+
+/// check if the given data looks like data encrypted by `encrypt`
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+fn derive_key(password: &[u8], salt: &[u8], iterations: u32) -> [u8; KEY_SIZE] {
+    let mut key = [0; KEY_SIZE];
+    let mut mac = Hmac::new(Sha512::new(), password);
+    pbkdf2(&mut mac, salt, iterations, &mut key);
+    key
+}
+
+/// encrypt the data with a key derived from the password
+pub fn encrypt<R>(password: &[u8], iterations: u32, data: &[u8], rng: &mut R) -> Vec<u8>
+where
+    R: RngCore + CryptoRng,
+{
+    assert!(
+        iterations > 0,
+        "the key derivation needs at least 1 iteration"
+    );
+
+    let mut salt = [0; SALT_SIZE];
+    let mut nonce = [0; NONCE_SIZE];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut nonce);
+
+    let mut output = Vec::with_capacity(HEADER_SIZE + data.len() + TAG_SIZE);
+    output.extend_from_slice(&MAGIC);
+    output.push(VERSION);
+    output.extend_from_slice(&iterations.to_be_bytes());
+    output.extend_from_slice(&salt);
+    output.extend_from_slice(&nonce);
+
+    let key = derive_key(password, &salt, iterations);
+    let mut ciphertext = vec![0; data.len()];
+    let mut tag = [0; TAG_SIZE];
+    ChaCha20Poly1305::new(&key, &nonce, &output).encrypt(data, &mut ciphertext, &mut tag);
+
+    output.extend_from_slice(&ciphertext);
+    output.extend_from_slice(&tag);
+    output
+}
+
+/// decrypt data encrypted with `encrypt`
+pub fn decrypt(password: &[u8], data: &[u8]) -> Result<Vec<u8>, DecryptionError> {
+    if !is_encrypted(data) {
+        return Err(DecryptionError::NotEncrypted);
+    }
+    if data.len() < HEADER_SIZE + TAG_SIZE {
+        return Err(DecryptionError::Malformed);
+    }
+
+    let (header, body) = data.split_at(HEADER_SIZE);
+    let version = header[MAGIC.len()];
+    if version != VERSION {
+        return Err(DecryptionError::UnsupportedVersion(version));
+    }
+    let (iterations, rest) = header[MAGIC.len() + 1..].split_at(4);
+    let (salt, nonce) = rest.split_at(SALT_SIZE);
+    let mut iterations_bytes = [0; 4];
+    iterations_bytes.copy_from_slice(iterations);
+    let iterations = u32::from_be_bytes(iterations_bytes);
+    if iterations == 0 {
+        return Err(DecryptionError::Malformed);
+    }
+
+    let (ciphertext, tag) = body.split_at(body.len() - TAG_SIZE);
+    let key = derive_key(password, salt, iterations);
+    let mut plaintext = vec![0; ciphertext.len()];
+    if ChaCha20Poly1305::new(&key, nonce, header).decrypt(ciphertext, &mut plaintext, tag) {
+        Ok(plaintext)
+    } else {
+        Err(DecryptionError::Authentication)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_chacha::ChaChaRng;
+    use rand_core::SeedableRng;
+
+    const ITERATIONS: u32 = 10;
+
+    #[test]
+    fn encrypt_decrypt() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let data = b"bft:\n  signing_key: ed25519_sk1...";
+        let encrypted = encrypt(b"password", ITERATIONS, data, &mut rng);
+
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(decrypt(b"password", &encrypted).unwrap(), data.to_vec());
+    }
+
+    #[test]
+    fn wrong_password() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let encrypted = encrypt(b"password", ITERATIONS, b"data", &mut rng);
+
+        match decrypt(b"passw0rd", &encrypted) {
+            Err(DecryptionError::Authentication) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn tampered_header() {
+        let mut rng = ChaChaRng::from_seed([0; 32]);
+        let mut encrypted = encrypt(b"password", ITERATIONS, b"data", &mut rng);
+        // the salt is authenticated along with the ciphertext
+        encrypted[HEADER_SIZE - NONCE_SIZE - 1] ^= 1;
+
+        match decrypt(b"password", &encrypted) {
+            Err(DecryptionError::Authentication) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn plaintext_is_not_encrypted() {
+        let data = b"bft:\n  signing_key: ed25519_sk1...";
+        assert!(!is_encrypted(data));
+        match decrypt(b"password", data) {
+            Err(DecryptionError::NotEncrypted) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
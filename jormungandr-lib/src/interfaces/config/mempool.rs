@@ -7,6 +7,40 @@ pub struct PoolMaxEntries(usize);
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub struct LogMaxEntries(usize);
 
+/// algorithm used by the leader to pick the fragments from the mempool
+/// when building a new block
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FragmentSelection {
+    /// fill the block with the fragments in the order they were received
+    OldestFirst,
+    /// fill the block with the fragments paying the highest fee per byte first
+    HighestFeeFirst,
+}
+
+/// limits applied to each individual source of fragments: every network
+/// peer is accounted separately, all the REST clients share the same limits.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FragmentOriginLimits {
+    /// maximum number of fragments a single origin can have pending in the mempool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_entries: Option<usize>,
+    /// rate at which a single origin can submit new fragments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimit>,
+}
+
+/// token bucket rate limit: the bucket holds up to `burst` tokens and is
+/// refilled with `fragments_per_second` tokens every second. Every fragment
+/// received consumes one token.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub fragments_per_second: u32,
+    pub burst: u32,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Mempool {
@@ -24,6 +58,15 @@ pub struct Mempool {
     pub log_ttl: Duration,
     /// interval between 2 garbage collection check of the mempool and the log cache.
     pub garbage_collection_interval: Duration,
+    /// algorithm used to select the fragments to put in the block
+    #[serde(default)]
+    pub fragment_selection: FragmentSelection,
+    /// limits applied to the fragments received from each network peer
+    #[serde(default)]
+    pub network_limits: FragmentOriginLimits,
+    /// limits applied to the fragments received from the REST interface
+    #[serde(default)]
+    pub rest_limits: FragmentOriginLimits,
 }
 
 impl Default for PoolMaxEntries {
@@ -38,6 +81,12 @@ impl Default for LogMaxEntries {
     }
 }
 
+impl Default for FragmentSelection {
+    fn default() -> Self {
+        FragmentSelection::OldestFirst
+    }
+}
+
 impl Default for Mempool {
     fn default() -> Self {
         Mempool {
@@ -46,6 +95,9 @@ impl Default for Mempool {
             log_max_entries: LogMaxEntries::default(),
             log_ttl: Duration::new(3600, 0),
             garbage_collection_interval: Duration::new(3600 / 4, 0),
+            fragment_selection: FragmentSelection::default(),
+            network_limits: FragmentOriginLimits::default(),
+            rest_limits: FragmentOriginLimits::default(),
         }
     }
 }
@@ -4,6 +4,8 @@ mod node;
 mod secret;
 
 pub use log::{Log, LogEntry, LogOutput};
-pub use mempool::{LogMaxEntries, Mempool, PoolMaxEntries};
+pub use mempool::{
+    FragmentOriginLimits, FragmentSelection, LogMaxEntries, Mempool, PoolMaxEntries, RateLimit,
+};
 pub use node::{Explorer, NodeConfig, P2p, Policy, Rest, TopicsOfInterest, TrustedPeer};
 pub use secret::{Bft, GenesisPraos, NodeSecret};
@@ -10,9 +10,12 @@ pub enum FragmentOrigin {
     /// origins of the fragment and eventually blacklisting
     /// the senders from sending us more fragment (in case
     /// they are invalids or so)
-    ///
-    /// TODO: add the network identifier/IP Address
-    Network,
+    Network {
+        /// the identifier of the peer that sent us the fragment,
+        /// unknown if the fragment was only seen in a block
+        #[serde(default)]
+        id: Option<poldercast::Id>,
+    },
     /// This marks the fragment is coming from the REST interface
     /// (a client wallet or another service).
     Rest,
@@ -29,6 +32,16 @@ pub enum FragmentStatus {
     InABlock { date: BlockDate, block: Hash },
 }
 
+/// result of looking up the log of a given fragment
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum FragmentLogLookup {
+    /// the fragment is known to the node
+    Found(FragmentLog),
+    /// the node has no log for this fragment: it was never received
+    /// or its log has expired
+    NotFound,
+}
+
 /// the log associated to a given fragment
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct FragmentLog {
@@ -0,0 +1,58 @@
+use crate::crypto::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+/// outcome of the submission of a fragment to the node
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FragmentSubmissionStatus {
+    /// the fragment has been added to the pool and propagated
+    Accepted,
+    /// the fragment is already known to the node, it is either pending
+    /// in the pool or it has already been processed
+    AlreadyKnown,
+    /// the fragment is not valid and will never be accepted
+    Invalid { reason: String },
+    /// the pool is full and the fragment does not pay enough fee to
+    /// take the place of a pending fragment
+    PoolFull,
+    /// the fragment has been refused by the limits applied to its origin
+    Rejected { reason: String },
+}
+
+/// the result of the submission of a given fragment
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FragmentSubmission {
+    fragment_id: Hash,
+    status: FragmentSubmissionStatus,
+}
+
+impl FragmentSubmissionStatus {
+    #[inline]
+    pub fn is_accepted(&self) -> bool {
+        self == &FragmentSubmissionStatus::Accepted
+    }
+}
+
+impl FragmentSubmission {
+    #[inline]
+    pub fn new(fragment_id: Hash, status: FragmentSubmissionStatus) -> Self {
+        FragmentSubmission {
+            fragment_id,
+            status,
+        }
+    }
+
+    #[inline]
+    pub fn fragment_id(&self) -> &Hash {
+        &self.fragment_id
+    }
+
+    #[inline]
+    pub fn status(&self) -> &FragmentSubmissionStatus {
+        &self.status
+    }
+
+    #[inline]
+    pub fn is_accepted(&self) -> bool {
+        self.status.is_accepted()
+    }
+}
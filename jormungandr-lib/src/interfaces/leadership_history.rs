@@ -0,0 +1,63 @@
+use crate::{
+    crypto::hash::Hash,
+    interfaces::{BlockDate, EnclaveLeaderId},
+    time::SystemTime,
+};
+use serde::{Deserialize, Serialize};
+
+/// the outcome of a slot won by one of the node's leaders
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum LeadershipHistoryStatus {
+    /// the slot has not been reconciled with the blockchain yet
+    Pending,
+    /// the block created for the slot is on the main branch
+    Block { block: Hash, chain_length: u32 },
+    /// the block created for the slot has been replaced by the block of
+    /// another branch
+    Orphaned { block: Hash, chain_length: u32 },
+    /// no block was created for the slot, the node was late or down
+    Missed,
+}
+
+/// a slot won by one of the node's leaders, kept in the node's storage
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeadershipHistoryEntry {
+    enclave_leader_id: EnclaveLeaderId,
+    scheduled_at_date: BlockDate,
+    scheduled_at_time: SystemTime,
+    status: LeadershipHistoryStatus,
+}
+
+impl LeadershipHistoryEntry {
+    pub fn new(
+        enclave_leader_id: EnclaveLeaderId,
+        scheduled_at_date: BlockDate,
+        scheduled_at_time: SystemTime,
+    ) -> Self {
+        LeadershipHistoryEntry {
+            enclave_leader_id,
+            scheduled_at_date,
+            scheduled_at_time,
+            status: LeadershipHistoryStatus::Pending,
+        }
+    }
+
+    pub fn enclave_leader_id(&self) -> &EnclaveLeaderId {
+        &self.enclave_leader_id
+    }
+    pub fn scheduled_at_date(&self) -> &BlockDate {
+        &self.scheduled_at_date
+    }
+    pub fn scheduled_at_time(&self) -> &SystemTime {
+        &self.scheduled_at_time
+    }
+    pub fn status(&self) -> &LeadershipHistoryStatus {
+        &self.status
+    }
+
+    /// set the outcome of the slot
+    pub fn set_status(&mut self, status: LeadershipHistoryStatus) {
+        self.status = status
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{crypto::hash::Hash, interfaces::BlockDate, time::SystemTime};
+use crate::{
+    crypto::hash::Hash,
+    interfaces::{BlockDate, Value},
+    time::SystemTime,
+};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -15,8 +19,20 @@ pub struct LeadershipLogId(EnclaveLeaderId, BlockDate);
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum LeadershipLogStatus {
     Pending,
-    Rejected { reason: String },
-    Block { block: Hash, chain_length: u32 },
+    Rejected {
+        reason: String,
+    },
+    Block {
+        block: Hash,
+        chain_length: u32,
+    },
+    /// the block has been assembled but not signed nor sent, the node
+    /// runs the leadership in dry-run mode
+    DryRun {
+        fragments: u32,
+        size: u32,
+        fees: Value,
+    },
 }
 
 /// provides information regarding events in the leadership schedule
@@ -32,6 +48,16 @@ pub struct LeadershipLog {
     enclave_leader_id: EnclaveLeaderId,
 }
 
+/// a slot of the leadership schedule won by one of the node's leaders,
+/// computed ahead of time
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeadershipScheduleEntry {
+    enclave_leader_id: EnclaveLeaderId,
+    scheduled_at_date: BlockDate,
+    scheduled_at_time: SystemTime,
+}
+
 impl EnclaveLeaderId {
     pub fn new() -> Self {
         EnclaveLeaderId(0)
@@ -121,6 +147,30 @@ impl LeadershipLog {
     }
 }
 
+impl LeadershipScheduleEntry {
+    pub fn new(
+        enclave_leader_id: EnclaveLeaderId,
+        scheduled_at_date: BlockDate,
+        scheduled_at_time: SystemTime,
+    ) -> Self {
+        LeadershipScheduleEntry {
+            enclave_leader_id,
+            scheduled_at_date,
+            scheduled_at_time,
+        }
+    }
+
+    pub fn enclave_leader_id(&self) -> &EnclaveLeaderId {
+        &self.enclave_leader_id
+    }
+    pub fn scheduled_at_date(&self) -> &BlockDate {
+        &self.scheduled_at_date
+    }
+    pub fn scheduled_at_time(&self) -> &SystemTime {
+        &self.scheduled_at_time
+    }
+}
+
 impl fmt::Display for EnclaveLeaderId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.0.fmt(f)
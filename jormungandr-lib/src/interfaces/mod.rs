@@ -6,10 +6,14 @@ mod blockdate;
 mod certificate;
 mod config;
 mod fragment_log;
+mod fragment_submission;
+mod leadership_history;
 mod leadership_log;
 mod linear_fee;
+mod node_event;
 mod old_address;
 mod ratio;
+mod remote_signer;
 mod reward_parameters;
 mod settings;
 mod stake_pool_stats;
@@ -31,13 +35,21 @@ pub use self::certificate::{
     SignedCertificate, CERTIFICATE_HRP, SIGNED_CERTIFICATE_HRP,
 };
 pub use self::config::*;
-pub use self::fragment_log::{FragmentLog, FragmentOrigin, FragmentStatus};
+pub use self::fragment_log::{FragmentLog, FragmentLogLookup, FragmentOrigin, FragmentStatus};
+pub use self::fragment_submission::{FragmentSubmission, FragmentSubmissionStatus};
+pub use self::leadership_history::{LeadershipHistoryEntry, LeadershipHistoryStatus};
 pub use self::leadership_log::{
-    EnclaveLeaderId, LeadershipLog, LeadershipLogId, LeadershipLogStatus,
+    EnclaveLeaderId, LeadershipLog, LeadershipLogId, LeadershipLogStatus, LeadershipScheduleEntry,
 };
 pub use self::linear_fee::LinearFeeDef;
+pub use self::node_event::{ChainReorg, ChainRollback, ChainTip, FragmentStatusUpdate, NodeEvent};
 pub use self::old_address::OldAddress;
 pub use self::ratio::{ParseRatioError, Ratio};
+pub use self::remote_signer::{
+    read_message as read_signer_message, write_message as write_signer_message,
+    SignerGenesisLeader, SignerLeader, SignerLeaderEvent, SignerProtocolError, SignerRequest,
+    SignerResponse,
+};
 pub use self::reward_parameters::RewardParams;
 pub use self::settings::{ParametersDef, RatioDef, SettingsDto, TaxTypeDef, TaxTypeSerde};
 pub use self::stake_pool_stats::{Rewards, StakePoolStats};
@@ -0,0 +1,66 @@
+use crate::{
+    crypto::hash::Hash,
+    interfaces::{BlockDate, FragmentStatus},
+    time::SystemTime,
+};
+use serde::{Deserialize, Serialize};
+
+/// a block at the tip of the node's blockchain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainTip {
+    pub hash: Hash,
+    pub chain_length: u32,
+    pub date: BlockDate,
+}
+
+/// the node switched to another branch, the blocks of the previous
+/// branch are rolled back
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainRollback {
+    /// the tip of the branch that has been abandoned
+    pub from: ChainTip,
+    /// the tip of the new branch
+    pub to: ChainTip,
+    /// the last block shared by the two branches
+    pub common_ancestor: ChainTip,
+    /// the number of blocks of the abandoned branch after the common
+    /// ancestor
+    pub depth: u32,
+    /// the blocks of the abandoned branch after the common ancestor, the
+    /// most recent first
+    pub dropped_blocks: Vec<Hash>,
+}
+
+/// a branch switch of the node, as recorded in the node's reorg history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainReorg {
+    pub time: SystemTime,
+    #[serde(flatten)]
+    pub rollback: ChainRollback,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FragmentStatusUpdate {
+    pub fragment_id: Hash,
+    pub status: FragmentStatus,
+}
+
+/// events pushed to the clients subscribed to the node's notifications
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeEvent {
+    /// the tip of the blockchain has been updated
+    Tip(ChainTip),
+    Rollback(ChainRollback),
+    FragmentStatus(FragmentStatusUpdate),
+}
+
+impl NodeEvent {
+    /// name of the event, as used in the event stream
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeEvent::Tip(_) => "tip",
+            NodeEvent::Rollback(_) => "rollback",
+            NodeEvent::FragmentStatus(_) => "fragment_status",
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! protocol spoken between the node and a remote signer over a Unix socket.
+//!
+//! Every message is a big endian `u32` length followed by the bincode
+//! encoding of the message. The node sends a `SignerRequest` and waits
+//! for the matching `SignerResponse` before sending the next request.
+//!
+//! The VRF keys of the Genesis Praos leaders stay in the signer as well:
+//! the node sends the ledger state the leadership of an epoch is computed
+//! from, and the signer evaluates the leadership of its leaders. The signer
+//! creates the headers of the blocks itself, only for the slots its leaders
+//! are leader of, so it never signs data chosen by the node.
+
+use crate::{
+    crypto::{hash::Hash, key::Identifier},
+    interfaces::{BlockDate, EnclaveLeaderId},
+};
+use chain_crypto::Ed25519;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+/// maximum size of an encoded message, larger messages are refused. The
+/// leadership states carry a whole ledger.
+pub const MAX_MESSAGE_SIZE: u32 = 512 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum SignerProtocolError {
+    #[error("cannot communicate with the remote signer")]
+    Io(#[from] io::Error),
+    #[error("invalid message encoding")]
+    Encoding(#[from] bincode::Error),
+    #[error("message of {size} bytes exceeds the maximum of {max} bytes", max = MAX_MESSAGE_SIZE)]
+    MessageTooLarge { size: usize },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SignerRequest {
+    /// list the leaders held by the signer
+    Leaders,
+    /// the ledger state (in its binary encoding) the leadership of the epoch
+    /// is computed from. It is kept by the signer under the
+    /// given identifier, chosen by the node, for the following requests.
+    LeadershipState {
+        state: u64,
+        epoch: u32,
+        ledger: Vec<u8>,
+    },
+    /// evaluate the Genesis Praos leadership of the signer's leaders on the
+    /// given slots of the epoch of the leadership state
+    LeadershipEvaluate {
+        state: u64,
+        slot_start: u32,
+        nb_slots: u32,
+    },
+    /// create the header of a block of the leader, if the leader is leader
+    /// of the slot in the leadership state: a BFT header signed with the
+    /// leader's key, or a Genesis Praos header with the VRF proof of the
+    /// slot and signed with the KES key. The KES key is evolved to the given
+    /// period first. The fragments of the block are given in their binary
+    /// encoding.
+    CreateHeader {
+        id: EnclaveLeaderId,
+        state: u64,
+        parent: Hash,
+        chain_length: u32,
+        date: BlockDate,
+        kes_period: Option<u32>,
+        fragments: Vec<Vec<u8>>,
+    },
+    /// evolve the KES keys of the leaders to the given period, the keys
+    /// already past this period are left as they are
+    EvolveKes { period: u32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SignerResponse {
+    Leaders(Vec<SignerLeader>),
+    /// the slots the signer's leaders are leader of
+    LeaderEvents(Vec<SignerLeaderEvent>),
+    /// the binary encoding of the header
+    Header(Vec<u8>),
+    /// the signer does not hold the leadership state, the node sends it
+    /// again with `LeadershipState` before retrying
+    UnknownLeadershipState,
+    /// the request is processed but has no result
+    Done,
+    /// the request cannot be fulfilled by the signer
+    Error(String),
+}
+
+/// the public part of a leader's secret the node needs to evaluate the BFT
+/// leadership schedule. The keys of the leader never leave the signer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignerLeader {
+    pub id: EnclaveLeaderId,
+    pub bft: Option<Identifier<Ed25519>>,
+    pub genesis: Option<SignerGenesisLeader>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignerGenesisLeader {
+    pub node_id: Hash,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignerLeaderEvent {
+    pub id: EnclaveLeaderId,
+    pub date: BlockDate,
+}
+
+/// write the message on the given stream, prefixed with its length
+pub fn write_message<W, T>(writer: &mut W, message: &T) -> Result<(), SignerProtocolError>
+where
+    W: Write,
+    T: Serialize,
+{
+    let bytes = bincode::serialize(message)?;
+    if bytes.len() > MAX_MESSAGE_SIZE as usize {
+        return Err(SignerProtocolError::MessageTooLarge { size: bytes.len() });
+    }
+    writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// read the next message from the given stream
+pub fn read_message<R, T>(reader: &mut R) -> Result<T, SignerProtocolError>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    let mut size = [0; 4];
+    reader.read_exact(&mut size)?;
+    let size = u32::from_be_bytes(size);
+    if size > MAX_MESSAGE_SIZE {
+        return Err(SignerProtocolError::MessageTooLarge {
+            size: size as usize,
+        });
+    }
+    let mut bytes = vec![0; size as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(bincode::deserialize(&bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn message_round_trip() {
+        let mut buffer = Vec::new();
+        let request = SignerRequest::LeadershipEvaluate {
+            state: 7,
+            slot_start: 10,
+            nb_slots: 3,
+        };
+        write_message(&mut buffer, &request).unwrap();
+        write_message(&mut buffer, &SignerRequest::Leaders).unwrap();
+
+        let mut reader = Cursor::new(buffer);
+        match read_message(&mut reader).unwrap() {
+            SignerRequest::LeadershipEvaluate {
+                state,
+                slot_start,
+                nb_slots,
+            } => {
+                assert_eq!(state, 7);
+                assert_eq!(slot_start, 10);
+                assert_eq!(nb_slots, 3);
+            }
+            request => panic!("unexpected request {:?}", request),
+        }
+        match read_message(&mut reader).unwrap() {
+            SignerRequest::Leaders => (),
+            request => panic!("unexpected request {:?}", request),
+        }
+    }
+
+    #[test]
+    fn leader_events_round_trip() {
+        let mut buffer = Vec::new();
+        let event = SignerLeaderEvent {
+            id: EnclaveLeaderId::new().next(),
+            date: chain_impl_mockchain::block::BlockDate {
+                epoch: 3,
+                slot_id: 14,
+            }
+            .into(),
+        };
+        let response = SignerResponse::LeaderEvents(vec![event.clone()]);
+        write_message(&mut buffer, &response).unwrap();
+
+        match read_message(&mut Cursor::new(buffer)).unwrap() {
+            SignerResponse::LeaderEvents(events) => assert_eq!(events, vec![event]),
+            response => panic!("unexpected response {:?}", response),
+        }
+    }
+
+    #[test]
+    fn oversized_message_is_refused() {
+        let mut reader = Cursor::new((MAX_MESSAGE_SIZE + 1).to_be_bytes().to_vec());
+        match read_message::<_, SignerRequest>(&mut reader) {
+            Err(SignerProtocolError::MessageTooLarge { .. }) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
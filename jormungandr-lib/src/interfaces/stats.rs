@@ -23,6 +23,7 @@ pub struct Stats {
     pub last_block_sum: Option<u32>,
     pub last_block_fees: Option<u32>,
     pub last_block_content_size: Option<u32>,
+    pub pruned_block_height: Option<String>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -0,0 +1,504 @@
+//! reference remote signer for the node.
+//!
+//! It loads the node's secret files and listens on a Unix socket for the
+//! node's leadership and signing requests, so the signing and VRF keys of
+//! the leaders are kept out of the node process. Set the same socket path
+//! in the `leadership.remote_signer` entry of the node's configuration.
+
+#[cfg(unix)]
+mod unix {
+    use chain_core::property::{Deserialize as _, Serialize as _};
+    use chain_crypto::Blake2b256;
+    use chain_impl_mockchain::{
+        fragment::{ContentsBuilder, Fragment},
+        header::{BlockVersion, ChainLength, HeaderBuilderNew},
+        leadership::{BftLeader, GenesisLeader, Leader, LeaderOutput, Leadership},
+        ledger::Ledger,
+    };
+    use jormungandr_lib::{
+        crypto::{
+            hash::Hash,
+            kes::{KesError, KesPeriods},
+            key::Identifier,
+            password_encryption::{self, DecryptionError, PASSPHRASE_ENV_VAR},
+        },
+        interfaces::{
+            read_signer_message, write_signer_message, BlockDate, EnclaveLeaderId, NodeSecret,
+            SignerGenesisLeader, SignerLeader, SignerLeaderEvent, SignerProtocolError,
+            SignerRequest, SignerResponse,
+        },
+    };
+    use std::{
+        env, fs, io,
+        os::unix::{
+            fs::FileTypeExt as _,
+            net::{UnixListener, UnixStream},
+        },
+        path::{Path, PathBuf},
+        process,
+        sync::{Arc, Mutex},
+        thread,
+    };
+    use structopt::StructOpt;
+    use thiserror::Error;
+
+    /// number of leadership states kept for the node, enough for the
+    /// current and the next epoch
+    const MAX_LEADERSHIP_STATES: usize = 4;
+
+    #[derive(StructOpt, Debug)]
+    #[structopt(name = "jormungandr-signer")]
+    struct Arguments {
+        /// path of the Unix socket to listen on for the node's requests
+        #[structopt(long = "socket", parse(from_os_str))]
+        socket: PathBuf,
+
+        /// the node secret file (in YAML format) of a leader. Can be given
+        /// multiple times.
+        #[structopt(long = "secret", parse(from_os_str))]
+        secrets: Vec<PathBuf>,
+
+        /// file to save the periods reached by the KES keys in, so the
+        /// older periods of the keys are skipped after a restart
+        #[structopt(long = "kes-periods", parse(from_os_str))]
+        kes_periods: Option<PathBuf>,
+    }
+
+    #[derive(Debug, Error)]
+    enum Error {
+        #[error("cannot read the secret file {path}")]
+        SecretIo {
+            path: PathBuf,
+            #[source]
+            source: io::Error,
+        },
+        #[error("invalid secret file {path}")]
+        SecretFormat {
+            path: PathBuf,
+            #[source]
+            source: serde_yaml::Error,
+        },
+        #[error("cannot decrypt the secret file {path}")]
+        SecretDecryption {
+            path: PathBuf,
+            #[source]
+            source: DecryptionError,
+        },
+        #[error("cannot evolve the KES keys")]
+        Kes(#[from] KesError),
+        #[error("cannot listen on the socket {path}")]
+        Listen {
+            path: PathBuf,
+            #[source]
+            source: io::Error,
+        },
+    }
+
+    struct Signer {
+        leaders: Mutex<Leaders>,
+        /// the leadership of the epochs computed from the states sent by the
+        /// node, the most recent last
+        leaderships: Mutex<Vec<(u64, Arc<Leadership>)>>,
+    }
+
+    struct Leaders {
+        secrets: Vec<SignerSecret>,
+        kes_periods: KesPeriods,
+    }
+
+    struct SignerSecret {
+        id: EnclaveLeaderId,
+        node_id: Option<Hash>,
+        leader: Leader,
+        /// the period the KES key of the Genesis Praos leader has reached
+        kes_period: u32,
+    }
+
+    fn load_leaders(paths: &[PathBuf]) -> Result<Vec<SignerSecret>, Error> {
+        let mut id = EnclaveLeaderId::new();
+        let mut leaders = Vec::with_capacity(paths.len());
+        for path in paths {
+            let mut data = fs::read(path).map_err(|source| Error::SecretIo {
+                path: path.clone(),
+                source,
+            })?;
+            if password_encryption::is_encrypted(&data) {
+                data = decrypt_secret(path, &data)?;
+            }
+            let secret: NodeSecret =
+                serde_yaml::from_slice(&data).map_err(|source| Error::SecretFormat {
+                    path: path.clone(),
+                    source,
+                })?;
+            // the ids are given in the same order as the node does for its
+            // own secret files
+            id = id.next();
+            leaders.push(SignerSecret {
+                id,
+                node_id: secret.genesis.as_ref().map(|genesis| genesis.node_id),
+                leader: Leader {
+                    bft_leader: secret.bft.map(|bft| BftLeader {
+                        sig_key: bft.signing_key.into_secret_key(),
+                    }),
+                    genesis_leader: secret.genesis.map(|genesis| GenesisLeader {
+                        node_id: Blake2b256::from(genesis.node_id).into(),
+                        sig_key: genesis.sig_key.into_secret_key(),
+                        vrf_key: genesis.vrf_key.into_secret_key(),
+                    }),
+                },
+                kes_period: 0,
+            });
+        }
+        Ok(leaders)
+    }
+
+    /// the passphrase is taken from the environment or prompted for
+    fn decrypt_secret(path: &Path, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let passphrase = match env::var(PASSPHRASE_ENV_VAR) {
+            Ok(passphrase) => passphrase,
+            Err(_) => rpassword::read_password_from_tty(Some(&format!(
+                "passphrase of {}: ",
+                path.display()
+            )))
+            .map_err(|source| Error::SecretIo {
+                path: path.to_path_buf(),
+                source,
+            })?,
+        };
+        password_encryption::decrypt(passphrase.as_bytes(), data).map_err(|source| {
+            Error::SecretDecryption {
+                path: path.to_path_buf(),
+                source,
+            }
+        })
+    }
+
+    impl Signer {
+        fn leadership(&self, state: u64) -> Option<Arc<Leadership>> {
+            let leaderships = self.leaderships.lock().unwrap();
+            leaderships
+                .iter()
+                .find(|(id, _)| *id == state)
+                .map(|(_, leadership)| Arc::clone(leadership))
+        }
+
+        fn handle_request(&self, request: SignerRequest) -> SignerResponse {
+            match request {
+                SignerRequest::Leaders => {
+                    let leaders = self.leaders.lock().unwrap();
+                    SignerResponse::Leaders(
+                        leaders
+                            .secrets
+                            .iter()
+                            .map(|secret| SignerLeader {
+                                id: secret.id,
+                                bft: secret
+                                    .leader
+                                    .bft_leader
+                                    .as_ref()
+                                    .map(|bft| Identifier::from(bft.sig_key.to_public())),
+                                genesis: secret
+                                    .node_id
+                                    .map(|node_id| SignerGenesisLeader { node_id }),
+                            })
+                            .collect(),
+                    )
+                }
+                SignerRequest::LeadershipState {
+                    state,
+                    epoch,
+                    ledger,
+                } => {
+                    let ledger = match Ledger::deserialize(ledger.as_slice()) {
+                        Ok(ledger) => ledger,
+                        Err(_) => return SignerResponse::Error("invalid ledger state".to_owned()),
+                    };
+                    let leadership = Arc::new(Leadership::new(epoch, &ledger));
+                    let mut leaderships = self.leaderships.lock().unwrap();
+                    leaderships.retain(|(id, _)| *id != state);
+                    if leaderships.len() >= MAX_LEADERSHIP_STATES {
+                        leaderships.remove(0);
+                    }
+                    leaderships.push((state, leadership));
+                    SignerResponse::Done
+                }
+                SignerRequest::LeadershipEvaluate {
+                    state,
+                    slot_start,
+                    nb_slots,
+                } => {
+                    let leadership = match self.leadership(state) {
+                        Some(leadership) => leadership,
+                        None => return SignerResponse::UnknownLeadershipState,
+                    };
+                    let leaders = self.leaders.lock().unwrap();
+                    let mut events = Vec::new();
+                    for slot in slot_start..slot_start + nb_slots {
+                        let date = leadership.date_at_slot(slot);
+                        for secret in leaders.secrets.iter() {
+                            if let Ok(LeaderOutput::GenesisPraos(..)) =
+                                leadership.is_leader_for_date(&secret.leader, date)
+                            {
+                                events.push(SignerLeaderEvent {
+                                    id: secret.id,
+                                    date: BlockDate::from(date),
+                                })
+                            }
+                        }
+                    }
+                    SignerResponse::LeaderEvents(events)
+                }
+                SignerRequest::CreateHeader {
+                    id,
+                    state,
+                    parent,
+                    chain_length,
+                    date,
+                    kes_period,
+                    fragments,
+                } => {
+                    let leadership = match self.leadership(state) {
+                        Some(leadership) => leadership,
+                        None => return SignerResponse::UnknownLeadershipState,
+                    };
+                    let mut leaders = self.leaders.lock().unwrap();
+                    let Leaders {
+                        secrets,
+                        kes_periods,
+                    } = &mut *leaders;
+                    let secret = match secrets.iter_mut().find(|secret| secret.id == id) {
+                        Some(secret) => secret,
+                        None => return SignerResponse::Error(format!("no leader {}", id)),
+                    };
+                    if let Some(period) = kes_period {
+                        match kes_periods.evolve_leader(
+                            &mut secret.leader,
+                            secret.kes_period,
+                            period,
+                        ) {
+                            Ok(period) => secret.kes_period = period,
+                            Err(error) => {
+                                return SignerResponse::Error(format!(
+                                    "cannot evolve the KES key: {}",
+                                    error
+                                ))
+                            }
+                        }
+                    }
+                    create_header(
+                        &leadership,
+                        secret,
+                        parent,
+                        chain_length,
+                        date.into(),
+                        fragments,
+                    )
+                }
+                SignerRequest::EvolveKes { period } => {
+                    let mut leaders = self.leaders.lock().unwrap();
+                    let Leaders {
+                        secrets,
+                        kes_periods,
+                    } = &mut *leaders;
+                    for secret in secrets.iter_mut() {
+                        match kes_periods.evolve_leader(
+                            &mut secret.leader,
+                            secret.kes_period,
+                            period,
+                        ) {
+                            Ok(period) => secret.kes_period = period,
+                            Err(error) => {
+                                return SignerResponse::Error(format!(
+                                    "cannot evolve the KES key of leader {}: {}",
+                                    secret.id, error
+                                ))
+                            }
+                        }
+                    }
+                    SignerResponse::Done
+                }
+            }
+        }
+    }
+
+    /// create the header of the block after checking the leader is leader
+    /// of the slot, the node cannot make the signer sign anything else
+    fn create_header(
+        leadership: &Leadership,
+        secret: &SignerSecret,
+        parent: Hash,
+        chain_length: u32,
+        date: chain_impl_mockchain::header::BlockDate,
+        fragments: Vec<Vec<u8>>,
+    ) -> SignerResponse {
+        let output = match leadership.is_leader_for_date(&secret.leader, date) {
+            Ok(output @ LeaderOutput::Bft(_)) | Ok(output @ LeaderOutput::GenesisPraos(..)) => {
+                output
+            }
+            _ => {
+                return SignerResponse::Error(format!(
+                    "leader {} is not leader of the slot {}",
+                    secret.id, date
+                ))
+            }
+        };
+
+        let mut contents = ContentsBuilder::new();
+        for fragment in fragments {
+            match Fragment::deserialize(fragment.as_slice()) {
+                Ok(fragment) => contents.push(fragment),
+                Err(_) => return SignerResponse::Error("invalid fragment".to_owned()),
+            }
+        }
+        let contents = contents.into();
+        let parent = parent.into_hash();
+        let chain_length = ChainLength::from(chain_length);
+        let header = match (output, &secret.leader) {
+            (
+                LeaderOutput::Bft(leader_id),
+                Leader {
+                    bft_leader: Some(bft),
+                    ..
+                },
+            ) => {
+                let builder = HeaderBuilderNew::new(BlockVersion::Ed25519Signed, &contents)
+                    .set_parent(&parent, chain_length)
+                    .set_date(date)
+                    .to_bft_builder()
+                    .expect("Valid Header Builder")
+                    .set_consensus_data(&leader_id);
+                let signature = bft.sig_key.sign_slice(builder.get_authenticated_data());
+                builder.set_signature(signature.into()).generalize()
+            }
+            (
+                LeaderOutput::GenesisPraos(node_id, witness),
+                Leader {
+                    genesis_leader: Some(genesis),
+                    ..
+                },
+            ) => {
+                let builder = HeaderBuilderNew::new(BlockVersion::KesVrfproof, &contents)
+                    .set_parent(&parent, chain_length)
+                    .set_date(date)
+                    .to_genesis_praos_builder()
+                    .expect("Valid Header Builder")
+                    .set_consensus_data(&node_id, &witness.into());
+                let signature = genesis.sig_key.sign_slice(builder.get_authenticated_data());
+                builder.set_signature(signature.into()).generalize()
+            }
+            _ => {
+                return SignerResponse::Error(format!(
+                    "leader {} cannot sign the slot {}",
+                    secret.id, date
+                ))
+            }
+        };
+        match header.serialize_as_vec() {
+            Ok(header) => SignerResponse::Header(header),
+            Err(_) => SignerResponse::Error("cannot encode the header".to_owned()),
+        }
+    }
+
+    fn serve(signer: &Signer, mut stream: UnixStream) -> Result<(), SignerProtocolError> {
+        loop {
+            let request = match read_signer_message(&mut stream) {
+                Ok(request) => request,
+                Err(SignerProtocolError::Io(error))
+                    if error.kind() == io::ErrorKind::UnexpectedEof =>
+                {
+                    // the node closed the connection
+                    return Ok(());
+                }
+                Err(error) => return Err(error),
+            };
+            let response = signer.handle_request(request);
+            write_signer_message(&mut stream, &response)?;
+        }
+    }
+
+    fn run(arguments: Arguments) -> Result<(), Error> {
+        let mut secrets = load_leaders(&arguments.secrets)?;
+        if secrets.is_empty() {
+            eprintln!("warning: no secret file given, the signer holds no leader");
+        }
+        let mut kes_periods = KesPeriods::load(arguments.kes_periods.clone())?;
+        // the keys loaded from the secret files start at the period 0, skip
+        // the periods they have already been used in
+        for secret in secrets.iter_mut() {
+            secret.kes_period =
+                kes_periods.evolve_leader(&mut secret.leader, secret.kes_period, 0)?;
+        }
+
+        let listen = || -> io::Result<UnixListener> {
+            // a socket left by a previous run would prevent binding
+            if let Ok(metadata) = fs::symlink_metadata(&arguments.socket) {
+                if metadata.file_type().is_socket() {
+                    fs::remove_file(&arguments.socket)?;
+                }
+            }
+            // only the user running the signer may connect to it: the socket
+            // is created without access for the others, rather than
+            // restricted after it is already reachable
+            let umask = unsafe { libc::umask(0o177) };
+            let listener = UnixListener::bind(&arguments.socket);
+            unsafe { libc::umask(umask) };
+            listener
+        };
+        let listener = listen().map_err(|source| Error::Listen {
+            path: arguments.socket.clone(),
+            source,
+        })?;
+        eprintln!(
+            "listening on {} with {} leader(s)",
+            arguments.socket.display(),
+            secrets.len()
+        );
+        let signer = Arc::new(Signer {
+            leaders: Mutex::new(Leaders {
+                secrets,
+                kes_periods,
+            }),
+            leaderships: Mutex::new(Vec::new()),
+        });
+
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    eprintln!("cannot accept connection: {}", error);
+                    continue;
+                }
+            };
+            let signer = Arc::clone(&signer);
+            thread::spawn(move || {
+                if let Err(error) = serve(&signer, stream) {
+                    eprintln!("connection closed: {}", error);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    pub fn main() {
+        if let Err(error) = run(Arguments::from_args()) {
+            eprintln!("{}", error);
+            let mut source = std::error::Error::source(&error);
+            while let Some(error) = source {
+                eprintln!("  caused by: {}", error);
+                source = error.source();
+            }
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn main() {
+    unix::main()
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("the remote signer is only available on Unix systems");
+    std::process::exit(1);
+}
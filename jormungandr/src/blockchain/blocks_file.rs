@@ -0,0 +1,410 @@
+//! portable files of the blocks of the main branch, to back up the
+//! blockchain or to seed the storage of a node without network access.
+//!
+//! The blocks follow the order of the main branch, starting with the block0.
+//! The file has the following layout, every block is prefixed with its size
+//! (4 bytes, big endian):
+//!
+//! ```text
+//! | magic (4) | version (1) | (block)* |
+//! ```
+
+use super::chain::AppliedBlock;
+use crate::{
+    blockcfg::{Block, HeaderHash},
+    blockchain::{
+        process_new_ref, Blockchain, Error as BlockchainError, PreCheckedHeader, Ref, Storage, Tip,
+        MAIN_BRANCH_TAG, PRUNED_TAG,
+    },
+    utils::framing::{read_frame, write_frame, FrameError},
+};
+use chain_core::property::{Deserialize as _, Serialize as _};
+use chain_storage::error::Error as StorageError;
+use futures03::{
+    compat::{Future01CompatExt as _, Stream01CompatExt as _},
+    stream::TryStreamExt as _,
+};
+use slog::Logger;
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+};
+use thiserror::Error;
+
+const MAGIC: [u8; 4] = *b"jblk";
+const VERSION: u8 = 1;
+
+/// number of imported blocks between two progress messages
+const PROGRESS_LOGGING_DISTANCE: u64 = 2500;
+
+#[derive(Debug, Error)]
+pub enum BlocksFileError {
+    #[error("cannot access the blocks file")]
+    Io(#[from] io::Error),
+    #[error("the file is not a blocks file")]
+    NotBlocksFile,
+    #[error("unsupported blocks file version {0}")]
+    UnsupportedVersion(u8),
+    #[error("the blocks file is malformed")]
+    Malformed,
+    #[error("the blocks file holds no block")]
+    Empty,
+    #[error("the blocks file starts with the block {found}, not with the block0 {expected}")]
+    WrongBlock0 {
+        expected: HeaderHash,
+        found: HeaderHash,
+    },
+    #[error("the contents of the stored blocks have been pruned or the node started from a ledger snapshot, the blocks cannot be exported")]
+    Pruned,
+    #[error("cannot access the stored blocks")]
+    Storage(#[from] StorageError),
+    #[error("the parent of the block {0} is not in the blocks file")]
+    MissingParent(HeaderHash),
+    #[error("the block {hash} is not valid")]
+    InvalidBlock {
+        hash: HeaderHash,
+        #[source]
+        source: BlockchainError,
+    },
+    #[error("cannot select the new tip")]
+    ChainSelection(#[source] BlockchainError),
+}
+
+/// write the blocks of the main branch, from the block0 up to the block of
+/// the `HEAD` tag. Returns the number of written blocks.
+pub async fn export_blocks<W: Write>(
+    storage: &Storage,
+    block0: &Block,
+    mut writer: W,
+) -> Result<u64, BlocksFileError> {
+    if storage
+        .get_tag(PRUNED_TAG.to_owned())
+        .compat()
+        .await?
+        .is_some()
+    {
+        return Err(BlocksFileError::Pruned);
+    }
+    let block0_hash = block0.header.hash();
+    let head = storage
+        .get_tag(MAIN_BRANCH_TAG.to_owned())
+        .compat()
+        .await?
+        .unwrap_or(block0_hash);
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[VERSION])?;
+    write_block(&mut writer, block0)?;
+    let mut written = 1;
+    let mut blocks = storage
+        .stream_from_to(block0_hash, head)
+        .compat()
+        .await?
+        .compat();
+    while let Some(block) = blocks.try_next().await? {
+        write_block(&mut writer, &block)?;
+        written += 1;
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+/// validate the blocks of the file and add them to the blockchain, as the
+/// blocks received from the network are. The blocks already held by the
+/// node are skipped. Returns the number of new blocks.
+pub async fn import_blocks<R: Read>(
+    blockchain: &Blockchain,
+    tip: &Tip,
+    mut reader: R,
+    logger: &Logger,
+) -> Result<u64, BlocksFileError> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(BlocksFileError::NotBlocksFile);
+    }
+    let mut version = [0; 1];
+    reader.read_exact(&mut version)?;
+    if version[0] != VERSION {
+        return Err(BlocksFileError::UnsupportedVersion(version[0]));
+    }
+    let block0 = read_block(&mut reader)?.ok_or(BlocksFileError::Empty)?;
+    if block0.header.hash() != *blockchain.block0() {
+        return Err(BlocksFileError::WrongBlock0 {
+            expected: *blockchain.block0(),
+            found: block0.header.hash(),
+        });
+    }
+
+    let mut imported = 0;
+    let mut last_ref = None;
+    let result = apply_blocks(blockchain, reader, &mut imported, &mut last_ref, logger).await;
+
+    // the tip is moved to the imported blocks even if a later block of the
+    // file is not valid
+    if let Some(new_ref) = last_ref {
+        process_new_ref(logger.clone(), blockchain.clone(), tip.clone(), new_ref)
+            .compat()
+            .await
+            .map_err(BlocksFileError::ChainSelection)?;
+    }
+    result.map(|()| imported)
+}
+
+async fn apply_blocks<R: Read>(
+    blockchain: &Blockchain,
+    mut reader: R,
+    imported: &mut u64,
+    last_ref: &mut Option<Arc<Ref>>,
+    logger: &Logger,
+) -> Result<(), BlocksFileError> {
+    while let Some(block) = read_block(&mut reader)? {
+        let hash = block.header.hash();
+        let invalid_block = |source| BlocksFileError::InvalidBlock { hash, source };
+
+        let pre_checked = blockchain
+            .pre_check_header(block.header.clone(), false)
+            .compat()
+            .await
+            .map_err(invalid_block)?;
+        let (header, parent_ref) = match pre_checked {
+            PreCheckedHeader::AlreadyPresent { .. } => continue,
+            PreCheckedHeader::MissingParent { .. } => {
+                return Err(BlocksFileError::MissingParent(hash))
+            }
+            PreCheckedHeader::HeaderWithCache { header, parent_ref } => (header, parent_ref),
+        };
+        let post_checked = blockchain
+            .post_check_header(header, parent_ref)
+            .compat()
+            .await
+            .map_err(invalid_block)?;
+        let applied = blockchain
+            .apply_and_store_block(post_checked, block)
+            .compat()
+            .await
+            .map_err(invalid_block)?;
+        if let AppliedBlock::New(_) = &applied {
+            *imported += 1;
+            if *imported % PROGRESS_LOGGING_DISTANCE == 0 {
+                info!(
+                    logger,
+                    "imported {} blocks, currently at {}",
+                    imported,
+                    applied.cached_ref().header().description()
+                );
+            }
+        }
+        *last_ref = Some(applied.cached_ref());
+    }
+    Ok(())
+}
+
+fn write_block<W: Write>(writer: &mut W, block: &Block) -> Result<(), BlocksFileError> {
+    let bytes = block
+        .serialize_as_vec()
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+    write_frame(writer, &bytes)?;
+    Ok(())
+}
+
+/// read the next block of the file, `None` at the end of the file
+fn read_block<R: Read>(reader: &mut R) -> Result<Option<Block>, BlocksFileError> {
+    let bytes = match read_frame(reader) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Ok(None),
+        Err(FrameError::Io(error)) => return Err(error.into()),
+        Err(FrameError::Truncated) => return Err(BlocksFileError::Malformed),
+    };
+    Block::deserialize(bytes.as_slice())
+        .map(Some)
+        .map_err(|_| BlocksFileError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockcfg::{
+            BlockDate, BlockVersion, ChainLength, ConsensusVersion, Contents, ContentsBuilder,
+            Header, HeaderBuilderNew,
+        },
+        blockchain::SledStore,
+    };
+    use chain_addr::Discrimination;
+    use chain_crypto::{Ed25519, SecretKey};
+    use chain_impl_mockchain::{fee::LinearFee, leadership::bft};
+    use jormungandr_lib::interfaces::{Block0Configuration, BlockchainConfiguration};
+    use std::time::Duration;
+
+    fn block0(leader: &SecretKey<Ed25519>) -> Block {
+        let mut blockchain_configuration = BlockchainConfiguration::new(
+            Discrimination::Test,
+            ConsensusVersion::Bft,
+            LinearFee::new(0, 0, 0),
+        );
+        blockchain_configuration.consensus_leader_ids = vec![leader.to_public().into()];
+        Block0Configuration {
+            blockchain_configuration,
+            initial: Vec::new(),
+        }
+        .to_block()
+    }
+
+    fn bft_block(leader: &SecretKey<Ed25519>, parent: &Header, slot_id: u32) -> Block {
+        let contents: Contents = ContentsBuilder::new().into();
+        let builder = HeaderBuilderNew::new(BlockVersion::Ed25519Signed, &contents)
+            .set_parent(
+                &parent.hash(),
+                ChainLength::from(u32::from(parent.chain_length()) + 1),
+            )
+            .set_date(BlockDate { epoch: 0, slot_id })
+            .to_bft_builder()
+            .unwrap()
+            .set_consensus_data(&bft::LeaderId::from(leader.to_public()));
+        let signature = leader.sign_slice(builder.get_authenticated_data());
+        let header = builder.set_signature(signature.into()).generalize();
+        Block { header, contents }
+    }
+
+    /// the blocks of the first slots of the epoch 0, after the block0
+    fn bft_chain(leader: &SecretKey<Ed25519>, block0: &Block, len: u32) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for slot_id in 1..=len {
+            let parent = blocks.last().map_or(&block0.header, |block| &block.header);
+            let block = bft_block(leader, parent, slot_id);
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    async fn new_blockchain(block0: &Block) -> (Blockchain, Tip) {
+        let storage = Storage::new(SledStore::temporary().unwrap());
+        let blockchain = Blockchain::new(
+            block0.header.hash(),
+            storage,
+            Duration::from_secs(3600),
+            false,
+        );
+        let branch = blockchain.load_from_block0(block0.clone()).await.unwrap();
+        (blockchain, Tip::new(branch))
+    }
+
+    fn blocks_file(blocks: &[&Block]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        for block in blocks {
+            write_block(&mut bytes, block).unwrap();
+        }
+        bytes
+    }
+
+    async fn apply(blockchain: &Blockchain, block: Block) {
+        let parent = match blockchain
+            .pre_check_header(block.header.clone(), false)
+            .compat()
+            .await
+            .unwrap()
+        {
+            PreCheckedHeader::HeaderWithCache { parent_ref, .. } => parent_ref,
+            _ => panic!("the parent of the block should be known"),
+        };
+        let post_checked = blockchain
+            .post_check_header(block.header.clone(), parent)
+            .compat()
+            .await
+            .unwrap();
+        blockchain
+            .apply_and_store_block(post_checked, block)
+            .compat()
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn exported_blocks_are_imported() {
+        let logger = Logger::root(slog::Discard, o!());
+        let leader = SecretKey::<Ed25519>::generate(rand::thread_rng());
+        let block0 = block0(&leader);
+        let blocks = bft_chain(&leader, &block0, 5);
+        let tip_hash = blocks.last().unwrap().header.hash();
+
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (blockchain, tip) = new_blockchain(&block0).await;
+            for block in &blocks {
+                apply(&blockchain, block.clone()).await;
+            }
+            blockchain
+                .storage()
+                .put_tag(MAIN_BRANCH_TAG.to_owned(), tip_hash)
+                .compat()
+                .await
+                .unwrap();
+            let mut file = Vec::new();
+            let written = export_blocks(blockchain.storage(), &block0, &mut file)
+                .await
+                .unwrap();
+            assert_eq!(written, 6);
+            // the blocks already held by the node are skipped
+            let imported = import_blocks(&blockchain, &tip, file.as_slice(), &logger)
+                .await
+                .unwrap();
+            assert_eq!(imported, 0);
+
+            let (blockchain, tip) = new_blockchain(&block0).await;
+            let imported = import_blocks(&blockchain, &tip, file.as_slice(), &logger)
+                .await
+                .unwrap();
+            assert_eq!(imported, 5);
+            assert_eq!(tip.get_ref_std().await.hash(), tip_hash);
+        });
+    }
+
+    #[test]
+    fn invalid_blocks_files_are_refused() {
+        let logger = Logger::root(slog::Discard, o!());
+        let leader = SecretKey::<Ed25519>::generate(rand::thread_rng());
+        let block0 = block0(&leader);
+        let other_block0 = self::block0(&SecretKey::<Ed25519>::generate(rand::thread_rng()));
+
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (blockchain, tip) = new_blockchain(&block0).await;
+            let import = |bytes: Vec<u8>| {
+                let blockchain = blockchain.clone();
+                let tip = tip.clone();
+                let logger = logger.clone();
+                async move { import_blocks(&blockchain, &tip, bytes.as_slice(), &logger).await }
+            };
+
+            match import(b"nope\x01".to_vec()).await {
+                Err(BlocksFileError::NotBlocksFile) => (),
+                _ => panic!("not a blocks file"),
+            }
+            let mut bytes = blocks_file(&[&block0]);
+            bytes[MAGIC.len()] = VERSION + 1;
+            match import(bytes).await {
+                Err(BlocksFileError::UnsupportedVersion(version)) => {
+                    assert_eq!(version, VERSION + 1)
+                }
+                _ => panic!("unsupported version"),
+            }
+            match import(blocks_file(&[&other_block0])).await {
+                Err(BlocksFileError::WrongBlock0 { expected, found }) => {
+                    assert_eq!(expected, block0.header.hash());
+                    assert_eq!(found, other_block0.header.hash());
+                }
+                _ => panic!("the file of another blockchain"),
+            }
+            // the size of the block is larger than the rest of the file
+            let mut bytes = blocks_file(&[&block0]);
+            bytes.extend_from_slice(&16u32.to_be_bytes());
+            bytes.extend_from_slice(&[0; 4]);
+            match import(bytes).await {
+                Err(BlocksFileError::Malformed) => (),
+                _ => panic!("truncated block"),
+            }
+        });
+    }
+}
@@ -55,7 +55,9 @@ use crate::{
         Block, Block0Error, BlockDate, ChainLength, Epoch, EpochRewardsInfo, Header, HeaderHash,
         Leadership, Ledger, LedgerParameters, RewardsInfoParameters,
     },
-    blockchain::{Branch, Checkpoints, Multiverse, Ref, Storage},
+    blockchain::{
+        Branch, Checkpoints, EpochTransitionRewards, Multiverse, Ref, Reorgs, Snapshot, Storage,
+    },
 };
 use chain_impl_mockchain::{leadership::Verification, ledger};
 use chain_storage::error::Error as StorageError;
@@ -115,6 +117,10 @@ error_chain! {
         CannotApplyBlock {
             description("Block cannot be applied on top of the previous block's ledger state"),
         }
+
+        SnapshotWithoutPreviousEpoch {
+            description("The ledger snapshot does not hold the state of the previous epoch"),
+        }
     }
 }
 
@@ -131,6 +137,11 @@ pub enum HeaderChainVerifyError {
 
 pub const MAIN_BRANCH_TAG: &str = "HEAD";
 
+/// tag of the block of the main branch up to which the node does not serve
+/// the blocks: their contents have been pruned, or the node started from a
+/// ledger snapshot taken at this block
+pub const PRUNED_TAG: &str = "PRUNED";
+
 /// Performs lightweight sanity checks on information fields of a block header
 /// against those in the header of the block's parent.
 /// The `parent` header must have been retrieved based on, or otherwise
@@ -182,7 +193,13 @@ pub struct Blockchain {
 
     storage: Storage,
 
+    reorgs: Reorgs,
+
     block0: HeaderHash,
+
+    /// report the rewards of every account at the epoch transitions, not
+    /// only the totals: the explorer indexes them
+    report_account_rewards: bool,
 }
 
 pub enum PreCheckedHeader {
@@ -224,10 +241,12 @@ pub enum PreCheckedHeader {
 pub struct PostCheckedHeader {
     header: Header,
     epoch_leadership_schedule: Arc<Leadership>,
+    epoch_leadership_state: Arc<Ledger>,
     epoch_ledger_parameters: Arc<LedgerParameters>,
     parent_ledger_state: Arc<Ledger>,
     time_frame: Arc<TimeFrame>,
     previous_epoch_state: Option<Arc<Ref>>,
+    epoch_rewards: Option<Arc<EpochTransitionRewards>>,
 }
 
 impl PostCheckedHeader {
@@ -258,13 +277,20 @@ impl AppliedBlock {
 }
 
 impl Blockchain {
-    pub fn new(block0: HeaderHash, storage: Storage, ref_cache_ttl: Duration) -> Self {
+    pub fn new(
+        block0: HeaderHash,
+        storage: Storage,
+        ref_cache_ttl: Duration,
+        report_account_rewards: bool,
+    ) -> Self {
         Blockchain {
             branches: Branches::new(),
             ref_cache: RefCache::new(ref_cache_ttl),
             ledgers: Multiverse::new(),
             storage,
+            reorgs: Reorgs::new(),
             block0,
+            report_account_rewards,
         }
     }
 
@@ -276,6 +302,11 @@ impl Blockchain {
         &self.storage
     }
 
+    /// the branch switches of the node
+    pub fn reorgs(&self) -> &Reorgs {
+        &self.reorgs
+    }
+
     pub fn branches(&self) -> &Branches {
         &self.branches
     }
@@ -292,8 +323,10 @@ impl Blockchain {
         ledger: Ledger,
         time_frame: Arc<TimeFrame>,
         leadership: Arc<Leadership>,
+        leadership_state: Arc<Ledger>,
         ledger_parameters: Arc<LedgerParameters>,
         previous_epoch_state: Option<Arc<Ref>>,
+        epoch_rewards: Option<Arc<EpochTransitionRewards>>,
     ) -> impl Future<Item = Arc<Ref>, Error = Infallible> {
         let chain_length = header.chain_length();
 
@@ -307,9 +340,11 @@ impl Blockchain {
                     ledger_ref,
                     time_frame,
                     leadership,
+                    leadership_state,
                     ledger_parameters,
                     header,
                     previous_epoch_state,
+                    epoch_rewards,
                 );
                 let reference = Arc::new(reference);
                 ref_cache
@@ -447,19 +482,23 @@ impl Blockchain {
         let (
             parent_ledger_state,
             epoch_leadership_schedule,
+            epoch_leadership_state,
             epoch_ledger_parameters,
             time_frame,
             previous_epoch_state,
-        ) = new_epoch_leadership_from(current_date.epoch, parent);
+            epoch_rewards,
+        ) = new_epoch_leadership_from(current_date.epoch, parent, self.report_account_rewards);
 
         match epoch_leadership_schedule.verify(&header) {
             Verification::Success => future::ok(PostCheckedHeader {
                 header,
                 epoch_leadership_schedule,
+                epoch_leadership_state,
                 epoch_ledger_parameters,
                 parent_ledger_state,
                 time_frame,
                 previous_epoch_state,
+                epoch_rewards,
             }),
             Verification::Failure(error) => {
                 future::err(ErrorKind::BlockHeaderVerificationFailed(error.to_string()).into())
@@ -475,10 +514,12 @@ impl Blockchain {
         let header = post_checked_header.header;
         let block_id = header.hash();
         let epoch_leadership_schedule = post_checked_header.epoch_leadership_schedule;
+        let epoch_leadership_state = post_checked_header.epoch_leadership_state;
         let epoch_ledger_parameters = post_checked_header.epoch_ledger_parameters;
         let ledger = post_checked_header.parent_ledger_state;
         let time_frame = post_checked_header.time_frame;
         let previous_epoch_state = post_checked_header.previous_epoch_state;
+        let epoch_rewards = post_checked_header.epoch_rewards;
 
         debug_assert!(block.header.hash() == block_id);
 
@@ -499,8 +540,10 @@ impl Blockchain {
                     new_ledger,
                     time_frame,
                     epoch_leadership_schedule,
+                    epoch_leadership_state,
                     epoch_ledger_parameters,
                     previous_epoch_state,
+                    epoch_rewards,
                 )
                 .map_err(|_: Infallible| unreachable!())
         })
@@ -546,21 +589,7 @@ impl Blockchain {
 
         let mut branches = self.branches.clone();
 
-        let time_frame = {
-            use crate::blockcfg::Block0DataSource as _;
-
-            let start_time = block0
-                .start_time()
-                .map_err(|err| Error::with_chain(err, ErrorKind::Block0InitialLedgerError))?;
-            let slot_duration = block0
-                .slot_duration()
-                .map_err(|err| Error::with_chain(err, ErrorKind::Block0InitialLedgerError))?;
-
-            TimeFrame::new(
-                chain_time::Timeline::new(start_time),
-                chain_time::SlotDuration::from_secs(slot_duration.as_secs() as u32),
-            )
-        };
+        let time_frame = block0_time_frame(block0)?;
 
         // we lift the creation of the ledger in the future type
         // this allow chaining of the operation and lifting the error handling
@@ -569,6 +598,7 @@ impl Blockchain {
             .map_err(|err| Error::with_chain(err, ErrorKind::Block0InitialLedgerError))?;
         let block0_leadership = Leadership::new(block0_date.epoch, &block0_ledger);
         let ledger_parameters = block0_leadership.ledger_parameters().clone();
+        let block0_leadership_state = Arc::new(block0_ledger.clone());
 
         let b = self
             .create_and_store_reference(
@@ -577,8 +607,10 @@ impl Blockchain {
                 block0_ledger,
                 Arc::new(time_frame),
                 Arc::new(block0_leadership),
+                block0_leadership_state,
                 Arc::new(ledger_parameters),
                 None,
+                None,
             )
             .compat()
             .await
@@ -681,16 +713,167 @@ impl Blockchain {
 
         let block0_branch = self.apply_block0(&block0).await?;
 
+        self.apply_stored_blocks(block0_branch, block0_id, head_hash, logger)
+            .await
+    }
+
+    /// load the blockchain from a ledger snapshot instead of the block0. The
+    /// block0 is still needed for the time frame of the blockchain.
+    ///
+    /// The first time the node starts from the snapshot, the snapshot block
+    /// is added to the storage as the root of the stored blocks. Afterwards
+    /// the stored blocks from the snapshot block to the `Head` are applied.
+    ///
+    /// # Errors
+    ///
+    /// The resulted future may fail if
+    ///
+    /// * the block0 does not hold a valid time frame: `ErrorKind::Block0InitialLedgerError`;
+    /// * the snapshot does not hold the state of the previous epoch:
+    ///   `ErrorKind::SnapshotWithoutPreviousEpoch`;
+    /// * other errors while interacting with the storage (IO errors)
+    ///
+    pub async fn load_from_snapshot(
+        &self,
+        block0: &Block,
+        snapshot: Snapshot,
+        logger: &Logger,
+    ) -> Result<Branch> {
+        use chain_impl_mockchain::block::ConsensusVersion;
+        use tokio_compat::prelude::*;
+
+        let time_frame = Arc::new(block0_time_frame(block0)?);
+        let (block, ledger, previous_epochs) = snapshot.into_parts();
+        let block_id = block.header.hash();
+
+        // the states of the previous epochs are only used for their ledger:
+        // their own leadership is not part of the snapshot, it is evaluated
+        // from their ledger instead
+        let mut previous_epoch_state = None;
+        for (header, ledger) in previous_epochs.into_iter().rev() {
+            let leadership = Leadership::new(header.block_date().epoch, &ledger);
+            let ledger_parameters = leadership.ledger_parameters().clone();
+            let leadership_state = Arc::new(ledger.clone());
+            let reference = self
+                .create_and_store_reference(
+                    header.hash(),
+                    header,
+                    ledger,
+                    Arc::clone(&time_frame),
+                    Arc::new(leadership),
+                    leadership_state,
+                    Arc::new(ledger_parameters),
+                    previous_epoch_state,
+                    None,
+                )
+                .compat()
+                .await
+                .unwrap();
+            previous_epoch_state = Some(reference);
+        }
+        let previous_epoch = previous_epoch_state.ok_or(ErrorKind::SnapshotWithoutPreviousEpoch)?;
+
+        // the leadership of the snapshot's epoch, evaluated as it was by
+        // `new_epoch_leadership_from` at the beginning of the epoch
+        let epoch_state = match previous_epoch.last_ref_previous_epoch() {
+            Some(reference)
+                if previous_epoch.ledger().consensus_version()
+                    == ConsensusVersion::GenesisPraos =>
+            {
+                reference.ledger()
+            }
+            _ => previous_epoch.ledger(),
+        };
+        let leadership = Leadership::new(block.header.block_date().epoch, &epoch_state);
+        let ledger_parameters = leadership.ledger_parameters().clone();
+        let reference = self
+            .create_and_store_reference(
+                block_id,
+                block.header.clone(),
+                ledger,
+                time_frame,
+                Arc::new(leadership),
+                epoch_state,
+                Arc::new(ledger_parameters),
+                Some(previous_epoch),
+                None,
+            )
+            .compat()
+            .await
+            .unwrap();
+        let snapshot_branch = Branch::new(reference);
+        self.branches
+            .clone()
+            .add(snapshot_branch.clone())
+            .compat()
+            .await
+            .unwrap();
+
+        let already_exist = self
+            .storage
+            .block_exists(block_id)
+            .compat()
+            .await
+            .map_err(|e| {
+                Error::with_chain(e, "Cannot check if the snapshot block is in storage")
+            })?;
+
+        if !already_exist {
+            self.storage
+                .put_snapshot_block(block)
+                .map_err(|e| Error::with_chain(e, "Cannot put the snapshot block in storage"))
+                .compat()
+                .await?;
+            self.storage
+                .put_tag(MAIN_BRANCH_TAG.to_owned(), block_id)
+                .map_err(|e| {
+                    Error::with_chain(e, "Cannot put the snapshot block's hash in the HEAD tag")
+                })
+                .compat()
+                .await?;
+            self.storage
+                .put_tag(PRUNED_TAG.to_owned(), block_id)
+                .map_err(|e| {
+                    Error::with_chain(e, "Cannot put the snapshot block's hash in the PRUNED tag")
+                })
+                .compat()
+                .await?;
+            return Ok(snapshot_branch);
+        }
+
+        let head_hash = self
+            .storage
+            .get_tag(MAIN_BRANCH_TAG.to_owned())
+            .map_err(|e| Error::with_chain(e, "Cannot get hash of the HEAD tag"))
+            .compat()
+            .await?
+            .ok_or_else(|| ErrorKind::NoTag(MAIN_BRANCH_TAG.to_owned()))?;
+
+        self.apply_stored_blocks(snapshot_branch, block_id, head_hash, logger)
+            .await
+    }
+
+    /// apply the stored blocks following `from` up to `to` on top of the
+    /// given branch, which points to `from`
+    async fn apply_stored_blocks(
+        &self,
+        branch: Branch,
+        from: HeaderHash,
+        to: HeaderHash,
+        logger: &Logger,
+    ) -> Result<Branch> {
+        use tokio_compat::prelude::*;
+
         self.storage
-            .stream_from_to(block0_id, head_hash)
-            .map_err(|e| Error::with_chain(e, "Cannot iterate blocks from block0 to HEAD"))
+            .stream_from_to(from, to)
+            .map_err(|e| Error::with_chain(e, "Cannot iterate the stored blocks up to HEAD"))
             .and_then(move |block_stream| {
                 block_stream
                     .map_err(|e| {
-                        Error::with_chain(e, "Error while iterating between block0 and HEAD")
+                        Error::with_chain(e, "Error while iterating the stored blocks up to HEAD")
                     })
                     .fold(
-                        (block0_branch, 0u64, self.clone()),
+                        (branch, 0u64, self.clone()),
                         move |(branch, processed, self2), block: Block| {
                             let header = block.header.clone();
 
@@ -763,7 +946,7 @@ impl Blockchain {
 fn write_reward_info(
     epoch: Epoch,
     parent_hash: HeaderHash,
-    rewards_info: EpochRewardsInfo,
+    rewards_info: &EpochRewardsInfo,
 ) -> std::io::Result<()> {
     use std::{env::var, fs::rename, fs::File, io::BufWriter, path::PathBuf};
 
@@ -801,18 +984,38 @@ fn write_reward_info(
     Ok(())
 }
 
+fn block0_time_frame(block0: &Block) -> Result<TimeFrame> {
+    use crate::blockcfg::Block0DataSource as _;
+
+    let start_time = block0
+        .start_time()
+        .map_err(|err| Error::with_chain(err, ErrorKind::Block0InitialLedgerError))?;
+    let slot_duration = block0
+        .slot_duration()
+        .map_err(|err| Error::with_chain(err, ErrorKind::Block0InitialLedgerError))?;
+
+    Ok(TimeFrame::new(
+        chain_time::Timeline::new(start_time),
+        chain_time::SlotDuration::from_secs(slot_duration.as_secs() as u32),
+    ))
+}
+
 pub fn new_epoch_leadership_from(
     epoch: Epoch,
     parent: Arc<Ref>,
+    report_account_rewards: bool,
 ) -> (
     Arc<Ledger>,
     Arc<Leadership>,
+    Arc<Ledger>,
     Arc<LedgerParameters>,
     Arc<TimeFrame>,
     Option<Arc<Ref>>,
+    Option<Arc<EpochTransitionRewards>>,
 ) {
     let parent_ledger_state = parent.ledger().clone();
     let parent_epoch_leadership_schedule = parent.epoch_leadership_schedule().clone();
+    let parent_epoch_leadership_state = parent.epoch_leadership_state().clone();
     let parent_epoch_ledger_parameters = parent.epoch_ledger_parameters().clone();
     let parent_time_frame = parent.time_frame().clone();
 
@@ -822,13 +1025,12 @@ pub fn new_epoch_leadership_from(
         // TODO: the time frame may change in the future, we will need to handle this
         //       special case but it is not actually clear how to modify the time frame
         //       for the blockchain
-        use chain_impl_mockchain::block::ConsensusVersion;
 
         // 1. distribute the rewards (if any) This will give us the transition state
-        let transition_state =
+        let (transition_state, epoch_rewards) =
             if let Some(distribution) = parent.epoch_leadership_schedule().stake_distribution() {
                 let store_rewards = std::env::var("JORMUNGANDR_REWARD_DUMP_DIRECTORY").is_ok();
-                let reward_info_dist = if store_rewards {
+                let reward_info_dist = if store_rewards || report_account_rewards {
                     RewardsInfoParameters::report_all()
                 } else {
                     RewardsInfoParameters::default()
@@ -841,27 +1043,21 @@ pub fn new_epoch_leadership_from(
                         reward_info_dist,
                     )
                     .expect("Distribution of rewards will not overflow");
-                if let Err(err) = write_reward_info(epoch, parent.hash(), rewards_info) {
+                if let Err(err) = write_reward_info(epoch, parent.hash(), &rewards_info) {
                     panic!("Error while storing the reward dump, err {}", err)
                 }
-                Arc::new(ledger)
+                let epoch_rewards = EpochTransitionRewards {
+                    info: rewards_info,
+                    treasury_balance: ledger.treasury_value(),
+                };
+                (Arc::new(ledger), Some(Arc::new(epoch_rewards)))
             } else {
-                parent_ledger_state.clone()
+                (parent_ledger_state.clone(), None)
             };
 
         // 2. now that the rewards have been distributed, prepare the schedule
         //    for the next leader
-        let epoch_state = if transition_state.consensus_version() == ConsensusVersion::GenesisPraos
-        {
-            // if there is no parent state available this might be because it is not
-            // available in memory or it is the epoch0 or epoch1
-            parent
-                .last_ref_previous_epoch()
-                .map(|r| r.ledger().clone())
-                .unwrap_or(parent_ledger_state.clone())
-        } else {
-            transition_state.clone()
-        };
+        let epoch_state = epoch_leadership_state(&parent, &transition_state);
 
         let leadership = Arc::new(Leadership::new(epoch, &epoch_state));
         let ledger_parameters = Arc::new(leadership.ledger_parameters().clone());
@@ -869,17 +1065,330 @@ pub fn new_epoch_leadership_from(
         (
             transition_state,
             leadership,
+            epoch_state,
             ledger_parameters,
             parent_time_frame,
             previous_epoch_state,
+            epoch_rewards,
         )
     } else {
         (
             parent_ledger_state,
             parent_epoch_leadership_schedule,
+            parent_epoch_leadership_state,
             parent_epoch_ledger_parameters,
             parent_time_frame,
             parent.last_ref_previous_epoch().map(Arc::clone),
+            None,
         )
     }
 }
+
+/// the ledger state the leadership of the epoch following the parent's epoch
+/// is computed from
+fn epoch_leadership_state(parent: &Ref, transition_state: &Arc<Ledger>) -> Arc<Ledger> {
+    use chain_impl_mockchain::block::ConsensusVersion;
+
+    if transition_state.consensus_version() == ConsensusVersion::GenesisPraos {
+        // if there is no parent state available this might be because it is not
+        // available in memory or it is the epoch0 or epoch1
+        parent
+            .last_ref_previous_epoch()
+            .map(|r| r.ledger().clone())
+            .unwrap_or_else(|| parent.ledger().clone())
+    } else {
+        transition_state.clone()
+    }
+}
+
+/// the leadership of the given epoch following the parent and the ledger
+/// state it is computed from.
+///
+/// Unlike `new_epoch_leadership_from`, the transition to the epoch is not
+/// recorded: the rewards are not dumped and a failure to distribute them is
+/// returned. It can be used to look at the leadership of an epoch the
+/// blockchain has not reached yet.
+pub fn epoch_leadership_of(epoch: Epoch, parent: &Ref) -> Result<(Arc<Leadership>, Arc<Ledger>)> {
+    if parent.block_date().epoch >= epoch {
+        return Ok((
+            parent.epoch_leadership_schedule().clone(),
+            parent.epoch_leadership_state().clone(),
+        ));
+    }
+
+    let transition_state =
+        if let Some(distribution) = parent.epoch_leadership_schedule().stake_distribution() {
+            let (ledger, _) = parent.ledger().distribute_rewards(
+                distribution,
+                &parent.epoch_ledger_parameters(),
+                RewardsInfoParameters::default(),
+            )?;
+            Arc::new(ledger)
+        } else {
+            parent.ledger().clone()
+        };
+    let epoch_state = epoch_leadership_state(parent, &transition_state);
+    let leadership = Arc::new(Leadership::new(epoch, &epoch_state));
+    Ok((leadership, epoch_state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockcfg::{
+            BftLeader, BlockVersion, ConsensusVersion, Contents, ContentsBuilder, HeaderBuilderNew,
+            Leader, LeaderOutput,
+        },
+        blockchain::{SledStore, MAIN_BRANCH_TAG},
+    };
+    use chain_addr::Discrimination;
+    use chain_crypto::{Ed25519, SecretKey};
+    use chain_impl_mockchain::{fee::LinearFee, leadership::bft};
+    use futures03::compat::Future01CompatExt as _;
+    use jormungandr_lib::interfaces::{
+        Block0Configuration, BlockchainConfiguration, NumberOfSlotsPerEpoch,
+    };
+
+    const SLOTS_PER_EPOCH: u32 = 4;
+    const EPOCH_STABILITY_DEPTH: u32 = 2;
+
+    fn block0(leader: &SecretKey<Ed25519>) -> Block {
+        let mut blockchain_configuration = BlockchainConfiguration::new(
+            Discrimination::Test,
+            ConsensusVersion::Bft,
+            LinearFee::new(0, 0, 0),
+        );
+        blockchain_configuration.consensus_leader_ids = vec![leader.to_public().into()];
+        blockchain_configuration.slots_per_epoch =
+            NumberOfSlotsPerEpoch::new(SLOTS_PER_EPOCH).unwrap();
+        blockchain_configuration.epoch_stability_depth = EPOCH_STABILITY_DEPTH.into();
+        Block0Configuration {
+            blockchain_configuration,
+            initial: Vec::new(),
+        }
+        .to_block()
+    }
+
+    fn bft_block(leader: &SecretKey<Ed25519>, parent: &Header, date: BlockDate) -> Block {
+        let contents: Contents = ContentsBuilder::new().into();
+        let builder = HeaderBuilderNew::new(BlockVersion::Ed25519Signed, &contents)
+            .set_parent(
+                &parent.hash(),
+                ChainLength::from(u32::from(parent.chain_length()) + 1),
+            )
+            .set_date(date)
+            .to_bft_builder()
+            .unwrap()
+            .set_consensus_data(&bft::LeaderId::from(leader.to_public()));
+        let signature = leader.sign_slice(builder.get_authenticated_data());
+        let header = builder.set_signature(signature.into()).generalize();
+        Block { header, contents }
+    }
+
+    /// the blocks of every slot after the block0 up to the given date
+    fn bft_chain(leader: &SecretKey<Ed25519>, block0: &Block, until: BlockDate) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut date = BlockDate::first();
+        while date != until {
+            date = if date.slot_id + 1 == SLOTS_PER_EPOCH {
+                BlockDate {
+                    epoch: date.epoch + 1,
+                    slot_id: 0,
+                }
+            } else {
+                BlockDate {
+                    epoch: date.epoch,
+                    slot_id: date.slot_id + 1,
+                }
+            };
+            let parent = blocks.last().map_or(&block0.header, |block| &block.header);
+            let block = bft_block(leader, parent, date);
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    async fn apply(blockchain: &Blockchain, block: Block) -> Arc<Ref> {
+        let parent = match blockchain
+            .pre_check_header(block.header.clone(), false)
+            .compat()
+            .await
+            .unwrap()
+        {
+            PreCheckedHeader::HeaderWithCache { parent_ref, .. } => parent_ref,
+            _ => panic!("the parent of the block should be known"),
+        };
+        let post_checked = blockchain
+            .post_check_header(block.header.clone(), parent)
+            .compat()
+            .await
+            .unwrap();
+        blockchain
+            .apply_and_store_block(post_checked, block)
+            .compat()
+            .await
+            .unwrap()
+            .cached_ref()
+    }
+
+    fn new_blockchain(block0: &Block, storage: &Storage) -> Blockchain {
+        Blockchain::new(
+            block0.header.hash(),
+            storage.clone(),
+            Duration::from_secs(3600),
+            false,
+        )
+    }
+
+    #[test]
+    fn restart_from_snapshot_applies_the_stored_blocks() {
+        let logger = Logger::root(slog::Discard, o!());
+        let leader = SecretKey::<Ed25519>::generate(rand::thread_rng());
+        let block0 = block0(&leader);
+        let blocks = bft_chain(
+            &leader,
+            &block0,
+            BlockDate {
+                epoch: 3,
+                slot_id: 1,
+            },
+        );
+        let tip_hash = blocks.last().unwrap().header.hash();
+
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            // the snapshot of the chain is taken at the end of the epoch 1,
+            // the last epoch deeper than the epoch stability depth
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            let blockchain = new_blockchain(&block0, &storage);
+            blockchain.load_from_block0(block0.clone()).await.unwrap();
+            let mut tip = None;
+            for block in &blocks {
+                tip = Some(apply(&blockchain, block.clone()).await);
+            }
+            let snapshot = Snapshot::from_tip(tip.unwrap(), &storage).await.unwrap();
+            assert_eq!(
+                snapshot.block().header.block_date(),
+                BlockDate {
+                    epoch: 1,
+                    slot_id: 3
+                }
+            );
+            let mut snapshot_bytes = Vec::new();
+            snapshot.write(&mut snapshot_bytes).unwrap();
+
+            // a node bootstrapped from the snapshot stores the blocks after it
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            let blockchain = new_blockchain(&block0, &storage);
+            let snapshot = Snapshot::read(snapshot_bytes.as_slice()).unwrap();
+            let snapshot_length = u32::from(snapshot.block().header.chain_length()) as usize;
+            let branch = blockchain
+                .load_from_snapshot(&block0, snapshot, &logger)
+                .await
+                .unwrap();
+            assert_eq!(
+                branch.get_ref_std().await.hash(),
+                blocks[snapshot_length - 1].header.hash()
+            );
+            for block in &blocks[snapshot_length..] {
+                apply(&blockchain, block.clone()).await;
+            }
+            storage
+                .put_tag(MAIN_BRANCH_TAG.to_owned(), tip_hash)
+                .compat()
+                .await
+                .unwrap();
+
+            // then restarts from the same snapshot
+            let blockchain = new_blockchain(&block0, &storage);
+            let snapshot = Snapshot::read(snapshot_bytes.as_slice()).unwrap();
+            let branch = blockchain
+                .load_from_snapshot(&block0, snapshot, &logger)
+                .await
+                .unwrap();
+            let tip = branch.get_ref_std().await;
+            assert_eq!(tip.hash(), tip_hash);
+            assert_eq!(u32::from(tip.chain_length()) as usize, blocks.len());
+
+            // the leadership of the next epoch is evaluated from the states
+            // restored from the snapshot
+            let next_date = BlockDate {
+                epoch: 4,
+                slot_id: 0,
+            };
+            let (leadership, _) = epoch_leadership_of(next_date.epoch, &tip).unwrap();
+            let leader_secret = Leader {
+                bft_leader: Some(BftLeader {
+                    sig_key: leader.clone(),
+                }),
+                genesis_leader: None,
+            };
+            match leadership.is_leader_for_date(&leader_secret, next_date) {
+                Ok(LeaderOutput::Bft(_)) => (),
+                _ => panic!("the leader should be leader of the next epoch"),
+            }
+            let next_block = bft_block(&leader, tip.header(), next_date);
+            let next = apply(&blockchain, next_block).await;
+            assert_eq!(next.block_date(), next_date);
+        });
+    }
+
+    #[test]
+    fn epoch_leadership_of_the_current_and_next_epoch() {
+        let leader = SecretKey::<Ed25519>::generate(rand::thread_rng());
+        let leader_secret = Leader {
+            bft_leader: Some(BftLeader {
+                sig_key: leader.clone(),
+            }),
+            genesis_leader: None,
+        };
+        let block0 = block0(&leader);
+        let blocks = bft_chain(
+            &leader,
+            &block0,
+            BlockDate {
+                epoch: 0,
+                slot_id: SLOTS_PER_EPOCH - 1,
+            },
+        );
+
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            let blockchain = new_blockchain(&block0, &storage);
+            blockchain.load_from_block0(block0.clone()).await.unwrap();
+            let mut tip = None;
+            for block in &blocks {
+                tip = Some(apply(&blockchain, block.clone()).await);
+            }
+            let tip = tip.unwrap();
+            let schedule = tip.epoch_leadership_schedule().clone();
+            let state = tip.epoch_leadership_state().clone();
+
+            // the current epoch is served from the tip
+            let (leadership, epoch_state) = epoch_leadership_of(0, &tip).unwrap();
+            assert!(Arc::ptr_eq(&leadership, &schedule));
+            assert!(Arc::ptr_eq(&epoch_state, &state));
+
+            let next_date = BlockDate {
+                epoch: 1,
+                slot_id: 0,
+            };
+            let (leadership, _) = epoch_leadership_of(next_date.epoch, &tip).unwrap();
+            assert_eq!(leadership.epoch(), next_date.epoch);
+            match leadership.is_leader_for_date(&leader_secret, next_date) {
+                Ok(LeaderOutput::Bft(_)) => (),
+                _ => panic!("the leader should be leader of the next epoch"),
+            }
+
+            // the tip is left in its epoch
+            assert!(Arc::ptr_eq(tip.epoch_leadership_schedule(), &schedule));
+            assert!(Arc::ptr_eq(tip.epoch_leadership_state(), &state));
+            assert_eq!(tip.epoch_leadership_schedule().epoch(), 0);
+            let next_block = bft_block(&leader, tip.header(), next_date);
+            let next = apply(&blockchain, next_block).await;
+            assert_eq!(next.epoch_leadership_schedule().epoch(), next_date.epoch);
+        });
+    }
+}
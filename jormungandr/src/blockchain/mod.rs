@@ -1,3 +1,4 @@
+mod blocks_file;
 mod branch;
 mod candidate;
 mod chain;
@@ -5,9 +6,13 @@ mod chain_selection;
 mod checkpoints;
 mod multiverse;
 mod process;
+mod pruning;
 mod reference;
 mod reference_cache;
+mod reorgs;
+mod snapshot;
 mod storage;
+mod storage_check;
 mod tip;
 
 // Constants
@@ -25,15 +30,21 @@ mod chunk_sizes {
 // Re-exports
 
 pub use self::{
+    blocks_file::{export_blocks, import_blocks, BlocksFileError},
     branch::Branch,
     chain::{
-        new_epoch_leadership_from, Blockchain, Error, ErrorKind, PreCheckedHeader, MAIN_BRANCH_TAG,
+        epoch_leadership_of, Blockchain, Error, ErrorKind, PreCheckedHeader, MAIN_BRANCH_TAG,
+        PRUNED_TAG,
     },
     chain_selection::{compare_against, ComparisonResult},
     checkpoints::Checkpoints,
     multiverse::Multiverse,
     process::{process_new_ref, Process},
-    reference::Ref,
-    storage::Storage,
+    pruning::{pruned_chain_length, Pruning, PruningError},
+    reference::{EpochTransitionRewards, Ref},
+    reorgs::Reorgs,
+    snapshot::{digest as snapshot_digest, Snapshot, SnapshotError, SNAPSHOT_FILE_NAME},
+    storage::{migrate_blocks, SledStore, SqliteStore, Storage},
+    storage_check::{check_storage, StorageCheck, StorageIssue},
     tip::Tip,
 };
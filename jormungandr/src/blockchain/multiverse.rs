@@ -1,6 +1,6 @@
 use crate::blockcfg::{ChainLength, HeaderHash, Ledger, Multiverse as MultiverseData};
 use chain_impl_mockchain::multiverse;
-use std::convert::Infallible;
+use std::{collections::HashMap, convert::Infallible};
 use tokio::{prelude::*, sync::lock::Lock};
 
 pub struct Multiverse<T> {
@@ -43,6 +43,36 @@ impl<T: Clone> Multiverse<T> {
         future::poll_fn(move || Ok(inner.poll_lock()))
             .map(move |guard| guard.get(&hash).as_deref().cloned())
     }
+
+    /// drop all the states but the ones of the given blocks, listed as
+    /// `(chain length, hash, parent hash)` with every parent before its
+    /// children. `relink` gives each kept state the new reference of the
+    /// state of its parent, if the parent is kept as well. Returns the new
+    /// references of the kept states.
+    pub fn retain<F>(
+        &self,
+        blocks: Vec<(ChainLength, HeaderHash, HeaderHash)>,
+        relink: F,
+    ) -> impl Future<Item = HashMap<HeaderHash, multiverse::Ref<T>>, Error = Infallible>
+    where
+        F: Fn(T, Option<multiverse::Ref<T>>) -> T,
+    {
+        let mut inner = self.inner.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |mut guard| {
+            let mut retained = MultiverseData::new();
+            let mut refs: HashMap<HeaderHash, multiverse::Ref<T>> = HashMap::new();
+            for (chain_length, hash, parent) in blocks {
+                if let Some(state) = guard.get(&hash) {
+                    let state = relink(state.as_ref().clone(), refs.get(&parent).cloned());
+                    let state_ref = retained.insert(chain_length, hash, state);
+                    refs.insert(hash, state_ref);
+                }
+            }
+            *guard = retained;
+            refs
+        })
+    }
 }
 
 impl Multiverse<Ledger> {
@@ -2,14 +2,16 @@ use super::{
     candidate,
     chain::{self, AppliedBlock},
     chain_selection::{self, ComparisonResult},
-    Blockchain, Error, ErrorKind, PreCheckedHeader, Ref, Tip, MAIN_BRANCH_TAG,
+    reorgs::{self, RollbackError},
+    Blockchain, Error, ErrorKind, PreCheckedHeader, Pruning, Ref, Tip, MAIN_BRANCH_TAG,
 };
 use crate::{
     blockcfg::{Block, FragmentId, Header},
     blockchain::Checkpoints,
     intercom::{self, BlockMsg, ExplorerMsg, NetworkMsg, PropagateMsg, TransactionMsg},
-    log,
+    log, metrics,
     network::p2p::Id as NodeId,
+    notifier::{chain_tip, Notifier},
     stats_counter::StatsCounter,
     utils::{
         async_msg::{self, MessageBox, MessageQueue},
@@ -21,20 +23,28 @@ use crate::{
     HeaderHash,
 };
 use chain_core::property::{Block as _, Fragment as _, HasHeader as _, Header as _};
-use jormungandr_lib::interfaces::FragmentStatus;
+use jormungandr_lib::interfaces::{FragmentStatus, NodeEvent};
 
 use futures::future::Either;
 use slog::Logger;
 use tokio::{prelude::*, timer::timeout};
 use tokio_compat::prelude::*;
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 type TimeoutError = timeout::Error<Error>;
 type PullHeadersScheduler = FireForgetScheduler<HeaderHash, NodeId, Checkpoints>;
 type GetNextBlockScheduler = FireForgetScheduler<HeaderHash, NodeId, ()>;
 
 const BRANCH_REPROCESSING_INTERVAL: Duration = Duration::from_secs(60);
+const STORAGE_PRUNING_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// a branch switch rolling back at least `1 / DEEP_REORG_DIVISOR` of the
+/// epoch stability depth raises a warning
+const DEEP_REORG_DIVISOR: u32 = 2;
 
 const DEFAULT_TIMEOUT_PROCESS_LEADERSHIP: u64 = 5;
 const DEFAULT_TIMEOUT_PROCESS_ANNOUNCEMENT: u64 = 5;
@@ -62,7 +72,9 @@ pub struct Process {
     pub network_msgbox: MessageBox<NetworkMsg>,
     pub fragment_msgbox: MessageBox<TransactionMsg>,
     pub explorer_msgbox: Option<MessageBox<ExplorerMsg>>,
+    pub notifier: Notifier,
     pub garbage_collection_interval: Duration,
+    pub pruning: Option<Pruning>,
 }
 
 impl Process {
@@ -72,6 +84,9 @@ impl Process {
         input: MessageQueue<BlockMsg>,
     ) -> impl Future<Item = (), Error = ()> {
         self.start_branch_reprocessing(&service_info);
+        if let Some(pruning) = self.pruning.take() {
+            self.start_storage_pruning(&service_info, pruning);
+        }
         let pull_headers_scheduler = self.spawn_pull_headers_scheduler(&service_info);
         let get_next_block_scheduler = self.spawn_get_next_block_scheduler(&service_info);
         input.for_each(move |msg| {
@@ -98,6 +113,7 @@ impl Process {
         let explorer_msg_box = self.explorer_msgbox.clone();
         let tx_msg_box = self.fragment_msgbox.clone();
         let stats_counter = self.stats_counter.clone();
+        let notifier = self.notifier.clone();
 
         match input {
             BlockMsg::LeadershipBlock(block) => {
@@ -119,6 +135,7 @@ impl Process {
                             tx_msg_box,
                             network_msg_box,
                             explorer_msg_box,
+                            notifier,
                             block,
                         )
                         .await
@@ -165,6 +182,7 @@ impl Process {
                         tx_msg_box,
                         network_msg_box,
                         explorer_msg_box,
+                        notifier,
                         get_next_block_scheduler,
                         handle,
                         stats_counter,
@@ -224,15 +242,40 @@ impl Process {
     fn start_branch_reprocessing(&self, info: &TokioServiceInfo) {
         let tip = self.blockchain_tip.clone();
         let blockchain = self.blockchain.clone();
+        let notifier = self.notifier.clone();
         let logger = info.logger().clone();
 
         info.run_periodic_std(
             "branch reprocessing",
             BRANCH_REPROCESSING_INTERVAL,
-            move || reprocess_tip(logger.clone(), blockchain.clone(), tip.clone()),
+            move || {
+                reprocess_tip(
+                    logger.clone(),
+                    blockchain.clone(),
+                    tip.clone(),
+                    notifier.clone(),
+                )
+            },
         )
     }
 
+    fn start_storage_pruning(&self, info: &TokioServiceInfo, pruning: Pruning) {
+        let tip = self.blockchain_tip.clone();
+        let storage = self.blockchain.storage().clone();
+        let logger = info.logger().clone();
+
+        info.run_periodic_std("storage pruning", STORAGE_PRUNING_INTERVAL, move || {
+            let pruning = pruning.clone();
+            let tip = tip.clone();
+            let storage = storage.clone();
+            let logger = logger.clone();
+            async move {
+                let tip = tip.get_ref_std().await;
+                pruning.prune(tip, storage, logger).await
+            }
+        })
+    }
+
     fn spawn_pull_headers_scheduler(&self, info: &TokioServiceInfo) -> PullHeadersScheduler {
         let network_msgbox = self.network_msgbox.clone();
         let scheduler_logger = info.logger().clone();
@@ -298,7 +341,12 @@ fn try_request_fragment_removal(
 /// this function will re-process the tip against the different branches
 /// this is because a branch may have become more interesting with time
 /// moving forward and branches may have been dismissed
-async fn reprocess_tip(logger: Logger, mut blockchain: Blockchain, tip: Tip) -> Result<(), Error> {
+async fn reprocess_tip(
+    logger: Logger,
+    mut blockchain: Blockchain,
+    tip: Tip,
+    notifier: Notifier,
+) -> Result<(), Error> {
     let branches: Vec<Arc<Ref>> = blockchain.branches().branches().compat().await.unwrap();
 
     let tip_as_ref = tip.get_ref_std().await;
@@ -309,7 +357,14 @@ async fn reprocess_tip(logger: Logger, mut blockchain: Blockchain, tip: Tip) ->
         .collect::<Vec<_>>();
 
     for other in others {
-        process_new_ref_std(&logger, &mut blockchain, tip.clone(), Arc::clone(other)).await?
+        process_new_ref_std(
+            &logger,
+            &mut blockchain,
+            tip.clone(),
+            &notifier,
+            Arc::clone(other),
+        )
+        .await?
     }
 
     Ok(())
@@ -324,16 +379,21 @@ async fn reprocess_tip(logger: Logger, mut blockchain: Blockchain, tip: Tip) ->
 /// If the current tip is not the one being updated we will then trigger
 /// chain selection after updating that other branch as it may be possible that
 /// this branch just became more interesting for the current consensus algorithm.
+///
+/// The update of the tip, and the rollback in case of a branch switch, are
+/// reported to the subscribers of the notifier.
 pub async fn process_new_ref_std(
     logger: &Logger,
     blockchain: &mut Blockchain,
     mut tip: Tip,
+    notifier: &Notifier,
     candidate: Arc<Ref>,
 ) -> Result<(), Error> {
     let candidate_hash = candidate.hash();
     let storage = blockchain.storage().clone();
 
     let tip_ref = tip.get_ref_std().await;
+    let new_tip = chain_tip(&candidate);
 
     let tip_updated = if tip_ref.hash() == candidate.block_parent_hash() {
         info!(
@@ -364,17 +424,19 @@ pub async fn process_new_ref_std(
                 );
                 let branch = blockchain
                     .branches_mut()
-                    .apply_or_create(candidate)
+                    .apply_or_create(candidate.clone())
                     .compat()
                     .await
                     .unwrap();
                 tip.swap_std(branch).await;
+                record_reorg(logger, blockchain, notifier, &tip_ref, &candidate).await;
                 true
             }
         }
     };
 
     if tip_updated {
+        notifier.notify(NodeEvent::Tip(new_tip));
         storage
             .put_tag(MAIN_BRANCH_TAG.to_owned(), candidate_hash)
             .map_err(|e| Error::with_chain(e, "Cannot update the main storage's tip"))
@@ -385,6 +447,189 @@ pub async fn process_new_ref_std(
     }
 }
 
+/// record the switch from the branch of `old_tip` to the branch of `new_tip`
+/// in the reorg history and report the rollback to the subscribers of the
+/// notifier. A warning is raised when the rollback comes close to the epoch
+/// stability depth. The chain selection does not refuse deeper switches yet,
+/// the common ancestor is only looked for up to the epoch stability depth so
+/// a deeper switch does not hold the processing of the blocks: it is only
+/// reported with a warning.
+async fn record_reorg(
+    logger: &Logger,
+    blockchain: &Blockchain,
+    notifier: &Notifier,
+    old_tip: &Ref,
+    new_tip: &Ref,
+) {
+    let epoch_stability_depth = new_tip.epoch_ledger_parameters().epoch_stability_depth;
+    let rollback = match reorgs::compute_rollback(
+        blockchain.storage(),
+        old_tip.header(),
+        new_tip.header(),
+        epoch_stability_depth,
+    )
+    .await
+    {
+        Ok(rollback) => rollback,
+        Err(RollbackError::TooDeep { .. }) => {
+            metrics::DEEP_CHAIN_REORGS.inc();
+            warn!(
+                logger,
+                "branch switch deeper than the epoch stability depth";
+                "epoch_stability_depth" => epoch_stability_depth,
+                "from" => %old_tip.hash(),
+                "to" => %new_tip.hash(),
+            );
+            return;
+        }
+        Err(e) => {
+            warn!(
+                logger,
+                "cannot find the common ancestor of the switched branches";
+                "reason" => %e,
+            );
+            return;
+        }
+    };
+
+    metrics::CHAIN_REORG_DEPTH.observe(rollback.depth.into());
+    if is_deep_reorg(rollback.depth, epoch_stability_depth) {
+        metrics::DEEP_CHAIN_REORGS.inc();
+        warn!(
+            logger,
+            "deep branch switch, close to the epoch stability depth";
+            "depth" => rollback.depth,
+            "epoch_stability_depth" => epoch_stability_depth,
+            "common_ancestor" => %rollback.common_ancestor.hash,
+        );
+    }
+
+    notifier.notify(NodeEvent::Rollback(rollback.clone()));
+    blockchain.reorgs().record(rollback).await;
+}
+
+/// the rollback of `depth` blocks comes close to the epoch stability depth
+fn is_deep_reorg(depth: u32, epoch_stability_depth: u32) -> bool {
+    depth.saturating_mul(DEEP_REORG_DIVISOR) >= epoch_stability_depth
+}
+
+/// process a new candidate block on top of the blockchain, this function may:
+///
+/// * update the current tip if the candidate's parent is the current tip;
+/// * update a branch if the candidate parent is that branch's tip;
+/// * create a new branch if none of the above;
+///
+/// If the current tip is not the one being updated we will then trigger
+/// chain selection after updating that other branch as it may be possible that
+/// this branch just became more interesting for the current consensus algorithm.
+///
+/// The update of the tip, and the rollback in case of a branch switch, are
+/// reported to the subscribers of the notifier.
+pub async fn process_new_ref_std(
+    logger: &Logger,
+    blockchain: &mut Blockchain,
+    mut tip: Tip,
+    notifier: &Notifier,
+    candidate: Arc<Ref>,
+) -> Result<(), Error> {
+    let candidate_hash = candidate.hash();
+    let storage = blockchain.storage().clone();
+
+    let tip_ref = tip.get_ref_std().await;
+    let new_tip = chain_tip(&candidate);
+
+    let tip_updated = if tip_ref.hash() == candidate.block_parent_hash() {
+        info!(
+            logger,
+            "update current branch tip: {} -> {}",
+            tip_ref.header().description(),
+            candidate.header().description(),
+        );
+        tip.update_ref_std(candidate).await;
+        true
+    } else {
+        match chain_selection::compare_against(blockchain.storage(), &tip_ref, &candidate) {
+            ComparisonResult::PreferCurrent => {
+                info!(
+                    logger,
+                    "create new branch with tip {} | current-tip {}",
+                    candidate.header().description(),
+                    tip_ref.header().description(),
+                );
+                false
+            }
+            ComparisonResult::PreferCandidate => {
+                info!(
+                    logger,
+                    "switching branch from {} to {}",
+                    tip_ref.header().description(),
+                    candidate.header().description(),
+                );
+                let branch = blockchain
+                    .branches_mut()
+                    .apply_or_create(candidate.clone())
+                    .compat()
+                    .await
+                    .unwrap();
+                tip.swap_std(branch).await;
+                record_reorg(logger, blockchain, notifier, &tip_ref, &candidate).await;
+                true
+            }
+        }
+    };
+
+    if tip_updated {
+        notifier.notify(NodeEvent::Tip(new_tip));
+        storage
+            .put_tag(MAIN_BRANCH_TAG.to_owned(), candidate_hash)
+            .map_err(|e| Error::with_chain(e, "Cannot update the main storage's tip"))
+            .compat()
+            .await
+    } else {
+        Ok(())
+    }
+}
+
+/// record the switch from the branch of `old_tip` to the branch of `new_tip`
+/// in the reorg history and report the rollback to the subscribers of the
+/// notifier. A warning is raised when the rollback comes close to the epoch
+/// stability depth, past which the node would refuse to switch.
+async fn record_reorg(
+    logger: &Logger,
+    blockchain: &Blockchain,
+    notifier: &Notifier,
+    old_tip: &Ref,
+    new_tip: &Ref,
+) {
+    let rollback = match reorgs::compute_rollback(blockchain.storage(), old_tip, new_tip).await {
+        Ok(rollback) => rollback,
+        Err(e) => {
+            warn!(
+                logger,
+                "cannot find the common ancestor of the switched branches";
+                "reason" => %e,
+            );
+            return;
+        }
+    };
+
+    metrics::CHAIN_REORG_DEPTH.observe(rollback.depth.into());
+    let epoch_stability_depth = new_tip.epoch_ledger_parameters().epoch_stability_depth;
+    if rollback.depth.saturating_mul(DEEP_REORG_DIVISOR) >= epoch_stability_depth {
+        metrics::DEEP_CHAIN_REORGS.inc();
+        warn!(
+            logger,
+            "deep branch switch, close to the epoch stability depth";
+            "depth" => rollback.depth,
+            "epoch_stability_depth" => epoch_stability_depth,
+            "common_ancestor" => %rollback.common_ancestor.hash,
+        );
+    }
+
+    notifier.notify(NodeEvent::Rollback(rollback.clone()));
+    blockchain.reorgs().record(rollback).await;
+}
+
 /// process a new candidate block on top of the blockchain, this function may:
 ///
 /// * update the current tip if the candidate's parent is the current tip;
@@ -459,6 +704,7 @@ async fn process_and_propagate_new_ref(
     logger: &Logger,
     blockchain: &mut Blockchain,
     tip: Tip,
+    notifier: &Notifier,
     new_block_ref: Arc<Ref>,
     network_msg_box: MessageBox<NetworkMsg>,
 ) -> Result<(), Error> {
@@ -467,7 +713,7 @@ async fn process_and_propagate_new_ref(
 
     debug!(logger, "processing the new block and propagating"; "hash" => %hash);
 
-    process_new_ref_std(logger, blockchain, tip, new_block_ref).await?;
+    process_new_ref_std(logger, blockchain, tip, notifier, new_block_ref).await?;
 
     debug!(logger, "propagating block to the network"; "hash" => %hash);
     network_msg_box
@@ -485,6 +731,7 @@ async fn process_leadership_block(
     mut tx_msg_box: MessageBox<TransactionMsg>,
     network_msg_box: MessageBox<NetworkMsg>,
     explorer_msg_box: Option<MessageBox<ExplorerMsg>>,
+    notifier: Notifier,
     block: Block,
 ) -> Result<(), Error> {
     let new_block_ref =
@@ -500,6 +747,7 @@ async fn process_leadership_block(
         &logger,
         &mut blockchain,
         blockchain_tip,
+        &notifier,
         Arc::clone(&new_block_ref),
         network_msg_box,
     )
@@ -507,7 +755,10 @@ async fn process_leadership_block(
 
     if let Some(msg_box) = explorer_msg_box {
         msg_box
-            .send(ExplorerMsg::NewBlock(block))
+            .send(ExplorerMsg::NewBlock(
+                block,
+                new_block_ref.epoch_rewards().cloned(),
+            ))
             .map_err(|_| "Cannot propagate block to explorer".to_string())
             .compat()
             .await?;
@@ -520,6 +771,7 @@ async fn process_leadership_block_inner(
     blockchain: &mut Blockchain,
     block: Block,
 ) -> Result<Arc<Ref>, Error> {
+    let started = Instant::now();
     let header = block.header();
     let parent_hash = block.parent_id();
     // This is a trusted block from the leadership task,
@@ -549,6 +801,9 @@ async fn process_leadership_block_inner(
                 .new_ref()
                 .expect("block from leadership must be unique");
             info!(logger, "block from leader event successfully stored");
+            metrics::BLOCK_APPLY_SECONDS
+                .with_label_values(&["leadership"])
+                .observe(started.elapsed().as_secs_f64());
             new_ref
         })
         .compat()
@@ -617,6 +872,7 @@ async fn process_network_blocks(
     mut tx_msg_box: MessageBox<TransactionMsg>,
     network_msg_box: MessageBox<NetworkMsg>,
     mut explorer_msg_box: Option<MessageBox<ExplorerMsg>>,
+    notifier: Notifier,
     mut get_next_block_scheduler: GetNextBlockScheduler,
     handle: intercom::RequestStreamHandle<Block, ()>,
     stats_counter: StatsCounter,
@@ -673,6 +929,7 @@ async fn process_network_blocks(
                 &logger,
                 &mut blockchain,
                 blockchain_tip,
+                &notifier,
                 Arc::clone(&new_block_ref),
                 network_msg_box,
             )
@@ -743,6 +1000,7 @@ async fn check_and_apply_block(
     explorer_msg_box: Option<&mut MessageBox<ExplorerMsg>>,
     logger: &Logger,
 ) -> Result<Option<Arc<Ref>>, chain::Error> {
+    let started = Instant::now();
     let explorer_enabled = explorer_msg_box.is_some();
     let post_checked = blockchain
         .post_check_header(block.header(), parent_ref)
@@ -768,6 +1026,9 @@ async fn check_and_apply_block(
         .compat()
         .await?;
     if let AppliedBlock::New(block_ref) = applied_block {
+        metrics::BLOCK_APPLY_SECONDS
+            .with_label_values(&["network"])
+            .observe(started.elapsed().as_secs_f64());
         let header = block_ref.header();
         debug!(
             logger,
@@ -781,7 +1042,10 @@ async fn check_and_apply_block(
         );
         if let Some(msg_box) = explorer_msg_box {
             msg_box
-                .try_send(ExplorerMsg::NewBlock(block_for_explorer.take().unwrap()))
+                .try_send(ExplorerMsg::NewBlock(
+                    block_for_explorer.take().unwrap(),
+                    block_ref.epoch_rewards().cloned(),
+                ))
                 .unwrap_or_else(|err| error!(logger, "cannot add block to explorer: {}", err));
         }
         Ok(Some(block_ref))
@@ -821,3 +1085,21 @@ fn chain_header_error_into_reply(err: candidate::Error) -> intercom::Error {
         _ => intercom::Error::failed(err.to_string()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deep_reorg_threshold() {
+        // half of the epoch stability depth, rounded up
+        assert!(!is_deep_reorg(0, 10));
+        assert!(!is_deep_reorg(4, 10));
+        assert!(is_deep_reorg(5, 10));
+        assert!(is_deep_reorg(10, 10));
+        assert!(!is_deep_reorg(4, 9));
+        assert!(is_deep_reorg(5, 9));
+        // no overflow for the largest rollbacks
+        assert!(is_deep_reorg(u32::max_value(), u32::max_value()));
+    }
+}
@@ -0,0 +1,263 @@
+//! pruning of the contents of the old blocks from the storage.
+//!
+//! The contents of the blocks of the main branch are removed once the
+//! blocks are older than the kept epochs and deeper than the epoch stability
+//! depth. Their headers and the links between the blocks are kept, so the
+//! checkpoints of the peers are still found in the storage. The most recent
+//! pruned block is recorded in the `PRUNED` tag.
+//!
+//! A ledger snapshot is saved at the most recent pruned block before the
+//! contents are removed: the node cannot apply the blocks from the block0
+//! anymore, it restarts from the snapshot.
+
+use crate::{
+    blockcfg::{ChainLength, Header, HeaderHash},
+    blockchain::{
+        snapshot::{stable_epoch_end, Snapshot, SnapshotError},
+        Ref, Storage, PRUNED_TAG,
+    },
+};
+use chain_storage::error::Error as StorageError;
+use futures03::{
+    compat::{Future01CompatExt as _, Stream01CompatExt as _},
+    stream::TryStreamExt as _,
+};
+use slog::Logger;
+use std::{mem, path::PathBuf, sync::Arc};
+use thiserror::Error;
+
+/// number of blocks whose contents are removed at once, the `PRUNED` tag
+/// is updated after every batch
+const PRUNING_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Error)]
+pub enum PruningError {
+    #[error("cannot save the ledger snapshot of the pruned blocks")]
+    Snapshot(#[from] SnapshotError),
+    #[error("cannot prune the stored blocks")]
+    Storage(#[from] StorageError),
+}
+
+#[derive(Clone)]
+pub struct Pruning {
+    kept_epochs: u32,
+    block0_hash: HeaderHash,
+    snapshot_path: PathBuf,
+}
+
+impl Pruning {
+    /// keep the contents of the blocks of the epoch of the tip and of the
+    /// `kept_epochs` previous epochs, the ledger snapshot is saved at
+    /// `snapshot_path`
+    pub fn new(kept_epochs: u32, block0_hash: HeaderHash, snapshot_path: PathBuf) -> Self {
+        Pruning {
+            kept_epochs,
+            block0_hash,
+            snapshot_path,
+        }
+    }
+
+    pub async fn prune(
+        &self,
+        tip: Arc<Ref>,
+        storage: Storage,
+        logger: Logger,
+    ) -> Result<(), PruningError> {
+        let boundary = match stable_epoch_end(&tip, self.kept_epochs) {
+            Some(boundary) => boundary,
+            None => return Ok(()),
+        };
+        let from = storage
+            .get_tag(PRUNED_TAG.to_owned())
+            .compat()
+            .await?
+            .unwrap_or(self.block0_hash);
+        // the blocks up to the boundary have already been pruned if the
+        // number of kept epochs has been raised since
+        match storage.is_ancestor(from, boundary.hash()).compat().await? {
+            Some(distance) if distance > 0 => (),
+            _ => return Ok(()),
+        }
+
+        Snapshot::at(&boundary, &storage)
+            .await?
+            .save_stored(&self.snapshot_path, &storage)
+            .await?;
+        info!(
+            logger,
+            "pruning the contents of the blocks up to {}",
+            boundary.header().description()
+        );
+
+        let mut blocks = storage
+            .stream_from_to(from, boundary.hash())
+            .compat()
+            .await?
+            .compat();
+        let mut headers = Vec::with_capacity(PRUNING_BATCH_SIZE);
+        while let Some(block) = blocks.try_next().await? {
+            headers.push(block.header);
+            if headers.len() == PRUNING_BATCH_SIZE {
+                let batch = mem::replace(&mut headers, Vec::with_capacity(PRUNING_BATCH_SIZE));
+                prune_batch(&storage, batch).await?;
+            }
+        }
+        prune_batch(&storage, headers).await?;
+        Ok(())
+    }
+}
+
+async fn prune_batch(storage: &Storage, headers: Vec<Header>) -> Result<(), StorageError> {
+    let last = match headers.last() {
+        Some(header) => header.hash(),
+        None => return Ok(()),
+    };
+    storage.prune_block_contents(headers).compat().await?;
+    storage.put_tag(PRUNED_TAG.to_owned(), last).compat().await
+}
+
+/// the chain length up to which the node does not serve the blocks of the
+/// main branch, if any
+pub async fn pruned_chain_length(storage: Storage) -> Result<Option<ChainLength>, StorageError> {
+    let pruned = match storage.get_tag(PRUNED_TAG.to_owned()).compat().await? {
+        Some(pruned) => pruned,
+        None => return Ok(None),
+    };
+    Ok(storage
+        .get_header(pruned)
+        .compat()
+        .await?
+        .map(|header| header.chain_length()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockcfg::{Block, BlockDate, BlockVersion, Contents, ContentsBuilder, HeaderBuilderNew},
+        blockchain::SledStore,
+    };
+
+    fn block0() -> Block {
+        let contents: Contents = ContentsBuilder::new().into();
+        let header = HeaderBuilderNew::new(BlockVersion::Genesis, &contents)
+            .set_genesis()
+            .set_date(BlockDate::first())
+            .to_unsigned_header()
+            .unwrap()
+            .generalize();
+        Block { header, contents }
+    }
+
+    fn child(parent: &Header) -> Block {
+        let contents: Contents = ContentsBuilder::new().into();
+        let chain_length = u32::from(parent.chain_length()) + 1;
+        let header = HeaderBuilderNew::new(BlockVersion::Genesis, &contents)
+            .set_parent(&parent.hash(), ChainLength::from(chain_length))
+            .set_date(BlockDate {
+                epoch: 0,
+                slot_id: chain_length,
+            })
+            .to_unsigned_header()
+            .unwrap()
+            .generalize();
+        Block { header, contents }
+    }
+
+    /// store the block0 and `length` blocks after it
+    async fn chain(storage: &Storage, length: usize) -> Vec<Block> {
+        let mut blocks = vec![block0()];
+        for _ in 0..length {
+            let block = child(&blocks.last().unwrap().header);
+            blocks.push(block);
+        }
+        for block in &blocks {
+            storage.put_block(block.clone()).compat().await.unwrap();
+        }
+        blocks
+    }
+
+    #[test]
+    fn pruned_blocks_keep_their_headers() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            let blocks = chain(&storage, 9).await;
+            let tip = blocks[9].header.hash();
+            // the blocks after the block0 up to the fifth one, in two
+            // batches as `Pruning::prune` does for the longer branches
+            for batch in blocks[1..6].chunks(3) {
+                let headers = batch.iter().map(|block| block.header.clone()).collect();
+                prune_batch(&storage, headers).await.unwrap();
+            }
+
+            assert_eq!(
+                storage
+                    .get_tag(PRUNED_TAG.to_owned())
+                    .compat()
+                    .await
+                    .unwrap(),
+                Some(blocks[5].header.hash())
+            );
+            assert_eq!(
+                pruned_chain_length(storage.clone()).await.unwrap(),
+                Some(ChainLength::from(5))
+            );
+
+            for block in &blocks[1..6] {
+                let hash = block.header.hash();
+                assert!(storage.get(hash).compat().await.unwrap().is_none());
+                let header = storage.get_header(hash).compat().await.unwrap();
+                assert_eq!(header.map(|header| header.hash()), Some(hash));
+                assert!(storage.block_exists(hash).compat().await.unwrap());
+                assert_eq!(
+                    storage.is_ancestor(hash, tip).compat().await.unwrap(),
+                    Some(9 - u64::from(u32::from(block.header.chain_length())))
+                );
+            }
+            for block in blocks[..1].iter().chain(&blocks[6..]) {
+                let hash = block.header.hash();
+                assert!(storage.get(hash).compat().await.unwrap().is_some());
+            }
+
+            // the checkpoints of a peer are still found among the pruned blocks
+            let checkpoints = vec![blocks[2].header.hash(), blocks[4].header.hash()];
+            let ancestor = storage
+                .find_closest_ancestor(checkpoints, tip)
+                .compat()
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(ancestor.header_hash, blocks[4].header.hash());
+            assert_eq!(ancestor.distance, 5);
+
+            // the blocks after the most recent pruned block are still served
+            let served: Vec<HeaderHash> = storage
+                .stream_from_to(blocks[5].header.hash(), tip)
+                .compat()
+                .await
+                .unwrap()
+                .compat()
+                .map_ok(|block| block.header.hash())
+                .try_collect()
+                .await
+                .unwrap();
+            let expected: Vec<HeaderHash> = blocks[6..]
+                .iter()
+                .map(|block| block.header.hash())
+                .collect();
+            assert_eq!(served, expected);
+        });
+    }
+
+    #[test]
+    fn unpruned_storage_has_no_pruned_chain_length() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            chain(&storage, 3).await;
+            prune_batch(&storage, Vec::new()).await.unwrap();
+            assert_eq!(pruned_chain_length(storage).await.unwrap(), None);
+        });
+    }
+}
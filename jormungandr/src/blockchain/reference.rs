@@ -1,5 +1,6 @@
 use crate::blockcfg::{
-    BlockDate, ChainLength, Header, HeaderHash, Leadership, Ledger, LedgerParameters,
+    BlockDate, ChainLength, EpochRewardsInfo, Header, HeaderHash, Leadership, Ledger,
+    LedgerParameters, Value,
 };
 use chain_impl_mockchain::multiverse;
 use chain_time::{
@@ -11,6 +12,13 @@ use std::{
     time::{Duration, SystemTime},
 };
 
+/// the rewards distributed at the transition into the epoch of a block
+pub struct EpochTransitionRewards {
+    pub info: EpochRewardsInfo,
+    /// the treasury once the rewards are distributed
+    pub treasury_balance: Value,
+}
+
 /// a reference to a block in the blockchain
 #[derive(Clone)]
 pub struct Ref {
@@ -25,6 +33,12 @@ pub struct Ref {
     /// this object will be shared between different Ref of the same epoch
     epoch_leadership_schedule: Arc<Leadership>,
 
+    /// the ledger state the leadership schedule was computed from, a
+    /// remote signer computes the same leadership from it
+    ///
+    /// this object will be shared between different Ref of the same epoch
+    epoch_leadership_state: Arc<Ledger>,
+
     /// pointer to the current ledger parameters
     ///
     /// The object will be shared between different Ref of the same epoch
@@ -39,6 +53,10 @@ pub struct Ref {
     /// the parent `Ref`. Otherwise it will be copied from `Ref` to `Ref`.
     ///
     previous_epoch_state: Option<Arc<Ref>>,
+
+    /// the rewards distributed before the block, if it is the first block
+    /// of its epoch on its branch
+    epoch_rewards: Option<Arc<EpochTransitionRewards>>,
 }
 
 impl Ref {
@@ -47,9 +65,11 @@ impl Ref {
         ledger: multiverse::Ref<Ledger>,
         time_frame: Arc<TimeFrame>,
         epoch_leadership_schedule: Arc<Leadership>,
+        epoch_leadership_state: Arc<Ledger>,
         epoch_ledger_parameters: Arc<LedgerParameters>,
         header: Header,
         previous_epoch_state: Option<Arc<Ref>>,
+        epoch_rewards: Option<Arc<EpochTransitionRewards>>,
     ) -> Self {
         debug_assert_eq!(
             *ledger.id(),
@@ -61,9 +81,11 @@ impl Ref {
             ledger,
             time_frame,
             epoch_leadership_schedule,
+            epoch_leadership_state,
             epoch_ledger_parameters,
             header,
             previous_epoch_state,
+            epoch_rewards,
         }
     }
 
@@ -107,6 +129,10 @@ impl Ref {
         &self.epoch_leadership_schedule
     }
 
+    pub fn epoch_leadership_state(&self) -> &Arc<Ledger> {
+        &self.epoch_leadership_state
+    }
+
     pub fn epoch_ledger_parameters(&self) -> &Arc<LedgerParameters> {
         &self.epoch_ledger_parameters
     }
@@ -115,6 +141,10 @@ impl Ref {
         self.previous_epoch_state.as_ref()
     }
 
+    pub fn epoch_rewards(&self) -> Option<&Arc<EpochTransitionRewards>> {
+        self.epoch_rewards.as_ref()
+    }
+
     /// get the chain_time's `Slot`. This allows to compute an accurate
     /// block time via a given time_frame or a precise block time
     pub fn slot(&self) -> Slot {
@@ -0,0 +1,216 @@
+//! history of the branch switches of the node.
+//!
+//! Every time the chain selection prefers a branch that does not extend the
+//! current tip, the node rolls back to the last block shared by the two
+//! branches. The rollback is recorded here so it can be inspected from the
+//! REST API.
+
+use crate::blockcfg::Header;
+use crate::blockchain::Storage;
+use chain_core::property::Header as _;
+use chain_storage::error::Error as StorageError;
+use futures03::compat::Future01CompatExt as _;
+use jormungandr_lib::{
+    interfaces::{ChainReorg, ChainRollback, ChainTip},
+    time::SystemTime,
+};
+use std::{collections::VecDeque, sync::Arc};
+use thiserror::Error;
+use tokio02::sync::RwLock;
+
+/// the number of branch switches kept in the history, the oldest ones are
+/// dropped first
+const MAX_RECORDED_REORGS: usize = 1024;
+
+#[derive(Debug, Error)]
+pub enum RollbackError {
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error("the rollback is deeper than {max_depth} blocks")]
+    TooDeep { max_depth: u32 },
+}
+
+/// the branch switches of the node, the oldest first
+#[derive(Clone)]
+pub struct Reorgs(Arc<RwLock<VecDeque<ChainReorg>>>);
+
+impl Reorgs {
+    pub fn new() -> Self {
+        Reorgs(Arc::new(RwLock::new(VecDeque::new())))
+    }
+
+    pub async fn record(&self, rollback: ChainRollback) {
+        let mut reorgs = self.0.write().await;
+        if reorgs.len() == MAX_RECORDED_REORGS {
+            reorgs.pop_front();
+        }
+        reorgs.push_back(ChainReorg {
+            time: SystemTime::now(),
+            rollback,
+        });
+    }
+
+    pub async fn all(&self) -> Vec<ChainReorg> {
+        self.0.read().await.iter().cloned().collect()
+    }
+}
+
+fn header_tip(header: &Header) -> ChainTip {
+    ChainTip {
+        hash: header.hash().into(),
+        chain_length: header.chain_length().into(),
+        date: header.block_date().into(),
+    }
+}
+
+async fn parent_header(storage: &Storage, header: &Header) -> Result<Header, StorageError> {
+    storage
+        .get_header(header.block_parent_hash())
+        .compat()
+        .await?
+        .ok_or(StorageError::BlockNotFound)
+}
+
+/// walk back the two branches from their tips until they meet. The chain
+/// selection does not limit the depth of a branch switch, so the walk gives
+/// up once more than `max_depth` blocks of the branch of `from` would be
+/// rolled back. The branch of `to` is only walked back down to the chain
+/// length reached on the branch of `from`.
+pub async fn compute_rollback(
+    storage: &Storage,
+    from: &Header,
+    to: &Header,
+    max_depth: u32,
+) -> Result<ChainRollback, RollbackError> {
+    let mut old = from.clone();
+    let mut new = to.clone();
+    let mut dropped_blocks = Vec::new();
+    while old.hash() != new.hash() {
+        if old.chain_length() >= new.chain_length() {
+            if dropped_blocks.len() as u32 >= max_depth {
+                return Err(RollbackError::TooDeep { max_depth });
+            }
+            let parent = parent_header(storage, &old).await?;
+            dropped_blocks.push(old.hash().into());
+            old = parent;
+        } else {
+            new = parent_header(storage, &new).await?;
+        }
+    }
+
+    Ok(ChainRollback {
+        from: header_tip(from),
+        to: header_tip(to),
+        common_ancestor: header_tip(&old),
+        depth: dropped_blocks.len() as u32,
+        dropped_blocks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockcfg::{
+            Block, BlockDate, BlockVersion, ChainLength, Contents, ContentsBuilder,
+            HeaderBuilderNew,
+        },
+        blockchain::SledStore,
+    };
+    use jormungandr_lib::crypto::hash::Hash;
+
+    fn block0() -> Block {
+        let contents: Contents = ContentsBuilder::new().into();
+        let header = HeaderBuilderNew::new(BlockVersion::Genesis, &contents)
+            .set_genesis()
+            .set_date(BlockDate::first())
+            .to_unsigned_header()
+            .unwrap()
+            .generalize();
+        Block { header, contents }
+    }
+
+    /// the child of `parent` at the given slot, the children of a block at
+    /// different slots are different blocks
+    fn child(parent: &Header, slot_id: u32) -> Block {
+        let contents: Contents = ContentsBuilder::new().into();
+        let chain_length = u32::from(parent.chain_length()) + 1;
+        let header = HeaderBuilderNew::new(BlockVersion::Genesis, &contents)
+            .set_parent(&parent.hash(), ChainLength::from(chain_length))
+            .set_date(BlockDate { epoch: 0, slot_id })
+            .to_unsigned_header()
+            .unwrap()
+            .generalize();
+        Block { header, contents }
+    }
+
+    /// store `length` blocks after `from`, one slot apart starting at
+    /// `first_slot`
+    async fn branch(storage: &Storage, from: &Block, length: u32, first_slot: u32) -> Vec<Block> {
+        let mut blocks = vec![from.clone()];
+        for slot_id in first_slot..first_slot + length {
+            let block = child(&blocks.last().unwrap().header, slot_id);
+            storage.put_block(block.clone()).compat().await.unwrap();
+            blocks.push(block);
+        }
+        blocks.remove(0);
+        blocks
+    }
+
+    /// the block0 followed by a main branch of 4 blocks, and a fork of 3
+    /// blocks from the second block of the main branch
+    async fn forked_storage() -> (Storage, Vec<Block>, Vec<Block>) {
+        let storage = Storage::new(SledStore::temporary().unwrap());
+        let block0 = block0();
+        storage.put_block(block0.clone()).compat().await.unwrap();
+        let mut main = vec![block0];
+        main.extend(branch(&storage, &main[0], 4, 1).await);
+        let fork = branch(&storage, &main[2], 3, 100).await;
+        (storage, main, fork)
+    }
+
+    #[test]
+    fn rollback_to_the_common_ancestor() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (storage, main, fork) = forked_storage().await;
+            let from = &main[4].header;
+            let to = &fork[2].header;
+            let rollback = compute_rollback(&storage, from, to, 10).await.unwrap();
+
+            assert_eq!(rollback.from, header_tip(from));
+            assert_eq!(rollback.to, header_tip(to));
+            assert_eq!(rollback.common_ancestor, header_tip(&main[2].header));
+            assert_eq!(rollback.depth, 2);
+            assert_eq!(
+                rollback.dropped_blocks,
+                vec![
+                    Hash::from(main[4].header.hash()),
+                    Hash::from(main[3].header.hash()),
+                ]
+            );
+
+            // switching back drops the blocks of the fork
+            let rollback = compute_rollback(&storage, to, from, 10).await.unwrap();
+            assert_eq!(rollback.common_ancestor, header_tip(&main[2].header));
+            assert_eq!(rollback.depth, 3);
+        });
+    }
+
+    #[test]
+    fn rollback_deeper_than_the_maximum_depth() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (storage, main, fork) = forked_storage().await;
+            let from = &main[4].header;
+            let to = &fork[2].header;
+
+            let rollback = compute_rollback(&storage, from, to, 2).await.unwrap();
+            assert_eq!(rollback.depth, 2);
+            match compute_rollback(&storage, from, to, 1).await {
+                Err(RollbackError::TooDeep { max_depth: 1 }) => (),
+                result => panic!("unexpected result {:?}", result.map(|r| r.depth)),
+            }
+        });
+    }
+}
@@ -0,0 +1,427 @@
+//! ledger snapshots to bootstrap a node without the blocks of the past.
+//!
+//! A snapshot is taken at the end of an epoch that is deeper than the epoch
+//! stability depth, so it cannot be rolled back anymore. It holds the last
+//! block of the epoch with the ledger state after it, along with the last
+//! header and ledger state of the previous epochs: the leadership of the
+//! next epochs is computed from them.
+//!
+//! The snapshot file has the following layout, every item is prefixed with
+//! its size (4 bytes, big endian):
+//!
+//! ```text
+//! | magic (4) | version (1) | previous epochs (1) | block | ledger | (header | ledger)* |
+//! ```
+//!
+//! A snapshot is trusted by the digest of the whole file: the Blake2b-256
+//! hash of its contents, which covers the ledger states and not only the
+//! block the snapshot is taken at. The copy kept in the storage directory is
+//! trusted by the digest recorded in the node storage when it was saved.
+
+use crate::{
+    blockcfg::{Block, Header, HeaderHash, Ledger},
+    blockchain::{Ref, Storage},
+    utils::framing::{read_frame, write_frame, FrameError},
+};
+use chain_core::property::{Deserialize, Serialize};
+use chain_crypto::Blake2b256;
+use chain_storage::error::Error as StorageError;
+use futures03::compat::Future01CompatExt as _;
+use jormungandr_lib::crypto::hash::Hash;
+use std::{
+    convert::TryFrom,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    iter,
+    path::Path,
+    sync::Arc,
+};
+use thiserror::Error;
+
+/// name of the copy of the snapshot the node started from, in the node's
+/// storage directory
+pub const SNAPSHOT_FILE_NAME: &str = "ledger_snapshot.bin";
+
+/// key of the record of the node storage holding the digest of the stored
+/// copy of the snapshot
+const SNAPSHOT_DIGEST_RECORD: &str = "ledger_snapshot_digest";
+
+const MAGIC: [u8; 4] = *b"jsnp";
+const VERSION: u8 = 1;
+/// the leadership of the epoch following the snapshot needs the ledger
+/// states of the 2 previous epochs
+const MAX_PREVIOUS_EPOCHS: usize = 2;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("cannot access the ledger snapshot")]
+    Io(#[from] io::Error),
+    #[error("the file is not a ledger snapshot")]
+    NotSnapshot,
+    #[error("unsupported ledger snapshot version {0}")]
+    UnsupportedVersion(u8),
+    #[error("the ledger snapshot is malformed")]
+    Malformed,
+    #[error("the digest of the ledger snapshot is {found}, not the trusted digest {expected}")]
+    Untrusted { expected: Hash, found: Hash },
+    #[error("the digest of the stored ledger snapshot is not recorded in the node storage")]
+    NoStoredDigest,
+    #[error("no epoch of the blockchain is deeper than the epoch stability depth yet")]
+    NoStableEpoch,
+    #[error("cannot read the block of the ledger snapshot from the storage")]
+    Storage(#[from] StorageError),
+}
+
+pub struct Snapshot {
+    /// the last block of the epoch
+    block: Block,
+    /// the ledger state after the block
+    ledger: Ledger,
+    /// the last header and ledger state of the previous epochs, the most
+    /// recent first
+    previous_epochs: Vec<(Header, Ledger)>,
+}
+
+impl Snapshot {
+    /// take the snapshot at the end of the most recent epoch of the branch
+    /// of `tip` which is deeper than the epoch stability depth
+    pub async fn from_tip(tip: Arc<Ref>, storage: &Storage) -> Result<Self, SnapshotError> {
+        let boundary = stable_epoch_end(&tip, 0).ok_or(SnapshotError::NoStableEpoch)?;
+        Snapshot::at(&boundary, storage).await
+    }
+
+    /// take the snapshot at the given end of an epoch
+    pub(super) async fn at(boundary: &Arc<Ref>, storage: &Storage) -> Result<Self, SnapshotError> {
+        let block = storage
+            .get(boundary.hash())
+            .compat()
+            .await?
+            .ok_or(StorageError::BlockNotFound)?;
+        let previous_epochs = iter::successors(boundary.last_ref_previous_epoch(), |reference| {
+            reference.last_ref_previous_epoch()
+        })
+        .take(MAX_PREVIOUS_EPOCHS)
+        .map(|reference| {
+            (
+                reference.header().clone(),
+                reference.ledger().as_ref().clone(),
+            )
+        })
+        .collect();
+
+        Ok(Snapshot {
+            block,
+            ledger: boundary.ledger().as_ref().clone(),
+            previous_epochs,
+        })
+    }
+
+    /// the hash of the block the snapshot is taken at
+    pub fn hash(&self) -> HeaderHash {
+        self.block.header.hash()
+    }
+
+    /// the block the snapshot is taken at
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
+
+    pub(super) fn into_parts(self) -> (Block, Ledger, Vec<(Header, Ledger)>) {
+        (self.block, self.ledger, self.previous_epochs)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Snapshot::read(BufReader::new(File::open(path)?))
+    }
+
+    /// load the snapshot if the digest of the file is the trusted digest,
+    /// the contents of the file are only decoded once they are trusted
+    pub fn load_trusted<P: AsRef<Path>>(path: P, trusted: &Hash) -> Result<Self, SnapshotError> {
+        let bytes = fs::read(path)?;
+        let found = digest(&bytes);
+        if found != *trusted {
+            return Err(SnapshotError::Untrusted {
+                expected: *trusted,
+                found,
+            });
+        }
+        Snapshot::read(bytes.as_slice())
+    }
+
+    /// load the copy of the storage directory, if its digest is the one
+    /// recorded in the node storage when the copy was saved
+    pub async fn load_stored<P: AsRef<Path>>(
+        path: P,
+        storage: &Storage,
+    ) -> Result<Self, SnapshotError> {
+        let trusted = stored_digest(storage)
+            .await?
+            .ok_or(SnapshotError::NoStoredDigest)?;
+        Snapshot::load_trusted(path, &trusted)
+    }
+
+    /// save the snapshot, the file is replaced only once the snapshot is
+    /// fully written. Return the digest of the file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<Hash, SnapshotError> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        let mut bytes = Vec::new();
+        self.write(&mut bytes)?;
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            writer.write_all(&bytes)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(tmp_path, path)?;
+        Ok(digest(&bytes))
+    }
+
+    /// save the copy of the storage directory, its digest is recorded in
+    /// the node storage so the copy is trusted when the node restarts
+    pub async fn save_stored<P: AsRef<Path>>(
+        &self,
+        path: P,
+        storage: &Storage,
+    ) -> Result<(), SnapshotError> {
+        let digest = self.save(path)?;
+        storage
+            .put_record(
+                SNAPSHOT_DIGEST_RECORD.to_owned(),
+                <[u8; 32]>::from(digest).to_vec(),
+            )
+            .compat()
+            .await?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, SnapshotError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(SnapshotError::NotSnapshot);
+        }
+        let mut version_and_count = [0; 2];
+        reader.read_exact(&mut version_and_count)?;
+        let [version, previous_epochs_count] = version_and_count;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let previous_epochs_count = previous_epochs_count as usize;
+        if previous_epochs_count == 0 || previous_epochs_count > MAX_PREVIOUS_EPOCHS {
+            return Err(SnapshotError::Malformed);
+        }
+
+        let block = read_item(&mut reader)?;
+        let ledger = read_item(&mut reader)?;
+        let mut previous_epochs = Vec::with_capacity(previous_epochs_count);
+        for _ in 0..previous_epochs_count {
+            let header = read_item(&mut reader)?;
+            let ledger = read_item(&mut reader)?;
+            previous_epochs.push((header, ledger));
+        }
+        Ok(Snapshot {
+            block,
+            ledger,
+            previous_epochs,
+        })
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), SnapshotError> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[VERSION, self.previous_epochs.len() as u8])?;
+        write_item(&mut writer, &self.block)?;
+        write_item(&mut writer, &self.ledger)?;
+        for (header, ledger) in &self.previous_epochs {
+            write_item(&mut writer, header)?;
+            write_item(&mut writer, ledger)?;
+        }
+        Ok(())
+    }
+}
+
+/// the digest of the contents of a snapshot file, to set as the
+/// `trusted_snapshot` of the node configuration
+pub fn digest(bytes: &[u8]) -> Hash {
+    Hash::from(Blake2b256::new(bytes))
+}
+
+/// the digest of the stored copy of the snapshot, if it has been recorded
+async fn stored_digest(storage: &Storage) -> Result<Option<Hash>, SnapshotError> {
+    let records = storage
+        .records(SNAPSHOT_DIGEST_RECORD.to_owned())
+        .compat()
+        .await?;
+    Ok(records
+        .into_iter()
+        .find(|(key, _)| key == SNAPSHOT_DIGEST_RECORD)
+        .and_then(|(_, value)| <[u8; 32]>::try_from(value.as_slice()).ok())
+        .map(Hash::from))
+}
+
+/// the end of the most recent epoch of the branch of `tip` which is deeper
+/// than the epoch stability depth, skipping the `kept_epochs` epochs before
+/// the epoch of the tip. The states of the epochs before the first one the
+/// node holds in memory are not available, such epochs are not returned.
+pub(super) fn stable_epoch_end(tip: &Arc<Ref>, kept_epochs: u32) -> Option<Arc<Ref>> {
+    let stability_depth = tip.epoch_ledger_parameters().epoch_stability_depth;
+    let tip_length = u32::from(tip.chain_length());
+
+    let mut candidate = tip.last_ref_previous_epoch();
+    for _ in 0..kept_epochs {
+        candidate = candidate?.last_ref_previous_epoch();
+    }
+    loop {
+        match candidate {
+            Some(reference) if reference.last_ref_previous_epoch().is_none() => return None,
+            Some(reference)
+                if tip_length - u32::from(reference.chain_length()) > stability_depth =>
+            {
+                return Some(Arc::clone(reference))
+            }
+            Some(reference) => candidate = reference.last_ref_previous_epoch(),
+            None => return None,
+        }
+    }
+}
+
+fn write_item<W: Write, T: Serialize>(writer: &mut W, item: &T) -> Result<(), SnapshotError> {
+    let bytes = item
+        .serialize_as_vec()
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+    write_frame(writer, &bytes)?;
+    Ok(())
+}
+
+fn read_item<R: Read, T: Deserialize>(reader: &mut R) -> Result<T, SnapshotError> {
+    let bytes = match read_frame(reader) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+        Err(FrameError::Io(error)) => return Err(error.into()),
+        Err(FrameError::Truncated) => return Err(SnapshotError::Malformed),
+    };
+    T::deserialize(bytes.as_slice()).map_err(|_| SnapshotError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::SledStore;
+    use std::io::Write as _;
+    use tempfile::NamedTempFile;
+
+    fn temp_file(bytes: &[u8]) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(bytes).unwrap();
+        file
+    }
+
+    fn header(version: u8, previous_epochs: u8) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[version, previous_epochs]);
+        bytes
+    }
+
+    #[test]
+    fn digest_covers_the_whole_file() {
+        let bytes = header(VERSION, 1);
+        let mut other = bytes.clone();
+        other.push(0);
+        assert_eq!(digest(&bytes), digest(&bytes.clone()));
+        assert_ne!(digest(&bytes), digest(&other));
+    }
+
+    #[test]
+    fn untrusted_snapshot_is_rejected() {
+        let bytes = header(VERSION, 1);
+        let file = temp_file(&bytes);
+        let path = file.path();
+        let trusted = digest(b"another snapshot");
+        match Snapshot::load_trusted(&path, &trusted) {
+            Err(SnapshotError::Untrusted { expected, found }) => {
+                assert_eq!(expected, trusted);
+                assert_eq!(found, digest(&bytes));
+            }
+            _ => panic!("the snapshot should not be trusted"),
+        }
+    }
+
+    #[test]
+    fn trusted_snapshot_is_decoded() {
+        // the digest matches, the truncated contents are then rejected
+        let bytes = header(VERSION, 1);
+        let file = temp_file(&bytes);
+        let path = file.path();
+        match Snapshot::load_trusted(&path, &digest(&bytes)) {
+            Err(SnapshotError::Io(error)) => {
+                assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof)
+            }
+            _ => panic!("the truncated snapshot should be rejected"),
+        }
+    }
+
+    #[test]
+    fn stored_copy_is_trusted_by_the_recorded_digest() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            let bytes = header(VERSION, 1);
+            let file = temp_file(&bytes);
+            let path = file.path();
+            match Snapshot::load_stored(&path, &storage).await {
+                Err(SnapshotError::NoStoredDigest) => (),
+                _ => panic!("the digest of the stored copy is not recorded"),
+            }
+
+            storage
+                .put_record(
+                    SNAPSHOT_DIGEST_RECORD.to_owned(),
+                    <[u8; 32]>::from(digest(&bytes)).to_vec(),
+                )
+                .compat()
+                .await
+                .unwrap();
+            match Snapshot::load_stored(&path, &storage).await {
+                Err(SnapshotError::Io(error)) => {
+                    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof)
+                }
+                _ => panic!("the stored copy should be decoded"),
+            }
+
+            // the copy has been replaced since the digest was recorded
+            let mut other = bytes.clone();
+            other.push(0);
+            fs::write(&path, &other).unwrap();
+            match Snapshot::load_stored(&path, &storage).await {
+                Err(SnapshotError::Untrusted { found, .. }) => assert_eq!(found, digest(&other)),
+                _ => panic!("the replaced copy should not be trusted"),
+            }
+        });
+    }
+
+    #[test]
+    fn malformed_snapshots_are_rejected() {
+        match Snapshot::read(&b"nope\x01\x01"[..]) {
+            Err(SnapshotError::NotSnapshot) => (),
+            _ => panic!("not a snapshot"),
+        }
+        match Snapshot::read(header(VERSION + 1, 1).as_slice()) {
+            Err(SnapshotError::UnsupportedVersion(version)) => assert_eq!(version, VERSION + 1),
+            _ => panic!("unsupported version"),
+        }
+        for previous_epochs in &[0, MAX_PREVIOUS_EPOCHS as u8 + 1] {
+            match Snapshot::read(header(VERSION, *previous_epochs).as_slice()) {
+                Err(SnapshotError::Malformed) => (),
+                _ => panic!("invalid number of previous epochs"),
+            }
+        }
+        // the size of the block is larger than the rest of the file
+        let mut bytes = header(VERSION, 1);
+        bytes.extend_from_slice(&16u32.to_be_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        match Snapshot::read(bytes.as_slice()) {
+            Err(SnapshotError::Malformed) => (),
+            _ => panic!("truncated block"),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! copy of the stored blocks from one storage backend to another.
+
+use super::{Storage, StorageError};
+use crate::{
+    blockcfg::{Block, HeaderHash},
+    blockchain::{MAIN_BRANCH_TAG, PRUNED_TAG},
+};
+use futures03::stream::TryStreamExt as _;
+
+/// copy the blocks of the main branch, from its root up to the block of the
+/// `HEAD` tag, along with the tags and the records of the node. The root is
+/// the block0, or the block of the `PRUNED` tag: the blocks before it are not
+/// copied. The contents of the block of the `PRUNED` tag may have been
+/// pruned, they are taken from the block of the ledger snapshot the node
+/// restarts from. Returns the number of copied blocks.
+pub async fn migrate_blocks(
+    from: &Storage,
+    to: &Storage,
+    block0_hash: HeaderHash,
+    snapshot_block: Option<Block>,
+) -> Result<u64, StorageError> {
+    let (from, to) = (&from.inner, &to.inner);
+    let pruned = from.get_tag(PRUNED_TAG.to_owned()).await?;
+    let head = from
+        .get_tag(MAIN_BRANCH_TAG.to_owned())
+        .await?
+        .unwrap_or(block0_hash);
+    let root = pruned.unwrap_or(block0_hash);
+
+    let root_block = match from.get(root).await? {
+        Some(block) => block,
+        None => snapshot_block
+            .filter(|block| block.header.hash() == root)
+            .ok_or(StorageError::BlockNotFound)?,
+    };
+    if pruned.is_some() {
+        to.put_snapshot_block(root_block).await?;
+    } else {
+        to.put_block(root_block).await?;
+    }
+    let mut copied = 1;
+
+    let mut blocks = from.stream_from_to(root, head).await?;
+    while let Some(block) = blocks.try_next().await? {
+        to.put_block(block).await?;
+        copied += 1;
+    }
+
+    if let Some(pruned) = pruned {
+        to.put_tag(PRUNED_TAG.to_owned(), pruned).await?;
+    }
+    to.put_tag(MAIN_BRANCH_TAG.to_owned(), head).await?;
+
+    for (key, value) in from.records(String::new()).await? {
+        to.put_record(key, value).await?;
+    }
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockcfg::{
+            BlockDate, BlockVersion, ChainLength, Contents, ContentsBuilder, Header,
+            HeaderBuilderNew,
+        },
+        blockchain::{SledStore, SqliteStore},
+    };
+    use chain_storage_sqlite_old::SQLiteBlockStore;
+
+    fn block0() -> Block {
+        let contents: Contents = ContentsBuilder::new().into();
+        let header = HeaderBuilderNew::new(BlockVersion::Genesis, &contents)
+            .set_genesis()
+            .set_date(BlockDate::first())
+            .to_unsigned_header()
+            .unwrap()
+            .generalize();
+        Block { header, contents }
+    }
+
+    fn branch(parent: &Header, length: usize) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::with_capacity(length);
+        for i in 0..length {
+            let parent = blocks.last().map_or(parent, |block| &block.header);
+            let chain_length = u32::from(parent.chain_length()) + 1;
+            let contents: Contents = ContentsBuilder::new().into();
+            let header = HeaderBuilderNew::new(BlockVersion::Genesis, &contents)
+                .set_parent(&parent.hash(), ChainLength::from(chain_length))
+                .set_date(BlockDate {
+                    epoch: 0,
+                    slot_id: 1 + i as u32,
+                })
+                .to_unsigned_header()
+                .unwrap()
+                .generalize();
+            blocks.push(Block { header, contents });
+        }
+        blocks
+    }
+
+    fn sqlite_storage() -> Storage {
+        Storage::new(SqliteStore::new(SQLiteBlockStore::memory(), None))
+    }
+
+    async fn assert_migrated(to: &Storage, migrated: &[Block], head: HeaderHash) {
+        let to = &to.inner;
+        for block in migrated {
+            assert!(to.block_exists(block.header.hash()).await.unwrap());
+        }
+        assert_eq!(
+            to.get_tag(MAIN_BRANCH_TAG.to_owned()).await.unwrap(),
+            Some(head)
+        );
+        assert_eq!(
+            to.records(String::new()).await.unwrap(),
+            vec![("record".to_owned(), b"value".to_vec())]
+        );
+    }
+
+    #[test]
+    fn main_branch_is_migrated() {
+        let block0 = block0();
+        let mut blocks = vec![block0.clone()];
+        blocks.extend(branch(&block0.header, 20));
+        let head = blocks[15].header.hash();
+        let from = sqlite_storage();
+
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            for block in &blocks {
+                from.inner.put_block(block.clone()).await.unwrap();
+            }
+            from.inner
+                .put_tag(MAIN_BRANCH_TAG.to_owned(), head)
+                .await
+                .unwrap();
+            from.inner
+                .put_record("record".to_owned(), b"value".to_vec())
+                .await
+                .unwrap();
+
+            let to = Storage::new(SledStore::temporary().unwrap());
+            let copied = migrate_blocks(&from, &to, block0.header.hash(), None)
+                .await
+                .unwrap();
+
+            assert_eq!(copied, 16);
+            assert_migrated(&to, &blocks[..16], head).await;
+            // the blocks after the `HEAD` tag are not copied
+            assert!(!to
+                .inner
+                .block_exists(blocks[16].header.hash())
+                .await
+                .unwrap());
+            assert_eq!(to.inner.get_tag(PRUNED_TAG.to_owned()).await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn pruned_branch_is_migrated_from_its_root() {
+        let block0 = block0();
+        let blocks = branch(&block0.header, 20);
+        let root = &blocks[5];
+        let head = blocks[19].header.hash();
+        let from = sqlite_storage();
+
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            // the node started from the ledger snapshot taken at the root
+            from.inner.put_snapshot_block(root.clone()).await.unwrap();
+            for block in &blocks[6..] {
+                from.inner.put_block(block.clone()).await.unwrap();
+            }
+            from.inner
+                .put_tag(PRUNED_TAG.to_owned(), root.header.hash())
+                .await
+                .unwrap();
+            from.inner
+                .put_tag(MAIN_BRANCH_TAG.to_owned(), head)
+                .await
+                .unwrap();
+            from.inner
+                .put_record("record".to_owned(), b"value".to_vec())
+                .await
+                .unwrap();
+
+            let to = Storage::new(SledStore::temporary().unwrap());
+            let copied = migrate_blocks(&from, &to, block0.header.hash(), None)
+                .await
+                .unwrap();
+
+            assert_eq!(copied, 15);
+            assert_migrated(&to, &blocks[5..], head).await;
+            assert_eq!(
+                to.inner.get_tag(PRUNED_TAG.to_owned()).await.unwrap(),
+                Some(root.header.hash())
+            );
+            assert!(!to
+                .inner
+                .block_exists(blocks[4].header.hash())
+                .await
+                .unwrap());
+            assert_eq!(
+                to.inner
+                    .is_ancestor(root.header.hash(), head)
+                    .await
+                    .unwrap(),
+                Some(14)
+            );
+        });
+    }
+}
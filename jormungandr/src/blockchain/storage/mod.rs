@@ -0,0 +1,476 @@
+//! storage of the blocks of the node.
+//!
+//! The blocks are stored by one of the backends implementing `BlockStore`:
+//! the SQLite file of `chain-storage-sqlite-old`, or an embedded key-value
+//! store. `Storage` adds the ordering of the write operations on top of the
+//! backend, along with a compatibility layer for the old futures API.
+//!
+//! Along with the blocks, the backends keep the records of the node: the
+//! data the node maintains about the blockchain, such as the leadership
+//! history, stored under a key of their own.
+
+mod migration;
+mod sled;
+mod sqlite;
+
+pub use self::{migration::migrate_blocks, sled::SledStore, sqlite::SqliteStore};
+
+use crate::blockcfg::{Block, Header, HeaderHash};
+use async_trait::async_trait;
+use futures::{Future as Future01, Sink as Sink01, Stream as Stream01};
+use futures03::{
+    compat::*,
+    prelude::*,
+    sink::{Sink, SinkExt},
+    stream::{self, BoxStream},
+};
+use std::{convert::identity, pin::Pin, sync::Arc};
+use tokio02::{sync::Mutex, task::spawn_blocking};
+
+pub use chain_storage::error::Error as StorageError;
+
+/// the blocks of a branch, from the oldest to the most recent
+pub type BlockStream = BoxStream<'static, Result<Block, StorageError>>;
+
+fn backend_error<E>(error: E) -> StorageError
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    StorageError::BackendError(Box::new(error))
+}
+
+async fn run_blocking_storage<F, R>(f: F) -> Result<R, StorageError>
+where
+    F: FnOnce() -> Result<R, StorageError> + Send + 'static,
+    R: Send + 'static,
+{
+    spawn_blocking(f)
+        .await
+        .map_err(|e| StorageError::BackendError(Box::new(e)))
+        .and_then(identity)
+}
+
+/// the operations of a storage backend. Every block is stored along with
+/// the link to its parent and its depth, counted from the root of the
+/// stored blocks: the block0, or the block of a ledger snapshot.
+///
+/// The write operations are called one at a time by `Storage`.
+#[async_trait]
+pub trait BlockStore: Send + Sync {
+    async fn get_tag(&self, tag: String) -> Result<Option<HeaderHash>, StorageError>;
+
+    async fn put_tag(&self, tag: String, header_hash: HeaderHash) -> Result<(), StorageError>;
+
+    /// get the block, `None` if the block is not stored or if its contents
+    /// have been pruned
+    async fn get(&self, header_hash: HeaderHash) -> Result<Option<Block>, StorageError>;
+
+    /// get the header of a block whose contents have been pruned
+    async fn get_pruned_header(
+        &self,
+        header_hash: HeaderHash,
+    ) -> Result<Option<Header>, StorageError>;
+
+    async fn block_exists(&self, header_hash: HeaderHash) -> Result<bool, StorageError>;
+
+    /// the parent the block is linked to by the storage, read without the
+    /// contents of the block. `Err(BlockNotFound)` if the block is not
+    /// linked.
+    async fn get_parent(&self, header_hash: HeaderHash) -> Result<HeaderHash, StorageError>;
+
+    /// put a block whose parent is already stored, or the block0
+    async fn put_block(&self, block: Block) -> Result<(), StorageError>;
+
+    /// put a block whose parent is not in the storage, such as the block
+    /// of a ledger snapshot. The block becomes a root of the stored blocks
+    /// as the block0 is: the depths of its descendants are counted from it.
+    async fn put_snapshot_block(&self, block: Block) -> Result<(), StorageError>;
+
+    /// remove the contents of the given blocks from the storage. The blocks
+    /// are still known to the storage: their headers are kept aside and
+    /// `block_exists` and the ancestry queries are not affected, but the
+    /// blocks cannot be retrieved anymore.
+    async fn prune_block_contents(&self, headers: Vec<Header>) -> Result<(), StorageError>;
+
+    /// Return values:
+    /// - `Ok(Some(distance))` - `ancestor` is an ancestor of `descendant`
+    /// - `Ok(None)` - `ancestor` is not an ancestor of `descendant`
+    /// - `Err(BlockNotFound)` - `ancestor` or `descendant` was not found
+    /// - `Err(_)` - some other storage error
+    async fn is_ancestor(
+        &self,
+        ancestor: HeaderHash,
+        descendant: HeaderHash,
+    ) -> Result<Option<u64>, StorageError>;
+
+    /// the records whose key starts with the given prefix, ordered by key
+    async fn records(&self, prefix: String) -> Result<Vec<(String, Vec<u8>)>, StorageError>;
+
+    /// put the record under the given key, replacing the previous one. The
+    /// record is on disk once the call returns.
+    async fn put_record(&self, key: String, value: Vec<u8>) -> Result<(), StorageError>;
+
+    /// Return values:
+    /// - `Ok(stream)` - `from` is ancestor of `to`, returns blocks between them
+    /// - `Err(CannotIterate)` - `from` is not ancestor of `to`
+    /// - `Err(BlockNotFound)` - `from` or `to` was not found
+    /// - `Err(_)` - some other storage error
+    async fn stream_from_to(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> Result<BlockStream, StorageError>;
+
+    /// stream a branch ending at `to` and starting after the ancestor at
+    /// distance `depth`, or after the root if `depth` is given as `None`
+    async fn stream_branch(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+    ) -> Result<BlockStream, StorageError>;
+
+    /// the closest of the checkpoints which is an ancestor of `descendant`,
+    /// the checkpoints which are not stored are ignored
+    async fn find_closest_ancestor(
+        &self,
+        checkpoints: Vec<HeaderHash>,
+        descendant: HeaderHash,
+    ) -> Result<Option<Ancestor>, StorageError> {
+        let mut ancestor = None;
+        for checkpoint in checkpoints {
+            // Checkpoints sent by a peer may not
+            // be present locally, so we need to ignore certain errors
+            match self.is_ancestor(checkpoint, descendant).await {
+                Ok(Some(distance)) => {
+                    if ancestor
+                        .as_ref()
+                        .map_or(true, |closest: &Ancestor| closest.distance > distance)
+                    {
+                        ancestor = Some(Ancestor {
+                            header_hash: checkpoint,
+                            distance,
+                        });
+                    }
+                }
+                Ok(None) | Err(StorageError::BlockNotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(ancestor)
+    }
+}
+
+#[derive(Clone)]
+pub struct Storage03 {
+    store: Arc<dyn BlockStore>,
+
+    // All write operations must be performed only via this lock. The lock helps
+    // us to ensure that all of the write operations are performed in the right
+    // sequence. Otherwise they can be performed out of the expected order (for
+    // example, by different tokio executors) which eventually leads to a panic
+    // because the block data would be inconsistent at the time of a write.
+    write_lock: Arc<Mutex<()>>,
+}
+
+// Compatibility layer for using new storage with old futures API.
+#[derive(Clone)]
+pub struct Storage {
+    inner: Storage03,
+}
+
+pub struct Ancestor {
+    pub header_hash: HeaderHash,
+    pub distance: u64,
+}
+
+impl Storage03 {
+    pub fn new<S: BlockStore + 'static>(store: S) -> Self {
+        Storage03 {
+            store: Arc::new(store),
+            write_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub async fn get_tag(&self, tag: String) -> Result<Option<HeaderHash>, StorageError> {
+        self.store.get_tag(tag).await
+    }
+
+    pub async fn put_tag(&self, tag: String, header_hash: HeaderHash) -> Result<(), StorageError> {
+        let _lock = self.write_lock.lock().await;
+        self.store.put_tag(tag, header_hash).await
+    }
+
+    pub async fn get(&self, header_hash: HeaderHash) -> Result<Option<Block>, StorageError> {
+        self.store.get(header_hash).await
+    }
+
+    /// get the header of the block, it is still available once the
+    /// contents of the block have been pruned
+    pub async fn get_header(
+        &self,
+        header_hash: HeaderHash,
+    ) -> Result<Option<Header>, StorageError> {
+        match self.store.get(header_hash).await? {
+            Some(block) => Ok(Some(block.header)),
+            None => self.store.get_pruned_header(header_hash).await,
+        }
+    }
+
+    pub async fn block_exists(&self, header_hash: HeaderHash) -> Result<bool, StorageError> {
+        self.store.block_exists(header_hash).await
+    }
+
+    /// the parent the block is linked to by the storage, it is found even
+    /// if the contents of the block are missing
+    pub async fn get_parent(&self, header_hash: HeaderHash) -> Result<HeaderHash, StorageError> {
+        self.store.get_parent(header_hash).await
+    }
+
+    pub async fn put_block(&self, block: Block) -> Result<(), StorageError> {
+        let _lock = self.write_lock.lock().await;
+        self.store.put_block(block).await
+    }
+
+    /// put a block whose parent is not in the storage, such as the block
+    /// of a ledger snapshot
+    pub async fn put_snapshot_block(&self, block: Block) -> Result<(), StorageError> {
+        let _lock = self.write_lock.lock().await;
+        self.store.put_snapshot_block(block).await
+    }
+
+    /// remove the contents of the given blocks from the storage, their
+    /// headers are kept
+    pub async fn prune_block_contents(&self, headers: Vec<Header>) -> Result<(), StorageError> {
+        let _lock = self.write_lock.lock().await;
+        self.store.prune_block_contents(headers).await
+    }
+
+    pub async fn is_ancestor(
+        &self,
+        ancestor: HeaderHash,
+        descendant: HeaderHash,
+    ) -> Result<Option<u64>, StorageError> {
+        self.store.is_ancestor(ancestor, descendant).await
+    }
+
+    /// the records whose key starts with the given prefix, ordered by key
+    pub async fn records(&self, prefix: String) -> Result<Vec<(String, Vec<u8>)>, StorageError> {
+        self.store.records(prefix).await
+    }
+
+    pub async fn put_record(&self, key: String, value: Vec<u8>) -> Result<(), StorageError> {
+        let _lock = self.write_lock.lock().await;
+        self.store.put_record(key, value).await
+    }
+
+    pub async fn stream_from_to(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> Result<BlockStream, StorageError> {
+        self.store.stream_from_to(from, to).await
+    }
+
+    /// Stream a branch ending at `to` and starting from the ancestor
+    /// at `depth` or at the first ancestor since genesis block
+    /// if `depth` is given as `None`.
+    ///
+    /// This function uses buffering in the sink to reduce lock contention.
+    pub async fn send_branch<S, E>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        sink: Pin<Box<S>>,
+    ) -> Result<(), S::Error>
+    where
+        S: Sink<Result<Block, E>>,
+        E: From<StorageError>,
+    {
+        let mut sink = sink;
+
+        match self.store.stream_branch(to, depth).await {
+            Ok(mut blocks) => {
+                while let Some(item) = blocks.next().await {
+                    sink.send(item.map_err(Into::into)).await?;
+                }
+                sink.close().await?;
+            }
+            Err(e) => {
+                sink.send_all(&mut stream::once(Box::pin(async { Ok(Err(e.into())) })))
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn find_closest_ancestor(
+        &self,
+        checkpoints: Vec<HeaderHash>,
+        descendant: HeaderHash,
+    ) -> Result<Option<Ancestor>, StorageError> {
+        self.store
+            .find_closest_ancestor(checkpoints, descendant)
+            .await
+    }
+}
+
+impl Storage {
+    pub fn new<S: BlockStore + 'static>(store: S) -> Self {
+        Self {
+            inner: Storage03::new(store),
+        }
+    }
+
+    pub fn get_tag(
+        &self,
+        tag: String,
+    ) -> impl Future01<Item = Option<HeaderHash>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.get_tag(tag).await }))
+    }
+
+    pub fn put_tag(
+        &self,
+        tag: String,
+        header_hash: HeaderHash,
+    ) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.put_tag(tag, header_hash).await },
+        ))
+    }
+
+    pub fn get(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future01<Item = Option<Block>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.get(header_hash).await }))
+    }
+
+    pub fn get_header(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future01<Item = Option<Header>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.get_header(header_hash).await }))
+    }
+
+    pub fn block_exists(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future01<Item = bool, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.block_exists(header_hash).await },
+        ))
+    }
+
+    pub fn get_parent(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future01<Item = HeaderHash, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.get_parent(header_hash).await }))
+    }
+
+    pub fn put_block(&self, block: Block) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.put_block(block).await }))
+    }
+
+    pub fn put_snapshot_block(
+        &self,
+        block: Block,
+    ) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(
+            async move { inner.put_snapshot_block(block).await },
+        ))
+    }
+
+    pub fn prune_block_contents(
+        &self,
+        headers: Vec<Header>,
+    ) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner.prune_block_contents(headers).await
+        }))
+    }
+
+    pub fn is_ancestor(
+        &self,
+        ancestor: HeaderHash,
+        descendant: HeaderHash,
+    ) -> impl Future01<Item = Option<u64>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner.is_ancestor(ancestor, descendant).await
+        }))
+    }
+
+    pub fn records(
+        &self,
+        prefix: String,
+    ) -> impl Future01<Item = Vec<(String, Vec<u8>)>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.records(prefix).await }))
+    }
+
+    pub fn put_record(
+        &self,
+        key: String,
+        value: Vec<u8>,
+    ) -> impl Future01<Item = (), Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move { inner.put_record(key, value).await }))
+    }
+
+    pub fn stream_from_to(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> impl Future01<Item = impl Stream01<Item = Block, Error = StorageError>, Error = StorageError>
+    {
+        let inner = self.inner.clone();
+        let fut = async move {
+            inner
+                .stream_from_to(from, to)
+                .map_ok(|stream| Compat::new(Box::pin(stream)))
+                .await
+        };
+        let res = Compat::new(Box::pin(fut));
+        res
+    }
+
+    pub fn send_branch<S, E>(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+        sink: S,
+    ) -> impl Future01<Item = (), Error = S::SinkError>
+    where
+        S: Sink01<SinkItem = Result<Block, E>>,
+        E: From<StorageError>,
+    {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner
+                .send_branch(to, depth, Box::pin(sink.sink_compat()))
+                .await
+        }))
+    }
+
+    pub fn find_closest_ancestor(
+        &self,
+        checkpoints: Vec<HeaderHash>,
+        descendant: HeaderHash,
+    ) -> impl Future01<Item = Option<Ancestor>, Error = StorageError> {
+        let inner = self.inner.clone();
+        Compat::new(Box::pin(async move {
+            inner.find_closest_ancestor(checkpoints, descendant).await
+        }))
+    }
+}
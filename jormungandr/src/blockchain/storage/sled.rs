@@ -0,0 +1,650 @@
+//! the block store backed by an embedded sled key-value store.
+//!
+//! The store is made of the following trees, all keyed by the hash of the
+//! block but the tags:
+//!
+//! * `blocks`: the contents of the blocks that have not been pruned;
+//! * `links`: the depth of the block, the link to its parent and a link to
+//!   a distant ancestor, so the ancestry queries do not go through every
+//!   block of the branch;
+//! * `pruned_headers`: the headers of the blocks whose contents have been
+//!   pruned;
+//! * `tags`: the tags, keyed by their name;
+//! * `records`: the records of the node, keyed by their key.
+//!
+//! The link of a block is written after its contents: a block is only
+//! known to the store once it is complete.
+
+use super::{backend_error, run_blocking_storage, BlockStore, BlockStream, StorageError};
+use crate::{
+    blockcfg::{Block, ChainLength, Header, HeaderHash},
+    metrics,
+};
+use async_trait::async_trait;
+use chain_core::property::{Deserialize as _, Serialize as _};
+use futures03::stream::{self, StreamExt as _};
+use sled::{Db, Tree};
+use std::{
+    io::{self, Read as _},
+    path::Path,
+};
+
+const BLOCKS_TREE: &str = "blocks";
+const LINKS_TREE: &str = "links";
+const PRUNED_HEADERS_TREE: &str = "pruned_headers";
+const TAGS_TREE: &str = "tags";
+const RECORDS_TREE: &str = "records";
+
+#[derive(Clone)]
+pub struct SledStore {
+    db: Db,
+    blocks: Tree,
+    links: Tree,
+    pruned_headers: Tree,
+    tags: Tree,
+    records: Tree,
+}
+
+/// the position of a block in the stored branches
+#[derive(Clone)]
+struct Link {
+    hash: HeaderHash,
+    /// the depth of the block, the root of the stored blocks is at depth 1
+    depth: u64,
+    parent: HeaderHash,
+    /// an ancestor whose depth is the depth of the block with its lowest
+    /// set bit cleared, or the root
+    far_depth: u64,
+    far: HeaderHash,
+}
+
+fn encode_hash(hash: &HeaderHash) -> Result<Vec<u8>, StorageError> {
+    hash.serialize_as_vec().map_err(backend_error)
+}
+
+fn decode_hash<R: io::BufRead>(reader: R) -> Result<HeaderHash, StorageError> {
+    HeaderHash::deserialize(reader).map_err(backend_error)
+}
+
+fn read_u64(reader: &mut &[u8]) -> Result<u64, StorageError> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes).map_err(backend_error)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+impl Link {
+    fn encode(&self) -> Result<Vec<u8>, StorageError> {
+        let mut bytes = encode_hash(&self.parent)?;
+        bytes.extend_from_slice(&self.depth.to_be_bytes());
+        bytes.extend_from_slice(&self.far_depth.to_be_bytes());
+        bytes.extend(encode_hash(&self.far)?);
+        Ok(bytes)
+    }
+
+    fn decode(hash: HeaderHash, mut bytes: &[u8]) -> Result<Self, StorageError> {
+        let parent = decode_hash(&mut bytes)?;
+        let depth = read_u64(&mut bytes)?;
+        let far_depth = read_u64(&mut bytes)?;
+        let far = decode_hash(&mut bytes)?;
+        Ok(Link {
+            hash,
+            depth,
+            parent,
+            far_depth,
+            far,
+        })
+    }
+
+    /// the next block on the way to the ancestor at the given depth
+    fn towards(&self, depth: u64) -> HeaderHash {
+        if self.far_depth >= depth && self.far_depth < self.depth {
+            self.far
+        } else {
+            self.parent
+        }
+    }
+}
+
+/// the state of the iteration over the blocks of a branch, the stack holds
+/// the links met on the way down from the end of the branch
+struct BranchIter {
+    store: SledStore,
+    to_depth: u64,
+    cur_depth: u64,
+    pending_links: Vec<Link>,
+}
+
+impl SledStore {
+    /// open the store in the given directory, it is created if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        let db = sled::Config::new()
+            .path(path)
+            .open()
+            .map_err(backend_error)?;
+        SledStore::with_db(db)
+    }
+
+    /// a store which is removed once dropped
+    pub fn temporary() -> Result<Self, StorageError> {
+        let db = sled::Config::new()
+            .temporary(true)
+            .open()
+            .map_err(backend_error)?;
+        SledStore::with_db(db)
+    }
+
+    /// no block, tag or record is stored
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty() && self.tags.is_empty() && self.records.is_empty()
+    }
+
+    fn with_db(db: Db) -> Result<Self, StorageError> {
+        let open_tree = |name| db.open_tree(name).map_err(backend_error);
+        Ok(SledStore {
+            blocks: open_tree(BLOCKS_TREE)?,
+            links: open_tree(LINKS_TREE)?,
+            pruned_headers: open_tree(PRUNED_HEADERS_TREE)?,
+            tags: open_tree(TAGS_TREE)?,
+            records: open_tree(RECORDS_TREE)?,
+            db,
+        })
+    }
+
+    /// run the operation on a blocking thread, the time to run it is
+    /// recorded in the storage metrics under the given query name
+    async fn run<F, R>(&self, query: &'static str, f: F) -> Result<R, StorageError>
+    where
+        F: FnOnce(&SledStore) -> Result<R, StorageError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let _timer = metrics::STORAGE_QUERY_SECONDS
+            .with_label_values(&[query])
+            .start_timer();
+        let store = self.clone();
+        run_blocking_storage(move || f(&store)).await
+    }
+
+    fn get_link(&self, hash: &HeaderHash) -> Result<Option<Link>, StorageError> {
+        match self.links.get(encode_hash(hash)?).map_err(backend_error)? {
+            Some(bytes) => Link::decode(*hash, bytes.as_ref()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn link(&self, hash: &HeaderHash) -> Result<Link, StorageError> {
+        self.get_link(hash)?.ok_or(StorageError::BlockNotFound)
+    }
+
+    fn get_block(&self, hash: &HeaderHash) -> Result<Option<Block>, StorageError> {
+        match self.blocks.get(encode_hash(hash)?).map_err(backend_error)? {
+            Some(bytes) => Block::deserialize(bytes.as_ref())
+                .map(Some)
+                .map_err(backend_error),
+            None => Ok(None),
+        }
+    }
+
+    /// the ancestor of the block at the given depth, the links met on the
+    /// way are given to `on_link`
+    fn ancestor_at<F>(
+        &self,
+        mut link: Link,
+        depth: u64,
+        mut on_link: F,
+    ) -> Result<Link, StorageError>
+    where
+        F: FnMut(&Link),
+    {
+        while link.depth > depth {
+            on_link(&link);
+            link = self.link(&link.towards(depth))?;
+        }
+        Ok(link)
+    }
+
+    fn is_ancestor_sync(
+        &self,
+        ancestor: &HeaderHash,
+        descendant: &HeaderHash,
+    ) -> Result<Option<u64>, StorageError> {
+        let ancestor = self.link(ancestor)?;
+        let descendant = self.link(descendant)?;
+        if ancestor.depth > descendant.depth {
+            return Ok(None);
+        }
+        let distance = descendant.depth - ancestor.depth;
+        let found = self.ancestor_at(descendant, ancestor.depth, |_| ())?;
+        if found.hash == ancestor.hash {
+            Ok(Some(distance))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn put(&self, block: &Block, link: Link) -> Result<(), StorageError> {
+        let hash = encode_hash(&link.hash)?;
+        let bytes = block.serialize_as_vec().map_err(backend_error)?;
+        self.blocks.insert(&hash, bytes).map_err(backend_error)?;
+        self.links
+            .insert(&hash, link.encode()?)
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn root_link(block: &Block) -> Link {
+        Link {
+            hash: block.header.hash(),
+            depth: 1,
+            parent: block.header.block_parent_hash(),
+            far_depth: 0,
+            far: block.header.block_parent_hash(),
+        }
+    }
+
+    fn branch_iter(&self, to: Link, distance: u64) -> BranchIter {
+        BranchIter {
+            store: self.clone(),
+            to_depth: to.depth,
+            cur_depth: to.depth - distance,
+            pending_links: vec![to],
+        }
+    }
+}
+
+impl BranchIter {
+    fn has_next(&self) -> bool {
+        self.cur_depth < self.to_depth
+    }
+
+    fn get_next(&mut self) -> Result<Block, StorageError> {
+        assert!(self.has_next());
+
+        self.cur_depth += 1;
+
+        // search back from the closest block we have seen on the way down
+        let link = self.pending_links.pop().unwrap();
+        let pending_links = &mut self.pending_links;
+        let link = self.store.ancestor_at(link, self.cur_depth, |link| {
+            pending_links.push(link.clone())
+        })?;
+        self.store
+            .get_block(&link.hash)?
+            .ok_or(StorageError::BlockNotFound)
+    }
+
+    fn into_stream(self) -> BlockStream {
+        stream::unfold(self, |mut iter| async move {
+            if !iter.has_next() {
+                return None;
+            }
+            let _timer = metrics::STORAGE_QUERY_SECONDS
+                .with_label_values(&["iterate_blocks"])
+                .start_timer();
+            let (res, iter) = run_blocking_storage(move || {
+                let res = iter.get_next();
+                Ok((res, iter))
+            })
+            .await
+            .unwrap();
+            Some((res, iter))
+        })
+        .boxed()
+    }
+}
+
+#[async_trait]
+impl BlockStore for SledStore {
+    async fn get_tag(&self, tag: String) -> Result<Option<HeaderHash>, StorageError> {
+        self.run("get_tag", move |store| {
+            match store.tags.get(tag.as_bytes()).map_err(backend_error)? {
+                Some(bytes) => decode_hash(bytes.as_ref()).map(Some),
+                None => Ok(None),
+            }
+        })
+        .await
+    }
+
+    async fn put_tag(&self, tag: String, header_hash: HeaderHash) -> Result<(), StorageError> {
+        self.run("put_tag", move |store| {
+            store
+                .tags
+                .insert(tag.as_bytes(), encode_hash(&header_hash)?)
+                .map_err(backend_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get(&self, header_hash: HeaderHash) -> Result<Option<Block>, StorageError> {
+        self.run("get_block", move |store| store.get_block(&header_hash))
+            .await
+    }
+
+    async fn get_pruned_header(
+        &self,
+        header_hash: HeaderHash,
+    ) -> Result<Option<Header>, StorageError> {
+        self.run("get_pruned_header", move |store| {
+            match store
+                .pruned_headers
+                .get(encode_hash(&header_hash)?)
+                .map_err(backend_error)?
+            {
+                Some(bytes) => Header::deserialize(bytes.as_ref())
+                    .map(Some)
+                    .map_err(backend_error),
+                None => Ok(None),
+            }
+        })
+        .await
+    }
+
+    async fn block_exists(&self, header_hash: HeaderHash) -> Result<bool, StorageError> {
+        self.run("block_exists", move |store| {
+            store
+                .links
+                .contains_key(encode_hash(&header_hash)?)
+                .map_err(backend_error)
+        })
+        .await
+    }
+
+    async fn get_parent(&self, header_hash: HeaderHash) -> Result<HeaderHash, StorageError> {
+        self.run("get_parent", move |store| {
+            store.link(&header_hash).map(|link| link.parent)
+        })
+        .await
+    }
+
+    async fn put_block(&self, block: Block) -> Result<(), StorageError> {
+        self.run("put_block", move |store| {
+            if block.header.chain_length() == ChainLength::from(0) {
+                return store.put(&block, SledStore::root_link(&block));
+            }
+            let parent = store.link(&block.header.block_parent_hash())?;
+            let depth = parent.depth + 1;
+            let far_depth = (depth & (depth - 1)).max(1);
+            let far = store.ancestor_at(parent.clone(), far_depth, |_| ())?;
+            let link = Link {
+                hash: block.header.hash(),
+                depth,
+                parent: parent.hash,
+                far_depth,
+                far: far.hash,
+            };
+            store.put(&block, link)
+        })
+        .await
+    }
+
+    async fn put_snapshot_block(&self, block: Block) -> Result<(), StorageError> {
+        self.run("put_snapshot_block", move |store| {
+            store.put(&block, SledStore::root_link(&block))
+        })
+        .await
+    }
+
+    async fn prune_block_contents(&self, headers: Vec<Header>) -> Result<(), StorageError> {
+        self.run("prune_block_contents", move |store| {
+            for header in headers {
+                let hash = encode_hash(&header.hash())?;
+                let bytes = header.serialize_as_vec().map_err(backend_error)?;
+                store
+                    .pruned_headers
+                    .insert(&hash, bytes)
+                    .map_err(backend_error)?;
+                store.blocks.remove(&hash).map_err(backend_error)?;
+            }
+            store.db.flush().map_err(backend_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn is_ancestor(
+        &self,
+        ancestor: HeaderHash,
+        descendant: HeaderHash,
+    ) -> Result<Option<u64>, StorageError> {
+        self.run("is_ancestor", move |store| {
+            store.is_ancestor_sync(&ancestor, &descendant)
+        })
+        .await
+    }
+
+    async fn records(&self, prefix: String) -> Result<Vec<(String, Vec<u8>)>, StorageError> {
+        self.run("records", move |store| {
+            store
+                .records
+                .scan_prefix(prefix.as_bytes())
+                .map(|record| {
+                    let (key, value) = record.map_err(backend_error)?;
+                    let key = String::from_utf8(key.to_vec()).map_err(backend_error)?;
+                    Ok((key, value.to_vec()))
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn put_record(&self, key: String, value: Vec<u8>) -> Result<(), StorageError> {
+        self.run("put_record", move |store| {
+            store
+                .records
+                .insert(key.as_bytes(), value)
+                .map_err(backend_error)?;
+            store.db.flush().map_err(backend_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn stream_from_to(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> Result<BlockStream, StorageError> {
+        let iter = self
+            .run("stream_from_to", move |store| {
+                match store.is_ancestor_sync(&from, &to)? {
+                    Some(distance) => Ok(store.branch_iter(store.link(&to)?, distance)),
+                    None => Err(StorageError::CannotIterate),
+                }
+            })
+            .await?;
+        Ok(iter.into_stream())
+    }
+
+    async fn stream_branch(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+    ) -> Result<BlockStream, StorageError> {
+        let iter = self
+            .run("send_branch", move |store| {
+                let to = store.link(&to)?;
+                let depth = depth.unwrap_or(to.depth - 1);
+                Ok(store.branch_iter(to, depth))
+            })
+            .await?;
+        Ok(iter.into_stream())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{BlockDate, BlockVersion, Contents, ContentsBuilder, HeaderBuilderNew};
+
+    fn block0() -> Block {
+        let contents: Contents = ContentsBuilder::new().into();
+        let header = HeaderBuilderNew::new(BlockVersion::Genesis, &contents)
+            .set_genesis()
+            .set_date(BlockDate::first())
+            .to_unsigned_header()
+            .unwrap()
+            .generalize();
+        Block { header, contents }
+    }
+
+    /// the blocks of a fork are told apart by the first slot of their dates
+    fn branch(parent: &Header, length: usize, first_slot: u32) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::with_capacity(length);
+        for i in 0..length {
+            let parent = blocks.last().map_or(parent, |block| &block.header);
+            let chain_length = u32::from(parent.chain_length()) + 1;
+            let contents: Contents = ContentsBuilder::new().into();
+            let header = HeaderBuilderNew::new(BlockVersion::Genesis, &contents)
+                .set_parent(&parent.hash(), ChainLength::from(chain_length))
+                .set_date(BlockDate {
+                    epoch: 0,
+                    slot_id: first_slot + i as u32,
+                })
+                .to_unsigned_header()
+                .unwrap()
+                .generalize();
+            blocks.push(Block { header, contents });
+        }
+        blocks
+    }
+
+    fn put_all(rt: &mut tokio02::runtime::Runtime, store: &SledStore, blocks: &[Block]) {
+        for block in blocks {
+            rt.block_on(store.put_block(block.clone())).unwrap();
+        }
+    }
+
+    #[test]
+    fn ancestors_of_a_branch() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        let store = SledStore::temporary().unwrap();
+        let block0 = block0();
+        let mut blocks = vec![block0.clone()];
+        blocks.extend(branch(&block0.header, 99, 1));
+        put_all(&mut rt, &store, &blocks);
+
+        for (i, ancestor) in blocks.iter().enumerate() {
+            for (j, descendant) in blocks.iter().enumerate() {
+                let found = store
+                    .is_ancestor_sync(&ancestor.header.hash(), &descendant.header.hash())
+                    .unwrap();
+                if i <= j {
+                    assert_eq!(found, Some((j - i) as u64));
+                } else {
+                    assert_eq!(found, None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn distant_ancestor_is_reached_through_the_far_links() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        let store = SledStore::temporary().unwrap();
+        let block0 = block0();
+        let mut blocks = vec![block0.clone()];
+        blocks.extend(branch(&block0.header, 99, 1));
+        put_all(&mut rt, &store, &blocks);
+
+        let tip = store.link(&blocks[99].header.hash()).unwrap();
+        assert_eq!(tip.depth, 100);
+        for depth in 1..=100 {
+            let mut visited = 0;
+            let found = store
+                .ancestor_at(tip.clone(), depth, |_| visited += 1)
+                .unwrap();
+            assert_eq!(found.hash, blocks[depth as usize - 1].header.hash());
+            assert_eq!(found.depth, depth);
+            assert!(visited <= 100 - depth);
+        }
+        // 100 -> 96 -> 64 -> 1
+        let mut visited = 0;
+        store.ancestor_at(tip, 1, |_| visited += 1).unwrap();
+        assert_eq!(visited, 3);
+    }
+
+    #[test]
+    fn ancestors_across_forks() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        let store = SledStore::temporary().unwrap();
+        let block0 = block0();
+        let mut main = vec![block0.clone()];
+        main.extend(branch(&block0.header, 20, 1));
+        // the fork leaves the main branch after the block 5
+        let fork = branch(&main[5].header, 30, 100);
+        put_all(&mut rt, &store, &main);
+        put_all(&mut rt, &store, &fork);
+
+        let hash = |block: &Block| block.header.hash();
+        let is_ancestor = |ancestor: &Block, descendant: &Block| {
+            store
+                .is_ancestor_sync(&hash(ancestor), &hash(descendant))
+                .unwrap()
+        };
+        assert_eq!(is_ancestor(&main[0], &fork[29]), Some(35));
+        assert_eq!(is_ancestor(&main[5], &fork[0]), Some(1));
+        assert_eq!(is_ancestor(&main[3], &fork[10]), Some(13));
+        for block in &main[6..] {
+            for fork_block in &fork {
+                assert_eq!(is_ancestor(block, fork_block), None);
+                assert_eq!(is_ancestor(fork_block, block), None);
+            }
+        }
+        // a fork block deeper than the main block
+        assert_eq!(is_ancestor(&fork[0], &main[20]), None);
+
+        let unknown = branch(&main[20].header, 1, 1000).remove(0);
+        match store.is_ancestor_sync(&hash(&unknown), &hash(&main[20])) {
+            Err(StorageError::BlockNotFound) => (),
+            _ => panic!("the block is not stored"),
+        }
+    }
+
+    #[test]
+    fn new_store_is_empty() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        let store = SledStore::temporary().unwrap();
+        assert!(store.is_empty());
+        rt.block_on(store.put_record("key".to_owned(), b"value".to_vec()))
+            .unwrap();
+        assert!(!store.is_empty());
+
+        let store = SledStore::temporary().unwrap();
+        rt.block_on(store.put_block(block0())).unwrap();
+        assert!(!store.is_empty());
+    }
+
+    #[test]
+    fn snapshot_block_is_a_root() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        let store = SledStore::temporary().unwrap();
+        let block0 = block0();
+        let blocks = branch(&block0.header, 40, 1);
+        // the blocks before the snapshot block are not stored
+        rt.block_on(store.put_snapshot_block(blocks[10].clone()))
+            .unwrap();
+        put_all(&mut rt, &store, &blocks[11..]);
+
+        let root = store.link(&blocks[10].header.hash()).unwrap();
+        assert_eq!(root.depth, 1);
+        let tip = store.link(&blocks[39].header.hash()).unwrap();
+        assert_eq!(tip.depth, 30);
+        let found = store.ancestor_at(tip, 1, |_| ()).unwrap();
+        assert_eq!(found.hash, root.hash);
+        assert_eq!(
+            store
+                .is_ancestor_sync(&blocks[10].header.hash(), &blocks[39].header.hash())
+                .unwrap(),
+            Some(29)
+        );
+        assert_eq!(
+            rt.block_on(store.get_parent(blocks[11].header.hash()))
+                .unwrap(),
+            blocks[10].header.hash()
+        );
+        match store.is_ancestor_sync(&blocks[9].header.hash(), &blocks[39].header.hash()) {
+            Err(StorageError::BlockNotFound) => (),
+            _ => panic!("the blocks before the snapshot block are not stored"),
+        }
+    }
+}
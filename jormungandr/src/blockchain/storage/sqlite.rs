@@ -0,0 +1,449 @@
+//! the block store backed by the SQLite file of `chain-storage-sqlite-old`.
+
+use super::{backend_error, run_blocking_storage, Ancestor, BlockStore, BlockStream, StorageError};
+use crate::{
+    blockcfg::{Block, Header, HeaderHash},
+    metrics,
+    start_up::{NodeStorage, NodeStorageConnection},
+};
+use async_trait::async_trait;
+use bb8::{ManageConnection, Pool, RunError};
+use chain_storage::store::{for_path_to_nth_ancestor, BlockInfo, BlockStore as _};
+use futures03::stream::{self, StreamExt as _};
+use rusqlite::types::ToSql;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio02::task::spawn_blocking;
+use tokio_compat::runtime;
+
+/// time to wait for the connections of the block store to release the lock
+/// of the SQLite file
+const SQLITE_BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// the block store has no table for the records of the node, they are kept
+/// in a table of their own in the same SQLite file
+const CREATE_RECORDS: &str =
+    "create table if not exists NodeRecords (key text primary key, value blob not null)";
+
+/// open a connection to the SQLite file of the block store, for the
+/// operations the block store does not provide
+fn open_sqlite(path: &Path) -> Result<rusqlite::Connection, StorageError> {
+    let connection = rusqlite::Connection::open(path).map_err(backend_error)?;
+    connection
+        .busy_timeout(SQLITE_BUSY_TIMEOUT)
+        .map_err(backend_error)?;
+    connection
+        .execute(CREATE_RECORDS, rusqlite::NO_PARAMS)
+        .map_err(backend_error)?;
+    Ok(connection)
+}
+
+/// run the query on a connection of the pool, the time to run the query is
+/// recorded in the storage metrics under the given query name.
+async fn run_blocking_with_connection<F, R>(
+    pool: &Pool<ConnectionManager>,
+    query: &'static str,
+    f: F,
+) -> Result<R, StorageError>
+where
+    F: FnOnce(&mut NodeStorageConnection) -> Result<R, StorageError> + Send + 'static,
+    R: Send + 'static,
+{
+    let _timer = metrics::STORAGE_QUERY_SECONDS
+        .with_label_values(&[query])
+        .start_timer();
+    pool.run(|mut connection| async move {
+        spawn_blocking(move || match f(&mut connection) {
+            Ok(r) => Ok((r, connection)),
+            Err(r) => Err((r, connection)),
+        })
+        .await
+        .unwrap()
+    })
+    .await
+    .map_err(|e| match e {
+        RunError::User(e) => e,
+        e => StorageError::BackendError(Box::new(e)),
+    })
+}
+
+#[derive(Clone)]
+struct ConnectionManager {
+    inner: Arc<NodeStorage>,
+}
+
+impl ConnectionManager {
+    pub fn new(storage: NodeStorage) -> Self {
+        Self {
+            inner: Arc::new(storage),
+        }
+    }
+}
+
+#[async_trait]
+impl ManageConnection for ConnectionManager {
+    type Connection = NodeStorageConnection;
+    type Error = StorageError;
+
+    async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let inner = self.inner.clone();
+        run_blocking_storage(move || inner.connect()).await
+    }
+
+    async fn is_valid(&self, conn: Self::Connection) -> Result<Self::Connection, Self::Error> {
+        run_blocking_storage(move || conn.ping().and(Ok(conn))).await
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.ping().is_ok()
+    }
+}
+
+pub struct SqliteStore {
+    pool: Pool<ConnectionManager>,
+
+    /// the SQLite file of the block store, if the blocks are not stored in
+    /// memory
+    sqlite_file: Option<Arc<PathBuf>>,
+
+    /// the records of the node when the blocks are stored in memory
+    memory_records: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+}
+
+struct BlockIterState {
+    to_depth: u64,
+    cur_depth: u64,
+    pending_infos: Vec<BlockInfo<HeaderHash>>,
+}
+
+impl SqliteStore {
+    pub fn new(storage: NodeStorage, sqlite_file: Option<PathBuf>) -> Self {
+        let mut rt = runtime::Builder::new()
+            .name_prefix("new-storage-worker-")
+            .core_threads(1)
+            .build()
+            .unwrap();
+
+        rt.block_on_std(async move {
+            let manager = ConnectionManager::new(storage);
+            let pool = Pool::builder().build(manager).await.unwrap();
+            let sqlite_file = sqlite_file.map(Arc::new);
+
+            SqliteStore {
+                pool,
+                sqlite_file,
+                memory_records: Arc::new(Mutex::new(BTreeMap::new())),
+            }
+        })
+    }
+
+    async fn run<F, R>(&self, query: &'static str, f: F) -> Result<R, StorageError>
+    where
+        F: FnOnce(&mut NodeStorageConnection) -> Result<R, StorageError> + Send + 'static,
+        R: Send + 'static,
+    {
+        run_blocking_with_connection(&self.pool, query, f).await
+    }
+
+    fn stream(&self, init_state: BlockIterState) -> BlockStream {
+        let pool = self.pool.clone();
+
+        stream::unfold((init_state, pool), |(mut state, pool)| async move {
+            if !state.has_next() {
+                return None;
+            }
+            let res = state.get_next(pool.clone()).await;
+            Some((res, (state, pool)))
+        })
+        .boxed()
+    }
+}
+
+#[async_trait]
+impl BlockStore for SqliteStore {
+    async fn get_tag(&self, tag: String) -> Result<Option<HeaderHash>, StorageError> {
+        self.run("get_tag", move |connection| connection.get_tag(&tag))
+            .await
+    }
+
+    async fn put_tag(&self, tag: String, header_hash: HeaderHash) -> Result<(), StorageError> {
+        self.run("put_tag", move |connection| {
+            connection.put_tag(&tag, &header_hash)
+        })
+        .await
+    }
+
+    async fn get(&self, header_hash: HeaderHash) -> Result<Option<Block>, StorageError> {
+        self.run("get_block", move |connection| {
+            match connection.get_block(&header_hash) {
+                Err(StorageError::BlockNotFound) => Ok(None),
+                Ok((block, _block_info)) => Ok(Some(block)),
+                Err(e) => Err(e),
+            }
+        })
+        .await
+    }
+
+    async fn get_pruned_header(
+        &self,
+        _header_hash: HeaderHash,
+    ) -> Result<Option<Header>, StorageError> {
+        // the contents of the blocks are never pruned from this store
+        Ok(None)
+    }
+
+    async fn block_exists(&self, header_hash: HeaderHash) -> Result<bool, StorageError> {
+        self.run("block_exists", move |connection| {
+            match connection.block_exists(&header_hash) {
+                Err(StorageError::BlockNotFound) => Ok(false),
+                Ok(r) => Ok(r),
+                Err(e) => Err(e),
+            }
+        })
+        .await
+    }
+
+    async fn get_parent(&self, header_hash: HeaderHash) -> Result<HeaderHash, StorageError> {
+        self.run("get_parent", move |connection| {
+            connection
+                .get_block_info(&header_hash)
+                .map(|block_info| block_info.parent_id())
+        })
+        .await
+    }
+
+    async fn put_block(&self, block: Block) -> Result<(), StorageError> {
+        self.run("put_block", move |connection| {
+            match connection.put_block(&block) {
+                Err(StorageError::BlockNotFound) => unreachable!(),
+                Err(e) => Err(e),
+                Ok(()) => Ok(()),
+            }
+        })
+        .await
+    }
+
+    async fn put_snapshot_block(&self, block: Block) -> Result<(), StorageError> {
+        self.run("put_snapshot_block", move |connection| {
+            let block_info =
+                BlockInfo::new(block.header.hash(), block.header.block_parent_hash(), 1);
+            connection.put_block_internal(&block, block_info)
+        })
+        .await
+    }
+
+    async fn prune_block_contents(&self, _headers: Vec<Header>) -> Result<(), StorageError> {
+        // the block store has no operation to remove the contents of a block
+        Err(StorageError::BackendError(
+            "the SQLite block store cannot be pruned, use the sled backend".into(),
+        ))
+    }
+
+    async fn is_ancestor(
+        &self,
+        ancestor: HeaderHash,
+        descendant: HeaderHash,
+    ) -> Result<Option<u64>, StorageError> {
+        self.run("is_ancestor", move |connection| {
+            connection.is_ancestor(&ancestor, &descendant)
+        })
+        .await
+    }
+
+    async fn records(&self, prefix: String) -> Result<Vec<(String, Vec<u8>)>, StorageError> {
+        let sqlite_file = match &self.sqlite_file {
+            Some(sqlite_file) => Arc::clone(sqlite_file),
+            None => {
+                let records = self.memory_records.lock().unwrap();
+                return Ok(records
+                    .range(prefix.clone()..)
+                    .take_while(|(key, _)| key.starts_with(&prefix))
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect());
+            }
+        };
+        let _timer = metrics::STORAGE_QUERY_SECONDS
+            .with_label_values(&["records"])
+            .start_timer();
+        run_blocking_storage(move || {
+            let connection = open_sqlite(&sqlite_file)?;
+            let mut select = connection
+                .prepare(
+                    "select key, value from NodeRecords \
+                     where substr(key, 1, length(?1)) = ?1 order by key",
+                )
+                .map_err(backend_error)?;
+            let records = select
+                .query_map(&[&prefix as &dyn ToSql], |row| (row.get(0), row.get(1)))
+                .map_err(backend_error)?
+                .collect::<Result<Vec<(String, Vec<u8>)>, _>>()
+                .map_err(backend_error)?;
+            Ok(records)
+        })
+        .await
+    }
+
+    async fn put_record(&self, key: String, value: Vec<u8>) -> Result<(), StorageError> {
+        let sqlite_file = match &self.sqlite_file {
+            Some(sqlite_file) => Arc::clone(sqlite_file),
+            None => {
+                self.memory_records.lock().unwrap().insert(key, value);
+                return Ok(());
+            }
+        };
+        let _timer = metrics::STORAGE_QUERY_SECONDS
+            .with_label_values(&["put_record"])
+            .start_timer();
+        run_blocking_storage(move || {
+            let connection = open_sqlite(&sqlite_file)?;
+            connection
+                .execute(
+                    "insert or replace into NodeRecords (key, value) values (?1, ?2)",
+                    &[&key as &dyn ToSql, &value],
+                )
+                .map_err(backend_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn stream_from_to(
+        &self,
+        from: HeaderHash,
+        to: HeaderHash,
+    ) -> Result<BlockStream, StorageError> {
+        let init_state = self
+            .run("stream_from_to", move |connection| {
+                match connection.is_ancestor(&from, &to) {
+                    Ok(Some(distance)) => match connection.get_block_info(&to) {
+                        Ok(to_info) => Ok(BlockIterState::new(to_info, distance)),
+                        Err(e) => Err(e),
+                    },
+                    Ok(None) => Err(StorageError::CannotIterate),
+                    Err(e) => Err(e),
+                }
+            })
+            .await?;
+
+        Ok(self.stream(init_state))
+    }
+
+    async fn stream_branch(
+        &self,
+        to: HeaderHash,
+        depth: Option<u64>,
+    ) -> Result<BlockStream, StorageError> {
+        let init_state = self
+            .run("send_branch", move |connection| {
+                connection.get_block_info(&to).map(|to_info| {
+                    let depth = depth.unwrap_or(to_info.depth - 1);
+                    BlockIterState::new(to_info, depth)
+                })
+            })
+            .await?;
+
+        Ok(self.stream(init_state))
+    }
+
+    async fn find_closest_ancestor(
+        &self,
+        checkpoints: Vec<HeaderHash>,
+        descendant: HeaderHash,
+    ) -> Result<Option<Ancestor>, StorageError> {
+        self.run("find_closest_ancestor", move |connection| {
+            let mut ancestor = None;
+            let mut closest_found = std::u64::MAX;
+            for checkpoint in checkpoints {
+                // Checkpoints sent by a peer may not
+                // be present locally, so we need to ignore certain errors
+                match connection.is_ancestor(&checkpoint, &descendant) {
+                    Ok(None) => {}
+                    Ok(Some(distance)) => {
+                        if closest_found > distance {
+                            ancestor = Some(checkpoint);
+                            closest_found = distance;
+                        }
+                    }
+                    Err(e) => {
+                        // Checkpoints sent by a peer may not
+                        // be present locally, so we need to ignore certain errors
+                        match e {
+                            StorageError::BlockNotFound => {
+                                // FIXME: add block hash into the error so we
+                                // can see which of the two it is.
+                                // For now, just ignore either.
+                            }
+                            _ => return Err(e),
+                        }
+                    }
+                }
+            }
+            Ok(ancestor.map(|header_hash| Ancestor {
+                header_hash,
+                distance: closest_found,
+            }))
+        })
+        .await
+    }
+}
+
+impl BlockIterState {
+    fn new(to_info: BlockInfo<HeaderHash>, distance: u64) -> Self {
+        BlockIterState {
+            to_depth: to_info.depth,
+            cur_depth: to_info.depth - distance,
+            pending_infos: vec![to_info],
+        }
+    }
+
+    fn has_next(&self) -> bool {
+        self.cur_depth < self.to_depth
+    }
+
+    async fn get_next(&mut self, pool: Pool<ConnectionManager>) -> Result<Block, StorageError> {
+        assert!(self.has_next());
+
+        self.cur_depth += 1;
+
+        let block_info = self.pending_infos.pop().unwrap();
+
+        let cur_depth = self.cur_depth;
+
+        let (mut pending_infos, block) =
+            run_blocking_with_connection(&pool, "iterate_blocks", move |store| {
+                if block_info.depth == cur_depth {
+                    // We've seen this block on a previous ancestor traversal.
+                    let (block, _block_info) = store.get_block(&block_info.block_hash)?;
+                    Ok((Vec::new(), block))
+                } else {
+                    // We don't have this block yet, so search back from
+                    // the furthest block that we do have.
+                    assert!(cur_depth < block_info.depth);
+                    let depth = block_info.depth;
+                    let parent = block_info.parent_id();
+                    let mut pending_infos = Vec::new();
+                    pending_infos.push(block_info);
+                    let block_info = for_path_to_nth_ancestor(
+                        &*store,
+                        &parent,
+                        depth - cur_depth - 1,
+                        |new_info| {
+                            pending_infos.push(new_info.clone());
+                        },
+                    )?;
+
+                    let (block, _block_info) = store.get_block(&block_info.block_hash)?;
+                    Ok((pending_infos, block))
+                }
+            })
+            .await?;
+
+        self.pending_infos.append(&mut pending_infos);
+
+        Ok(block)
+    }
+}
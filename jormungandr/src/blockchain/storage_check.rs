@@ -0,0 +1,425 @@
+//! offline check of the integrity of the main branch in the storage.
+//!
+//! The main branch is walked from the block of the `HEAD` tag back to its
+//! root: the block0, or the block of the `PRUNED` tag the node restarts from
+//! once the contents of the older blocks have been pruned. Every block is
+//! read from the storage and checked against its hash and against the header
+//! of its parent with `pre_verify_link`. Only the header of the root is read
+//! once the contents of the blocks up to it have been pruned. The walk goes
+//! on below a missing or damaged block through the parent link kept by the
+//! storage.
+//!
+//! If the node crashed while writing, the `HEAD` tag can point at a block
+//! whose ancestors are missing or damaged. The tag can then be rewound to the
+//! most recent block whose whole branch is valid.
+
+use crate::{
+    blockcfg::{Header, HeaderHash},
+    blockchain::{
+        chain::{pre_verify_link, HeaderChainVerifyError},
+        Storage, MAIN_BRANCH_TAG, PRUNED_TAG,
+    },
+};
+use chain_storage::error::Error as StorageError;
+use futures03::compat::Future01CompatExt as _;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum StorageIssue {
+    #[error("the `HEAD` tag is missing")]
+    NoTip,
+    #[error("the block {0} is missing")]
+    MissingBlock(HeaderHash),
+    #[error("the block {hash} cannot be read")]
+    UnreadableBlock {
+        hash: HeaderHash,
+        #[source]
+        source: StorageError,
+    },
+    #[error("the block stored as {hash} has the hash {found}")]
+    WrongHash { hash: HeaderHash, found: HeaderHash },
+    #[error("the contents of the block {0} do not match its header")]
+    CorruptContents(HeaderHash),
+    #[error("the block {0} is not linked to the root of the main branch")]
+    Unlinked(HeaderHash),
+    #[error("the block {hash} does not follow its parent")]
+    InvalidLink {
+        hash: HeaderHash,
+        #[source]
+        source: HeaderChainVerifyError,
+    },
+}
+
+/// outcome of the check of the main branch
+pub struct StorageCheck {
+    root: HeaderHash,
+    checked: u64,
+    issues: Vec<StorageIssue>,
+    /// the most recent block whose whole branch is valid, `None` when the
+    /// storage does not link the main branch to the root
+    last_valid: Option<HeaderHash>,
+}
+
+impl StorageCheck {
+    /// number of blocks read from the storage
+    pub fn checked(&self) -> u64 {
+        self.checked
+    }
+
+    /// the issues found, from the tip towards the root
+    pub fn issues(&self) -> &[StorageIssue] {
+        &self.issues
+    }
+
+    /// the block the `HEAD` tag is rewound to by `repair`. If the main
+    /// branch is not linked to the root, the tag is rewound to the root and
+    /// the following blocks are downloaded again from the network.
+    pub fn rewind_target(&self) -> HeaderHash {
+        self.last_valid.unwrap_or(self.root)
+    }
+
+    /// rewind the `HEAD` tag to the most recent block whose whole branch is
+    /// valid, nothing is done if no issue has been found
+    pub async fn repair(&self, storage: &Storage) -> Result<(), StorageError> {
+        if self.issues.is_empty() {
+            return Ok(());
+        }
+        storage
+            .put_tag(MAIN_BRANCH_TAG.to_owned(), self.rewind_target())
+            .compat()
+            .await
+    }
+}
+
+/// walk the main branch from the block of the `HEAD` tag back to its root
+pub async fn check_storage(
+    storage: &Storage,
+    block0_hash: HeaderHash,
+) -> Result<StorageCheck, StorageError> {
+    let root = storage
+        .get_tag(PRUNED_TAG.to_owned())
+        .compat()
+        .await?
+        .unwrap_or(block0_hash);
+    let mut check = StorageCheck {
+        root,
+        checked: 0,
+        issues: Vec::new(),
+        last_valid: None,
+    };
+    let head = match storage.get_tag(MAIN_BRANCH_TAG.to_owned()).compat().await? {
+        Some(head) => head,
+        None => {
+            check.issues.push(StorageIssue::NoTip);
+            return Ok(check);
+        }
+    };
+    match storage.is_ancestor(root, head).compat().await {
+        Ok(Some(_)) => (),
+        Ok(None) | Err(StorageError::BlockNotFound) => {
+            check.issues.push(StorageIssue::Unlinked(head));
+            return Ok(check);
+        }
+        Err(e) => return Err(e),
+    }
+
+    // the candidate is moved below every damaged block found on the way down
+    let mut candidate = head;
+    let mut hash = head;
+    let mut child: Option<Header> = None;
+    loop {
+        let header = match storage.get(hash).compat().await {
+            Ok(Some(block)) => {
+                check.checked += 1;
+                let found = block.header.hash();
+                if found != hash {
+                    check.issues.push(StorageIssue::WrongHash { hash, found });
+                    None
+                } else {
+                    let (content_hash, content_size) = block.contents.compute_hash_size();
+                    if content_hash != block.header.block_content_hash()
+                        || content_size != block.header.block_content_size()
+                    {
+                        check.issues.push(StorageIssue::CorruptContents(hash));
+                        // nothing below the root is part of the main branch
+                        candidate = if hash == root {
+                            root
+                        } else {
+                            block.header.block_parent_hash()
+                        };
+                    }
+                    Some(block.header)
+                }
+            }
+            // only the header of the root is kept once the contents of the
+            // blocks up to the root have been pruned
+            Ok(None) if hash == root => match storage.get_header(hash).compat().await? {
+                Some(header) => {
+                    check.checked += 1;
+                    let found = header.hash();
+                    if found != hash {
+                        check.issues.push(StorageIssue::WrongHash { hash, found });
+                        None
+                    } else {
+                        Some(header)
+                    }
+                }
+                None => {
+                    check.issues.push(StorageIssue::MissingBlock(hash));
+                    None
+                }
+            },
+            Ok(None) => {
+                check.issues.push(StorageIssue::MissingBlock(hash));
+                None
+            }
+            Err(source) => {
+                check
+                    .issues
+                    .push(StorageIssue::UnreadableBlock { hash, source });
+                None
+            }
+        };
+
+        let parent = match header {
+            Some(header) => {
+                if let Some(child) = &child {
+                    if let Err(source) = pre_verify_link(child, &header) {
+                        check.issues.push(StorageIssue::InvalidLink {
+                            hash: child.hash(),
+                            source,
+                        });
+                        candidate = hash;
+                    }
+                }
+                let parent = header.block_parent_hash();
+                child = Some(header);
+                parent
+            }
+            // the block cannot be trusted, the search goes on below it
+            // through the parent link kept by the storage
+            None => {
+                if hash == root {
+                    break;
+                }
+                child = None;
+                let parent = match storage.get_parent(hash).compat().await {
+                    Ok(parent) => parent,
+                    Err(StorageError::BlockNotFound) => {
+                        check.issues.push(StorageIssue::Unlinked(hash));
+                        return Ok(check);
+                    }
+                    Err(e) => return Err(e),
+                };
+                candidate = parent;
+                parent
+            }
+        };
+
+        if hash == root {
+            break;
+        }
+        hash = parent;
+    }
+
+    check.last_valid = Some(candidate);
+    Ok(check)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockcfg::{
+            Block, BlockDate, BlockVersion, ChainLength, ConfigParams, Contents, ContentsBuilder,
+            Fragment, HeaderBuilderNew,
+        },
+        blockchain::SledStore,
+    };
+
+    fn empty_contents() -> Contents {
+        ContentsBuilder::new().into()
+    }
+
+    fn block0() -> Block {
+        let contents = empty_contents();
+        let header = HeaderBuilderNew::new(BlockVersion::Genesis, &contents)
+            .set_genesis()
+            .set_date(BlockDate::first())
+            .to_unsigned_header()
+            .unwrap()
+            .generalize();
+        Block { header, contents }
+    }
+
+    fn child_header(parent: &Header, contents: &Contents) -> Header {
+        let chain_length = u32::from(parent.chain_length()) + 1;
+        HeaderBuilderNew::new(BlockVersion::Genesis, contents)
+            .set_parent(&parent.hash(), ChainLength::from(chain_length))
+            .set_date(BlockDate {
+                epoch: 0,
+                slot_id: chain_length,
+            })
+            .to_unsigned_header()
+            .unwrap()
+            .generalize()
+    }
+
+    fn child(parent: &Header) -> Block {
+        let contents = empty_contents();
+        let header = child_header(parent, &contents);
+        Block { header, contents }
+    }
+
+    /// store the block0 and `length` blocks after it, the `HEAD` tag is set
+    /// to the last block
+    async fn chain(storage: &Storage, length: usize) -> Vec<Block> {
+        let mut blocks = vec![block0()];
+        for _ in 0..length {
+            let block = child(&blocks.last().unwrap().header);
+            blocks.push(block);
+        }
+        for block in &blocks {
+            storage.put_block(block.clone()).compat().await.unwrap();
+        }
+        let head = blocks.last().unwrap().header.hash();
+        storage
+            .put_tag(MAIN_BRANCH_TAG.to_owned(), head)
+            .compat()
+            .await
+            .unwrap();
+        blocks
+    }
+
+    #[test]
+    fn valid_branch_has_no_issue() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            let blocks = chain(&storage, 4).await;
+            let check = check_storage(&storage, blocks[0].header.hash())
+                .await
+                .unwrap();
+            assert!(check.issues().is_empty());
+            assert_eq!(check.checked(), 5);
+            assert_eq!(check.rewind_target(), blocks[4].header.hash());
+        });
+    }
+
+    #[test]
+    fn missing_tip_is_reported() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            let block0 = block0();
+            storage.put_block(block0.clone()).compat().await.unwrap();
+            let check = check_storage(&storage, block0.header.hash()).await.unwrap();
+            match check.issues() {
+                [StorageIssue::NoTip] => (),
+                _ => panic!("the `HEAD` tag is missing"),
+            }
+        });
+    }
+
+    #[test]
+    fn pruned_root_is_accepted() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            let blocks = chain(&storage, 4).await;
+            let headers = blocks[..3]
+                .iter()
+                .map(|block| block.header.clone())
+                .collect();
+            storage
+                .prune_block_contents(headers)
+                .compat()
+                .await
+                .unwrap();
+            storage
+                .put_tag(PRUNED_TAG.to_owned(), blocks[2].header.hash())
+                .compat()
+                .await
+                .unwrap();
+
+            let check = check_storage(&storage, blocks[0].header.hash())
+                .await
+                .unwrap();
+            assert!(check.issues().is_empty());
+            assert_eq!(check.checked(), 3);
+            assert_eq!(check.rewind_target(), blocks[4].header.hash());
+        });
+    }
+
+    #[test]
+    fn walk_goes_on_below_a_missing_block() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            let blocks = chain(&storage, 4).await;
+            // the contents of a block of the main branch are lost, the
+            // storage still links the block to its parent
+            storage
+                .prune_block_contents(vec![blocks[2].header.clone()])
+                .compat()
+                .await
+                .unwrap();
+
+            let check = check_storage(&storage, blocks[0].header.hash())
+                .await
+                .unwrap();
+            match check.issues() {
+                [StorageIssue::MissingBlock(hash)] => assert_eq!(*hash, blocks[2].header.hash()),
+                _ => panic!("the block 2 is missing"),
+            }
+            assert_eq!(check.checked(), 4);
+            assert_eq!(check.rewind_target(), blocks[1].header.hash());
+        });
+    }
+
+    #[test]
+    fn corrupt_contents_are_reported() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            let mut blocks = chain(&storage, 2).await;
+            // the header of the block does not match its contents
+            let mut contents = ContentsBuilder::new();
+            contents.push(Fragment::Initial(ConfigParams::new()));
+            let corrupt = Block {
+                header: child_header(&blocks[2].header, &empty_contents()),
+                contents: contents.into(),
+            };
+            let tip = child(&corrupt.header);
+            for block in vec![corrupt, tip] {
+                storage.put_block(block.clone()).compat().await.unwrap();
+                blocks.push(block);
+            }
+            storage
+                .put_tag(MAIN_BRANCH_TAG.to_owned(), blocks[4].header.hash())
+                .compat()
+                .await
+                .unwrap();
+
+            let check = check_storage(&storage, blocks[0].header.hash())
+                .await
+                .unwrap();
+            match check.issues() {
+                [StorageIssue::CorruptContents(hash)] => {
+                    assert_eq!(*hash, blocks[3].header.hash())
+                }
+                _ => panic!("the contents of the block 3 are corrupt"),
+            }
+            assert_eq!(check.checked(), 5);
+            assert_eq!(check.rewind_target(), blocks[2].header.hash());
+
+            check.repair(&storage).await.unwrap();
+            let head = storage
+                .get_tag(MAIN_BRANCH_TAG.to_owned())
+                .compat()
+                .await
+                .unwrap();
+            assert_eq!(head, Some(blocks[2].header.hash()));
+        });
+    }
+}
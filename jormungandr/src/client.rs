@@ -1,10 +1,11 @@
 use crate::blockcfg::{Block, Header, HeaderHash};
-use crate::blockchain::{Storage, Tip};
+use crate::blockchain::{pruned_chain_length, Storage, Tip};
 use crate::intercom::{ClientMsg, Error, ReplySendError, ReplyStreamHandle};
 use crate::utils::task::{Input, TokioServiceInfo};
 use chain_core::property::HasHeader;
 
 use futures::future::Either;
+use futures03::{compat::Future01CompatExt as _, future::TryFutureExt as _};
 use tokio::prelude::*;
 use tokio::timer::Timeout;
 
@@ -131,9 +132,16 @@ fn handle_get_headers_range(
     let storage = task_data.storage.clone();
     storage
         .find_closest_ancestor(checkpoints, to)
+        .map_err(Into::into)
+        .and_then(move |maybe_ancestor| {
+            let (from, depth) = match maybe_ancestor {
+                Some(ancestor) => (Some(ancestor.header_hash), Some(ancestor.distance)),
+                None => (None, None),
+            };
+            check_not_pruned(storage.clone(), from).map(move |()| (storage, depth))
+        })
         .then(move |res| match res {
-            Ok(maybe_ancestor) => {
-                let depth = maybe_ancestor.map(|ancestor| ancestor.distance);
+            Ok((storage, depth)) => {
                 let fut = storage
                     .send_branch(
                         to,
@@ -144,21 +152,65 @@ fn handle_get_headers_range(
                     .then(|_: Result<_, ReplySendError>| Ok(()));
                 Either::A(fut)
             }
-            Err(e) => Either::B(handle.async_error(e.into())),
+            Err(e) => Either::B(handle.async_error(e)),
         })
 }
 
+/// reject the requests for the blocks following `from`, or following the
+/// block0 if `from` is not given, if their contents have been pruned
+fn check_not_pruned(
+    storage: Storage,
+    from: Option<HeaderHash>,
+) -> impl Future<Item = (), Error = Error> {
+    Box::pin(check_not_pruned_std(storage, from)).compat()
+}
+
+async fn check_not_pruned_std(storage: Storage, from: Option<HeaderHash>) -> Result<(), Error> {
+    let pruned = match pruned_chain_length(storage.clone()).await? {
+        Some(pruned) => pruned,
+        None => return Ok(()),
+    };
+    let from_length = match from {
+        Some(from) => storage
+            .get_header(from)
+            .compat()
+            .await?
+            .map(|header| u32::from(header.chain_length())),
+        None => None,
+    };
+    match from_length {
+        Some(from_length) if from_length >= u32::from(pruned) => Ok(()),
+        _ => Err(Error::failed_precondition(format!(
+            "this node does not serve the blocks up to chain length {}",
+            pruned
+        ))),
+    }
+}
+
 fn get_blocks(storage: Storage, ids: Vec<HeaderHash>) -> impl Stream<Item = Block, Error = Error> {
     stream::iter_ok(ids).and_then(move |id| {
+        let get_header = storage.get_header(id);
         storage
             .get(id)
             .map_err(Into::into)
             .and_then(move |maybe_block| match maybe_block {
-                Some(block) => Ok(block),
-                None => Err(Error::not_found(format!(
-                    "block {} is not known to this node",
-                    id
-                ))),
+                Some(block) => Either::A(future::ok(block)),
+                // the header is still stored if the contents of the block
+                // have been pruned
+                None => Either::B(
+                    get_header.map_err(Into::into).and_then(
+                        move |maybe_header| match maybe_header {
+                            Some(_) => Err(Error::failed_precondition(format!(
+                                "block {} has been pruned from the storage of this node",
+                                id
+                            ))),
+                            None => Err(Error::not_found(format!(
+                                "block {} is not known to this node",
+                                id
+                            ))),
+                        },
+                    ),
+                ),
             })
     })
 }
@@ -168,16 +220,15 @@ fn get_headers(
     ids: Vec<HeaderHash>,
 ) -> impl Stream<Item = Header, Error = Error> {
     stream::iter_ok(ids).and_then(move |id| {
-        storage
-            .get(id)
-            .map_err(Into::into)
-            .and_then(move |maybe_block| match maybe_block {
-                Some(block) => Ok(block.header()),
+        storage.get_header(id).map_err(Into::into).and_then(
+            move |maybe_header| match maybe_header {
+                Some(header) => Ok(header),
                 None => Err(Error::not_found(format!(
                     "block {} is not known to this node",
                     id
                 ))),
-            })
+            },
+        )
     })
 }
 
@@ -194,15 +245,20 @@ fn handle_pull_blocks_to_tip(
             let tip_hash = tip.hash();
             storage
                 .find_closest_ancestor(checkpoints, tip_hash)
-                .map(move |maybe_ancestor| {
-                    let depth = maybe_ancestor.map(|ancestor| ancestor.distance);
-                    (storage, tip_hash, depth)
+                .map_err(Into::into)
+                .and_then(move |maybe_ancestor| {
+                    let (from, depth) = match maybe_ancestor {
+                        Some(ancestor) => (Some(ancestor.header_hash), Some(ancestor.distance)),
+                        None => (None, None),
+                    };
+                    check_not_pruned(storage.clone(), from)
+                        .map(move |()| (storage, tip_hash, depth))
                 })
         })
         .then(move |res| match res {
             Ok((storage, to, depth)) => {
                 Either::A(storage.send_branch(to, depth, handle).then(|_| Ok(())))
             }
-            Err(e) => Either::B(handle.async_error(e.into())),
+            Err(e) => Either::B(handle.async_error(e)),
         })
 }
@@ -1,8 +1,12 @@
 use chain_storage::error::Error as StorageError;
 
 error_chain! {
+    links {
+        Blockchain(crate::blockchain::Error, crate::blockchain::ErrorKind);
+    }
     foreign_links {
         StorageError(StorageError);
+        Persistence(super::PersistenceError);
     }
     errors {
         BlockNotFound(hash: String) {
@@ -21,6 +25,10 @@ error_chain! {
             description("tried to index already indexed block")
             display("block '{}' is already indexed", id)
         }
+        InputExceedsBalance(id: String) {
+            description("an input spends more than the balance of its address")
+            display("an input of transaction '{}' spends more than the balance of its address", id)
+        }
         ChainLengthBlockAlreadyExists(chain_length: u32) {
             description("tried to index already indexed chainlength in the given branch")
             display("chain length: {} is already indexed", chain_length)
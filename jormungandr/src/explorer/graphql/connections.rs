@@ -1,6 +1,6 @@
 use super::error::ErrorKind;
-use super::scalars::{BlockCount, IndexCursor, PoolCount, TransactionCount};
-use super::{Block, Context, Pool, Transaction};
+use super::scalars::{BlockCount, EpochCount, IndexCursor, PoolCount, TransactionCount};
+use super::{Block, Context, Epoch, Pool, Transaction};
 use crate::blockcfg::HeaderHash;
 use crate::explorer::indexing::ExplorerTransaction;
 use juniper::FieldResult;
@@ -69,6 +69,20 @@ impl PoolEdge {
     }
 }
 
+#[juniper::object(
+    Context = Context
+)]
+impl EpochEdge {
+    pub fn node(&self) -> &Epoch {
+        &self.node
+    }
+
+    /// A cursor for use in pagination
+    pub fn cursor(&self) -> &IndexCursor {
+        &self.cursor
+    }
+}
+
 #[juniper::object(
     Context = Context,
     name = "BlockConnection"
@@ -126,6 +140,25 @@ impl PoolConnection {
     }
 }
 
+#[juniper::object(
+    Context = Context,
+    name = "EpochConnection"
+)]
+impl EpochConnection {
+    pub fn page_info(&self) -> &PageInfo {
+        &self.page_info
+    }
+
+    pub fn edges(&self) -> &Vec<EpochEdge> {
+        &self.edges
+    }
+
+    /// A count of the total number of objects in this connection, ignoring pagination.
+    pub fn total_count(&self) -> &EpochCount {
+        &self.total_count
+    }
+}
+
 pub struct PageInfo {
     pub has_next_page: bool,
     pub has_previous_page: bool,
@@ -154,6 +187,11 @@ pub struct PoolEdge {
     pub cursor: IndexCursor,
 }
 
+pub struct EpochEdge {
+    node: Epoch,
+    pub cursor: IndexCursor,
+}
+
 pub trait Edge {
     type Node;
     fn new(node: Self::Node, cursor: IndexCursor) -> Self;
@@ -257,6 +295,7 @@ where
 pub type BlockConnection = Connection<BlockEdge, BlockCount>;
 pub type TransactionConnection = Connection<TransactionEdge, TransactionCount>;
 pub type PoolConnection = Connection<PoolEdge, PoolCount>;
+pub type EpochConnection = Connection<EpochEdge, EpochCount>;
 
 #[derive(Clone)]
 pub enum TransactionNodeFetchInfo {
@@ -305,6 +344,17 @@ impl Edge for PoolEdge {
     }
 }
 
+impl Edge for EpochEdge {
+    type Node = Epoch;
+    fn new(node: Self::Node, cursor: IndexCursor) -> Self {
+        EpochEdge { node, cursor }
+    }
+
+    fn cursor<'a>(&'a self) -> &'a IndexCursor {
+        &self.cursor
+    }
+}
+
 fn compute_range_boundaries(
     total_elements: InclusivePaginationInterval<u64>,
     pagination_arguments: ValidatedPaginationArguments<u64>,
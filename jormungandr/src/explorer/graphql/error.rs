@@ -20,6 +20,10 @@ error_chain! {
             description("invalid cursor in pagination query"),
             display("invalid cursor in pagination query: {}", msg)
         }
+        StateNotRetained(block: String) {
+            description("the state at the block is not retained"),
+            display("the state at block {} is older than the states kept by the explorer", block)
+        }
         InvalidAddress(address: String) {
             description("failed to parse address"),
             display("invalid address: {}", address)
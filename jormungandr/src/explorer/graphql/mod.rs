@@ -2,12 +2,13 @@ mod connections;
 mod error;
 mod scalars;
 use self::connections::{
-    BlockConnection, InclusivePaginationInterval, PaginationArguments, PaginationInterval,
-    PoolConnection, TransactionConnection, TransactionNodeFetchInfo,
+    BlockConnection, EpochConnection, InclusivePaginationInterval, PaginationArguments,
+    PaginationInterval, PoolConnection, TransactionConnection, TransactionNodeFetchInfo,
 };
 use self::error::ErrorKind;
 use super::indexing::{
-    BlockProducer, EpochData, ExplorerAddress, ExplorerBlock, ExplorerTransaction, StakePoolData,
+    AddressState, BlockProducer, EpochData, EpochRewardsData, ExplorerAddress, ExplorerBlock,
+    ExplorerTransaction, StakePoolData,
 };
 use super::persistent_sequence::PersistentSequence;
 use crate::blockcfg::{self, FragmentId, HeaderHash};
@@ -23,7 +24,7 @@ use tokio::prelude::*;
 
 use self::scalars::{
     BlockCount, ChainLength, EpochNumber, IndexCursor, NonZero, PoolId, PublicKey, Slot,
-    TimeOffsetSeconds, Value,
+    SpendingCounter, TimeOffsetSeconds, Value,
 };
 
 use crate::explorer::{ExplorerDB, Settings};
@@ -421,25 +422,76 @@ impl TransactionOutput {
 
 struct Address {
     id: ExplorerAddress,
+    /// The block at which the state of the address is queried, the tip of
+    /// the longest branch if `None`
+    block: Option<HeaderHash>,
 }
 
 impl Address {
-    fn from_bech32(bech32: &String) -> FieldResult<Address> {
+    fn from_bech32(
+        bech32: &String,
+        block: Option<String>,
+        db: &ExplorerDB,
+    ) -> FieldResult<Address> {
         let addr = chain_addr::AddressReadable::from_string_anyprefix(bech32)
             .map(|adr| ExplorerAddress::New(adr.to_address()))
             .or_else(|_| OldAddress::from_str(bech32).map(|a| ExplorerAddress::Old(a)))
             .map_err(|_| ErrorKind::InvalidAddress(bech32.clone()))?;
 
-        Ok(Address { id: addr })
+        let block = match block {
+            Some(block) => {
+                let block = HeaderHash::from_str(&block)?;
+                if !db
+                    .is_state_retained(block)
+                    .wait()
+                    .unwrap_or_else(|e| match e {})
+                {
+                    let indexed = db
+                        .get_block(&block)
+                        .wait()
+                        .unwrap_or_else(|e| match e {})
+                        .is_some();
+                    return Err(if indexed {
+                        ErrorKind::StateNotRetained(block.to_string()).into()
+                    } else {
+                        ErrorKind::NotFound("Block not found".to_owned()).into()
+                    });
+                }
+                Some(block)
+            }
+            None => None,
+        };
+
+        Ok(Address { id: addr, block })
+    }
+
+    fn get_state(&self, db: &ExplorerDB) -> AddressState {
+        db.get_address_state(&self.id, self.block)
+            .wait()
+            .unwrap_or_else(|e| match e {})
+            .unwrap_or_default()
     }
 }
 
 impl From<&ExplorerAddress> for Address {
     fn from(addr: &ExplorerAddress) -> Address {
-        Address { id: addr.clone() }
+        Address {
+            id: addr.clone(),
+            block: None,
+        }
     }
 }
 
+/// An output not spent yet
+#[derive(juniper::GraphQLObject)]
+struct UnspentOutput {
+    /// The transaction creating the output
+    transaction_id: String,
+    /// The index of the output in the transaction
+    index: i32,
+    amount: Value,
+}
+
 #[juniper::object(
     Context = Context
 )]
@@ -456,8 +508,49 @@ impl Address {
         }
     }
 
-    fn delegation() -> FieldResult<Pool> {
-        Err(ErrorKind::Unimplemented.into())
+    /// The sum of the unspent outputs of an utxo address, or the value of
+    /// an account address
+    fn balance(&self, context: &Context) -> Value {
+        self.get_state(&context.db).balance.into()
+    }
+
+    /// The unspent outputs of an utxo address, empty for an account address
+    fn unspent_outputs(&self, context: &Context) -> Vec<UnspentOutput> {
+        let mut utxos: Vec<UnspentOutput> = self
+            .get_state(&context.db)
+            .utxos
+            .iter()
+            .map(|((transaction_id, index), value)| UnspentOutput {
+                transaction_id: format!("{}", transaction_id),
+                index: (*index).into(),
+                amount: value.as_ref().into(),
+            })
+            .collect();
+        utxos.sort_unstable_by(|a, b| {
+            (&a.transaction_id, a.index).cmp(&(&b.transaction_id, b.index))
+        });
+        utxos
+    }
+
+    /// The number of transactions spent from an account address
+    fn spending_counter(&self, context: &Context) -> SpendingCounter {
+        self.get_state(&context.db).spending_counter.into()
+    }
+
+    /// The pools the account address delegates to
+    fn delegation(&self, context: &Context) -> Vec<Pool> {
+        use chain_impl_mockchain::account::DelegationType;
+
+        match self.get_state(&context.db).delegation {
+            DelegationType::NonDelegated => vec![],
+            DelegationType::Full(id) => vec![Pool::from_valid_id(id)],
+            DelegationType::Ratio(delegation_ratio) => delegation_ratio
+                .pools()
+                .iter()
+                .cloned()
+                .map(|(p, _)| Pool::from_valid_id(p))
+                .collect(),
+        }
     }
 
     fn transactions(
@@ -637,9 +730,7 @@ impl PoolRegistration {
                     ))
                 }
             })
-            .map(|explorer_address| Address {
-                id: explorer_address,
-            })
+            .map(|explorer_address| Address::from(&explorer_address))
     }
 
     // Genesis Praos keys
@@ -856,6 +947,39 @@ impl Pool {
             data: Some(data),
         }
     }
+
+    fn get_data(&self, db: &ExplorerDB) -> FieldResult<StakePoolData> {
+        match &self.data {
+            Some(data) => Ok(data.clone()),
+            None => db
+                .get_stake_pool_data(&self.id)
+                .wait()
+                .unwrap()
+                .ok_or(ErrorKind::NotFound("Stake pool not found".to_owned()).into()),
+        }
+    }
+}
+
+#[derive(juniper::GraphQLEnum, Clone, Copy, PartialEq, Eq)]
+pub enum PoolStatus {
+    Active,
+    /// The pool has a retirement certificate for a future time
+    Retiring,
+    Retired,
+}
+
+impl PoolStatus {
+    /// The status of the pool, at the given number of seconds since the
+    /// start of the blockchain
+    pub(super) fn of(data: &StakePoolData, time_since_block0: u64) -> PoolStatus {
+        match &data.retirement {
+            None => PoolStatus::Active,
+            Some(retirement) if u64::from(retirement.retirement_time) > time_since_block0 => {
+                PoolStatus::Retiring
+            }
+            Some(_) => PoolStatus::Retired,
+        }
+    }
 }
 
 #[juniper::object(
@@ -916,17 +1040,42 @@ impl Pool {
         })
     }
 
+    /// The registration in effect, as set by the last update of the pool
     pub fn registration(&self, context: &Context) -> FieldResult<PoolRegistration> {
-        match &self.data {
-            Some(data) => Ok(data.registration.clone().into()),
-            None => context
-                .db
-                .get_stake_pool_data(&self.id)
-                .wait()
-                .unwrap()
-                .map(|data| PoolRegistration::from(data.registration.clone()))
-                .ok_or(ErrorKind::NotFound("Stake pool not found".to_owned()).into()),
-        }
+        self.get_data(&context.db)
+            .map(|data| PoolRegistration::from(data.registration))
+    }
+
+    /// The registrations replaced by the updates of the pool, or by a new
+    /// registration once the pool retired, the oldest first
+    pub fn previous_registrations(&self, context: &Context) -> FieldResult<Vec<PoolRegistration>> {
+        let data = self.get_data(&context.db)?;
+        Ok((0..data.previous_registrations.len())
+            .filter_map(|i| data.previous_registrations.get(i))
+            .map(|registration| PoolRegistration::from(registration.as_ref().clone()))
+            .collect())
+    }
+
+    pub fn retirement(&self, context: &Context) -> FieldResult<Option<PoolRetirement>> {
+        self.get_data(&context.db)
+            .map(|data| data.retirement.map(PoolRetirement::from))
+    }
+
+    /// The status of the pool at the date of the latest block
+    pub fn status(&self, context: &Context) -> FieldResult<PoolStatus> {
+        let data = self.get_data(&context.db)?;
+        let time = context.db.get_latest_block_time().wait()?;
+        Ok(PoolStatus::of(&data, time))
+    }
+
+    /// The accounts delegating, fully or partially, to the pool
+    pub fn delegators(&self, context: &Context) -> FieldResult<Vec<Address>> {
+        Ok(self
+            .get_data(&context.db)?
+            .delegators
+            .iter()
+            .map(Address::from)
+            .collect())
     }
 }
 
@@ -1015,7 +1164,8 @@ struct FeeSettings {
     certificate_owner_stake_delegation: Value,
 }
 
-struct Epoch {
+#[derive(Clone)]
+pub struct Epoch {
     id: blockcfg::Epoch,
 }
 
@@ -1039,9 +1189,39 @@ impl Epoch {
         self.id.into()
     }
 
-    /// Not yet implemented
-    pub fn stake_distribution(&self) -> FieldResult<StakeDistribution> {
-        Err(ErrorKind::Unimplemented.into())
+    /// The stake delegated to each pool, as used for the leader election
+    /// of this epoch
+    pub fn stake_distribution(&self, context: &Context) -> FieldResult<StakeDistribution> {
+        context
+            .db
+            .get_stake_distribution(self.id)
+            .wait()
+            .unwrap_or_else(|e| match e {})
+            .map(|distribution| StakeDistribution {
+                pools: distribution
+                    .pools
+                    .into_iter()
+                    .map(|(id, stake)| PoolStakeDistribution {
+                        pool: Pool::from_valid_id(id),
+                        delegated_stake: Value(format!("{}", stake)),
+                    })
+                    .collect(),
+            })
+            .ok_or_else(|| {
+                ErrorKind::NotFound("stake distribution of the epoch is not known".to_owned())
+                    .into()
+            })
+    }
+
+    /// The rewards distributed at the transition into this epoch, if they
+    /// have been recorded
+    pub fn rewards(&self, context: &Context) -> Option<EpochRewards> {
+        context
+            .db
+            .get_epoch_rewards(self.id)
+            .wait()
+            .unwrap_or_else(|e| match e {})
+            .map(|data| EpochRewards { data })
     }
 
     /// Get a paginated view of all the blocks in this epoch
@@ -1121,6 +1301,108 @@ impl Epoch {
     }
 }
 
+struct EpochRewards {
+    data: EpochRewardsData,
+}
+
+#[juniper::object(
+    Context = Context,
+)]
+impl EpochRewards {
+    /// The rewards distributed to the pools and the treasury, taken from
+    /// the reward pot and the fees
+    pub fn total_rewards(&self) -> FieldResult<Value> {
+        Ok(self.data.drawn.checked_add(self.data.fees)?.into())
+    }
+
+    /// The value taken from the reward pot
+    pub fn drawn(&self) -> Value {
+        self.data.drawn.into()
+    }
+
+    /// The fees collected during the previous epoch
+    pub fn fees(&self) -> Value {
+        self.data.fees.into()
+    }
+
+    /// The value sent to the treasury
+    pub fn treasury(&self) -> Value {
+        self.data.treasury.into()
+    }
+
+    /// The value of the treasury after the distribution
+    pub fn treasury_balance(&self) -> Value {
+        self.data.treasury_balance.into()
+    }
+
+    pub fn pools(&self) -> Vec<PoolRewards> {
+        self.data
+            .pools
+            .iter()
+            .map(|(id, taxes, rewards)| PoolRewards {
+                pool: Pool::from_valid_id(id.clone()),
+                taxes: taxes.into(),
+                rewards: rewards.into(),
+            })
+            .collect()
+    }
+
+    /// The rewards credited to each account
+    pub fn accounts(&self) -> Vec<AccountReward> {
+        self.data
+            .accounts
+            .iter()
+            .map(|(address, reward)| AccountReward {
+                account: Address::from(address),
+                reward: reward.into(),
+            })
+            .collect()
+    }
+}
+
+struct PoolRewards {
+    pool: Pool,
+    taxes: Value,
+    rewards: Value,
+}
+
+#[juniper::object(
+    Context = Context,
+)]
+impl PoolRewards {
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    /// The taxes collected by the pool
+    pub fn taxes(&self) -> &Value {
+        &self.taxes
+    }
+
+    /// The rewards distributed to the delegators of the pool
+    pub fn rewards(&self) -> &Value {
+        &self.rewards
+    }
+}
+
+struct AccountReward {
+    account: Address,
+    reward: Value,
+}
+
+#[juniper::object(
+    Context = Context,
+)]
+impl AccountReward {
+    pub fn account(&self) -> &Address {
+        &self.account
+    }
+
+    pub fn reward(&self) -> &Value {
+        &self.reward
+    }
+}
+
 struct StakeDistribution {
     pools: Vec<PoolStakeDistribution>,
 }
@@ -1224,16 +1506,53 @@ impl Query {
         Epoch::from_epoch_number(id)
     }
 
-    fn address(bech32: String, context: &Context) -> FieldResult<Address> {
-        Address::from_bech32(&bech32)
+    /// query all the epochs up to the epoch of the tip in a paginated view
+    fn all_epochs(
+        &self,
+        first: Option<i32>,
+        last: Option<i32>,
+        before: Option<IndexCursor>,
+        after: Option<IndexCursor>,
+        context: &Context,
+    ) -> FieldResult<EpochConnection> {
+        let last_epoch = latest_block(context)?.date().epoch;
+
+        let boundaries = PaginationInterval::Inclusive(InclusivePaginationInterval {
+            lower_bound: 0u32,
+            upper_bound: last_epoch,
+        });
+
+        let pagination_arguments = PaginationArguments {
+            first,
+            last,
+            before: before.map(u32::try_from).transpose()?,
+            after: after.map(u32::try_from).transpose()?,
+        }
+        .validate()?;
+
+        EpochConnection::new(boundaries, pagination_arguments, |range| match range {
+            PaginationInterval::Empty => vec![],
+            PaginationInterval::Inclusive(range) => (range.lower_bound..=range.upper_bound)
+                .map(|id| (Epoch { id }, id))
+                .collect(),
+        })
+    }
+
+    /// The address, with its balance and delegation at the given block or
+    /// at the tip of the longest branch
+    fn address(bech32: String, block: Option<String>, context: &Context) -> FieldResult<Address> {
+        Address::from_bech32(&bech32, block, &context.db)
     }
 
     pub fn stake_pool(id: PoolId, context: &Context) -> FieldResult<Pool> {
         Pool::from_string_id(&id.0, &context.db)
     }
 
+    /// query the stake pools in a paginated view, optionally only the ones
+    /// with the given status
     pub fn all_stake_pools(
         &self,
+        status: Option<PoolStatus>,
         first: Option<i32>,
         last: Option<i32>,
         before: Option<IndexCursor>,
@@ -1241,6 +1560,10 @@ impl Query {
         context: &Context,
     ) -> FieldResult<PoolConnection> {
         let mut stake_pools = context.db.get_stake_pools().wait()?;
+        if let Some(status) = status {
+            let time = context.db.get_latest_block_time().wait()?;
+            stake_pools.retain(|(_, data)| PoolStatus::of(data, time) == status);
+        }
 
         // Although it's probably not a big performance concern
         // There are a few alternatives to not have to sort this
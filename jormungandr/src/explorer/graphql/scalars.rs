@@ -32,6 +32,12 @@ pub struct TransactionCount(pub String);
 #[derive(juniper::GraphQLScalarValue)]
 pub struct PoolCount(pub String);
 
+#[derive(juniper::GraphQLScalarValue)]
+pub struct EpochCount(pub String);
+
+#[derive(juniper::GraphQLScalarValue)]
+pub struct SpendingCounter(pub String);
+
 #[derive(juniper::GraphQLScalarValue)]
 pub struct PublicKey(pub String);
 
@@ -129,6 +135,12 @@ impl From<chain_time::TimeOffsetSeconds> for TimeOffsetSeconds {
     }
 }
 
+impl From<u32> for SpendingCounter {
+    fn from(counter: u32) -> SpendingCounter {
+        SpendingCounter(format!("{}", counter))
+    }
+}
+
 impl From<u64> for TransactionCount {
     fn from(n: u64) -> TransactionCount {
         TransactionCount(format!("{}", n))
@@ -141,6 +153,12 @@ impl From<u64> for PoolCount {
     }
 }
 
+impl From<u64> for EpochCount {
+    fn from(n: u64) -> EpochCount {
+        EpochCount(format!("{}", n))
+    }
+}
+
 impl From<u32> for IndexCursor {
     fn from(number: u32) -> IndexCursor {
         IndexCursor(number.into())
@@ -1,4 +1,5 @@
 use super::persistent_sequence::PersistentSequence;
+use super::set::HamtSet;
 use imhamt;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
@@ -7,10 +8,11 @@ use crate::blockcfg::{Block, BlockDate, ChainLength, Epoch, Fragment, FragmentId
 use chain_addr::{Address, Discrimination};
 use chain_core::property::Block as _;
 use chain_core::property::Fragment as _;
+use chain_impl_mockchain::account::DelegationType;
 use chain_impl_mockchain::block::Proof;
-use chain_impl_mockchain::certificate::{Certificate, PoolId, PoolRegistration};
+use chain_impl_mockchain::certificate::{Certificate, PoolId, PoolRegistration, PoolRetirement};
 use chain_impl_mockchain::leadership::bft;
-use chain_impl_mockchain::transaction::{InputEnum, TransactionSlice, Witness};
+use chain_impl_mockchain::transaction::{InputEnum, TransactionSlice, UtxoPointer, Witness};
 use chain_impl_mockchain::value::Value;
 use std::{convert::TryInto, sync::Arc};
 
@@ -23,15 +25,60 @@ pub type Blocks = Hamt<HeaderHash, ExplorerBlock>;
 pub type ChainLengths = Hamt<ChainLength, HeaderHash>;
 
 pub type Addresses = Hamt<ExplorerAddress, PersistentSequence<FragmentId>>;
+pub type AddressStates = Hamt<ExplorerAddress, AddressState>;
+pub type Utxos = Hamt<(FragmentId, u8), Value>;
 pub type Epochs = Hamt<Epoch, EpochData>;
 
 pub type StakePoolBlocks = Hamt<PoolId, PersistentSequence<HeaderHash>>;
 pub type StakePool = Hamt<PoolId, StakePoolData>;
 
+pub type StakeDistributions = Hamt<Epoch, StakeDistributionData>;
+pub type EpochRewards = Hamt<Epoch, EpochRewardsData>;
+
 #[derive(Clone)]
 pub struct StakePoolData {
+    /// The registration in effect, replaced by the pool updates
     pub registration: PoolRegistration,
-    // TODO: Track updates and retirement here too?
+    /// The registrations replaced by the pool updates or by a registration
+    /// of the pool once retired, the oldest first
+    pub previous_registrations: PersistentSequence<PoolRegistration>,
+    pub retirement: Option<PoolRetirement>,
+    /// The accounts delegating, fully or partially, to the pool
+    pub delegators: HamtSet<ExplorerAddress>,
+}
+
+impl StakePoolData {
+    pub fn new(registration: PoolRegistration) -> Self {
+        StakePoolData {
+            registration,
+            previous_registrations: PersistentSequence::new(),
+            retirement: None,
+            delegators: HamtSet::new(),
+        }
+    }
+}
+
+/// Stake delegated to each pool, as used for the leader election of an epoch
+#[derive(Clone)]
+pub struct StakeDistributionData {
+    pub pools: Vec<(PoolId, Value)>,
+}
+
+/// Rewards distributed at the transition into an epoch
+#[derive(Clone)]
+pub struct EpochRewardsData {
+    /// The value taken from the reward pot
+    pub drawn: Value,
+    /// The fees collected during the previous epoch
+    pub fees: Value,
+    /// The value sent to the treasury
+    pub treasury: Value,
+    /// The value of the treasury after the distribution
+    pub treasury_balance: Value,
+    /// The taxes collected and the rewards distributed by each pool
+    pub pools: Vec<(PoolId, Value, Value)>,
+    /// The rewards credited to each account
+    pub accounts: Vec<(ExplorerAddress, Value)>,
 }
 
 /// Block with unified inputs the metadata needed in the queries
@@ -69,6 +116,8 @@ pub struct ExplorerTransaction {
 pub struct ExplorerInput {
     pub address: ExplorerAddress,
     pub value: Value,
+    /// The output spent by an utxo input, `None` for an account input
+    pub utxo_pointer: Option<UtxoPointer>,
 }
 
 #[derive(Clone)]
@@ -77,6 +126,29 @@ pub struct ExplorerOutput {
     pub value: Value,
 }
 
+/// Balance and delegation of an address. The unspent outputs are only used
+/// by the utxo addresses, the spending counter and the delegation by the
+/// account addresses
+#[derive(Clone)]
+pub struct AddressState {
+    pub balance: Value,
+    /// The unspent outputs, by transaction and index of the output
+    pub utxos: Utxos,
+    pub spending_counter: u32,
+    pub delegation: DelegationType,
+}
+
+impl Default for AddressState {
+    fn default() -> Self {
+        AddressState {
+            balance: Value::zero(),
+            utxos: Utxos::new(),
+            spending_counter: 0,
+            delegation: DelegationType::NonDelegated,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct EpochData {
     pub first_block: HeaderHash,
@@ -101,6 +173,17 @@ impl Hash for ExplorerAddress {
     }
 }
 
+impl ExplorerAddress {
+    /// An account address keeps a value instead of unspent outputs
+    pub fn is_account(&self) -> bool {
+        match self {
+            ExplorerAddress::New(Address(_, chain_addr::Kind::Account(_)))
+            | ExplorerAddress::New(Address(_, chain_addr::Kind::Multisig(_))) => true,
+            _ => false,
+        }
+    }
+}
+
 impl ExplorerBlock {
     /// Map the given `Block` to the `ExplorerBlock`, transforming all the transactions
     /// using the previous state to transform the utxo inputs to the form (Address, Amount)
@@ -323,7 +406,11 @@ impl ExplorerTransaction {
                             .into(),
                     );
                     let address = ExplorerAddress::New(Address(discrimination, kind));
-                    Some(ExplorerInput { address, value })
+                    Some(ExplorerInput {
+                        address,
+                        value,
+                        utxo_pointer: None,
+                    })
                 }
                 (InputEnum::AccountInput(id, value), Witness::Multisig(_)) => {
                     let kind = chain_addr::Kind::Multisig(
@@ -333,7 +420,11 @@ impl ExplorerTransaction {
                             .expect("multisig identifier size doesn't match address kind"),
                     );
                     let address = ExplorerAddress::New(Address(discrimination, kind));
-                    Some(ExplorerInput { address, value })
+                    Some(ExplorerInput {
+                        address,
+                        value,
+                        utxo_pointer: None,
+                    })
                 }
                 (InputEnum::UtxoInput(utxo_pointer), _witness) => {
                     let tx = utxo_pointer.transaction_id;
@@ -356,6 +447,7 @@ impl ExplorerTransaction {
                     Some(ExplorerInput {
                         address: output.address.clone(),
                         value: output.value,
+                        utxo_pointer: Some(utxo_pointer),
                     })
                 }
                 _ => None,
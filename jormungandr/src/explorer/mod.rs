@@ -1,33 +1,50 @@
 pub mod error;
 pub mod graphql;
 mod indexing;
+mod persistence;
 mod persistent_sequence;
+mod set;
+
+pub use self::persistence::{PersistenceError, INDEX_FILE_NAME};
 
 use self::error::{Error, ErrorKind, Result};
 use self::graphql::Context;
 use self::indexing::{
-    Addresses, Blocks, ChainLengths, EpochData, Epochs, ExplorerAddress, ExplorerBlock, StakePool,
-    StakePoolBlocks, StakePoolData, Transactions,
+    AddressState, AddressStates, Addresses, Blocks, ChainLengths, EpochData, EpochRewards,
+    EpochRewardsData, Epochs, ExplorerAddress, ExplorerBlock, ExplorerTransaction,
+    StakeDistributionData, StakeDistributions, StakePool, StakePoolBlocks, StakePoolData,
+    Transactions,
 };
+use self::persistence::Checkpoint;
 use self::persistent_sequence::PersistentSequence;
 
 use self::future::Either;
 use crate::blockcfg::{
-    Block, ChainLength, ConfigParam, ConfigParams, ConsensusVersion, Epoch, Fragment, FragmentId,
-    HeaderHash,
+    Block, BlockDate, ChainLength, ConfigParam, ConfigParams, ConsensusVersion, Epoch, Fragment,
+    FragmentId, HeaderHash,
 };
-use crate::blockchain::{Blockchain, Multiverse, MAIN_BRANCH_TAG};
+use crate::blockchain::{Blockchain, EpochTransitionRewards, Multiverse, Ref, MAIN_BRANCH_TAG};
 use crate::intercom::ExplorerMsg;
 use crate::utils::task::{Input, TokioServiceInfo};
-use chain_addr::Discrimination;
+use chain_addr::{Address, Discrimination};
 use chain_core::property::Block as _;
+use chain_impl_mockchain::account::DelegationType;
 use chain_impl_mockchain::certificate::{Certificate, PoolId};
 use chain_impl_mockchain::fee::LinearFee;
+use chain_impl_mockchain::leadership::LeadershipConsensus;
 use chain_impl_mockchain::multiverse;
-use std::convert::Infallible;
+use chain_impl_mockchain::value::Value;
+use futures03::compat::Compat;
+use jormungandr_lib::interfaces::DEFAULT_EPOCH_STABILITY_DEPTH;
+use slog::Logger;
+use std::collections::{HashMap, HashSet};
+use std::convert::{Infallible, TryInto};
+use std::io;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::prelude::*;
 use tokio::sync::lock::{Lock, LockGuard};
+use tokio02::task::spawn_blocking;
 
 #[derive(Clone)]
 pub struct Explorer {
@@ -53,10 +70,25 @@ pub struct ExplorerDB {
     /// performed using the state of this branch, the HeaderHash is used as key for the
     /// multiverse, and the ChainLength is used in the updating process.
     longest_chain_tip: Tip,
+    /// The last block deeper than the epoch stability depth the indexes
+    /// have been saved at, the multiverse only keeps the states from it
+    stable: Lock<Stable>,
+    /// Where the indexes are saved, `None` if they are only kept in memory
+    index_path: Option<PathBuf>,
+    block0_hash: HeaderHash,
     pub blockchain_config: BlockchainConfig,
     blockchain: Blockchain,
 }
 
+struct Stable {
+    block: HeaderHash,
+    chain_length: ChainLength,
+    epoch: Epoch,
+    /// The blocks indexed after the stable block, as
+    /// `(chain length, hash, parent hash)`, in the order they were indexed
+    recent_blocks: Vec<(ChainLength, HeaderHash, HeaderHash)>,
+}
+
 #[derive(Clone)]
 pub struct BlockchainConfig {
     /// Used to construct `Address` from `AccountIndentifier` when processing transaction
@@ -64,6 +96,14 @@ pub struct BlockchainConfig {
     discrimination: Discrimination,
     consensus_version: ConsensusVersion,
     fees: LinearFee,
+    /// Duration of the slots in seconds, and number of slots of the epochs,
+    /// as set by the block0: the pool retirement times are relative to the
+    /// start of the blockchain
+    slot_duration: u64,
+    slots_per_epoch: u64,
+    /// The blocks deeper than it are not rolled back anymore, their states
+    /// are saved and dropped from the multiverse
+    epoch_stability_depth: u32,
 }
 
 /// Inmutable data structure used to represent the explorer's state at a given Block
@@ -75,10 +115,13 @@ struct State {
     transactions: Transactions,
     blocks: Blocks,
     addresses: Addresses,
+    address_states: AddressStates,
     epochs: Epochs,
     chain_lengths: ChainLengths,
     stake_pool_data: StakePool,
     stake_pool_blocks: StakePoolBlocks,
+    stake_distributions: StakeDistributions,
+    epoch_rewards: EpochRewards,
 }
 
 #[derive(Clone)]
@@ -122,15 +165,28 @@ impl Explorer {
         };
 
         let mut explorer_db = self.db.clone();
+        let stable_db = self.db.clone();
         let logger = info.logger().clone();
         match bquery {
-            ExplorerMsg::NewBlock(block) => info.spawn(
+            ExplorerMsg::NewBlock(block, transition_rewards) => info.spawn(
                 "apply block",
                 explorer_db
-                    .apply_block(block)
+                    .apply_block(block, transition_rewards)
+                    // the states before the stable block are dropped from
+                    // the multiverse, so the GCRoot is not used
+                    .and_then(move |_gc_root| {
+                        stable_db
+                            .advance_stable()
+                            .map_err(|_: Infallible| unreachable!())
+                            .and_then(move |checkpoint| match checkpoint {
+                                Some(checkpoint) => {
+                                    Either::A(stable_db.save_checkpoint(checkpoint))
+                                }
+                                None => Either::B(future::ok(())),
+                            })
+                    })
                     .then(move |result| match result {
-                        // XXX: There is no garbage collection now, so the GCRoot is not used
-                        Ok(_gc_root) => Ok(()),
+                        Ok(()) => Ok(()),
                         Err(err) => Err(error!(logger, "Explorer error: {}", err)),
                     }),
             ),
@@ -142,8 +198,15 @@ impl Explorer {
 impl ExplorerDB {
     /// Apply all the blocks in the [block0, MAIN_BRANCH_TAG], also extract the static
     /// Blockchain settings from the Block0 (Discrimination)
+    /// The indexes saved at `index_path` are loaded if they are on the main branch,
+    /// only the blocks after them are applied.
     /// This function is only called once on the node's bootstrap phase
-    pub fn bootstrap(block0: Block, blockchain: &Blockchain) -> Result<Self> {
+    pub fn bootstrap(
+        block0: Block,
+        blockchain: &Blockchain,
+        index_path: Option<PathBuf>,
+        logger: &Logger,
+    ) -> Result<Self> {
         use tokio_compat::runtime;
 
         let mut rt = runtime::Builder::new()
@@ -164,72 +227,146 @@ impl ExplorerDB {
                 .expect("the Initial fragment to be present in the genesis block"),
         );
 
-        let block = ExplorerBlock::resolve_from(
-            &block0,
-            blockchain_config.discrimination,
-            &Transactions::new(),
-            &Blocks::new(),
-        );
+        let block0_id = block0.id();
+        let head = rt
+            .block_on(blockchain.storage().get_tag(MAIN_BRANCH_TAG.to_owned()))?
+            .ok_or_else(|| {
+                Error::from(ErrorKind::BootstrapError(
+                    "Couldn't read the HEAD tag from storage".to_owned(),
+                ))
+            })?;
 
-        let blocks = apply_block_to_blocks(Blocks::new(), &block)?;
-        let epochs = apply_block_to_epochs(Epochs::new(), &block);
-        let chain_lengths = apply_block_to_chain_lengths(ChainLengths::new(), &block)?;
-        let transactions = apply_block_to_transactions(Transactions::new(), &block)?;
-        let addresses = apply_block_to_addresses(Addresses::new(), &block)?;
-        let (stake_pool_data, stake_pool_blocks) =
-            apply_block_to_stake_pools(StakePool::new(), StakePoolBlocks::new(), &block);
-
-        let initial_state = State {
-            blocks,
-            epochs,
-            chain_lengths,
-            transactions,
-            addresses,
-            stake_pool_data,
-            stake_pool_blocks,
-            parent_ref: None,
+        let checkpoint = match &index_path {
+            Some(path) if path.exists() => match Checkpoint::load(path, block0_id) {
+                Ok(checkpoint) => {
+                    let on_main_branch = rt
+                        .block_on(blockchain.storage().is_ancestor(checkpoint.block, head))?
+                        .is_some();
+                    if on_main_branch {
+                        Some(checkpoint)
+                    } else {
+                        warn!(
+                            logger,
+                            "the saved explorer indexes are not on the main branch, ignoring them"
+                        );
+                        None
+                    }
+                }
+                Err(error) => {
+                    warn!(
+                        logger,
+                        "cannot load the saved explorer indexes, indexing from the block0";
+                        "reason" => %error
+                    );
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        let (root, root_length, root_state) = match checkpoint {
+            Some(Checkpoint {
+                block,
+                chain_length,
+                state,
+            }) => {
+                info!(
+                    logger,
+                    "resuming the explorer indexing after block {}", block;
+                    "chain_length" => %chain_length
+                );
+                (block, chain_length, state)
+            }
+            None => {
+                let block0_ref = rt.block_on(blockchain.get_ref(block0_id))?;
+                let state = block0_state(&block0, &blockchain_config, block0_ref)?;
+                (block0_id, block0.header.chain_length(), state)
+            }
         };
+        let root_epoch = root_state
+            .blocks
+            .lookup(&root)
+            .map(|block| block.date().epoch)
+            .ok_or_else(|| {
+                Error::from(ErrorKind::BootstrapError(
+                    "the saved explorer indexes do not contain their block".to_owned(),
+                ))
+            })?;
 
         let multiverse = Multiverse::<State>::new();
-        let block0_id = block0.id();
-        let initial_state_ref = rt
-            .block_on(multiverse.insert(block0.chain_length(), block0_id, initial_state))
+        let root_state_ref = rt
+            .block_on(multiverse.insert(root_length, root, root_state))
             .expect("The multiverse to be empty");
 
         let bootstraped_db = ExplorerDB {
             multiverse,
             longest_chain_tip: Tip::new(Branch {
-                state_ref: initial_state_ref,
-                length: block0.header.chain_length(),
+                state_ref: root_state_ref,
+                length: root_length,
+            }),
+            stable: Lock::new(Stable {
+                block: root,
+                chain_length: root_length,
+                epoch: root_epoch,
+                recent_blocks: Vec::new(),
             }),
+            index_path,
+            block0_hash: block0_id,
             blockchain_config,
             blockchain: blockchain.clone(),
         };
 
-        rt.block_on(
+        let (db, checkpoint) = rt.block_on(
             blockchain
                 .storage()
-                .get_tag(MAIN_BRANCH_TAG.to_owned())
-                .map_err(|err| err.into())
-                .and_then(move |head_option| match head_option {
-                    None => Either::A(future::err(Error::from(ErrorKind::BootstrapError(
-                        "Couldn't read the HEAD tag from storage".to_owned(),
-                    )))),
-                    Some(head) => Either::B(
-                        blockchain
-                            .storage()
-                            .stream_from_to(block0_id, head)
-                            .map_err(|err| Error::from(err)),
-                    ),
-                })
+                .stream_from_to(root, head)
+                .map_err(|err| Error::from(err))
                 .and_then(move |stream| {
-                    stream
-                        .map_err(|err| Error::from(err))
-                        .fold(bootstraped_db, |mut db, block| {
-                            db.apply_block(block).and_then(|_gc_root| Ok(db))
-                        })
+                    stream.map_err(|err| Error::from(err)).fold(
+                        (bootstraped_db, None),
+                        |(mut db, checkpoint), block| {
+                            // the rewards distributed by the node are only
+                            // kept in the `Ref` of the block
+                            let block_id = block.id();
+                            let transition_rewards = db
+                                .blockchain
+                                .get_ref(block_id)
+                                .map_err(Error::from)
+                                .and_then(move |block_ref| {
+                                    block_ref.ok_or_else(|| {
+                                        Error::from(ErrorKind::BootstrapError(format!(
+                                            "the state of block {} is not loaded",
+                                            block_id
+                                        )))
+                                    })
+                                })
+                                .map(|block_ref| block_ref.epoch_rewards().cloned());
+                            transition_rewards.and_then(move |transition_rewards| {
+                                db.apply_block(block, transition_rewards).and_then(
+                                    move |_gc_root| {
+                                        db.advance_stable()
+                                            .map_err(|_: Infallible| unreachable!())
+                                            .map(move |advanced| (db, advanced.or(checkpoint)))
+                                    },
+                                )
+                            })
+                        },
+                    )
                 }),
-        )
+        )?;
+
+        // the indexes are only saved once all the stored blocks are applied
+        if let (Some(checkpoint), Some(path)) = (checkpoint, db.index_path.as_ref()) {
+            if let Err(error) = checkpoint.save(path, db.block0_hash) {
+                warn!(
+                    logger,
+                    "cannot save the explorer indexes";
+                    "reason" => %error
+                );
+            }
+        }
+
+        Ok(db)
     }
 
     /// Try to add a new block to the indexes, this can fail if the parent of the block is
@@ -240,82 +377,141 @@ impl ExplorerDB {
     fn apply_block(
         &mut self,
         block: Block,
+        transition_rewards: Option<Arc<EpochTransitionRewards>>,
     ) -> impl Future<Item = multiverse::Ref<State>, Error = Error> {
         let previous_block = block.header.block_parent_hash();
         let chain_length = block.header.chain_length();
         let block_id = block.header.hash();
         let multiverse = self.multiverse.clone();
         let current_tip = self.longest_chain_tip.clone();
+        let stable = self.stable.clone();
         let discrimination = self.blockchain_config.discrimination.clone();
 
-        multiverse
-            .get_ref(previous_block)
-            .map_err(|_: Infallible| unreachable!())
-            .and_then(move |maybe_previous_state| match maybe_previous_state {
-                Some(state_ref) => {
-                    let State {
-                        parent_ref: _,
-                        transactions,
-                        blocks,
-                        addresses,
-                        epochs,
-                        chain_lengths,
-                        stake_pool_data,
-                        stake_pool_blocks,
-                    } = state_ref.state().clone();
-
-                    let explorer_block =
-                        ExplorerBlock::resolve_from(&block, discrimination, &transactions, &blocks);
-
-                    Ok((
-                        state_ref,
-                        apply_block_to_transactions(transactions, &explorer_block)?,
-                        apply_block_to_blocks(blocks, &explorer_block)?,
-                        apply_block_to_addresses(addresses, &explorer_block)?,
-                        apply_block_to_epochs(epochs, &explorer_block),
-                        apply_block_to_chain_lengths(chain_lengths, &explorer_block)?,
-                        apply_block_to_stake_pools(
+        self.blockchain
+            .get_ref(block_id)
+            .map_err(Error::from)
+            .join(
+                multiverse
+                    .get_ref(previous_block)
+                    .map_err(|_: Infallible| unreachable!()),
+            )
+            .and_then(
+                move |(block_ref, maybe_previous_state)| match maybe_previous_state {
+                    Some(state_ref) => {
+                        let State {
+                            parent_ref: _,
+                            transactions,
+                            blocks,
+                            addresses,
+                            address_states,
+                            epochs,
+                            chain_lengths,
                             stake_pool_data,
                             stake_pool_blocks,
+                            stake_distributions,
+                            epoch_rewards,
+                        } = state_ref.state().clone();
+
+                        let explorer_block = ExplorerBlock::resolve_from(
+                            &block,
+                            discrimination,
+                            &transactions,
+                            &blocks,
+                        );
+
+                        // the rewards are credited before the transactions
+                        // of the first block of the epoch are applied
+                        let (epoch_rewards, rewards) = apply_block_to_epoch_rewards(
+                            epoch_rewards,
                             &explorer_block,
-                        ),
-                    ))
-                }
-                None => Err(Error::from(ErrorKind::AncestorNotFound(format!(
-                    "{}",
-                    block.id()
-                )))),
-            })
+                            transition_rewards.as_deref(),
+                            discrimination,
+                        );
+                        let address_states =
+                            apply_rewards_to_address_states(address_states, rewards.as_deref());
+
+                        // the delegators of the pools need the delegations
+                        // before the block
+                        let stake_pools = apply_block_to_stake_pools(
+                            stake_pool_data,
+                            stake_pool_blocks,
+                            &address_states,
+                            &explorer_block,
+                            discrimination,
+                        );
+
+                        Ok((
+                            state_ref,
+                            apply_block_to_transactions(transactions, &explorer_block)?,
+                            apply_block_to_blocks(blocks, &explorer_block)?,
+                            apply_block_to_addresses(addresses, &explorer_block)?,
+                            apply_block_to_address_states(
+                                address_states,
+                                &explorer_block,
+                                discrimination,
+                            )?,
+                            apply_block_to_epochs(epochs, &explorer_block),
+                            apply_block_to_chain_lengths(chain_lengths, &explorer_block)?,
+                            stake_pools,
+                            apply_block_to_stake_distributions(
+                                stake_distributions,
+                                &explorer_block,
+                                block_ref,
+                            ),
+                            epoch_rewards,
+                        ))
+                    }
+                    None => Err(Error::from(ErrorKind::AncestorNotFound(format!(
+                        "{}",
+                        block.id()
+                    )))),
+                },
+            )
             .and_then(
                 move |(
                     parent_ref,
                     transactions,
                     blocks,
                     addresses,
+                    address_states,
                     epochs,
                     chain_lengths,
                     stake_pools,
+                    stake_distributions,
+                    epoch_rewards,
                 )| {
                     let chain_length = chain_length.clone();
                     let block_id = block_id.clone();
                     let (stake_pool_data, stake_pool_blocks) = stake_pools;
-                    multiverse
-                        .insert(
-                            chain_length,
-                            block_id,
-                            State {
-                                parent_ref: Some(parent_ref),
-                                transactions,
-                                blocks,
-                                addresses,
-                                epochs,
-                                chain_lengths,
-                                stake_pool_data,
-                                stake_pool_blocks,
-                            },
-                        )
+                    let state = State {
+                        parent_ref: Some(parent_ref),
+                        transactions,
+                        blocks,
+                        addresses,
+                        address_states,
+                        epochs,
+                        chain_lengths,
+                        stake_pool_data,
+                        stake_pool_blocks,
+                        stake_distributions,
+                        epoch_rewards,
+                    };
+                    // the block is recorded along with its state, so the
+                    // state is kept when the stable block moves
+                    get_lock(&stable)
+                        .and_then(move |mut stable| {
+                            multiverse
+                                .insert(chain_length, block_id, state)
+                                .map(move |state_ref| {
+                                    stable.recent_blocks.push((
+                                        chain_length,
+                                        block_id,
+                                        previous_block,
+                                    ));
+                                    (state_ref, chain_length)
+                                })
+                        })
                         .map_err(|_: Infallible| unreachable!())
-                        .map(move |state_ref| (state_ref, chain_length))
                 },
             )
             .and_then(move |(state_ref, chain_length)| {
@@ -329,6 +525,111 @@ impl ExplorerDB {
             })
     }
 
+    /// Move the stable block to the end of the most recent epoch of the longest
+    /// branch which is deeper than the epoch stability depth. The states of the
+    /// blocks before it, and of the branches not descending from it, are dropped
+    /// from the multiverse. Returns the indexes at the new stable block, if it
+    /// has moved.
+    fn advance_stable(&self) -> impl Future<Item = Option<Checkpoint>, Error = Infallible> {
+        let multiverse = self.multiverse.clone();
+        let stable = self.stable.clone();
+        let epoch_stability_depth = self.blockchain_config.epoch_stability_depth;
+
+        let tip = self.longest_chain_tip.clone();
+
+        // the stable block is locked first: it is held while the indexes are
+        // saved, the tip is not locked meanwhile
+        get_lock(&stable).and_then(move |mut stable| {
+            get_lock(&tip.0).and_then(move |mut tip| {
+                let tip_id = *tip.state_ref.id();
+                let (block, chain_length, epoch) = match stable_epoch_end(
+                    tip.state_ref.state(),
+                    &tip_id,
+                    &stable,
+                    epoch_stability_depth,
+                ) {
+                    Some(stable_end) => stable_end,
+                    None => return Either::A(future::ok(None)),
+                };
+
+                let mut descendants = HashSet::new();
+                descendants.insert(block);
+                let mut kept_blocks = vec![(chain_length, block, block)];
+                for &(length, hash, parent) in &stable.recent_blocks {
+                    if length > chain_length && descendants.contains(&parent) {
+                        descendants.insert(hash);
+                        kept_blocks.push((length, hash, parent));
+                    }
+                }
+
+                Either::B(
+                    multiverse
+                        .retain(kept_blocks.clone(), |state, parent_ref| State {
+                            parent_ref,
+                            ..state
+                        })
+                        .map(move |refs| {
+                            let tip_ref = refs
+                                .get(&tip_id)
+                                .expect("the longest branch to descend from the stable block");
+                            tip.state_ref = tip_ref.clone();
+
+                            kept_blocks.remove(0);
+                            *stable = Stable {
+                                block,
+                                chain_length,
+                                epoch,
+                                recent_blocks: kept_blocks,
+                            };
+
+                            refs.get(&block).map(|state_ref| Checkpoint {
+                                block,
+                                chain_length,
+                                state: state_ref.state().clone(),
+                            })
+                        }),
+                )
+            })
+        })
+    }
+
+    /// Save the indexes at the stable block next to the node storage, if the
+    /// node has one. The saves share a temporary file, they are done one at
+    /// a time while holding the stable block. The indexes are not saved if the
+    /// stable block has moved since, the indexes at the new one are saved
+    /// instead.
+    ///
+    /// The whole indexes are written every time, so a save takes longer as
+    /// the chain grows. It runs on a blocking thread and does not reduce the
+    /// memory used by the indexes.
+    fn save_checkpoint(&self, checkpoint: Checkpoint) -> impl Future<Item = (), Error = Error> {
+        let index_path = self.index_path.clone();
+        let block0_hash = self.block0_hash;
+        get_lock(&self.stable)
+            .map_err(|_: Infallible| unreachable!())
+            .and_then(move |stable| {
+                Compat::new(Box::pin(async move {
+                    let path = match index_path {
+                        Some(path) => path,
+                        None => return Ok(()),
+                    };
+                    if stable.block != checkpoint.block {
+                        return Ok(());
+                    }
+                    let saved = spawn_blocking(move || checkpoint.save(path, block0_hash)).await;
+                    drop(stable);
+                    saved
+                        .unwrap_or_else(|error| {
+                            Err(PersistenceError::Io(io::Error::new(
+                                io::ErrorKind::Other,
+                                error,
+                            )))
+                        })
+                        .map_err(Error::from)
+                }))
+            })
+    }
+
     pub fn get_latest_block_hash(&self) -> impl Future<Item = HeaderHash, Error = Infallible> {
         self.longest_chain_tip.get_block_id()
     }
@@ -389,6 +690,41 @@ impl ExplorerDB {
         })
     }
 
+    /// get the balance and delegation of the address, at the given block or
+    /// at the tip of the longest branch
+    pub fn get_address_state(
+        &self,
+        address: &ExplorerAddress,
+        block_id: Option<HeaderHash>,
+    ) -> impl Future<Item = Option<AddressState>, Error = Infallible> {
+        let address = address.clone();
+        let lookup = move |state: State| {
+            state
+                .address_states
+                .lookup(&address)
+                .map(|state| state.as_ref().clone())
+        };
+        match block_id {
+            Some(block_id) => Either::A(
+                self.with_state_at(block_id, lookup)
+                    .map(|maybe_state| maybe_state.flatten()),
+            ),
+            None => Either::B(self.with_latest_state(lookup)),
+        }
+    }
+
+    /// check if the state at the block is kept, in any branch. Only the
+    /// states from the stable block are kept, the block may be indexed but
+    /// older than them
+    pub fn is_state_retained(
+        &self,
+        block_id: HeaderHash,
+    ) -> impl Future<Item = bool, Error = Infallible> {
+        self.multiverse
+            .get(block_id)
+            .map(|maybe_state| maybe_state.is_some())
+    }
+
     // Get the hashes of all blocks in the range [from, to)
     // the ChainLength is returned to for easy of use in the case where
     // `to` is greater than the max
@@ -450,6 +786,30 @@ impl ExplorerDB {
         })
     }
 
+    pub fn get_epoch_rewards(
+        &self,
+        epoch: Epoch,
+    ) -> impl Future<Item = Option<EpochRewardsData>, Error = Infallible> {
+        self.with_latest_state(move |state| {
+            state
+                .epoch_rewards
+                .lookup(&epoch)
+                .map(|rewards| rewards.as_ref().clone())
+        })
+    }
+
+    pub fn get_stake_distribution(
+        &self,
+        epoch: Epoch,
+    ) -> impl Future<Item = Option<StakeDistributionData>, Error = Infallible> {
+        self.with_latest_state(move |state| {
+            state
+                .stake_distributions
+                .lookup(&epoch)
+                .map(|d| d.as_ref().clone())
+        })
+    }
+
     /// run given function with the longest branch's state
     fn with_latest_state<T>(
         &self,
@@ -464,6 +824,36 @@ impl ExplorerDB {
         })
     }
 
+    /// run given function with the state at the given block, `None` if the
+    /// block is not indexed
+    fn with_state_at<T>(
+        &self,
+        block_id: HeaderHash,
+        f: impl Fn(State) -> T,
+    ) -> impl Future<Item = Option<T>, Error = Infallible> {
+        self.multiverse
+            .get(block_id)
+            .map(move |maybe_state| maybe_state.map(f))
+    }
+
+    /// the number of seconds between the start of the blockchain and the
+    /// slot of the latest block, the retirement times of the stake pools
+    /// are compared to it
+    pub fn get_latest_block_time(&self) -> impl Future<Item = u64, Error = Infallible> {
+        let multiverse = self.multiverse.clone();
+        let blockchain_config = self.blockchain_config.clone();
+        self.get_latest_block_hash().and_then(move |branch_id| {
+            multiverse.get(branch_id).and_then(move |maybe_state| {
+                let state = maybe_state.expect("the longest chain to be indexed");
+                let block = state
+                    .blocks
+                    .lookup(&branch_id)
+                    .expect("the latest block to be indexed");
+                Ok(blockchain_config.time_since_block0(block.date()))
+            })
+        })
+    }
+
     fn blockchain(&self) -> &Blockchain {
         &self.blockchain
     }
@@ -474,6 +864,78 @@ fn get_lock<L>(lock: &Lock<L>) -> impl Future<Item = LockGuard<L>, Error = Infal
     future::poll_fn(move || Ok(lock.poll_lock()))
 }
 
+fn block0_state(
+    block0: &Block,
+    blockchain_config: &BlockchainConfig,
+    block0_ref: Option<Arc<Ref>>,
+) -> Result<State> {
+    let block = ExplorerBlock::resolve_from(
+        block0,
+        blockchain_config.discrimination,
+        &Transactions::new(),
+        &Blocks::new(),
+    );
+
+    let blocks = apply_block_to_blocks(Blocks::new(), &block)?;
+    let epochs = apply_block_to_epochs(Epochs::new(), &block);
+    let chain_lengths = apply_block_to_chain_lengths(ChainLengths::new(), &block)?;
+    let transactions = apply_block_to_transactions(Transactions::new(), &block)?;
+    let addresses = apply_block_to_addresses(Addresses::new(), &block)?;
+    let address_states = apply_block_to_address_states(
+        AddressStates::new(),
+        &block,
+        blockchain_config.discrimination,
+    )?;
+    let (stake_pool_data, stake_pool_blocks) = apply_block_to_stake_pools(
+        StakePool::new(),
+        StakePoolBlocks::new(),
+        &AddressStates::new(),
+        &block,
+        blockchain_config.discrimination,
+    );
+    let stake_distributions =
+        apply_block_to_stake_distributions(StakeDistributions::new(), &block, block0_ref);
+
+    Ok(State {
+        blocks,
+        epochs,
+        chain_lengths,
+        transactions,
+        addresses,
+        address_states,
+        stake_pool_data,
+        stake_pool_blocks,
+        stake_distributions,
+        epoch_rewards: EpochRewards::new(),
+        parent_ref: None,
+    })
+}
+
+/// The last block of the most recent epoch of the branch of `tip` which is
+/// deeper than the epoch stability depth, if it is after the stable block
+fn stable_epoch_end(
+    state: &State,
+    tip: &HeaderHash,
+    stable: &Stable,
+    epoch_stability_depth: u32,
+) -> Option<(HeaderHash, ChainLength, Epoch)> {
+    let tip = state.blocks.lookup(tip)?;
+    let tip_length = u32::from(tip.chain_length());
+
+    (stable.epoch..tip.date().epoch)
+        .rev()
+        .filter_map(|epoch| {
+            state
+                .epochs
+                .lookup(&epoch)
+                .and_then(|data| state.blocks.lookup(&data.last_block))
+                .map(|block| (epoch, block))
+        })
+        .take_while(|(_, block)| block.chain_length() > stable.chain_length)
+        .find(|(_, block)| tip_length - u32::from(block.chain_length()) > epoch_stability_depth)
+        .map(|(epoch, block)| (block.id(), block.chain_length(), epoch))
+}
+
 fn apply_block_to_transactions(
     mut transactions: Transactions,
     block: &ExplorerBlock,
@@ -527,6 +989,122 @@ fn apply_block_to_addresses(mut addresses: Addresses, block: &ExplorerBlock) ->
     Ok(addresses)
 }
 
+/// update the balance of the addresses spending and receiving value in the
+/// block, and the delegation of the accounts. The rewards are credited to the
+/// accounts separately, see `apply_rewards_to_address_states`.
+fn apply_block_to_address_states(
+    mut states: AddressStates,
+    block: &ExplorerBlock,
+    discrimination: Discrimination,
+) -> Result<AddressStates> {
+    // the outputs spent in the same block must be created first
+    let mut transactions: Vec<_> = block.transactions.values().collect();
+    transactions.sort_unstable_by_key(|tx| tx.offset_in_block);
+
+    for tx in transactions {
+        for input in tx.inputs() {
+            // the ledger has already checked the inputs of the block
+            let balance = states
+                .lookup(&input.address)
+                .map_or_else(Value::zero, |state| state.balance)
+                .checked_sub(input.value)
+                .map_err(|_| ErrorKind::InputExceedsBalance(format!("{}", tx.id())))?;
+            states = update_address_state(states, &input.address, |state| {
+                state.balance = balance;
+                match &input.utxo_pointer {
+                    Some(pointer) => {
+                        if let Ok(utxos) = state
+                            .utxos
+                            .remove(&(pointer.transaction_id, pointer.output_index))
+                        {
+                            state.utxos = utxos;
+                        }
+                    }
+                    None => state.spending_counter += 1,
+                }
+            });
+        }
+
+        for (index, output) in tx.outputs().iter().enumerate() {
+            states = update_address_state(states, &output.address, |state| {
+                state.balance = state
+                    .balance
+                    .checked_add(output.value)
+                    .expect("balance to not overflow");
+                if !output.address.is_account() {
+                    state.utxos = state
+                        .utxos
+                        .insert((tx.id(), index.try_into().unwrap()), Arc::new(output.value))
+                        .expect("output to be indexed only once");
+                }
+            });
+        }
+
+        if let Some((address, delegation)) = delegation_certificate(tx, discrimination) {
+            states = update_address_state(states, &address, |state| state.delegation = delegation);
+        }
+    }
+
+    Ok(states)
+}
+
+/// the account and the delegation set by the certificate of the transaction,
+/// if it is a delegation certificate
+fn delegation_certificate(
+    tx: &ExplorerTransaction,
+    discrimination: Discrimination,
+) -> Option<(ExplorerAddress, DelegationType)> {
+    match &tx.certificate {
+        Some(Certificate::StakeDelegation(delegation)) => {
+            let kind = match delegation.account_id.to_single_account() {
+                Some(single) => chain_addr::Kind::Account(single.into()),
+                None => chain_addr::Kind::Multisig(
+                    delegation
+                        .account_id
+                        .to_multi_account()
+                        .as_ref()
+                        .try_into()
+                        .expect("multisig identifier size doesn't match address kind"),
+                ),
+            };
+            Some((
+                ExplorerAddress::New(Address(discrimination, kind)),
+                delegation.get_delegation_type().clone(),
+            ))
+        }
+        // the owner delegates the account used as input of the certificate
+        Some(Certificate::OwnerStakeDelegation(delegation)) => tx.inputs().first().map(|input| {
+            (
+                input.address.clone(),
+                delegation.get_delegation_type().clone(),
+            )
+        }),
+        _ => None,
+    }
+}
+
+fn delegated_pools(delegation: &DelegationType) -> Vec<PoolId> {
+    match delegation {
+        DelegationType::NonDelegated => vec![],
+        DelegationType::Full(id) => vec![id.clone()],
+        DelegationType::Ratio(ratio) => ratio.pools().iter().map(|(id, _)| id.clone()).collect(),
+    }
+}
+
+fn update_address_state(
+    states: AddressStates,
+    address: &ExplorerAddress,
+    f: impl FnOnce(&mut AddressState),
+) -> AddressStates {
+    let mut state = states
+        .lookup(address)
+        .map(|state| state.as_ref().clone())
+        .unwrap_or_default();
+    f(&mut state);
+    let state = Arc::new(state);
+    states.insert_or_update_simple(address.clone(), Arc::clone(&state), |_| Some(state))
+}
+
 fn apply_block_to_epochs(epochs: Epochs, block: &ExplorerBlock) -> Epochs {
     let epoch_id = block.date().epoch;
     let block_id = block.id();
@@ -564,10 +1142,16 @@ fn apply_block_to_chain_lengths(
         })
 }
 
+/// index the blocks produced by the pools, the certificates registering,
+/// updating and retiring the pools, and the delegators of the pools. The
+/// previous delegation of the accounts is taken from the address states of
+/// the parent block.
 fn apply_block_to_stake_pools(
     data: StakePool,
     blocks: StakePoolBlocks,
+    address_states: &AddressStates,
     block: &ExplorerBlock,
+    discrimination: Discrimination,
 ) -> (StakePool, StakePoolBlocks) {
     let mut blocks = match &block.producer() {
         indexing::BlockProducer::StakePool(id) => blocks
@@ -583,32 +1167,200 @@ fn apply_block_to_stake_pools(
     };
 
     let mut data = data;
+    // the delegations changed by the previous transactions of the block
+    let mut delegations: HashMap<ExplorerAddress, DelegationType> = HashMap::new();
 
-    for tx in block.transactions.values() {
-        if let Some(cert) = &tx.certificate {
-            blocks = match cert {
-                Certificate::PoolRegistration(registration) => blocks
-                    .insert(registration.to_id(), Arc::new(PersistentSequence::new()))
-                    .expect("pool was registered more than once"),
-                _ => blocks,
-            };
-            data = match cert {
-                Certificate::PoolRegistration(registration) => data
-                    .insert(
-                        registration.to_id(),
-                        Arc::new(StakePoolData {
-                            registration: registration.clone(),
-                        }),
-                    )
-                    .expect("pool was registered more than once"),
-                _ => data,
-            };
+    let mut transactions: Vec<_> = block.transactions.values().collect();
+    transactions.sort_unstable_by_key(|tx| tx.offset_in_block);
+
+    for tx in transactions {
+        if let Some((address, delegation)) = delegation_certificate(tx, discrimination) {
+            let previous = delegations
+                .get(&address)
+                .cloned()
+                .or_else(|| {
+                    address_states
+                        .lookup(&address)
+                        .map(|state| state.delegation.clone())
+                })
+                .unwrap_or(DelegationType::NonDelegated);
+            for pool in delegated_pools(&previous) {
+                data = update_stake_pool(data, &pool, |pool| {
+                    pool.delegators = pool.delegators.remove_element(&address)
+                });
+            }
+            for pool in delegated_pools(&delegation) {
+                data = update_stake_pool(data, &pool, |pool| {
+                    pool.delegators = pool.delegators.add_element(address.clone())
+                });
+            }
+            delegations.insert(address, delegation);
+        }
+
+        if let Some(certificate) = &tx.certificate {
+            let (new_data, new_blocks) = apply_pool_certificate(data, blocks, certificate);
+            data = new_data;
+            blocks = new_blocks;
         }
     }
 
     (data, blocks)
 }
 
+/// index the certificate registering, updating or retiring a pool. A
+/// retired pool can be registered again: the registration replaces the
+/// previous one and cancels the retirement, as an update would.
+fn apply_pool_certificate(
+    data: StakePool,
+    blocks: StakePoolBlocks,
+    certificate: &Certificate,
+) -> (StakePool, StakePoolBlocks) {
+    match certificate {
+        Certificate::PoolRegistration(registration) => {
+            let id = registration.to_id();
+            if data.lookup(&id).is_some() {
+                let data = update_stake_pool(data, &id, |pool| {
+                    let previous = std::mem::replace(&mut pool.registration, registration.clone());
+                    pool.previous_registrations = pool.previous_registrations.append(previous);
+                    pool.retirement = None;
+                });
+                return (data, blocks);
+            }
+            let blocks = blocks
+                .insert(id.clone(), Arc::new(PersistentSequence::new()))
+                .expect("the blocks of a pool to be indexed along with its registration");
+            let data = data
+                .insert(id, Arc::new(StakePoolData::new(registration.clone())))
+                .expect("the pool not to be registered yet");
+            (data, blocks)
+        }
+        Certificate::PoolUpdate(update) => {
+            let data = update_stake_pool(data, &update.pool_id, |pool| {
+                let previous =
+                    std::mem::replace(&mut pool.registration, update.new_pool_reg.clone());
+                pool.previous_registrations = pool.previous_registrations.append(previous);
+            });
+            (data, blocks)
+        }
+        Certificate::PoolRetirement(retirement) => {
+            let data = update_stake_pool(data, &retirement.pool_id, |pool| {
+                pool.retirement = Some(retirement.clone())
+            });
+            (data, blocks)
+        }
+        _ => (data, blocks),
+    }
+}
+
+fn update_stake_pool(
+    data: StakePool,
+    id: &PoolId,
+    f: impl FnOnce(&mut StakePoolData),
+) -> StakePool {
+    data.update(id, |pool| -> std::result::Result<_, Infallible> {
+        let mut pool = pool.as_ref().clone();
+        f(&mut pool);
+        Ok(Some(Arc::new(pool)))
+    })
+    .expect("the stake pool to be registered")
+}
+
+/// record the stake distribution used for the leader election of the epoch
+/// of the block, if it is the first block of its epoch on this branch. The
+/// distribution is taken from the leadership of the block, it is not known
+/// if the block's `Ref` has already left the cache of the node, or with the
+/// BFT consensus.
+fn apply_block_to_stake_distributions(
+    distributions: StakeDistributions,
+    block: &ExplorerBlock,
+    block_ref: Option<Arc<Ref>>,
+) -> StakeDistributions {
+    let epoch = block.date().epoch;
+    if distributions.lookup(&epoch).is_some() {
+        return distributions;
+    }
+
+    let block_ref = match block_ref {
+        Some(block_ref) => block_ref,
+        None => return distributions,
+    };
+    let pools = match block_ref.epoch_leadership_schedule().consensus() {
+        LeadershipConsensus::GenesisPraos(genesis_praos) => genesis_praos
+            .distribution()
+            .to_pools
+            .iter()
+            .map(|(id, info)| (id.clone(), Value(info.stake.total.into())))
+            .collect(),
+        _ => return distributions,
+    };
+
+    distributions
+        .insert(epoch, Arc::new(StakeDistributionData { pools }))
+        .expect("the stake distribution of the epoch to be recorded only once")
+}
+
+/// record the rewards distributed at the transition into the epoch of the
+/// block, if it is the first block of its epoch on this branch. The rewards
+/// are the ones the node distributed when it applied the block. The recorded
+/// rewards are returned to be credited to the accounts.
+fn apply_block_to_epoch_rewards(
+    rewards: EpochRewards,
+    block: &ExplorerBlock,
+    transition_rewards: Option<&EpochTransitionRewards>,
+    discrimination: Discrimination,
+) -> (EpochRewards, Option<Arc<EpochRewardsData>>) {
+    let (info, treasury_balance) = match transition_rewards {
+        Some(transition_rewards) => (
+            &transition_rewards.info,
+            transition_rewards.treasury_balance,
+        ),
+        None => return (rewards, None),
+    };
+
+    let data = Arc::new(EpochRewardsData {
+        drawn: info.drawn,
+        fees: info.fees,
+        treasury: info.treasury,
+        treasury_balance,
+        pools: info
+            .stake_pools
+            .iter()
+            .map(|(id, (taxed, distributed))| (id.clone(), *taxed, *distributed))
+            .collect(),
+        accounts: info
+            .accounts
+            .iter()
+            .map(|(id, received)| {
+                let kind = chain_addr::Kind::Account(id.clone().into());
+                (
+                    ExplorerAddress::New(Address(discrimination, kind)),
+                    *received,
+                )
+            })
+            .collect(),
+    });
+
+    let rewards = rewards
+        .insert(block.date().epoch, Arc::clone(&data))
+        .expect("the rewards of the epoch to be recorded only once");
+    (rewards, Some(data))
+}
+
+fn apply_rewards_to_address_states(
+    mut states: AddressStates,
+    rewards: Option<&EpochRewardsData>,
+) -> AddressStates {
+    for (address, received) in rewards.iter().flat_map(|rewards| rewards.accounts.iter()) {
+        states = update_address_state(states, address, |state| {
+            state.balance = state
+                .balance
+                .checked_add(*received)
+                .expect("balance to not overflow")
+        });
+    }
+    states
+}
+
 impl BlockchainConfig {
     fn from_config_params(params: &ConfigParams) -> BlockchainConfig {
         let discrimination = params
@@ -638,12 +1390,49 @@ impl BlockchainConfig {
             .next()
             .expect("fee is not in config params");
 
+        let slot_duration = params
+            .iter()
+            .filter_map(|param| match param {
+                ConfigParam::SlotDuration(duration) => Some(u64::from(*duration)),
+                _ => None,
+            })
+            .next()
+            .expect("slot duration to be present");
+
+        let slots_per_epoch = params
+            .iter()
+            .filter_map(|param| match param {
+                ConfigParam::SlotsPerEpoch(slots) => Some(u64::from(*slots)),
+                _ => None,
+            })
+            .next()
+            .expect("slots per epoch to be present");
+
+        let epoch_stability_depth = params
+            .iter()
+            .filter_map(|param| match param {
+                ConfigParam::EpochStabilityDepth(depth) => Some(*depth),
+                _ => None,
+            })
+            .next()
+            .unwrap_or(DEFAULT_EPOCH_STABILITY_DEPTH);
+
         BlockchainConfig {
             discrimination,
             consensus_version,
             fees,
+            slot_duration,
+            slots_per_epoch,
+            epoch_stability_depth,
         }
     }
+
+    /// the number of seconds between the start of the blockchain and the
+    /// start of the slot of the date
+    fn time_since_block0(&self, date: BlockDate) -> u64 {
+        let slot = u64::from(date.epoch) * self.slots_per_epoch + u64::from(date.slot_id);
+        slot * self.slot_duration
+    }
 }
 
 impl Tip {
@@ -668,3 +1457,503 @@ impl Tip {
         get_lock(&self.0).map(|guard| *guard.state_ref.id())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::graphql::PoolStatus;
+    use super::indexing::{ExplorerInput, ExplorerOutput};
+    use super::*;
+    use crate::blockcfg::{BlockVersion, ContentsBuilder, EpochRewardsInfo, HeaderBuilderNew};
+    use crate::blockchain::{SledStore, Storage};
+    use chain_crypto::{Curve25519_2HashDH, Ed25519, SecretKey, SumEd25519_12};
+    use chain_impl_mockchain::certificate::{
+        OwnerStakeDelegation, PoolOwnersSigned, PoolPermissions, PoolRegistration, PoolRetirement,
+        PoolSignature, SignedCertificate, StakeDelegation,
+    };
+    use chain_impl_mockchain::leadership::genesis::GenesisPraosLeader;
+    use chain_impl_mockchain::rewards::TaxType;
+    use chain_impl_mockchain::transaction::{
+        AccountBindingSignature, SingleAccountBindingSignature, TxBuilder,
+        UnspecifiedAccountIdentifier, UtxoPointer,
+    };
+    use chain_time::DurationSeconds;
+    use jormungandr_lib::interfaces::{
+        Block0Configuration, BlockchainConfiguration, Initial, InitialUTxO,
+    };
+
+    fn registration() -> PoolRegistration {
+        PoolRegistration {
+            serial: 0,
+            permissions: PoolPermissions::new(1),
+            start_validity: DurationSeconds::from(0).into(),
+            owners: vec![SecretKey::<Ed25519>::generate(rand::thread_rng()).to_public()],
+            operators: vec![].into(),
+            rewards: TaxType::zero(),
+            reward_account: None,
+            keys: GenesisPraosLeader {
+                kes_public_key: SecretKey::<SumEd25519_12>::generate(rand::thread_rng())
+                    .to_public(),
+                vrf_public_key: SecretKey::<Curve25519_2HashDH>::generate(rand::thread_rng())
+                    .to_public(),
+            },
+        }
+    }
+
+    fn retirement(pool_id: PoolId, retirement_time: u64) -> Certificate {
+        Certificate::PoolRetirement(PoolRetirement {
+            pool_id,
+            retirement_time: DurationSeconds::from(retirement_time).into(),
+        })
+    }
+
+    #[test]
+    fn registered_pool_is_indexed() {
+        let registration = registration();
+        let id = registration.to_id();
+        let (data, blocks) = apply_pool_certificate(
+            StakePool::new(),
+            StakePoolBlocks::new(),
+            &Certificate::PoolRegistration(registration),
+        );
+        let pool = data.lookup(&id).expect("the pool to be registered");
+        assert!(pool.retirement.is_none());
+        assert_eq!(pool.previous_registrations.len(), 0);
+        assert!(PoolStatus::of(pool, 0) == PoolStatus::Active);
+        assert!(blocks.lookup(&id).is_some());
+    }
+
+    #[test]
+    fn retired_pool_status_depends_on_the_time() {
+        let registration = registration();
+        let id = registration.to_id();
+        let (data, blocks) = apply_pool_certificate(
+            StakePool::new(),
+            StakePoolBlocks::new(),
+            &Certificate::PoolRegistration(registration),
+        );
+        let (data, _) = apply_pool_certificate(data, blocks, &retirement(id.clone(), 100));
+        let pool = data.lookup(&id).unwrap();
+        assert!(PoolStatus::of(pool, 50) == PoolStatus::Retiring);
+        assert!(PoolStatus::of(pool, 100) == PoolStatus::Retired);
+        assert!(PoolStatus::of(pool, 150) == PoolStatus::Retired);
+    }
+
+    #[test]
+    fn retired_pool_can_be_registered_again() {
+        let registration = registration();
+        let id = registration.to_id();
+        let certificate = Certificate::PoolRegistration(registration);
+        let (data, blocks) =
+            apply_pool_certificate(StakePool::new(), StakePoolBlocks::new(), &certificate);
+        let block_id = HeaderHash::hash_bytes(&[1, 2, 3]);
+        let blocks = blocks
+            .update(&id, |pool_blocks| -> std::result::Result<_, Infallible> {
+                Ok(Some(Arc::new(pool_blocks.append(block_id))))
+            })
+            .unwrap();
+        let (data, blocks) = apply_pool_certificate(data, blocks, &retirement(id.clone(), 100));
+
+        let (data, blocks) = apply_pool_certificate(data, blocks, &certificate);
+
+        let pool = data.lookup(&id).unwrap();
+        assert!(pool.retirement.is_none());
+        assert_eq!(pool.previous_registrations.len(), 1);
+        assert!(PoolStatus::of(pool, 150) == PoolStatus::Active);
+        let pool_blocks = blocks.lookup(&id).unwrap();
+        assert_eq!(pool_blocks.len(), 1);
+        assert_eq!(*pool_blocks.get(0u32).unwrap().as_ref(), block_id);
+    }
+
+    #[test]
+    fn time_since_block0_of_a_block_date() {
+        let config = BlockchainConfig {
+            discrimination: Discrimination::Test,
+            consensus_version: ConsensusVersion::Bft,
+            fees: LinearFee::new(0, 0, 0),
+            slot_duration: 2,
+            slots_per_epoch: 10,
+            epoch_stability_depth: 10,
+        };
+        assert_eq!(config.time_since_block0(BlockDate::first()), 0);
+        assert_eq!(
+            config.time_since_block0(BlockDate {
+                epoch: 3,
+                slot_id: 4
+            }),
+            68
+        );
+    }
+
+    fn first_block_of_epoch_1() -> ExplorerBlock {
+        block_of(BlockDate {
+            epoch: 1,
+            slot_id: 0,
+        })
+    }
+
+    fn block_of(date: BlockDate) -> ExplorerBlock {
+        let contents = ContentsBuilder::new().into();
+        let header = HeaderBuilderNew::new(BlockVersion::Genesis, &contents)
+            .set_parent(&HeaderHash::hash_bytes(&[0]), ChainLength::from(1))
+            .set_date(date)
+            .to_unsigned_header()
+            .unwrap()
+            .generalize();
+        ExplorerBlock::resolve_from(
+            &Block { header, contents },
+            Discrimination::Test,
+            &Transactions::new(),
+            &Blocks::new(),
+        )
+    }
+
+    #[test]
+    fn transition_rewards_are_recorded_and_credited() {
+        let account = SecretKey::<Ed25519>::generate(rand::thread_rng()).to_public();
+        let mut info = EpochRewardsInfo {
+            drawn: Value(100),
+            fees: Value(20),
+            treasury: Value(30),
+            stake_pools: Default::default(),
+            accounts: Default::default(),
+        };
+        info.accounts.insert(account.clone().into(), Value(90));
+        let transition_rewards = EpochTransitionRewards {
+            info,
+            treasury_balance: Value(130),
+        };
+        let block = first_block_of_epoch_1();
+
+        let (rewards, data) = apply_block_to_epoch_rewards(
+            EpochRewards::new(),
+            &block,
+            Some(&transition_rewards),
+            Discrimination::Test,
+        );
+
+        let recorded = rewards.lookup(&1).expect("the rewards of the epoch 1");
+        assert_eq!(recorded.drawn, Value(100));
+        assert_eq!(recorded.fees, Value(20));
+        assert_eq!(recorded.treasury, Value(30));
+        assert_eq!(recorded.treasury_balance, Value(130));
+        let address = ExplorerAddress::New(Address(
+            Discrimination::Test,
+            chain_addr::Kind::Account(account),
+        ));
+        assert_eq!(recorded.accounts.len(), 1);
+        assert!(recorded.accounts[0] == (address.clone(), Value(90)));
+
+        let states = apply_rewards_to_address_states(AddressStates::new(), data.as_deref());
+        assert_eq!(states.lookup(&address).unwrap().balance, Value(90));
+    }
+
+    /// a Genesis block0 with one pool, to which an account of the given
+    /// balance delegates its stake
+    fn genesis_block0(stake: u64) -> (Block, PoolId) {
+        let owner = SecretKey::<Ed25519>::generate(rand::thread_rng());
+        let mut registration = registration();
+        registration.owners = vec![owner.to_public()];
+        let pool_id = registration.to_id();
+        let auth_data = TxBuilder::new()
+            .set_payload(&registration)
+            .set_ios(&[], &[])
+            .set_witnesses(&[])
+            .get_auth_data();
+        let signature = SingleAccountBindingSignature::new(&auth_data, |d| owner.sign_slice(&d.0));
+        let registration = SignedCertificate::PoolRegistration(
+            registration,
+            PoolSignature::Owners(PoolOwnersSigned {
+                signatures: vec![(0, signature)],
+            }),
+        );
+
+        let account = SecretKey::<Ed25519>::generate(rand::thread_rng());
+        let delegation = StakeDelegation {
+            account_id: UnspecifiedAccountIdentifier::from_single_account(
+                account.to_public().into(),
+            ),
+            delegation: DelegationType::Full(pool_id.clone()),
+        };
+        let auth_data = TxBuilder::new()
+            .set_payload(&delegation)
+            .set_ios(&[], &[])
+            .set_witnesses(&[])
+            .get_auth_data();
+        let signature =
+            AccountBindingSignature::new_single(&auth_data, |d| account.sign_slice(&d.0));
+        let delegation = SignedCertificate::StakeDelegation(delegation, signature);
+
+        let mut blockchain_configuration = BlockchainConfiguration::new(
+            Discrimination::Test,
+            ConsensusVersion::GenesisPraos,
+            LinearFee::new(0, 0, 0),
+        );
+        blockchain_configuration.consensus_leader_ids =
+            vec![SecretKey::<Ed25519>::generate(rand::thread_rng())
+                .to_public()
+                .into()];
+        let address = Address(
+            Discrimination::Test,
+            chain_addr::Kind::Account(account.to_public()),
+        );
+        let block0 = Block0Configuration {
+            blockchain_configuration,
+            initial: vec![
+                Initial::Fund(vec![InitialUTxO {
+                    address: address.into(),
+                    value: stake.into(),
+                }]),
+                Initial::Cert(registration.into()),
+                Initial::Cert(delegation.into()),
+            ],
+        }
+        .to_block();
+        (block0, pool_id)
+    }
+
+    async fn block0_ref(block0: &Block) -> Arc<Ref> {
+        let storage = Storage::new(SledStore::temporary().unwrap());
+        let blockchain = Blockchain::new(
+            block0.header.hash(),
+            storage,
+            std::time::Duration::from_secs(3600),
+            false,
+        );
+        blockchain
+            .load_from_block0(block0.clone())
+            .await
+            .unwrap()
+            .get_ref_std()
+            .await
+    }
+
+    #[test]
+    fn stake_distribution_of_a_genesis_epoch_is_recorded() {
+        let (block0, pool_id) = genesis_block0(1000);
+        let block = ExplorerBlock::resolve_from(
+            &block0,
+            Discrimination::Test,
+            &Transactions::new(),
+            &Blocks::new(),
+        );
+
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        let block0_ref = rt.block_on(block0_ref(&block0));
+        let distributions =
+            apply_block_to_stake_distributions(StakeDistributions::new(), &block, Some(block0_ref));
+
+        let distribution = distributions
+            .lookup(&0)
+            .expect("the stake distribution of the epoch 0");
+        assert_eq!(distribution.pools.len(), 1);
+        assert!(distribution.pools[0] == (pool_id, Value(1000)));
+    }
+
+    #[test]
+    fn stake_distribution_is_recorded_once_per_epoch() {
+        let (block0, pool_id) = genesis_block0(1000);
+        let (other_block0, _) = genesis_block0(500);
+        let first_block = ExplorerBlock::resolve_from(
+            &block0,
+            Discrimination::Test,
+            &Transactions::new(),
+            &Blocks::new(),
+        );
+        let second_block = block_of(BlockDate {
+            epoch: 0,
+            slot_id: 1,
+        });
+
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        let block0_ref = rt.block_on(block0_ref(&block0));
+        let other_ref = rt.block_on(block0_ref(&other_block0));
+        let distributions = apply_block_to_stake_distributions(
+            StakeDistributions::new(),
+            &first_block,
+            Some(block0_ref),
+        );
+        // a later block of the epoch does not overwrite the distribution
+        let distributions =
+            apply_block_to_stake_distributions(distributions, &second_block, Some(other_ref));
+
+        let distribution = distributions.lookup(&0).unwrap();
+        assert_eq!(distribution.pools.len(), 1);
+        assert!(distribution.pools[0] == (pool_id, Value(1000)));
+    }
+
+    fn account_address() -> ExplorerAddress {
+        let pk = SecretKey::<Ed25519>::generate(rand::thread_rng()).to_public();
+        ExplorerAddress::New(Address(Discrimination::Test, chain_addr::Kind::Account(pk)))
+    }
+
+    fn utxo_address() -> ExplorerAddress {
+        let pk = SecretKey::<Ed25519>::generate(rand::thread_rng()).to_public();
+        ExplorerAddress::New(Address(Discrimination::Test, chain_addr::Kind::Single(pk)))
+    }
+
+    fn transaction(
+        seed: u8,
+        offset_in_block: u32,
+        inputs: Vec<ExplorerInput>,
+        outputs: Vec<ExplorerOutput>,
+        certificate: Option<Certificate>,
+    ) -> ExplorerTransaction {
+        ExplorerTransaction {
+            id: FragmentId::hash_bytes(&[seed]),
+            inputs,
+            outputs,
+            certificate,
+            offset_in_block,
+        }
+    }
+
+    fn block_with(transactions: Vec<ExplorerTransaction>) -> ExplorerBlock {
+        let mut block = first_block_of_epoch_1();
+        block.transactions = transactions.into_iter().map(|tx| (tx.id, tx)).collect();
+        block
+    }
+
+    fn funded(address: &ExplorerAddress, balance: u64) -> AddressStates {
+        update_address_state(AddressStates::new(), address, |state| {
+            state.balance = Value(balance)
+        })
+    }
+
+    #[test]
+    fn utxo_created_and_spent_in_the_same_block() {
+        let (account, a, b) = (account_address(), utxo_address(), utxo_address());
+        let tx1 = transaction(
+            1,
+            0,
+            vec![ExplorerInput {
+                address: account.clone(),
+                value: Value(10),
+                utxo_pointer: None,
+            }],
+            vec![ExplorerOutput {
+                address: a.clone(),
+                value: Value(10),
+            }],
+            None,
+        );
+        let tx2 = transaction(
+            2,
+            1,
+            vec![ExplorerInput {
+                address: a.clone(),
+                value: Value(10),
+                utxo_pointer: Some(UtxoPointer {
+                    transaction_id: tx1.id,
+                    output_index: 0,
+                    value: Value(10),
+                }),
+            }],
+            vec![ExplorerOutput {
+                address: b.clone(),
+                value: Value(10),
+            }],
+            None,
+        );
+        let tx2_id = tx2.id;
+        // the spending transaction comes first in the map
+        let block = block_with(vec![tx2, tx1]);
+
+        let states =
+            apply_block_to_address_states(funded(&account, 15), &block, Discrimination::Test)
+                .unwrap();
+
+        let state = states.lookup(&account).unwrap();
+        assert_eq!(state.balance, Value(5));
+        assert_eq!(state.spending_counter, 1);
+        let state = states.lookup(&a).unwrap();
+        assert_eq!(state.balance, Value(0));
+        assert_eq!(state.utxos.iter().count(), 0);
+        let state = states.lookup(&b).unwrap();
+        assert_eq!(state.balance, Value(10));
+        assert_eq!(state.utxos.lookup(&(tx2_id, 0)), Some(&Value(10)));
+    }
+
+    #[test]
+    fn input_exceeding_the_balance_is_an_error() {
+        let account = account_address();
+        let block = block_with(vec![transaction(
+            1,
+            0,
+            vec![ExplorerInput {
+                address: account.clone(),
+                value: Value(10),
+                utxo_pointer: None,
+            }],
+            vec![],
+            None,
+        )]);
+
+        match apply_block_to_address_states(funded(&account, 5), &block, Discrimination::Test) {
+            Err(Error(ErrorKind::InputExceedsBalance(_), _)) => (),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("the overspending input was accepted"),
+        }
+    }
+
+    #[test]
+    fn stake_delegation_delegates_the_account() {
+        let pk = SecretKey::<Ed25519>::generate(rand::thread_rng()).to_public();
+        let pool_id = registration().to_id();
+        let certificate = Certificate::StakeDelegation(StakeDelegation {
+            account_id: UnspecifiedAccountIdentifier::from_single_account(pk.clone().into()),
+            delegation: DelegationType::Full(pool_id.clone()),
+        });
+        let block = block_with(vec![transaction(1, 0, vec![], vec![], Some(certificate))]);
+
+        let states =
+            apply_block_to_address_states(AddressStates::new(), &block, Discrimination::Test)
+                .unwrap();
+
+        let address =
+            ExplorerAddress::New(Address(Discrimination::Test, chain_addr::Kind::Account(pk)));
+        match &states.lookup(&address).unwrap().delegation {
+            DelegationType::Full(id) => assert!(*id == pool_id),
+            _ => panic!("the account is not fully delegated"),
+        }
+    }
+
+    #[test]
+    fn owner_stake_delegation_delegates_the_input_account() {
+        let account = account_address();
+        let pool_id = registration().to_id();
+        let certificate = Certificate::OwnerStakeDelegation(OwnerStakeDelegation {
+            delegation: DelegationType::Full(pool_id.clone()),
+        });
+        let block = block_with(vec![transaction(
+            1,
+            0,
+            vec![ExplorerInput {
+                address: account.clone(),
+                value: Value(1),
+                utxo_pointer: None,
+            }],
+            vec![],
+            Some(certificate),
+        )]);
+
+        let states =
+            apply_block_to_address_states(funded(&account, 1), &block, Discrimination::Test)
+                .unwrap();
+
+        let state = states.lookup(&account).unwrap();
+        assert_eq!(state.spending_counter, 1);
+        match &state.delegation {
+            DelegationType::Full(id) => assert!(*id == pool_id),
+            _ => panic!("the account is not fully delegated"),
+        }
+    }
+
+    #[test]
+    fn no_rewards_without_a_transition() {
+        let block = first_block_of_epoch_1();
+        let (rewards, data) =
+            apply_block_to_epoch_rewards(EpochRewards::new(), &block, None, Discrimination::Test);
+        assert!(rewards.lookup(&1).is_none());
+        assert!(data.is_none());
+    }
+}
@@ -0,0 +1,1150 @@
+//! on-disk copy of the explorer indexes.
+//!
+//! The indexes are saved as they are after the last block of an epoch which
+//! is deeper than the epoch stability depth, so they cannot be rolled back
+//! anymore. When the node restarts, the explorer loads them and only indexes
+//! the blocks after this one, instead of every block from the block0.
+//!
+//! The file is a snapshot of the indexes, not a store: it is written again as
+//! a whole each time the stable block moves, and it is only read when the node
+//! starts, the queries are answered from the indexes in memory. The snapshot
+//! only saves the startup time: the whole indexes are still held in memory,
+//! and the cost of every write grows linearly with the size of the chain.
+//!
+//! The file has the following layout, the integers are big endian and every
+//! index or list is prefixed with its number of entries (4 bytes):
+//!
+//! ```text
+//! | magic (4) | version (1) | block0 hash (32) | block hash (32) | chain length (4) | indexes |
+//! ```
+
+use super::indexing::{
+    AddressState, BlockProducer, EpochData, EpochRewardsData, ExplorerAddress, ExplorerBlock,
+    ExplorerInput, ExplorerOutput, ExplorerTransaction, Hamt, StakeDistributionData, StakePoolData,
+};
+use super::persistent_sequence::PersistentSequence;
+use super::set::HamtSet;
+use super::State;
+use crate::blockcfg::{BlockDate, ChainLength, HeaderHash};
+use crate::utils::framing::{read_frame, write_frame, FrameError};
+use chain_addr::Address;
+use chain_core::{mempack::ReadBuf, mempack::Readable as _, property::Serialize as _};
+use chain_crypto::{Ed25519, PublicKey};
+use chain_impl_mockchain::account::{DelegationRatio, DelegationType};
+use chain_impl_mockchain::certificate::{Certificate, PoolId, PoolRegistration, PoolRetirement};
+use chain_impl_mockchain::key::Hash;
+use chain_impl_mockchain::leadership::bft;
+use chain_impl_mockchain::transaction::UtxoPointer;
+use chain_impl_mockchain::value::Value;
+use jormungandr_lib::{crypto::hash::Hash as LibHash, interfaces::Certificate as LibCertificate};
+use std::{
+    fs::{self, File},
+    hash::Hash as StdHash,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::Arc,
+};
+use thiserror::Error;
+
+/// name of the file of the explorer indexes, in the node's storage directory
+pub const INDEX_FILE_NAME: &str = "explorer_index.bin";
+
+const MAGIC: [u8; 4] = *b"jexp";
+const VERSION: u8 = 1;
+
+#[derive(Debug, Error)]
+pub enum PersistenceError {
+    #[error("cannot access the explorer indexes")]
+    Io(#[from] io::Error),
+    #[error("the file is not an explorer index")]
+    NotIndex,
+    #[error("unsupported explorer index version {0}")]
+    UnsupportedVersion(u8),
+    #[error("the explorer index is malformed")]
+    Malformed,
+    #[error("the explorer index is built from the block0 {found}, not from {expected}")]
+    OtherBlockchain {
+        expected: HeaderHash,
+        found: HeaderHash,
+    },
+}
+
+/// the indexes at a block deeper than the epoch stability depth
+pub(super) struct Checkpoint {
+    pub block: HeaderHash,
+    pub chain_length: ChainLength,
+    pub state: State,
+}
+
+impl Checkpoint {
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        block0_hash: HeaderHash,
+    ) -> Result<Self, PersistenceError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(PersistenceError::NotIndex);
+        }
+        let version = u8::decode(&mut reader)?;
+        if version != VERSION {
+            return Err(PersistenceError::UnsupportedVersion(version));
+        }
+        let found = Hash::decode(&mut reader)?;
+        if found != block0_hash {
+            return Err(PersistenceError::OtherBlockchain {
+                expected: block0_hash,
+                found,
+            });
+        }
+
+        let block = Hash::decode(&mut reader)?;
+        let chain_length = ChainLength::decode(&mut reader)?;
+        let state = State {
+            parent_ref: None,
+            transactions: decode_hamt(&mut reader)?,
+            blocks: decode_hamt(&mut reader)?,
+            addresses: decode_hamt(&mut reader)?,
+            address_states: decode_hamt(&mut reader)?,
+            epochs: decode_hamt(&mut reader)?,
+            chain_lengths: decode_hamt(&mut reader)?,
+            stake_pool_data: decode_hamt(&mut reader)?,
+            stake_pool_blocks: decode_hamt(&mut reader)?,
+            stake_distributions: decode_hamt(&mut reader)?,
+            epoch_rewards: decode_hamt(&mut reader)?,
+        };
+        Ok(Checkpoint {
+            block,
+            chain_length,
+            state,
+        })
+    }
+
+    /// save the indexes, the file is replaced only once the indexes are
+    /// fully written
+    pub fn save<P: AsRef<Path>>(
+        &self,
+        path: P,
+        block0_hash: HeaderHash,
+    ) -> Result<(), PersistenceError> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            writer.write_all(&MAGIC)?;
+            VERSION.encode(&mut writer)?;
+            block0_hash.encode(&mut writer)?;
+            self.block.encode(&mut writer)?;
+            self.chain_length.encode(&mut writer)?;
+
+            let state = &self.state;
+            encode_hamt(&mut writer, &state.transactions)?;
+            encode_hamt(&mut writer, &state.blocks)?;
+            encode_hamt(&mut writer, &state.addresses)?;
+            encode_hamt(&mut writer, &state.address_states)?;
+            encode_hamt(&mut writer, &state.epochs)?;
+            encode_hamt(&mut writer, &state.chain_lengths)?;
+            encode_hamt(&mut writer, &state.stake_pool_data)?;
+            encode_hamt(&mut writer, &state.stake_pool_blocks)?;
+            encode_hamt(&mut writer, &state.stake_distributions)?;
+            encode_hamt(&mut writer, &state.epoch_rewards)?;
+
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+trait Encode {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+}
+
+trait Decode: Sized {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError>;
+}
+
+fn encode_len<W: Write>(writer: &mut W, len: usize) -> io::Result<()> {
+    (len as u32).encode(writer)
+}
+
+fn encode_hamt<W: Write, K: Encode, V: Encode>(
+    writer: &mut W,
+    hamt: &Hamt<K, V>,
+) -> io::Result<()> {
+    encode_len(writer, hamt.iter().count())?;
+    for (key, value) in hamt.iter() {
+        key.encode(writer)?;
+        value.as_ref().encode(writer)?;
+    }
+    Ok(())
+}
+
+fn decode_hamt<R: Read, K, V>(reader: &mut R) -> Result<Hamt<K, V>, PersistenceError>
+where
+    K: Decode + StdHash + Eq + Clone,
+    V: Decode,
+{
+    let mut hamt = Hamt::new();
+    for _ in 0..u32::decode(reader)? {
+        let key = K::decode(reader)?;
+        let value = V::decode(reader)?;
+        hamt = hamt
+            .insert(key, Arc::new(value))
+            .map_err(|_| PersistenceError::Malformed)?;
+    }
+    Ok(hamt)
+}
+
+fn encode_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_frame(writer, bytes)
+}
+
+fn decode_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>, PersistenceError> {
+    match read_frame(reader) {
+        Ok(Some(bytes)) => Ok(bytes),
+        Ok(None) => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+        Err(FrameError::Io(error)) => Err(error.into()),
+        Err(FrameError::Truncated) => Err(PersistenceError::Malformed),
+    }
+}
+
+impl Encode for u8 {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&[*self])
+    }
+}
+
+impl Decode for u8 {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        let mut bytes = [0; 1];
+        reader.read_exact(&mut bytes)?;
+        Ok(bytes[0])
+    }
+}
+
+impl Encode for u32 {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.to_be_bytes())
+    }
+}
+
+impl Decode for u32 {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        Ok(u32::from_be_bytes(bytes))
+    }
+}
+
+impl Encode for u64 {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.to_be_bytes())
+    }
+}
+
+impl Decode for u64 {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        let mut bytes = [0; 8];
+        reader.read_exact(&mut bytes)?;
+        Ok(u64::from_be_bytes(bytes))
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            None => 0u8.encode(writer),
+            Some(value) => {
+                1u8.encode(writer)?;
+                value.encode(writer)
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        match u8::decode(reader)? {
+            0 => Ok(None),
+            1 => T::decode(reader).map(Some),
+            _ => Err(PersistenceError::Malformed),
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        encode_len(writer, self.len())?;
+        self.iter().try_for_each(|item| item.encode(writer))
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        (0..u32::decode(reader)?)
+            .map(|_| T::decode(reader))
+            .collect()
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.0.encode(writer)?;
+        self.1.encode(writer)
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        Ok((A::decode(reader)?, B::decode(reader)?))
+    }
+}
+
+impl<A: Encode, B: Encode, C: Encode> Encode for (A, B, C) {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.0.encode(writer)?;
+        self.1.encode(writer)?;
+        self.2.encode(writer)
+    }
+}
+
+impl<A: Decode, B: Decode, C: Decode> Decode for (A, B, C) {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        Ok((A::decode(reader)?, B::decode(reader)?, C::decode(reader)?))
+    }
+}
+
+impl<T: Encode> Encode for PersistentSequence<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.len().encode(writer)?;
+        for index in 0..self.len() {
+            match self.get(index) {
+                Some(item) => item.as_ref().encode(writer)?,
+                None => return Err(io::Error::new(io::ErrorKind::Other, "missing element")),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Decode> Decode for PersistentSequence<T> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        let mut sequence = PersistentSequence::new();
+        for _ in 0..u64::decode(reader)? {
+            sequence = sequence.append(T::decode(reader)?);
+        }
+        Ok(sequence)
+    }
+}
+
+impl<T: Encode + StdHash + Eq + Clone> Encode for HamtSet<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        encode_len(writer, self.iter().count())?;
+        self.iter().try_for_each(|element| element.encode(writer))
+    }
+}
+
+impl<T: Decode + StdHash + Eq + Clone> Decode for HamtSet<T> {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        let mut set = HamtSet::new();
+        for _ in 0..u32::decode(reader)? {
+            set = set.add_element(T::decode(reader)?);
+        }
+        Ok(set)
+    }
+}
+
+impl Encode for Hash {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let bytes: [u8; 32] = LibHash::from(*self).into();
+        writer.write_all(&bytes)
+    }
+}
+
+impl Decode for Hash {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        let mut bytes = [0; 32];
+        reader.read_exact(&mut bytes)?;
+        Ok(LibHash::from(bytes).into_hash())
+    }
+}
+
+impl Encode for PoolId {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let bytes: [u8; 32] = LibHash::from(self.clone()).into();
+        writer.write_all(&bytes)
+    }
+}
+
+impl Decode for PoolId {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        let mut bytes = [0; 32];
+        reader.read_exact(&mut bytes)?;
+        Ok(LibHash::from(bytes).into_digest_of())
+    }
+}
+
+impl Encode for ChainLength {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        u32::from(*self).encode(writer)
+    }
+}
+
+impl Decode for ChainLength {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        u32::decode(reader).map(ChainLength::from)
+    }
+}
+
+impl Encode for BlockDate {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.epoch.encode(writer)?;
+        self.slot_id.encode(writer)
+    }
+}
+
+impl Decode for BlockDate {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        Ok(BlockDate {
+            epoch: u32::decode(reader)?,
+            slot_id: u32::decode(reader)?,
+        })
+    }
+}
+
+impl Encode for Value {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.0.encode(writer)
+    }
+}
+
+impl Decode for Value {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        u64::decode(reader).map(Value)
+    }
+}
+
+impl Encode for ExplorerAddress {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            ExplorerAddress::New(address) => {
+                0u8.encode(writer)?;
+                encode_bytes(writer, &address.to_bytes())
+            }
+            ExplorerAddress::Old(address) => {
+                1u8.encode(writer)?;
+                encode_bytes(writer, address.to_string().as_bytes())
+            }
+        }
+    }
+}
+
+impl Decode for ExplorerAddress {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        let tag = u8::decode(reader)?;
+        let bytes = decode_bytes(reader)?;
+        match tag {
+            0 => Address::from_bytes(&bytes)
+                .map(ExplorerAddress::New)
+                .map_err(|_| PersistenceError::Malformed),
+            1 => String::from_utf8(bytes)
+                .ok()
+                .and_then(|address| address.parse().ok())
+                .map(ExplorerAddress::Old)
+                .ok_or(PersistenceError::Malformed),
+            _ => Err(PersistenceError::Malformed),
+        }
+    }
+}
+
+impl Encode for Certificate {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let bytes = LibCertificate(self.clone()).serialize_as_vec()?;
+        encode_bytes(writer, &bytes)
+    }
+}
+
+impl Decode for Certificate {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        let bytes = decode_bytes(reader)?;
+        LibCertificate::read(&mut ReadBuf::from(&bytes))
+            .map(|certificate| certificate.0)
+            .map_err(|_| PersistenceError::Malformed)
+    }
+}
+
+impl Encode for PoolRegistration {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        Certificate::PoolRegistration(self.clone()).encode(writer)
+    }
+}
+
+impl Decode for PoolRegistration {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        match Certificate::decode(reader)? {
+            Certificate::PoolRegistration(registration) => Ok(registration),
+            _ => Err(PersistenceError::Malformed),
+        }
+    }
+}
+
+impl Encode for PoolRetirement {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        Certificate::PoolRetirement(self.clone()).encode(writer)
+    }
+}
+
+impl Decode for PoolRetirement {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        match Certificate::decode(reader)? {
+            Certificate::PoolRetirement(retirement) => Ok(retirement),
+            _ => Err(PersistenceError::Malformed),
+        }
+    }
+}
+
+impl Encode for DelegationType {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            DelegationType::NonDelegated => 0u8.encode(writer),
+            DelegationType::Full(pool) => {
+                1u8.encode(writer)?;
+                pool.encode(writer)
+            }
+            DelegationType::Ratio(ratio) => {
+                2u8.encode(writer)?;
+                ratio.pools().to_vec().encode(writer)
+            }
+        }
+    }
+}
+
+impl Decode for DelegationType {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        match u8::decode(reader)? {
+            0 => Ok(DelegationType::NonDelegated),
+            1 => PoolId::decode(reader).map(DelegationType::Full),
+            2 => {
+                let pools: Vec<(PoolId, u8)> = Vec::decode(reader)?;
+                // the parts of the ratio are the sum of the parts of the pools
+                let parts = pools
+                    .iter()
+                    .try_fold(0u8, |parts, (_, pool_parts)| parts.checked_add(*pool_parts))
+                    .ok_or(PersistenceError::Malformed)?;
+                DelegationRatio::new(parts, pools)
+                    .map(DelegationType::Ratio)
+                    .ok_or(PersistenceError::Malformed)
+            }
+            _ => Err(PersistenceError::Malformed),
+        }
+    }
+}
+
+impl Encode for UtxoPointer {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.transaction_id.encode(writer)?;
+        self.output_index.encode(writer)?;
+        self.value.encode(writer)
+    }
+}
+
+impl Decode for UtxoPointer {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        Ok(UtxoPointer {
+            transaction_id: Hash::decode(reader)?,
+            output_index: u8::decode(reader)?,
+            value: Value::decode(reader)?,
+        })
+    }
+}
+
+impl Encode for BlockProducer {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            BlockProducer::None => 0u8.encode(writer),
+            BlockProducer::StakePool(pool) => {
+                1u8.encode(writer)?;
+                pool.encode(writer)
+            }
+            BlockProducer::BftLeader(leader) => {
+                2u8.encode(writer)?;
+                encode_bytes(writer, leader.as_public_key().as_ref())
+            }
+        }
+    }
+}
+
+impl Decode for BlockProducer {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        match u8::decode(reader)? {
+            0 => Ok(BlockProducer::None),
+            1 => PoolId::decode(reader).map(BlockProducer::StakePool),
+            2 => PublicKey::<Ed25519>::from_binary(&decode_bytes(reader)?)
+                .map(|key| BlockProducer::BftLeader(bft::LeaderId::from(key)))
+                .map_err(|_| PersistenceError::Malformed),
+            _ => Err(PersistenceError::Malformed),
+        }
+    }
+}
+
+impl Encode for ExplorerInput {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.address.encode(writer)?;
+        self.value.encode(writer)?;
+        self.utxo_pointer.encode(writer)
+    }
+}
+
+impl Decode for ExplorerInput {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        Ok(ExplorerInput {
+            address: ExplorerAddress::decode(reader)?,
+            value: Value::decode(reader)?,
+            utxo_pointer: Option::decode(reader)?,
+        })
+    }
+}
+
+impl Encode for ExplorerOutput {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.address.encode(writer)?;
+        self.value.encode(writer)
+    }
+}
+
+impl Decode for ExplorerOutput {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        Ok(ExplorerOutput {
+            address: ExplorerAddress::decode(reader)?,
+            value: Value::decode(reader)?,
+        })
+    }
+}
+
+impl Encode for ExplorerTransaction {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.id.encode(writer)?;
+        self.inputs.encode(writer)?;
+        self.outputs.encode(writer)?;
+        self.certificate.encode(writer)?;
+        self.offset_in_block.encode(writer)
+    }
+}
+
+impl Decode for ExplorerTransaction {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        Ok(ExplorerTransaction {
+            id: Hash::decode(reader)?,
+            inputs: Vec::decode(reader)?,
+            outputs: Vec::decode(reader)?,
+            certificate: Option::decode(reader)?,
+            offset_in_block: u32::decode(reader)?,
+        })
+    }
+}
+
+impl Encode for ExplorerBlock {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        encode_len(writer, self.transactions.len())?;
+        for transaction in self.transactions.values() {
+            transaction.encode(writer)?;
+        }
+        self.id.encode(writer)?;
+        self.date.encode(writer)?;
+        self.chain_length.encode(writer)?;
+        self.parent_hash.encode(writer)?;
+        self.producer.encode(writer)?;
+        self.total_input.encode(writer)?;
+        self.total_output.encode(writer)
+    }
+}
+
+impl Decode for ExplorerBlock {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        let transactions: Vec<ExplorerTransaction> = Vec::decode(reader)?;
+        Ok(ExplorerBlock {
+            transactions: transactions
+                .into_iter()
+                .map(|transaction| (transaction.id, transaction))
+                .collect(),
+            id: Hash::decode(reader)?,
+            date: BlockDate::decode(reader)?,
+            chain_length: ChainLength::decode(reader)?,
+            parent_hash: Hash::decode(reader)?,
+            producer: BlockProducer::decode(reader)?,
+            total_input: Value::decode(reader)?,
+            total_output: Value::decode(reader)?,
+        })
+    }
+}
+
+impl Encode for AddressState {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.balance.encode(writer)?;
+        encode_hamt(writer, &self.utxos)?;
+        self.spending_counter.encode(writer)?;
+        self.delegation.encode(writer)
+    }
+}
+
+impl Decode for AddressState {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        Ok(AddressState {
+            balance: Value::decode(reader)?,
+            utxos: decode_hamt(reader)?,
+            spending_counter: u32::decode(reader)?,
+            delegation: DelegationType::decode(reader)?,
+        })
+    }
+}
+
+impl Encode for EpochData {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.first_block.encode(writer)?;
+        self.last_block.encode(writer)?;
+        self.total_blocks.encode(writer)
+    }
+}
+
+impl Decode for EpochData {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        Ok(EpochData {
+            first_block: Hash::decode(reader)?,
+            last_block: Hash::decode(reader)?,
+            total_blocks: u32::decode(reader)?,
+        })
+    }
+}
+
+impl Encode for StakePoolData {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.registration.encode(writer)?;
+        self.previous_registrations.encode(writer)?;
+        self.retirement.encode(writer)?;
+        self.delegators.encode(writer)
+    }
+}
+
+impl Decode for StakePoolData {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        Ok(StakePoolData {
+            registration: PoolRegistration::decode(reader)?,
+            previous_registrations: PersistentSequence::decode(reader)?,
+            retirement: Option::decode(reader)?,
+            delegators: HamtSet::decode(reader)?,
+        })
+    }
+}
+
+impl Encode for StakeDistributionData {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.pools.encode(writer)
+    }
+}
+
+impl Decode for StakeDistributionData {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        Ok(StakeDistributionData {
+            pools: Vec::decode(reader)?,
+        })
+    }
+}
+
+impl Encode for EpochRewardsData {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.drawn.encode(writer)?;
+        self.fees.encode(writer)?;
+        self.treasury.encode(writer)?;
+        self.treasury_balance.encode(writer)?;
+        self.pools.encode(writer)?;
+        self.accounts.encode(writer)
+    }
+}
+
+impl Decode for EpochRewardsData {
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, PersistenceError> {
+        Ok(EpochRewardsData {
+            drawn: Value::decode(reader)?,
+            fees: Value::decode(reader)?,
+            treasury: Value::decode(reader)?,
+            treasury_balance: Value::decode(reader)?,
+            pools: Vec::decode(reader)?,
+            accounts: Vec::decode(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_addr::{Discrimination, Kind};
+    use chain_crypto::{Curve25519_2HashDH, SecretKey, SumEd25519_12};
+    use chain_impl_mockchain::certificate::PoolPermissions;
+    use chain_impl_mockchain::leadership::genesis::GenesisPraosLeader;
+    use chain_impl_mockchain::rewards::TaxType;
+    use chain_time::DurationSeconds;
+    use tempfile::tempdir;
+
+    fn encoded<T: Encode>(value: &T) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        value.encode(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// the value is decoded from its whole encoding, and encoded again to the
+    /// same bytes
+    fn round_trip<T: Encode + Decode>(value: &T) -> T {
+        let bytes = encoded(value);
+        let mut reader = bytes.as_slice();
+        let decoded = T::decode(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(encoded(&decoded), bytes);
+        decoded
+    }
+
+    /// no prefix of the encoding of the value can be decoded
+    fn truncated<T: Encode + Decode>(value: &T) {
+        let bytes = encoded(value);
+        for len in 0..bytes.len() {
+            match T::decode(&mut &bytes[..len]) {
+                Err(PersistenceError::Io(_)) | Err(PersistenceError::Malformed) => (),
+                Err(error) => panic!("unexpected error: {}", error),
+                Ok(_) => panic!("decoded {} bytes out of {}", len, bytes.len()),
+            }
+        }
+    }
+
+    fn malformed<T: Decode>(bytes: &[u8]) {
+        match T::decode(&mut &bytes[..]) {
+            Err(PersistenceError::Malformed) => (),
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("malformed bytes were decoded"),
+        }
+    }
+
+    fn hash(byte: u8) -> HeaderHash {
+        LibHash::from([byte; 32]).into_hash()
+    }
+
+    fn pool_id(byte: u8) -> PoolId {
+        LibHash::from([byte; 32]).into_digest_of()
+    }
+
+    fn public_key() -> PublicKey<Ed25519> {
+        SecretKey::<Ed25519>::generate(rand::thread_rng()).to_public()
+    }
+
+    fn account() -> ExplorerAddress {
+        ExplorerAddress::New(Address(Discrimination::Test, Kind::Account(public_key())))
+    }
+
+    fn old_address() -> ExplorerAddress {
+        ExplorerAddress::Old(
+            "DdzFFzCqrht5TM5GznWhJ3GTpKawtJuA295F8igwXQXyt2ih1TL1XKnZqRBQBoLpyYVKfNKgCXPBUYruUneC83KjGK6QNAoBSqRJovbG"
+                .parse()
+                .unwrap(),
+        )
+    }
+
+    fn registration() -> PoolRegistration {
+        PoolRegistration {
+            serial: 1,
+            permissions: PoolPermissions::new(1),
+            start_validity: DurationSeconds::from(0).into(),
+            owners: vec![public_key()],
+            operators: vec![].into(),
+            rewards: TaxType::zero(),
+            reward_account: None,
+            keys: GenesisPraosLeader {
+                kes_public_key: SecretKey::<SumEd25519_12>::generate(rand::thread_rng())
+                    .to_public(),
+                vrf_public_key: SecretKey::<Curve25519_2HashDH>::generate(rand::thread_rng())
+                    .to_public(),
+            },
+        }
+    }
+
+    fn retirement() -> PoolRetirement {
+        PoolRetirement {
+            pool_id: pool_id(1),
+            retirement_time: DurationSeconds::from(100).into(),
+        }
+    }
+
+    fn transaction() -> ExplorerTransaction {
+        ExplorerTransaction {
+            id: hash(2),
+            inputs: vec![ExplorerInput {
+                address: old_address(),
+                value: Value(10),
+                utxo_pointer: Some(UtxoPointer {
+                    transaction_id: hash(3),
+                    output_index: 1,
+                    value: Value(10),
+                }),
+            }],
+            outputs: vec![ExplorerOutput {
+                address: account(),
+                value: Value(9),
+            }],
+            certificate: Some(Certificate::PoolRetirement(retirement())),
+            offset_in_block: 0,
+        }
+    }
+
+    fn block(producer: BlockProducer) -> ExplorerBlock {
+        let transaction = transaction();
+        ExplorerBlock {
+            transactions: vec![(transaction.id, transaction)].into_iter().collect(),
+            id: hash(4),
+            date: BlockDate {
+                epoch: 1,
+                slot_id: 2,
+            },
+            chain_length: ChainLength::from(3),
+            parent_hash: hash(5),
+            producer,
+            total_input: Value(10),
+            total_output: Value(9),
+        }
+    }
+
+    fn single<K: StdHash + Eq + Clone, V>(key: K, value: V) -> Hamt<K, V> {
+        Hamt::new().insert_or_update_simple(key, Arc::new(value), |_| None)
+    }
+
+    fn state() -> State {
+        let account = account();
+        let address_state = AddressState {
+            balance: Value(9),
+            utxos: single((hash(2), 0), Value(1)),
+            spending_counter: 2,
+            delegation: DelegationType::Full(pool_id(1)),
+        };
+        let mut pool = StakePoolData::new(registration());
+        pool.previous_registrations = pool.previous_registrations.append(registration());
+        pool.retirement = Some(retirement());
+        pool.delegators = pool.delegators.add_element(account.clone());
+        let rewards = EpochRewardsData {
+            drawn: Value(100),
+            fees: Value(10),
+            treasury: Value(5),
+            treasury_balance: Value(50),
+            pools: vec![(pool_id(1), Value(5), Value(100))],
+            accounts: vec![(account.clone(), Value(95))],
+        };
+        State {
+            parent_ref: None,
+            transactions: single(hash(2), hash(4)),
+            blocks: single(hash(4), block(BlockProducer::StakePool(pool_id(1)))),
+            addresses: single(account.clone(), PersistentSequence::new().append(hash(2))),
+            address_states: single(account, address_state),
+            epochs: single(
+                1,
+                EpochData {
+                    first_block: hash(4),
+                    last_block: hash(4),
+                    total_blocks: 1,
+                },
+            ),
+            chain_lengths: single(ChainLength::from(3), hash(4)),
+            stake_pool_data: single(pool_id(1), pool),
+            stake_pool_blocks: single(pool_id(1), PersistentSequence::new().append(hash(4))),
+            stake_distributions: single(
+                1,
+                StakeDistributionData {
+                    pools: vec![(pool_id(1), Value(1000))],
+                },
+            ),
+            epoch_rewards: single(1, rewards),
+        }
+    }
+
+    fn checkpoint() -> Checkpoint {
+        Checkpoint {
+            block: hash(4),
+            chain_length: ChainLength::from(3),
+            state: state(),
+        }
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let block0 = hash(0);
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("explorer_index");
+        checkpoint().save(&path, block0).unwrap();
+        assert!(!path.with_extension("tmp").exists());
+
+        let loaded = Checkpoint::load(&path, block0).unwrap();
+        assert_eq!(loaded.block, hash(4));
+        assert_eq!(u32::from(loaded.chain_length), 3);
+        let block = loaded.state.blocks.lookup(&hash(4)).unwrap();
+        assert_eq!(block.transactions.len(), 1);
+        assert!(loaded.state.stake_pool_data.lookup(&pool_id(1)).is_some());
+        assert!(loaded.state.epoch_rewards.lookup(&1).is_some());
+
+        // the loaded indexes are saved to the same bytes
+        let other_path = dir.path().join("other_explorer_index");
+        loaded.save(&other_path, block0).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), fs::read(&other_path).unwrap());
+    }
+
+    #[test]
+    fn checkpoint_of_another_blockchain() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("explorer_index");
+        checkpoint().save(&path, hash(0)).unwrap();
+        match Checkpoint::load(&path, hash(1)) {
+            Err(PersistenceError::OtherBlockchain { expected, found }) => {
+                assert_eq!(expected, hash(1));
+                assert_eq!(found, hash(0));
+            }
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("the indexes of another blockchain were loaded"),
+        }
+    }
+
+    #[test]
+    fn checkpoint_header_is_checked() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("explorer_index");
+        checkpoint().save(&path, hash(0)).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        let mut other_magic = bytes.clone();
+        other_magic[0] = b'x';
+        fs::write(&path, other_magic).unwrap();
+        match Checkpoint::load(&path, hash(0)) {
+            Err(PersistenceError::NotIndex) => (),
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("a file which is not an index was loaded"),
+        }
+
+        let mut other_version = bytes;
+        other_version[MAGIC.len()] = VERSION + 1;
+        fs::write(&path, other_version).unwrap();
+        match Checkpoint::load(&path, hash(0)) {
+            Err(PersistenceError::UnsupportedVersion(version)) => assert_eq!(version, VERSION + 1),
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("an unsupported version was loaded"),
+        }
+    }
+
+    #[test]
+    fn truncated_checkpoint_is_refused() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("explorer_index");
+        checkpoint().save(&path, hash(0)).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        for len in &[0, MAGIC.len() + 1, 50, bytes.len() / 2, bytes.len() - 1] {
+            fs::write(&path, &bytes[..*len]).unwrap();
+            match Checkpoint::load(&path, hash(0)) {
+                Err(PersistenceError::Io(_)) | Err(PersistenceError::Malformed) => (),
+                Err(error) => panic!("unexpected error: {}", error),
+                Ok(_) => panic!("{} bytes out of {} were loaded", len, bytes.len()),
+            }
+        }
+    }
+
+    #[test]
+    fn option_round_trip() {
+        round_trip(&None::<u32>);
+        assert_eq!(round_trip(&Some(7u32)), Some(7));
+        truncated(&Some(7u32));
+        malformed::<Option<u32>>(&[2, 0, 0, 0, 7]);
+    }
+
+    #[test]
+    fn explorer_address_round_trip() {
+        for address in vec![account(), old_address()] {
+            assert!(round_trip(&address) == address);
+            truncated(&address);
+        }
+        let mut unknown_tag = encoded(&account());
+        unknown_tag[0] = 2;
+        malformed::<ExplorerAddress>(&unknown_tag);
+        let mut not_an_address = encoded(&old_address());
+        *not_an_address.last_mut().unwrap() = 0xff;
+        malformed::<ExplorerAddress>(&not_an_address);
+    }
+
+    #[test]
+    fn delegation_type_round_trip() {
+        let ratio = DelegationRatio::new(3, vec![(pool_id(1), 1), (pool_id(2), 2)]).unwrap();
+        for delegation in vec![
+            DelegationType::NonDelegated,
+            DelegationType::Full(pool_id(1)),
+            DelegationType::Ratio(ratio),
+        ] {
+            round_trip(&delegation);
+            truncated(&delegation);
+        }
+        malformed::<DelegationType>(&[3]);
+
+        // the parts of the pools overflow the parts of the ratio
+        let mut overflow = vec![2];
+        vec![(pool_id(1), 200u8), (pool_id(2), 100u8)]
+            .encode(&mut overflow)
+            .unwrap();
+        malformed::<DelegationType>(&overflow);
+    }
+
+    #[test]
+    fn block_producer_round_trip() {
+        for producer in vec![
+            BlockProducer::None,
+            BlockProducer::StakePool(pool_id(1)),
+            BlockProducer::BftLeader(bft::LeaderId::from(public_key())),
+        ] {
+            round_trip(&block(producer.clone()));
+            round_trip(&producer);
+            truncated(&producer);
+        }
+        malformed::<BlockProducer>(&[3]);
+        // a BFT leader key of the wrong size
+        let mut short_key = vec![2];
+        encode_bytes(&mut short_key, &[0; 31]).unwrap();
+        malformed::<BlockProducer>(&short_key);
+    }
+
+    #[test]
+    fn certificate_round_trip() {
+        round_trip(&Certificate::PoolRegistration(registration()));
+        round_trip(&Certificate::PoolRetirement(retirement()));
+        round_trip(&registration());
+        round_trip(&retirement());
+        truncated(&retirement());
+        malformed::<Certificate>(&encoded(&vec![0xffu8; 8]));
+        // the certificates are decoded with their expected kind
+        malformed::<PoolRegistration>(&encoded(&retirement()));
+        malformed::<PoolRetirement>(&encoded(&registration()));
+    }
+
+    #[test]
+    fn indexes_round_trip() {
+        let state = state();
+        round_trip(&transaction());
+        truncated(&transaction());
+        round_trip(state.address_states.iter().next().unwrap().1.as_ref());
+        round_trip(state.stake_pool_data.iter().next().unwrap().1.as_ref());
+        round_trip(state.epoch_rewards.iter().next().unwrap().1.as_ref());
+        round_trip(state.stake_distributions.iter().next().unwrap().1.as_ref());
+        round_trip(state.epochs.iter().next().unwrap().1.as_ref());
+    }
+
+    #[test]
+    fn bytes_longer_than_the_input_are_refused() {
+        let mut bytes = Vec::new();
+        1000u32.encode(&mut bytes).unwrap();
+        bytes.extend_from_slice(&[0; 10]);
+        match decode_bytes(&mut bytes.as_slice()) {
+            Err(PersistenceError::Malformed) => (),
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("truncated bytes were decoded"),
+        }
+    }
+}
@@ -2,7 +2,8 @@ use crate::{
     blockcfg::{Value, ValueError},
     fragment::{Fragment, FragmentId},
 };
-use std::time::SystemTime;
+use chain_impl_mockchain::transaction::Transaction;
+use std::{cmp::Ordering, time::SystemTime};
 
 pub struct PoolEntry {
     // reference of the fragment stored in the pool
@@ -33,11 +34,7 @@ impl PoolEntry {
         let raw = fragment.to_raw();
         let fragment_size = raw.size_bytes_plus_size();
         let fragment_ref = raw.id();
-        // TODO: the fragment fee is not yet computed. Yet we should
-        // have an explicit fee in the message. So we need to be able
-        // to extract this information without the need to compute the
-        // fee from the ledger's fee settings.
-        let fragment_fee = Value::zero();
+        let fragment_fee = fragment_fee(fragment);
 
         PoolEntry {
             fragment_ref: fragment_ref,
@@ -70,6 +67,10 @@ impl PoolEntry {
         &self.fragment_size
     }
     #[inline]
+    pub fn fee_rate(&self) -> FeeRate {
+        FeeRate::new(self.fragment_fee, self.fragment_size)
+    }
+    #[inline]
     pub fn received_at(&self) -> &SystemTime {
         &self.received_at
     }
@@ -90,3 +91,93 @@ impl PoolEntry {
         self.ancestors_size + self.fragment_size
     }
 }
+
+/// fee paid by a fragment per byte of its serialized size, used to rank
+/// the fragments of the pool.
+///
+/// Two rates are compared without losing precision, by comparing
+/// `fee_a * size_b` with `fee_b * size_a`.
+#[derive(Debug, Clone, Copy)]
+pub struct FeeRate {
+    fee: Value,
+    size: usize,
+}
+
+impl FeeRate {
+    pub fn new(fee: Value, size: usize) -> Self {
+        FeeRate { fee, size }
+    }
+}
+
+impl Ord for FeeRate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let a = u128::from(self.fee.0) * other.size as u128;
+        let b = u128::from(other.fee.0) * self.size as u128;
+        a.cmp(&b)
+    }
+}
+
+impl PartialOrd for FeeRate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for FeeRate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FeeRate {}
+
+/// the fee declared by the fragment: the difference between its inputs
+/// and its outputs. This does not require the ledger's fee settings, but
+/// it is only what the fragment claims to pay until the fragment has been
+/// applied to a ledger.
+pub(crate) fn fragment_fee(fragment: &Fragment) -> Value {
+    match fragment {
+        Fragment::Transaction(tx) => transaction_fee(tx),
+        Fragment::OwnerStakeDelegation(tx) => transaction_fee(tx),
+        Fragment::StakeDelegation(tx) => transaction_fee(tx),
+        Fragment::PoolRegistration(tx) => transaction_fee(tx),
+        Fragment::PoolRetirement(tx) => transaction_fee(tx),
+        Fragment::PoolUpdate(tx) => transaction_fee(tx),
+        Fragment::Initial(_)
+        | Fragment::OldUtxoDeclaration(_)
+        | Fragment::UpdateProposal(_)
+        | Fragment::UpdateVote(_) => Value::zero(),
+    }
+}
+
+fn transaction_fee<P>(tx: &Transaction<P>) -> Value {
+    tx.total_input()
+        .and_then(|input| tx.total_output().and_then(|output| input - output))
+        .unwrap_or_else(|_| Value::zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fee_rate_is_fee_per_byte() {
+        assert!(FeeRate::new(Value(10), 100) < FeeRate::new(Value(20), 100));
+        assert!(FeeRate::new(Value(10), 100) > FeeRate::new(Value(10), 200));
+        assert!(FeeRate::new(Value(30), 200) < FeeRate::new(Value(20), 100));
+        assert_eq!(FeeRate::new(Value(10), 100), FeeRate::new(Value(20), 200));
+        assert_eq!(
+            FeeRate::new(Value::zero(), 100),
+            FeeRate::new(Value::zero(), 1)
+        );
+    }
+
+    #[test]
+    fn fee_rate_does_not_overflow() {
+        let max = FeeRate::new(Value(u64::max_value()), 1);
+        let almost_max = FeeRate::new(Value(u64::max_value() - 1), 1);
+        let huge_fragment = FeeRate::new(Value(u64::max_value()), usize::max_value());
+        assert!(almost_max < max);
+        assert!(huge_fragment < almost_max);
+    }
+}
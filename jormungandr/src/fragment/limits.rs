@@ -0,0 +1,258 @@
+use jormungandr_lib::interfaces::{FragmentOrigin, FragmentOriginLimits, RateLimit};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LimitError {
+    #[error("too many fragments pending from the same origin (at most {max_entries})")]
+    QuotaExceeded { max_entries: usize },
+    #[error("rate limit exceeded (at most {fragments_per_second} fragments per second)")]
+    RateLimited { fragments_per_second: u32 },
+}
+
+/// Per origin quotas and rate limits of the fragment pool.
+///
+/// every network peer is accounted separately, the REST interface
+/// is accounted as one single origin.
+pub struct OriginLimits {
+    network: FragmentOriginLimits,
+    rest: FragmentOriginLimits,
+    entries: HashMap<FragmentOrigin, usize>,
+    buckets: HashMap<FragmentOrigin, TokenBucket>,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl OriginLimits {
+    pub fn new(network: FragmentOriginLimits, rest: FragmentOriginLimits) -> Self {
+        OriginLimits {
+            network,
+            rest,
+            entries: HashMap::new(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    fn limits_of(&self, origin: &FragmentOrigin) -> &FragmentOriginLimits {
+        match origin {
+            FragmentOrigin::Network { .. } => &self.network,
+            FragmentOrigin::Rest => &self.rest,
+        }
+    }
+
+    /// check the given origin is allowed to add a new fragment in the pool,
+    /// consuming one token of its rate limit if it is.
+    pub fn check(&mut self, origin: FragmentOrigin, now: Instant) -> Result<(), LimitError> {
+        let limits = self.limits_of(&origin).clone();
+
+        if let Some(max_entries) = limits.max_entries {
+            let entries = self.entries.get(&origin).cloned().unwrap_or(0);
+            if entries >= max_entries {
+                return Err(LimitError::QuotaExceeded { max_entries });
+            }
+        }
+
+        if let Some(rate_limit) = limits.rate_limit {
+            let bucket = self
+                .buckets
+                .entry(origin)
+                .or_insert_with(|| TokenBucket::new(&rate_limit, now));
+            if !bucket.try_consume(&rate_limit, now) {
+                return Err(LimitError::RateLimited {
+                    fragments_per_second: rate_limit.fragments_per_second,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// account a fragment added to the pool for the given origin
+    pub fn inserted(&mut self, origin: FragmentOrigin) {
+        *self.entries.entry(origin).or_insert(0) += 1;
+    }
+
+    /// account a fragment of the given origin that left the pool
+    pub fn removed(&mut self, origin: &FragmentOrigin) {
+        if let Some(entries) = self.entries.get_mut(origin) {
+            *entries -= 1;
+            if *entries == 0 {
+                self.entries.remove(origin);
+            }
+        }
+    }
+
+    /// forget about the origins that have no pending fragments and
+    /// whose rate limit bucket is full again
+    pub fn purge_idle(&mut self, now: Instant) {
+        let network = &self.network;
+        let rest = &self.rest;
+        self.buckets.retain(|origin, bucket| {
+            let limits = match origin {
+                FragmentOrigin::Network { .. } => network,
+                FragmentOrigin::Rest => rest,
+            };
+            match &limits.rate_limit {
+                Some(rate_limit) => !bucket.is_full(rate_limit, now),
+                None => false,
+            }
+        });
+    }
+}
+
+impl TokenBucket {
+    fn new(rate_limit: &RateLimit, now: Instant) -> Self {
+        TokenBucket {
+            tokens: rate_limit.burst as f64,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, rate_limit: &RateLimit, now: Instant) {
+        let elapsed = now
+            .checked_duration_since(self.last_refill)
+            .unwrap_or_else(|| Duration::from_secs(0));
+        let tokens = self.tokens + elapsed.as_secs_f64() * rate_limit.fragments_per_second as f64;
+        self.tokens = tokens.min(rate_limit.burst as f64);
+        self.last_refill = now;
+    }
+
+    fn try_consume(&mut self, rate_limit: &RateLimit, now: Instant) -> bool {
+        self.refill(rate_limit, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn is_full(&mut self, rate_limit: &RateLimit, now: Instant) -> bool {
+        self.refill(rate_limit, now);
+        self.tokens >= rate_limit.burst as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE_LIMIT: RateLimit = RateLimit {
+        fragments_per_second: 2,
+        burst: 3,
+    };
+
+    fn rate_limited() -> FragmentOriginLimits {
+        FragmentOriginLimits {
+            max_entries: None,
+            rate_limit: Some(RATE_LIMIT),
+        }
+    }
+
+    #[test]
+    fn token_bucket_starts_full() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(&RATE_LIMIT, now);
+        assert!(bucket.is_full(&RATE_LIMIT, now));
+        for _ in 0..RATE_LIMIT.burst {
+            assert!(bucket.try_consume(&RATE_LIMIT, now));
+        }
+        assert!(!bucket.try_consume(&RATE_LIMIT, now));
+    }
+
+    #[test]
+    fn token_bucket_refills_over_time() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(&RATE_LIMIT, now);
+        for _ in 0..RATE_LIMIT.burst {
+            assert!(bucket.try_consume(&RATE_LIMIT, now));
+        }
+
+        let later = now + Duration::from_millis(500);
+        assert!(bucket.try_consume(&RATE_LIMIT, later));
+        assert!(!bucket.try_consume(&RATE_LIMIT, later));
+        assert!(!bucket.is_full(&RATE_LIMIT, later));
+    }
+
+    #[test]
+    fn token_bucket_does_not_exceed_burst() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(&RATE_LIMIT, now);
+        let later = now + Duration::from_secs(3600);
+        assert!(bucket.is_full(&RATE_LIMIT, later));
+        for _ in 0..RATE_LIMIT.burst {
+            assert!(bucket.try_consume(&RATE_LIMIT, later));
+        }
+        assert!(!bucket.try_consume(&RATE_LIMIT, later));
+    }
+
+    #[test]
+    fn token_bucket_ignores_time_going_backward() {
+        let now = Instant::now() + Duration::from_secs(1);
+        let mut bucket = TokenBucket::new(&RATE_LIMIT, now);
+        for _ in 0..RATE_LIMIT.burst {
+            assert!(bucket.try_consume(&RATE_LIMIT, now));
+        }
+        let earlier = now - Duration::from_secs(1);
+        assert!(!bucket.try_consume(&RATE_LIMIT, earlier));
+    }
+
+    #[test]
+    fn quota_is_per_origin() {
+        let limits = FragmentOriginLimits {
+            max_entries: Some(1),
+            rate_limit: None,
+        };
+        let mut origin_limits = OriginLimits::new(limits, FragmentOriginLimits::default());
+        let now = Instant::now();
+
+        let peer = FragmentOrigin::Network { id: None };
+        assert!(origin_limits.check(peer, now).is_ok());
+        origin_limits.inserted(peer);
+        match origin_limits.check(peer, now) {
+            Err(LimitError::QuotaExceeded { max_entries: 1 }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // the REST interface has no limits
+        origin_limits.inserted(FragmentOrigin::Rest);
+        assert!(origin_limits.check(FragmentOrigin::Rest, now).is_ok());
+
+        origin_limits.removed(&peer);
+        assert!(origin_limits.check(peer, now).is_ok());
+    }
+
+    #[test]
+    fn rate_limit_rejects_bursts() {
+        let mut origin_limits = OriginLimits::new(FragmentOriginLimits::default(), rate_limited());
+        let now = Instant::now();
+        for _ in 0..RATE_LIMIT.burst {
+            assert!(origin_limits.check(FragmentOrigin::Rest, now).is_ok());
+        }
+        match origin_limits.check(FragmentOrigin::Rest, now) {
+            Err(LimitError::RateLimited {
+                fragments_per_second: 2,
+            }) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn purge_idle_forgets_full_buckets() {
+        let mut origin_limits = OriginLimits::new(FragmentOriginLimits::default(), rate_limited());
+        let now = Instant::now();
+        assert!(origin_limits.check(FragmentOrigin::Rest, now).is_ok());
+
+        origin_limits.purge_idle(now);
+        assert_eq!(origin_limits.buckets.len(), 1);
+
+        origin_limits.purge_idle(now + Duration::from_secs(1));
+        assert!(origin_limits.buckets.is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::fragment::FragmentId;
+use crate::{fragment::FragmentId, notifier::Notifier};
 use futures03::future;
 use jormungandr_lib::interfaces::{FragmentLog, FragmentStatus};
 use std::sync::Arc;
@@ -11,8 +11,12 @@ use tokio02::{
 pub struct Logs(Arc<Mutex<internal::Logs>>);
 
 impl Logs {
-    pub fn new(max_entries: usize, ttl: Duration) -> Self {
-        Logs(Arc::new(Mutex::new(internal::Logs::new(max_entries, ttl))))
+    pub fn new(max_entries: usize, ttl: Duration, notifier: Notifier) -> Self {
+        Logs(Arc::new(Mutex::new(internal::Logs::new(
+            max_entries,
+            ttl,
+            notifier,
+        ))))
     }
 
     /// Returns true if fragment was registered
@@ -28,18 +32,38 @@ impl Logs {
         self.run_on_inner(move |inner| inner.insert_all(logs)).await
     }
 
+    /// Returns number of registered fragments, the given logs take the
+    /// place of the existing logs of the same fragments
+    pub async fn replace_all(
+        &mut self,
+        logs: impl IntoIterator<Item = FragmentLog>,
+    ) -> Result<usize, ()> {
+        self.run_on_inner(move |inner| {
+            logs.into_iter()
+                .map(|log| inner.replace(log))
+                .filter(|was_registered| *was_registered)
+                .count()
+        })
+        .await
+    }
+
     pub async fn exists(&self, fragment_id: FragmentId) -> Result<bool, ()> {
         self.run_on_inner(move |inner| inner.exists(&fragment_id.into()))
             .await
     }
 
-    pub async fn exist_all(
+    /// Returns the log of every given fragment, `None` for unknown fragments
+    pub async fn get_all(
         &self,
         fragment_ids: impl IntoIterator<Item = FragmentId>,
-    ) -> Result<Vec<bool>, ()> {
+    ) -> Result<Vec<Option<FragmentLog>>, ()> {
         let hashes = fragment_ids.into_iter().map(Into::into);
-        self.run_on_inner(move |inner| inner.exist_all(hashes))
-            .await
+        self.run_on_inner(move |inner| {
+            hashes
+                .map(|fragment_id| inner.get(&fragment_id).cloned())
+                .collect()
+        })
+        .await
     }
 
     pub async fn modify(
@@ -70,6 +94,21 @@ impl Logs {
         future::poll_fn(move |cx| inner.poll_purge(cx)).await
     }
 
+    /// insert logs restored from a snapshot, dropping the ones that would
+    /// have expired in the meantime. Returns number of restored logs
+    pub async fn restore_all(
+        &mut self,
+        logs: impl IntoIterator<Item = FragmentLog>,
+    ) -> Result<usize, ()> {
+        self.run_on_inner(move |inner| {
+            logs.into_iter()
+                .map(|log| inner.restore(log))
+                .filter(|was_restored| *was_restored)
+                .count()
+        })
+        .await
+    }
+
     pub async fn logs(&self) -> Result<Vec<FragmentLog>, ()> {
         self.run_on_inner(move |inner| inner.logs().cloned().collect())
             .await
@@ -86,35 +125,42 @@ impl Logs {
 }
 
 pub(super) mod internal {
+    use crate::notifier::Notifier;
     use futures03::{
         stream::Stream,
         task::{Context, Poll},
     };
     use jormungandr_lib::{
         crypto::hash::Hash,
-        interfaces::{FragmentLog, FragmentOrigin, FragmentStatus},
+        interfaces::{
+            FragmentLog, FragmentOrigin, FragmentStatus, FragmentStatusUpdate, NodeEvent,
+        },
     };
     use std::{
         collections::hash_map::{Entry, HashMap},
         pin::Pin,
-        time::Duration,
+        time::{Duration, SystemTime},
     };
     use tokio02::time::{self, delay_queue, DelayQueue, Instant};
 
+    /// new logs and status modifications are reported to the
+    /// subscribers of the notifier
     pub struct Logs {
         max_entries: usize,
         entries: HashMap<Hash, (FragmentLog, delay_queue::Key)>,
         expirations: Pin<Box<DelayQueue<Hash>>>,
         ttl: Duration,
+        notifier: Notifier,
     }
 
     impl Logs {
-        pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        pub fn new(max_entries: usize, ttl: Duration, notifier: Notifier) -> Self {
             Logs {
                 max_entries,
                 entries: HashMap::new(),
                 expirations: Box::pin(DelayQueue::new()),
                 ttl,
+                notifier,
             }
         }
 
@@ -122,15 +168,35 @@ pub(super) mod internal {
             self.entries.contains_key(fragment_id)
         }
 
-        pub fn exist_all(&self, fragment_ids: impl IntoIterator<Item = Hash>) -> Vec<bool> {
-            fragment_ids
-                .into_iter()
-                .map(|fragment_id| self.exists(&fragment_id))
-                .collect()
+        pub fn get(&self, fragment_id: &Hash) -> Option<&FragmentLog> {
+            self.entries.get(fragment_id).map(|(log, _)| log)
         }
 
         /// Returns true if fragment was registered
         pub fn insert(&mut self, log: FragmentLog) -> bool {
+            let ttl = self.ttl;
+            let fragment_id = *log.fragment_id();
+            let status = log.status().clone();
+            let inserted = self.insert_with_ttl(log, ttl);
+            if inserted {
+                self.notify(fragment_id, status);
+            }
+            inserted
+        }
+
+        /// Returns true if the fragment was registered, the ttl of the log
+        /// is reduced by the time elapsed since its last update
+        pub fn restore(&mut self, log: FragmentLog) -> bool {
+            let elapsed = SystemTime::now()
+                .duration_since(*log.last_updated_at().as_ref())
+                .unwrap_or_default();
+            match self.ttl.checked_sub(elapsed) {
+                Some(ttl) if ttl > Duration::from_secs(0) => self.insert_with_ttl(log, ttl),
+                _ => false,
+            }
+        }
+
+        fn insert_with_ttl(&mut self, log: FragmentLog, ttl: Duration) -> bool {
             if self.max_entries < self.entries.len() {
                 false
             } else {
@@ -139,12 +205,33 @@ pub(super) mod internal {
                     Entry::Occupied(_) => return false,
                     Entry::Vacant(entry) => entry,
                 };
-                let delay = self.expirations.insert(fragment_id, self.ttl);
+                let delay = self.expirations.insert(fragment_id, ttl);
                 entry.insert((log, delay));
                 true
             }
         }
 
+        /// Returns true if fragment was registered, the log takes the place
+        /// of the existing log of the fragment if there is one
+        pub fn replace(&mut self, log: FragmentLog) -> bool {
+            let ttl = self.ttl;
+            let fragment_id = *log.fragment_id();
+            let status = log.status().clone();
+            match self.entries.get_mut(&fragment_id) {
+                Some((existing, delay)) => {
+                    *existing = log;
+                    self.expirations.reset_at(delay, Instant::now() + ttl);
+                }
+                None => {
+                    if !self.insert_with_ttl(log, ttl) {
+                        return false;
+                    }
+                }
+            }
+            self.notify(fragment_id, status);
+            true
+        }
+
         /// Returns number of registered fragments
         pub fn insert_all(&mut self, logs: impl IntoIterator<Item = FragmentLog>) -> usize {
             logs.into_iter()
@@ -159,6 +246,7 @@ pub(super) mod internal {
         }
 
         pub fn modify(&mut self, fragment_id: &Hash, status: FragmentStatus) {
+            self.notify(*fragment_id, status.clone());
             let len = self.entries.len();
             match self.entries.entry(fragment_id.clone()) {
                 Entry::Occupied(mut entry) => {
@@ -178,7 +266,7 @@ pub(super) mod internal {
                         entry.insert((
                             FragmentLog::new(
                                 fragment_id.clone().into_hash(),
-                                FragmentOrigin::Network,
+                                FragmentOrigin::Network { id: None },
                             ),
                             delay,
                         ));
@@ -187,6 +275,14 @@ pub(super) mod internal {
             }
         }
 
+        fn notify(&self, fragment_id: Hash, status: FragmentStatus) {
+            self.notifier
+                .notify(NodeEvent::FragmentStatus(FragmentStatusUpdate {
+                    fragment_id,
+                    status,
+                }));
+        }
+
         pub fn poll_purge(&mut self, cx: &mut Context) -> Poll<Result<(), time::Error>> {
             loop {
                 match self.expirations.as_mut().poll_next(cx) {
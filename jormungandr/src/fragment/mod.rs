@@ -1,12 +1,18 @@
 mod entry;
+mod limits;
 mod logs;
 mod pool;
 mod process;
 pub mod selection;
+mod snapshot;
+#[cfg(test)]
+pub mod testing;
 
+pub(crate) use self::entry::fragment_fee;
 pub use self::entry::PoolEntry;
 pub use self::logs::Logs;
 pub use self::pool::Pool;
 pub use self::process::Process;
+pub use self::snapshot::{Snapshot, SnapshotError, SNAPSHOT_FILE_NAME};
 
 pub use crate::blockcfg::{Fragment, FragmentId};
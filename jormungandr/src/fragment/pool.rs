@@ -1,17 +1,30 @@
 use crate::{
     blockcfg::{BlockDate, Ledger, LedgerParameters},
-    fragment::{selection::FragmentSelectionAlgorithm, Fragment, FragmentId, Logs},
+    blockchain::Ref,
+    fragment::{
+        limits::{LimitError, OriginLimits},
+        selection::{rejection_reason, FragmentSelectionAlgorithm},
+        snapshot::{PendingFragment, Snapshot, SnapshotError},
+        Fragment, FragmentId, Logs,
+    },
     intercom::{NetworkMsg, PropagateMsg},
+    metrics::{self, RejectionReason},
+    notifier::Notifier,
     utils::async_msg::MessageBox,
 };
-use chain_core::property::Fragment as _;
+use chain_core::property::{Deserialize as _, Fragment as _, Serialize as _};
 use chain_impl_mockchain::transaction::Transaction;
 use futures03::{compat::*, future, sink::SinkExt};
-use jormungandr_lib::interfaces::{FragmentLog, FragmentOrigin, FragmentStatus};
+use jormungandr_lib::interfaces::{
+    FragmentLog, FragmentOrigin, FragmentStatus, FragmentSubmission, FragmentSubmissionStatus,
+};
 use slog::Logger;
-use std::{sync::Arc, time::Duration};
+use std::{path::Path, sync::Arc, time::Duration};
 use tokio02::{sync::Mutex, time};
 
+const EVICTED_REASON: &str = "evicted from the pool by a fragment paying a higher fee per byte";
+const LIMITED_REASON: &str = "refused by the limits of the origin";
+
 #[derive(Clone)]
 pub struct Pool {
     logs: Logs,
@@ -23,12 +36,17 @@ impl Pool {
     pub fn new(
         max_entries: usize,
         ttl: Duration,
+        origin_limits: OriginLimits,
         logs: Logs,
         network_msg_box: MessageBox<NetworkMsg>,
     ) -> Self {
         Pool {
             logs,
-            pool: Arc::new(Mutex::new(internal::Pool::new(max_entries, ttl))),
+            pool: Arc::new(Mutex::new(internal::Pool::new(
+                max_entries,
+                ttl,
+                origin_limits,
+            ))),
             network_msg_box,
         }
     }
@@ -37,42 +55,129 @@ impl Pool {
         &self.logs
     }
 
-    /// Returns number of registered fragments
+    /// Returns the outcome of the submission of every fragment, in the
+    /// order they were given.
+    ///
+    /// A fragment can only evict a pending fragment from the full pool once
+    /// it has been applied successfully to the ledger of the given tip.
     pub async fn insert_and_propagate_all(
         &mut self,
         origin: FragmentOrigin,
-        mut fragments: Vec<Fragment>,
+        fragments: Vec<Fragment>,
+        tip: Arc<Ref>,
         logger: Logger,
-    ) -> Result<usize, ()> {
-        fragments.retain(is_fragment_valid);
+    ) -> Result<Vec<FragmentSubmission>, ()> {
         if fragments.is_empty() {
-            return Ok(0);
+            return Ok(Vec::new());
         }
-        let mut logs = self.logs.clone();
+        let check_ledger = |fragment: &Fragment| check_against_ledger(&tip, fragment);
+        let (submissions, inserted_fragments) = self
+            .insert_all(origin, fragments, &check_ledger, &logger)
+            .await?;
         let mut network_msg_box = self.network_msg_box.clone().sink_compat();
-        let fragment_ids = fragments.iter().map(Fragment::id).collect::<Vec<_>>();
-        let fragments_exist_in_logs = self.logs.exist_all(fragment_ids).await?;
-        let mut pool = self.pool.lock().await;
-        let new_fragments = fragments
-            .into_iter()
-            .zip(fragments_exist_in_logs)
-            .filter(|(_, exists_in_logs)| !exists_in_logs)
-            .map(|(fragment, _)| fragment);
-        let new_fragments = pool.insert_all(new_fragments);
-        let count = new_fragments.len();
-        let fragment_logs = new_fragments
-            .iter()
-            .map(move |fragment| FragmentLog::new(fragment.id().into(), origin))
-            .collect::<Vec<_>>();
-        for fragment in new_fragments.into_iter() {
+        for fragment in inserted_fragments.into_iter() {
             let fragment_msg = NetworkMsg::Propagate(PropagateMsg::Fragment(fragment));
             network_msg_box
                 .send(fragment_msg)
                 .await
                 .map_err(|e| error!(logger, "cannot propagate fragment to network: {}", e))?;
         }
+        Ok(submissions)
+    }
+
+    /// insert the fragments in the pool and record them in the fragment
+    /// logs. Returns the outcome of the submission of every fragment along
+    /// with the fragments inserted in the pool.
+    async fn insert_all(
+        &mut self,
+        origin: FragmentOrigin,
+        fragments: Vec<Fragment>,
+        check_ledger: &(dyn Fn(&Fragment) -> Result<(), String> + Sync),
+        logger: &Logger,
+    ) -> Result<(Vec<FragmentSubmission>, Vec<Fragment>), ()> {
+        let mut logs = self.logs.clone();
+        let fragment_ids = fragments.iter().map(Fragment::id).collect::<Vec<_>>();
+        let fragments_logs = self.logs.get_all(fragment_ids).await?;
+        let mut submissions = Vec::with_capacity(fragments.len());
+        let mut inserted_fragments = Vec::new();
+        let mut evicted_fragments = Vec::new();
+        let mut fragment_logs = Vec::new();
+        // the logs of the fragments refused by the limits of an origin are
+        // replaced by the outcome of the later submissions
+        let mut replaced_logs = Vec::new();
+        {
+            let mut pool = self.pool.lock().await;
+            for (fragment, log) in fragments.into_iter().zip(fragments_logs) {
+                let fragment_id = fragment.id();
+                let limited = log.as_ref().map_or(false, is_refused_by_limits);
+                let status = if log.is_some() && !limited {
+                    FragmentSubmissionStatus::AlreadyKnown
+                } else if let Err(reason) = check_fragment(&fragment) {
+                    metrics::fragment_rejected(RejectionReason::Invalid);
+                    FragmentSubmissionStatus::Invalid { reason }
+                } else {
+                    match pool.insert(origin, fragment, check_ledger) {
+                        Ok((fragment, evicted)) => {
+                            let log = FragmentLog::new(fragment_id.into(), origin);
+                            if limited {
+                                replaced_logs.push(log);
+                            } else {
+                                fragment_logs.push(log);
+                            }
+                            inserted_fragments.push(fragment);
+                            evicted_fragments.extend(evicted);
+                            FragmentSubmissionStatus::Accepted
+                        }
+                        Err(internal::InsertError::AlreadyInPool) => {
+                            FragmentSubmissionStatus::AlreadyKnown
+                        }
+                        Err(internal::InsertError::PoolFull) => {
+                            metrics::fragment_rejected(RejectionReason::PoolFull);
+                            FragmentSubmissionStatus::PoolFull
+                        }
+                        Err(internal::InsertError::Ledger(reason)) => {
+                            metrics::fragment_rejected(RejectionReason::Ledger);
+                            // not logged: the fragment may become valid with
+                            // a later tip and be submitted again
+                            FragmentSubmissionStatus::Rejected { reason }
+                        }
+                        Err(internal::InsertError::Limit(error)) => {
+                            metrics::fragment_rejected(match error {
+                                LimitError::QuotaExceeded { .. } => RejectionReason::QuotaExceeded,
+                                LimitError::RateLimited { .. } => RejectionReason::RateLimited,
+                            });
+                            debug!(logger, "fragment rejected by the origin limits";
+                                "fragment_id" => %fragment_id,
+                                "reason" => %error,
+                            );
+                            let reason = format!("{}: {}", LIMITED_REASON, error);
+                            let mut log = FragmentLog::new(fragment_id.into(), origin);
+                            log.modify(FragmentStatus::Rejected {
+                                reason: reason.clone(),
+                            });
+                            replaced_logs.push(log);
+                            FragmentSubmissionStatus::Rejected { reason }
+                        }
+                    }
+                };
+                submissions.push(FragmentSubmission::new(fragment_id.into(), status));
+            }
+        }
         logs.insert_all(fragment_logs).await?;
-        Ok(count)
+        logs.replace_all(replaced_logs).await?;
+        if !evicted_fragments.is_empty() {
+            debug!(logger, "fragments evicted from the pool";
+                "count" => evicted_fragments.len(),
+            );
+            logs.modify_all(
+                evicted_fragments,
+                FragmentStatus::Rejected {
+                    reason: EVICTED_REASON.to_owned(),
+                },
+            )
+            .await?;
+        }
+        Ok((submissions, inserted_fragments))
     }
 
     pub async fn remove_added_to_block(
@@ -91,6 +196,92 @@ impl Pool {
         self.logs.poll_purge().await
     }
 
+    /// take a snapshot of the pending fragments and of the fragment logs
+    /// and save it in the given file
+    pub async fn save_snapshot(&self, path: &Path) -> Result<(), SnapshotError> {
+        let fragments = {
+            let pool = self.pool.lock().await;
+            pool.entries()
+                .map(|(fragment, origin, entry)| {
+                    Ok(PendingFragment {
+                        origin: *origin,
+                        received_at: (*entry.received_at()).into(),
+                        raw: fragment.serialize_as_vec()?,
+                    })
+                })
+                .collect::<Result<Vec<_>, std::io::Error>>()?
+        };
+        let logs = self.logs.logs().await.unwrap_or_default();
+        Snapshot { fragments, logs }.save(path)
+    }
+
+    /// restore the pending fragments and the fragment logs from a snapshot.
+    ///
+    /// Every fragment is revalidated against the ledger of the given tip,
+    /// the invalid ones are marked as rejected. The fragments that have been
+    /// in the pool for longer than the fragment ttl are dropped.
+    ///
+    /// Returns number of restored fragments
+    pub async fn restore_snapshot(
+        &mut self,
+        snapshot: Snapshot,
+        tip: Arc<Ref>,
+        logger: &Logger,
+    ) -> Result<usize, ()> {
+        let Snapshot { fragments, logs } = snapshot;
+        let restored_logs = self.logs.restore_all(logs).await?;
+        debug!(logger, "restored fragment logs from snapshot"; "count" => restored_logs);
+
+        let ledger_params = tip.epoch_ledger_parameters().as_ref().clone();
+        let block_date = tip.block_date();
+        let mut ledger_simulation = tip.ledger().as_ref().clone();
+        let now = std::time::SystemTime::now();
+        let mut rejected = Vec::new();
+        let mut count = 0;
+        let mut pool = self.pool.lock().await;
+        for pending in fragments {
+            let fragment = match Fragment::deserialize(pending.raw.as_slice()) {
+                Ok(fragment) => fragment,
+                Err(error) => {
+                    warn!(logger, "cannot decode fragment from the mempool snapshot"; "reason" => %error);
+                    continue;
+                }
+            };
+            let elapsed = now
+                .duration_since(pending.received_at.into())
+                .unwrap_or_default();
+            let ttl = match pool.ttl().checked_sub(elapsed) {
+                Some(ttl) if ttl > Duration::from_secs(0) => ttl,
+                _ => continue,
+            };
+            if !is_fragment_valid(&fragment) {
+                continue;
+            }
+            let fragment_id = fragment.id();
+            match ledger_simulation.apply_fragment(&ledger_params, &fragment, block_date) {
+                Ok(ledger_new) => {
+                    ledger_simulation = ledger_new;
+                }
+                Err(error) => {
+                    metrics::fragment_rejected(RejectionReason::Ledger);
+                    rejected.push((fragment_id, rejection_reason(&error)));
+                    continue;
+                }
+            }
+            if pool.restore(pending.origin, fragment, ttl).is_ok() {
+                count += 1;
+            }
+        }
+        drop(pool);
+
+        for (fragment_id, reason) in rejected {
+            self.logs
+                .modify(fragment_id, FragmentStatus::Rejected { reason })
+                .await?;
+        }
+        Ok(count)
+    }
+
     pub async fn select<SelectAlg>(
         &mut self,
         ledger: Ledger,
@@ -107,140 +298,330 @@ impl Pool {
         selection_alg.select(&ledger, &ledger_params, block_date, &mut logs, &mut pool);
         Ok(selection_alg)
     }
+
+    /// run the fragment selection on a simulation of the pool: the
+    /// fragments stay in the pool, the fragment logs and the metrics are
+    /// left untouched
+    pub async fn select_dry_run<SelectAlg>(
+        &self,
+        ledger: Ledger,
+        block_date: BlockDate,
+        ledger_params: LedgerParameters,
+        selection_alg: SelectAlg,
+    ) -> Result<SelectAlg, ()>
+    where
+        SelectAlg: FragmentSelectionAlgorithm,
+    {
+        let mut simulation = self.simulation().await;
+        simulation
+            .select(ledger, block_date, ledger_params, selection_alg)
+            .await
+    }
+
+    /// a pool holding a copy of the fragments of this pool, with logs of
+    /// its own, which does not record any metric
+    async fn simulation(&self) -> Pool {
+        let pool = self.pool.lock().await;
+        Pool {
+            logs: Logs::new(pool.fragments().count(), pool.ttl(), Notifier::new()),
+            pool: Arc::new(Mutex::new(pool.simulation())),
+            network_msg_box: self.network_msg_box.clone(),
+        }
+    }
+}
+
+/// the fragment was refused by the limits of an origin, it is still
+/// accepted when it is submitted again
+fn is_refused_by_limits(log: &FragmentLog) -> bool {
+    match log.status() {
+        FragmentStatus::Rejected { reason } => reason.starts_with(LIMITED_REASON),
+        _ => false,
+    }
+}
+
+fn check_against_ledger(tip: &Ref, fragment: &Fragment) -> Result<(), String> {
+    tip.ledger()
+        .apply_fragment(tip.epoch_ledger_parameters(), fragment, tip.block_date())
+        .map(|_| ())
+        .map_err(|error| rejection_reason(&error))
 }
 
 fn is_fragment_valid(fragment: &Fragment) -> bool {
+    check_fragment(fragment).is_ok()
+}
+
+/// check the fragment is acceptable in the pool, returns the reason why
+/// it is not otherwise
+fn check_fragment(fragment: &Fragment) -> Result<(), String> {
     match fragment {
         // never valid in the pool, only acceptable in genesis
-        Fragment::Initial(_) => false,
-        Fragment::OldUtxoDeclaration(_) => false,
+        Fragment::Initial(_) | Fragment::OldUtxoDeclaration(_) => {
+            Err("fragment is only valid in the genesis block".to_owned())
+        }
         // general transactions stuff
-        Fragment::Transaction(ref tx) => is_transaction_valid(tx),
-        Fragment::StakeDelegation(ref tx) => is_transaction_valid(tx),
-        Fragment::OwnerStakeDelegation(ref tx) => is_transaction_valid(tx),
-        Fragment::PoolRegistration(ref tx) => is_transaction_valid(tx),
-        Fragment::PoolRetirement(ref tx) => is_transaction_valid(tx),
+        Fragment::Transaction(ref tx) => check_transaction(tx),
+        Fragment::StakeDelegation(ref tx) => check_transaction(tx),
+        Fragment::OwnerStakeDelegation(ref tx) => check_transaction(tx),
+        Fragment::PoolRegistration(ref tx) => check_transaction(tx),
+        Fragment::PoolRetirement(ref tx) => check_transaction(tx),
         // disabled for now
-        Fragment::PoolUpdate(_) => false,
-        Fragment::UpdateProposal(_) => false,
-        Fragment::UpdateVote(_) => false,
+        Fragment::PoolUpdate(_) | Fragment::UpdateProposal(_) | Fragment::UpdateVote(_) => {
+            Err("fragment type is not accepted in the pool".to_owned())
+        }
     }
 }
 
-fn is_transaction_valid<E>(tx: &Transaction<E>) -> bool {
-    tx.verify_possibly_balanced().is_ok()
+fn check_transaction<E>(tx: &Transaction<E>) -> Result<(), String> {
+    tx.verify_possibly_balanced()
+        .map_err(|error| format!("transaction is not balanced: {}", error))
 }
 
 pub(super) mod internal {
     use super::*;
-    use crate::fragment::PoolEntry;
+    use crate::fragment::{entry::FeeRate, limits::LimitError, PoolEntry};
     use futures03::{
         stream::Stream,
         task::{Context, Poll},
     };
     use std::{
-        collections::{hash_map::Entry, HashMap, VecDeque},
+        collections::{BTreeMap, BTreeSet, HashMap},
         pin::Pin,
-        sync::Arc,
+        time::Instant,
     };
+    use thiserror::Error;
     use tokio02::time::{delay_queue, DelayQueue};
 
+    #[derive(Debug, Error)]
+    pub enum InsertError {
+        #[error("fragment already in the pool")]
+        AlreadyInPool,
+        #[error("the pool is full")]
+        PoolFull,
+        #[error(transparent)]
+        Limit(#[from] LimitError),
+        #[error("{0}")]
+        Ledger(String),
+    }
+
+    struct Item {
+        entry: PoolEntry,
+        fragment: Fragment,
+        origin: FragmentOrigin,
+        /// order of arrival in the pool
+        sequence: u64,
+        expiration: delay_queue::Key,
+    }
+
     pub struct Pool {
         max_entries: usize,
-        entries: HashMap<FragmentId, (Arc<PoolEntry>, Fragment, delay_queue::Key)>,
-        entries_by_time: VecDeque<FragmentId>,
+        entries: HashMap<FragmentId, Item>,
+        /// fragment IDs indexed by order of arrival
+        entries_by_time: BTreeMap<u64, FragmentId>,
+        /// fragment IDs indexed by fee per byte, then order of arrival
+        entries_by_fee: BTreeSet<(FeeRate, u64, FragmentId)>,
+        next_sequence: u64,
         expirations: Pin<Box<DelayQueue<FragmentId>>>,
         ttl: Duration,
+        origin_limits: OriginLimits,
+        /// the simulations of the pool do not record the metrics of the
+        /// node's mempool
+        records_metrics: bool,
     }
 
     impl Pool {
-        pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        pub fn new(max_entries: usize, ttl: Duration, origin_limits: OriginLimits) -> Self {
             Pool {
                 max_entries,
                 entries: HashMap::new(),
-                entries_by_time: VecDeque::new(),
+                entries_by_time: BTreeMap::new(),
+                entries_by_fee: BTreeSet::new(),
+                next_sequence: 0,
                 expirations: Box::pin(DelayQueue::new()),
                 ttl,
+                origin_limits,
+                records_metrics: true,
             }
         }
 
-        /// Returns clone of fragment if it was registered
-        pub fn insert(&mut self, fragment: Fragment) -> Option<Fragment> {
-            if self.max_entries < self.entries.len() {
-                None
-            } else {
-                let fragment_id = fragment.id();
-                let entry = match self.entries.entry(fragment_id) {
-                    Entry::Occupied(_) => return None,
-                    Entry::Vacant(vacant) => vacant,
-                };
-                let pool_entry = Arc::new(PoolEntry::new(&fragment));
-                let delay = self.expirations.insert(fragment_id, self.ttl);
-                entry.insert((pool_entry, fragment.clone(), delay));
-                self.entries_by_time.push_back(fragment_id);
-                Some(fragment)
-            }
+        /// Returns clone of fragment if it was registered, along with the ID
+        /// of the fragment that was evicted to make room for it, if any.
+        ///
+        /// When the pool is full the new fragment evicts the entry paying the
+        /// lowest fee per byte, only if it pays a strictly higher fee per byte.
+        /// As the fee is the one declared by the fragment, the fragment has to
+        /// pass `check_ledger` before it can evict anything.
+        pub fn insert(
+            &mut self,
+            origin: FragmentOrigin,
+            fragment: Fragment,
+            check_ledger: &dyn Fn(&Fragment) -> Result<(), String>,
+        ) -> Result<(Fragment, Option<FragmentId>), InsertError> {
+            let ttl = self.ttl;
+            self.insert_with(origin, fragment, ttl, Some(check_ledger))
         }
 
-        /// Returns clones of registered fragments
-        pub fn insert_all(
+        /// insert a fragment restored from a snapshot with its remaining time
+        /// to live. The fragment must already have been checked against the
+        /// ledger, the origin limits are accounted but not enforced.
+        pub fn restore(
             &mut self,
-            fragments: impl IntoIterator<Item = Fragment>,
-        ) -> Vec<Fragment> {
-            fragments
-                .into_iter()
-                .take(
-                    self.max_entries
-                        .checked_sub(self.entries.len())
-                        .unwrap_or(0),
-                )
-                .filter_map(|fragment| self.insert(fragment))
-                .collect()
+            origin: FragmentOrigin,
+            fragment: Fragment,
+            ttl: Duration,
+        ) -> Result<(Fragment, Option<FragmentId>), InsertError> {
+            self.insert_with(origin, fragment, ttl, None)
         }
 
-        pub fn remove(&mut self, fragment_id: &FragmentId) -> Option<Fragment> {
-            if let Some((_, fragment, cache_key)) = self.entries.remove(fragment_id) {
-                self.entries_by_time
-                    .iter()
-                    .position(|id| id == fragment_id)
-                    .map(|position| {
-                        self.entries_by_time.remove(position);
-                    });
-                self.expirations.remove(&cache_key);
-                Some(fragment)
+        /// the origin limits and the ledger are only checked when
+        /// `check_ledger` is given
+        fn insert_with(
+            &mut self,
+            origin: FragmentOrigin,
+            fragment: Fragment,
+            ttl: Duration,
+            check_ledger: Option<&dyn Fn(&Fragment) -> Result<(), String>>,
+        ) -> Result<(Fragment, Option<FragmentId>), InsertError> {
+            let fragment_id = fragment.id();
+            if self.entries.contains_key(&fragment_id) {
+                return Err(InsertError::AlreadyInPool);
+            }
+            let entry = PoolEntry::new(&fragment);
+            let fee_rate = entry.fee_rate();
+            let to_evict = if self.max_entries <= self.entries.len() {
+                match self.entries_by_fee.iter().next() {
+                    Some((lowest_rate, _, lowest_id)) if *lowest_rate < fee_rate => {
+                        Some(*lowest_id)
+                    }
+                    _ => return Err(InsertError::PoolFull),
+                }
             } else {
                 None
+            };
+            if let Some(check_ledger) = check_ledger {
+                self.origin_limits.check(origin, Instant::now())?;
+                if to_evict.is_some() {
+                    check_ledger(&fragment).map_err(InsertError::Ledger)?;
+                }
+            }
+            if let Some(evicted_id) = &to_evict {
+                self.remove(evicted_id);
+                if self.records_metrics {
+                    metrics::MEMPOOL_EVICTIONS.inc();
+                }
+            }
+
+            let sequence = self.next_sequence;
+            self.next_sequence += 1;
+            let expiration = self.expirations.insert(fragment_id, ttl);
+            self.origin_limits.inserted(origin);
+            self.entries_by_time.insert(sequence, fragment_id);
+            self.entries_by_fee
+                .insert((fee_rate, sequence, fragment_id));
+            self.entries.insert(
+                fragment_id,
+                Item {
+                    entry,
+                    fragment: fragment.clone(),
+                    origin,
+                    sequence,
+                    expiration,
+                },
+            );
+            self.record_size();
+            Ok((fragment, to_evict))
+        }
+
+        pub fn ttl(&self) -> Duration {
+            self.ttl
+        }
+
+        /// copy of the pool with the same fragments in the same order, to
+        /// simulate a selection. The origin limits are not copied and the
+        /// copy does not record any metric.
+        pub fn simulation(&self) -> Pool {
+            let mut pool = Pool::new(
+                self.max_entries,
+                self.ttl,
+                OriginLimits::new(Default::default(), Default::default()),
+            );
+            pool.records_metrics = false;
+            for (fragment, origin, _) in self.entries() {
+                let _ = pool.restore(*origin, fragment.clone(), self.ttl);
+            }
+            pool
+        }
+
+        /// count a fragment rejected while selected from the pool
+        pub fn fragment_rejected(&self, reason: RejectionReason) {
+            if self.records_metrics {
+                metrics::fragment_rejected(reason);
+            }
+        }
+
+        fn record_size(&self) {
+            if self.records_metrics {
+                metrics::MEMPOOL_FRAGMENTS.set(self.entries.len() as i64);
             }
         }
 
+        pub fn remove(&mut self, fragment_id: &FragmentId) -> Option<Fragment> {
+            let item = self.entries.remove(fragment_id)?;
+            self.expirations.remove(&item.expiration);
+            Some(self.unindex(item))
+        }
+
         pub fn remove_all(&mut self, fragment_ids: impl IntoIterator<Item = FragmentId>) {
-            // TODO fix terrible performance, entries_by_time are linear searched N times
             for fragment_id in fragment_ids {
                 self.remove(&fragment_id);
             }
         }
 
+        /// Iterate over the fragments of the pool, in the order they were received
+        pub fn fragments(&self) -> impl Iterator<Item = &Fragment> {
+            self.entries_by_time
+                .values()
+                .map(move |fragment_id| &self.entries[fragment_id].fragment)
+        }
+
+        /// Iterate over the fragments of the pool along with their origin and
+        /// entry, in the order they were received
+        pub fn entries(&self) -> impl Iterator<Item = (&Fragment, &FragmentOrigin, &PoolEntry)> {
+            self.entries_by_time.values().map(move |fragment_id| {
+                let item = &self.entries[fragment_id];
+                (&item.fragment, &item.origin, &item.entry)
+            })
+        }
+
         pub fn remove_oldest(&mut self) -> Option<Fragment> {
-            let fragment_id = self.entries_by_time.pop_front()?;
-            let (_, fragment, cache_key) = self
-                .entries
+            let fragment_id = *self.entries_by_time.values().next()?;
+            let fragment = self
                 .remove(&fragment_id)
                 .expect("Pool lost fragment ID consistency");
-            self.expirations.remove(&cache_key);
             Some(fragment)
         }
 
+        /// remove the item from the indexes, the item must already
+        /// be removed from the entries
+        fn unindex(&mut self, item: Item) -> Fragment {
+            self.entries_by_time.remove(&item.sequence);
+            self.entries_by_fee.remove(&(
+                item.entry.fee_rate(),
+                item.sequence,
+                *item.entry.fragment_ref(),
+            ));
+            self.origin_limits.removed(&item.origin);
+            self.record_size();
+            item.fragment
+        }
+
         pub fn poll_purge(&mut self, cx: &mut Context) -> Poll<Result<(), time::Error>> {
+            self.origin_limits.purge_idle(Instant::now());
             loop {
                 match self.expirations.as_mut().poll_next(cx) {
                     Poll::Ready(Some(Ok(entry))) => {
-                        self.entries.remove(entry.get_ref());
-                        self.entries_by_time
-                            .iter()
-                            .position(|id| id == entry.get_ref())
-                            .map(|position| {
-                                self.entries_by_time.remove(position);
-                            });
+                        if let Some(item) = self.entries.remove(entry.get_ref()) {
+                            self.unindex(item);
+                        }
                     }
                     Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
                     Poll::Ready(None) => return Poll::Ready(Ok(())),
@@ -250,3 +631,459 @@ pub(super) mod internal {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::internal::{InsertError, Pool};
+    use super::*;
+    use crate::{
+        blockcfg::Block,
+        blockchain::{Blockchain, SledStore, Storage},
+        fragment::{
+            limits::OriginLimits,
+            selection::HighestFeeFirst,
+            testing::{empty_block0, empty_ledger, transaction},
+        },
+        utils::async_msg,
+    };
+    use chain_impl_mockchain::{config::ConfigParam, fragment::config::ConfigParams};
+    use jormungandr_lib::{crypto::hash::Hash, interfaces::FragmentOriginLimits};
+
+    /// fragments of distinct IDs, none of them pays a fee
+    fn fragment(n: u32) -> Fragment {
+        let mut params = ConfigParams::new();
+        params.push(ConfigParam::SlotsPerEpoch(n));
+        Fragment::Initial(params)
+    }
+
+    fn pool(max_entries: usize) -> Pool {
+        Pool::new(
+            max_entries,
+            Duration::from_secs(3600),
+            OriginLimits::new(Default::default(), Default::default()),
+        )
+    }
+
+    /// the mempool of the node, with the logs of the submissions
+    fn mempool(max_entries: usize) -> super::Pool {
+        mempool_with_limits(
+            max_entries,
+            OriginLimits::new(Default::default(), Default::default()),
+        )
+    }
+
+    fn mempool_with_limits(max_entries: usize, origin_limits: OriginLimits) -> super::Pool {
+        let (network_msg_box, _) = async_msg::channel(1);
+        let ttl = Duration::from_secs(3600);
+        super::Pool::new(
+            max_entries,
+            ttl,
+            origin_limits,
+            Logs::new(100, ttl, Notifier::new()),
+            network_msg_box,
+        )
+    }
+
+    fn accept(_: &Fragment) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn reject(_: &Fragment) -> Result<(), String> {
+        Err("rejected by the ledger".to_owned())
+    }
+
+    async fn statuses(mempool: &super::Pool, fragments: &[&Fragment]) -> Vec<FragmentStatus> {
+        let fragment_ids = fragments.iter().map(|fragment| fragment.id());
+        mempool
+            .logs()
+            .get_all(fragment_ids)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|log| log.expect("fragment is not logged").status().clone())
+            .collect()
+    }
+
+    #[test]
+    fn simulation_keeps_the_pool() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut pool = pool(10);
+            for n in 0..3 {
+                pool.insert(FragmentOrigin::Rest, fragment(n), &accept)
+                    .unwrap();
+            }
+            let ids: Vec<_> = pool.fragments().map(|fragment| fragment.id()).collect();
+
+            let mut simulation = pool.simulation();
+            let simulated_ids: Vec<_> = simulation
+                .fragments()
+                .map(|fragment| fragment.id())
+                .collect();
+            assert_eq!(simulated_ids, ids);
+
+            // selecting from the simulation leaves the pool untouched
+            assert_eq!(simulation.remove_oldest().map(|f| f.id()), Some(ids[0]));
+            assert_eq!(simulation.fragments().count(), 2);
+            assert_eq!(pool.fragments().count(), 3);
+        });
+    }
+
+    #[test]
+    fn full_pool_only_evicts_for_a_higher_fee() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut pool = pool(2);
+            for n in 0..2 {
+                pool.insert(FragmentOrigin::Rest, fragment(n), &accept)
+                    .unwrap();
+            }
+            match pool.insert(FragmentOrigin::Rest, fragment(2), &accept) {
+                Err(InsertError::PoolFull) => (),
+                _ => panic!("a fragment paying the same fee should not evict"),
+            }
+            match pool.insert(FragmentOrigin::Rest, fragment(0), &accept) {
+                Err(InsertError::AlreadyInPool) => (),
+                _ => panic!("the fragment is already in the pool"),
+            }
+            assert_eq!(pool.fragments().count(), 2);
+        });
+    }
+
+    #[test]
+    fn higher_fee_per_byte_evicts_the_lowest_entry() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let logger = Logger::root(slog::Discard, o!());
+            let mut mempool = mempool(2);
+            let lowest = transaction(0, 10, 1);
+            let higher = transaction(1, 20, 1);
+            let highest = transaction(2, 30, 1);
+            let entries = vec![lowest.clone(), higher.clone()];
+            mempool
+                .insert_all(FragmentOrigin::Rest, entries, &accept, &logger)
+                .await
+                .unwrap();
+
+            let (submissions, inserted) = mempool
+                .insert_all(
+                    FragmentOrigin::Rest,
+                    vec![highest.clone()],
+                    &accept,
+                    &logger,
+                )
+                .await
+                .unwrap();
+            assert!(submissions[0].is_accepted());
+            assert_eq!(
+                inserted
+                    .iter()
+                    .map(|fragment| fragment.id())
+                    .collect::<Vec<_>>(),
+                vec![highest.id()]
+            );
+            let ids: Vec<_> = mempool
+                .pool
+                .lock()
+                .await
+                .fragments()
+                .map(|fragment| fragment.id())
+                .collect();
+            assert_eq!(ids, vec![higher.id(), highest.id()]);
+            assert_eq!(
+                statuses(&mempool, &[&lowest, &higher, &highest]).await,
+                vec![
+                    FragmentStatus::Rejected {
+                        reason: EVICTED_REASON.to_owned()
+                    },
+                    FragmentStatus::Pending,
+                    FragmentStatus::Pending,
+                ]
+            );
+
+            // the evicted fragment is known to the node
+            let (submissions, _) = mempool
+                .insert_all(FragmentOrigin::Rest, vec![lowest], &accept, &logger)
+                .await
+                .unwrap();
+            assert_eq!(
+                submissions[0].status(),
+                &FragmentSubmissionStatus::AlreadyKnown
+            );
+        });
+    }
+
+    #[test]
+    fn ledger_is_checked_before_evicting() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let logger = Logger::root(slog::Discard, o!());
+            let mut mempool = mempool(2);
+            let lowest = transaction(0, 10, 1);
+            let higher = transaction(1, 20, 1);
+            let highest = transaction(2, 30, 1);
+            let entries = vec![lowest.clone(), higher.clone()];
+            mempool
+                .insert_all(FragmentOrigin::Rest, entries, &accept, &logger)
+                .await
+                .unwrap();
+
+            match mempool
+                .pool
+                .lock()
+                .await
+                .insert(FragmentOrigin::Rest, highest.clone(), &reject)
+            {
+                Err(InsertError::Ledger(_)) => (),
+                _ => panic!("a fragment rejected by the ledger should not evict"),
+            }
+            let (submissions, inserted) = mempool
+                .insert_all(
+                    FragmentOrigin::Rest,
+                    vec![highest.clone()],
+                    &reject,
+                    &logger,
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                submissions[0].status(),
+                &FragmentSubmissionStatus::Rejected {
+                    reason: "rejected by the ledger".to_owned()
+                }
+            );
+            assert!(inserted.is_empty());
+
+            let ids: Vec<_> = mempool
+                .pool
+                .lock()
+                .await
+                .fragments()
+                .map(|fragment| fragment.id())
+                .collect();
+            assert_eq!(ids, vec![lowest.id(), higher.id()]);
+            assert_eq!(
+                statuses(&mempool, &[&lowest, &higher]).await,
+                vec![FragmentStatus::Pending, FragmentStatus::Pending]
+            );
+            assert!(!mempool.logs().exists(highest.id()).await.unwrap());
+        });
+    }
+
+    #[test]
+    fn dry_run_selection_leaves_the_pool_and_the_logs() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let logger = Logger::root(slog::Discard, o!());
+            let mut mempool = mempool(10);
+            let fragments = vec![transaction(0, 10, 1), transaction(1, 20, 1)];
+            mempool
+                .insert_all(FragmentOrigin::Rest, fragments.clone(), &accept, &logger)
+                .await
+                .unwrap();
+
+            // the ledger rejects the fragments in the simulated selection
+            let (ledger, parameters) = empty_ledger();
+            let selection = mempool
+                .select_dry_run(
+                    ledger,
+                    BlockDate::first(),
+                    parameters,
+                    HighestFeeFirst::new(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(selection.finalize().iter().count(), 0);
+
+            assert_eq!(mempool.pool.lock().await.fragments().count(), 2);
+            assert_eq!(
+                statuses(&mempool, &[&fragments[0], &fragments[1]]).await,
+                vec![FragmentStatus::Pending, FragmentStatus::Pending]
+            );
+        });
+    }
+
+    async fn tip_of(block0: Block) -> Arc<Ref> {
+        let storage = Storage::new(SledStore::temporary().unwrap());
+        let blockchain = Blockchain::new(
+            block0.header.hash(),
+            storage,
+            Duration::from_secs(3600),
+            false,
+        );
+        blockchain
+            .load_from_block0(block0)
+            .await
+            .unwrap()
+            .get_ref_std()
+            .await
+    }
+
+    fn pending(fragment: &Fragment, received_at: std::time::SystemTime) -> PendingFragment {
+        PendingFragment {
+            origin: FragmentOrigin::Rest,
+            received_at: received_at.into(),
+            raw: fragment.serialize_as_vec().unwrap(),
+        }
+    }
+
+    #[test]
+    fn restore_drops_the_expired_and_rejected_fragments() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let logger = Logger::root(slog::Discard, o!());
+            let mut mempool = mempool(10);
+            let tip = tip_of(empty_block0()).await;
+            // refused by the ledger of the tip, as if it had been applied
+            let rejected = transaction(0, 10, 1);
+            let expired = transaction(1, 10, 1);
+            let now = std::time::SystemTime::now();
+            let snapshot = Snapshot {
+                fragments: vec![
+                    pending(&rejected, now),
+                    pending(&expired, now - Duration::from_secs(7200)),
+                ],
+                logs: vec![
+                    FragmentLog::new(rejected.id(), FragmentOrigin::Rest),
+                    FragmentLog::new(expired.id(), FragmentOrigin::Rest),
+                ],
+            };
+
+            let restored = mempool
+                .restore_snapshot(snapshot, tip, &logger)
+                .await
+                .unwrap();
+
+            assert_eq!(restored, 0);
+            assert_eq!(mempool.pool.lock().await.fragments().count(), 0);
+            let statuses = statuses(&mempool, &[&rejected, &expired]).await;
+            match &statuses[0] {
+                FragmentStatus::Rejected { .. } => (),
+                status => panic!("unexpected status {:?}", status),
+            }
+            assert_eq!(statuses[1], FragmentStatus::Pending);
+        });
+    }
+
+    #[test]
+    fn submission_refused_by_the_limits_is_logged_until_accepted() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let logger = Logger::root(slog::Discard, o!());
+            let rest_limits = FragmentOriginLimits {
+                max_entries: Some(1),
+                rate_limit: None,
+            };
+            let mut mempool =
+                mempool_with_limits(10, OriginLimits::new(Default::default(), rest_limits));
+            let pending = transaction(0, 10, 1);
+            let limited = transaction(1, 10, 1);
+            let network = FragmentOrigin::Network { id: None };
+            mempool
+                .insert_all(FragmentOrigin::Rest, vec![pending], &accept, &logger)
+                .await
+                .unwrap();
+
+            let (submissions, inserted) = mempool
+                .insert_all(
+                    FragmentOrigin::Rest,
+                    vec![limited.clone()],
+                    &accept,
+                    &logger,
+                )
+                .await
+                .unwrap();
+            assert!(inserted.is_empty());
+            let reason = match submissions[0].status() {
+                FragmentSubmissionStatus::Rejected { reason } => reason.clone(),
+                _ => panic!("the fragment exceeds the limits of the origin"),
+            };
+            assert!(reason.starts_with(LIMITED_REASON));
+            assert_eq!(
+                statuses(&mempool, &[&limited]).await,
+                vec![FragmentStatus::Rejected { reason }]
+            );
+
+            // another origin is within its limits
+            let (submissions, _) = mempool
+                .insert_all(network, vec![limited.clone()], &accept, &logger)
+                .await
+                .unwrap();
+            assert!(submissions[0].is_accepted());
+            let log = mempool
+                .logs()
+                .get_all(vec![limited.id()])
+                .await
+                .unwrap()
+                .remove(0)
+                .expect("fragment is not logged");
+            assert_eq!(log.status(), &FragmentStatus::Pending);
+            assert_eq!(log.received_from(), &network);
+
+            // once accepted, the fragment is known to the node
+            let (submissions, _) = mempool
+                .insert_all(network, vec![limited], &accept, &logger)
+                .await
+                .unwrap();
+            assert_eq!(
+                submissions[0].status(),
+                &FragmentSubmissionStatus::AlreadyKnown
+            );
+        });
+    }
+
+    #[test]
+    fn batch_submission_reports_every_fragment_in_order() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let logger = Logger::root(slog::Discard, o!());
+            let mut mempool = mempool(10);
+            let known = transaction(0, 10, 1);
+            let accepted = transaction(1, 10, 1);
+            mempool
+                .insert_all(FragmentOrigin::Rest, vec![known.clone()], &accept, &logger)
+                .await
+                .unwrap();
+
+            let batch = vec![
+                accepted.clone(),
+                fragment(0),
+                known.clone(),
+                accepted.clone(),
+            ];
+            let (submissions, inserted) = mempool
+                .insert_all(FragmentOrigin::Rest, batch.clone(), &accept, &logger)
+                .await
+                .unwrap();
+
+            let ids: Vec<_> = submissions
+                .iter()
+                .map(|submission| *submission.fragment_id())
+                .collect();
+            let batch_ids: Vec<Hash> = batch.iter().map(|fragment| fragment.id().into()).collect();
+            assert_eq!(ids, batch_ids);
+            assert_eq!(submissions[0].status(), &FragmentSubmissionStatus::Accepted);
+            match submissions[1].status() {
+                FragmentSubmissionStatus::Invalid { .. } => (),
+                status => panic!("unexpected status {:?}", status),
+            }
+            assert_eq!(
+                submissions[2].status(),
+                &FragmentSubmissionStatus::AlreadyKnown
+            );
+            // a fragment given twice in the batch is only inserted once
+            assert_eq!(
+                submissions[3].status(),
+                &FragmentSubmissionStatus::AlreadyKnown
+            );
+            assert_eq!(
+                inserted
+                    .iter()
+                    .map(|fragment| fragment.id())
+                    .collect::<Vec<_>>(),
+                vec![accepted.id()]
+            );
+            assert!(!mempool.logs().exists(fragment(0).id()).await.unwrap());
+        });
+    }
+}
@@ -1,6 +1,8 @@
 use crate::{
-    fragment::{Logs, Pool},
-    intercom::{NetworkMsg, TransactionMsg},
+    blockchain::Tip,
+    fragment::{limits::OriginLimits, Logs, Pool, Snapshot},
+    intercom::{self, NetworkMsg, TransactionMsg},
+    notifier::Notifier,
     stats_counter::StatsCounter,
     utils::{
         async_msg::{MessageBox, MessageQueue},
@@ -8,28 +10,41 @@ use crate::{
     },
 };
 use futures03::{compat::*, stream::StreamExt};
-use std::time::Duration;
+use jormungandr_lib::interfaces::{FragmentOriginLimits, FragmentSubmission};
+use std::{path::PathBuf, time::Duration};
 
 pub struct Process {
     pool: Pool,
     logs: Logs,
     garbage_collection_interval: Duration,
+    snapshot_path: Option<PathBuf>,
 }
 
 impl Process {
     pub fn new(
         pool_max_entries: usize,
         pool_ttl: Duration,
+        network_limits: FragmentOriginLimits,
+        rest_limits: FragmentOriginLimits,
         logs_max_entries: usize,
         logs_ttl: Duration,
         garbage_collection_interval: Duration,
+        snapshot_path: Option<PathBuf>,
         network_msg_box: MessageBox<NetworkMsg>,
+        notifier: Notifier,
     ) -> Self {
-        let logs = Logs::new(logs_max_entries, logs_ttl);
+        let logs = Logs::new(logs_max_entries, logs_ttl, notifier);
         Process {
-            pool: Pool::new(pool_max_entries, pool_ttl, logs.clone(), network_msg_box),
+            pool: Pool::new(
+                pool_max_entries,
+                pool_ttl,
+                OriginLimits::new(network_limits, rest_limits),
+                logs.clone(),
+                network_msg_box,
+            ),
             logs,
             garbage_collection_interval,
+            snapshot_path,
         }
     }
 
@@ -44,8 +59,11 @@ impl Process {
         self,
         service_info: TokioServiceInfo,
         stats_counter: StatsCounter,
+        blockchain_tip: Tip,
         input: MessageQueue<TransactionMsg>,
     ) -> Result<(), ()> {
+        self.restore_snapshot(&service_info, blockchain_tip.clone())
+            .await?;
         self.start_pool_garbage_collector(&service_info);
         let mut input = input.compat();
         while let Some(input_result) = input.next().await {
@@ -63,12 +81,35 @@ impl Process {
                     // put them in another pool.
 
                     let stats_counter = stats_counter.clone();
+                    let tip = blockchain_tip.get_ref_std().await;
 
                     self.pool
                         .clone()
-                        .insert_and_propagate_all(origin, txs, service_info.logger().clone())
+                        .insert_and_propagate_all(origin, txs, tip, service_info.logger().clone())
                         .await
-                        .map(move |count| stats_counter.add_tx_recv_cnt(count))?;
+                        .map(move |submissions| {
+                            stats_counter.add_tx_recv_cnt(count_accepted(&submissions))
+                        })?;
+                }
+                TransactionMsg::SubmitTransactions(origin, txs, reply_handle) => {
+                    let tip = blockchain_tip.get_ref_std().await;
+                    let result = self
+                        .pool
+                        .clone()
+                        .insert_and_propagate_all(origin, txs, tip, service_info.logger().clone())
+                        .await;
+                    match result {
+                        Ok(submissions) => {
+                            stats_counter.add_tx_recv_cnt(count_accepted(&submissions));
+                            reply_handle.reply_ok(submissions);
+                        }
+                        Err(()) => {
+                            reply_handle.reply_error(intercom::Error::failed(
+                                "cannot insert the fragments in the pool",
+                            ));
+                            return Err(());
+                        }
+                    }
                 }
                 TransactionMsg::RemoveTransactions(fragment_ids, status) => {
                     self.pool
@@ -82,15 +123,71 @@ impl Process {
         Ok(())
     }
 
+    async fn restore_snapshot(
+        &self,
+        service_info: &TokioServiceInfo,
+        blockchain_tip: Tip,
+    ) -> Result<(), ()> {
+        let logger = service_info.logger();
+        let path = match &self.snapshot_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let snapshot = match Snapshot::load(path) {
+            Ok(Some(snapshot)) => snapshot,
+            Ok(None) => return Ok(()),
+            Err(error) => {
+                warn!(logger, "cannot load the mempool snapshot, starting with an empty pool";
+                    "path" => ?path,
+                    "reason" => %error,
+                );
+                return Ok(());
+            }
+        };
+        let tip = blockchain_tip.get_ref_std().await;
+        let count = self
+            .pool
+            .clone()
+            .restore_snapshot(snapshot, tip, logger)
+            .await?;
+        info!(
+            logger,
+            "restored {} fragments from the mempool snapshot", count
+        );
+        Ok(())
+    }
+
     fn start_pool_garbage_collector(&self, service_info: &TokioServiceInfo) {
         let pool = self.pool.clone();
+        let snapshot_path = self.snapshot_path.clone();
+        let logger = service_info.logger().clone();
         service_info.run_periodic_std(
             "pool garbage collection",
             self.garbage_collection_interval,
             move || {
                 let mut pool = pool.clone();
-                async move { pool.poll_purge().await }
+                let snapshot_path = snapshot_path.clone();
+                let logger = logger.clone();
+                async move {
+                    let purged = pool.poll_purge().await;
+                    if let Some(path) = snapshot_path {
+                        if let Err(error) = pool.save_snapshot(&path).await {
+                            warn!(logger, "cannot save the mempool snapshot";
+                                "path" => ?path,
+                                "reason" => %error,
+                            );
+                        }
+                    }
+                    purged
+                }
             },
         )
     }
 }
+
+fn count_accepted(submissions: &[FragmentSubmission]) -> usize {
+    submissions
+        .iter()
+        .filter(|submission| submission.is_accepted())
+        .count()
+}
@@ -3,9 +3,11 @@ use super::pool::internal::Pool;
 use crate::{
     blockcfg::{BlockDate, Contents, ContentsBuilder, Ledger, LedgerParameters},
     fragment::FragmentId,
+    metrics::RejectionReason,
 };
 use chain_core::property::Fragment as _;
 use jormungandr_lib::interfaces::FragmentStatus;
+use std::error::Error;
 
 pub enum SelectionOutput {
     Commit { fragment_id: FragmentId },
@@ -69,13 +71,13 @@ impl FragmentSelectionAlgorithm for OldestFirst {
                         ledger_simulation = ledger_new;
                     }
                     Err(error) => {
-                        use std::error::Error as _;
-                        let error = if let Some(source) = error.source() {
-                            format!("{}: {}", error, source)
-                        } else {
-                            error.to_string()
-                        };
-                        logs.modify(&id.into(), FragmentStatus::Rejected { reason: error })
+                        pool.fragment_rejected(RejectionReason::Ledger);
+                        logs.modify(
+                            &id.into(),
+                            FragmentStatus::Rejected {
+                                reason: rejection_reason(&error),
+                            },
+                        )
                     }
                 }
 
@@ -88,3 +90,234 @@ impl FragmentSelectionAlgorithm for OldestFirst {
         }
     }
 }
+
+/// Fill the block with the fragments paying the highest fee per byte first.
+///
+/// The fee of a fragment is the fee it declares, the difference between its
+/// inputs and its outputs. Fragments paying the same fee per byte are selected
+/// in the order they were received.
+pub struct HighestFeeFirst {
+    builder: ContentsBuilder,
+    current_total_size: u32,
+}
+
+impl HighestFeeFirst {
+    pub fn new() -> Self {
+        HighestFeeFirst {
+            builder: ContentsBuilder::new(),
+            current_total_size: 0,
+        }
+    }
+}
+
+impl FragmentSelectionAlgorithm for HighestFeeFirst {
+    fn finalize(self) -> Contents {
+        self.builder.into()
+    }
+
+    fn select(
+        &mut self,
+        ledger: &Ledger,
+        ledger_params: &LedgerParameters,
+        block_date: BlockDate,
+        logs: &mut Logs,
+        pool: &mut Pool,
+    ) {
+        let mut ledger_simulation = ledger.clone();
+
+        for (id, fragment_size) in candidates(pool) {
+            let total_size = self.current_total_size + fragment_size;
+
+            if total_size > ledger_params.block_content_max_size {
+                // leave it in the pool, it may fit in a later block
+                continue;
+            }
+
+            let fragment = match pool.remove(&id) {
+                Some(fragment) => fragment,
+                None => continue,
+            };
+
+            match ledger_simulation.apply_fragment(ledger_params, &fragment, block_date) {
+                Ok(ledger_new) => {
+                    self.builder.push(fragment);
+                    ledger_simulation = ledger_new;
+                    self.current_total_size = total_size;
+                }
+                Err(error) => {
+                    pool.fragment_rejected(RejectionReason::Ledger);
+                    logs.modify(
+                        &id.into(),
+                        FragmentStatus::Rejected {
+                            reason: rejection_reason(&error),
+                        },
+                    )
+                }
+            }
+
+            if self.current_total_size == ledger_params.block_content_max_size {
+                break;
+            }
+        }
+    }
+}
+
+/// the fragments of the pool with their size, paying the highest fee per
+/// byte first
+fn candidates(pool: &Pool) -> Vec<(FragmentId, u32)> {
+    let mut candidates = pool
+        .entries()
+        .map(|(_, _, entry)| {
+            (
+                *entry.fragment_ref(),
+                entry.fee_rate(),
+                *entry.fragment_size() as u32,
+            )
+        })
+        .collect::<Vec<_>>();
+    // the entries are in the order they were received and the sort is
+    // stable, so the arrival order is kept for equal fee rates
+    candidates.sort_by(|(_, rate_a, _), (_, rate_b, _)| rate_b.cmp(rate_a));
+    candidates
+        .into_iter()
+        .map(|(id, _, size)| (id, size))
+        .collect()
+}
+
+pub(super) fn rejection_reason(error: &dyn Error) -> String {
+    if let Some(source) = error.source() {
+        format!("{}: {}", error, source)
+    } else {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockcfg::Fragment,
+        fragment::{
+            limits::OriginLimits,
+            testing::{empty_ledger, transaction},
+        },
+        notifier::Notifier,
+    };
+    use jormungandr_lib::interfaces::{FragmentLog, FragmentOrigin};
+    use std::time::Duration;
+
+    fn accept(_: &Fragment) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn pool(fragments: &[&Fragment]) -> Pool {
+        let mut pool = Pool::new(
+            10,
+            Duration::from_secs(3600),
+            OriginLimits::new(Default::default(), Default::default()),
+        );
+        for fragment in fragments {
+            pool.insert(FragmentOrigin::Rest, (*fragment).clone(), &accept)
+                .unwrap();
+        }
+        pool
+    }
+
+    fn ids(fragments: &[&Fragment]) -> Vec<FragmentId> {
+        fragments.iter().map(|fragment| fragment.id()).collect()
+    }
+
+    fn candidate_ids(pool: &Pool) -> Vec<FragmentId> {
+        candidates(pool).into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn highest_fee_per_byte_first() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let low = transaction(0, 10, 1);
+            let high = transaction(1, 30, 1);
+            let middle = transaction(2, 20, 1);
+            // the same fee for a larger fragment
+            let large = transaction(3, 20, 4);
+            let pool = pool(&[&low, &high, &middle, &large]);
+            assert_eq!(candidate_ids(&pool), ids(&[&high, &middle, &large, &low]));
+        });
+    }
+
+    #[test]
+    fn equal_fee_rates_keep_the_arrival_order() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let first = transaction(0, 10, 1);
+            let second = transaction(1, 10, 1);
+            let third = transaction(2, 10, 1);
+            let pool = pool(&[&second, &first, &third]);
+            assert_eq!(candidate_ids(&pool), ids(&[&second, &first, &third]));
+        });
+    }
+
+    #[test]
+    fn fragment_rejected_by_the_ledger_is_logged() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let fragment = transaction(0, 10, 1);
+            let mut pool = pool(&[&fragment]);
+            let mut logs = Logs::new(10, Duration::from_secs(3600), Notifier::new());
+            logs.insert(FragmentLog::new(fragment.id(), FragmentOrigin::Rest));
+            let (ledger, parameters) = empty_ledger();
+
+            let mut selection = HighestFeeFirst::new();
+            selection.select(
+                &ledger,
+                &parameters,
+                BlockDate::first(),
+                &mut logs,
+                &mut pool,
+            );
+
+            assert_eq!(selection.finalize().iter().count(), 0);
+            assert_eq!(pool.fragments().count(), 0);
+            match logs.get(&fragment.id().into()).unwrap().status() {
+                FragmentStatus::Rejected { .. } => (),
+                status => panic!("unexpected status {:?}", status),
+            }
+        });
+    }
+
+    #[test]
+    fn fragment_larger_than_the_block_is_left_in_the_pool() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let small = transaction(0, 10, 1);
+            // pays a higher fee per byte, but does not fit in the block
+            let large = transaction(1, 100, 4);
+            let mut pool = pool(&[&small, &large]);
+            let mut logs = Logs::new(10, Duration::from_secs(3600), Notifier::new());
+            logs.insert(FragmentLog::new(small.id(), FragmentOrigin::Rest));
+            logs.insert(FragmentLog::new(large.id(), FragmentOrigin::Rest));
+            let (ledger, mut parameters) = empty_ledger();
+            parameters.block_content_max_size = small.to_raw().size_bytes_plus_size() as u32;
+
+            let mut selection = HighestFeeFirst::new();
+            selection.select(
+                &ledger,
+                &parameters,
+                BlockDate::first(),
+                &mut logs,
+                &mut pool,
+            );
+
+            // the small fragment is selected, then rejected by the ledger
+            assert_eq!(candidate_ids(&pool), ids(&[&large]));
+            assert_eq!(
+                logs.get(&large.id().into()).unwrap().status(),
+                &FragmentStatus::Pending
+            );
+            match logs.get(&small.id().into()).unwrap().status() {
+                FragmentStatus::Rejected { .. } => (),
+                status => panic!("unexpected status {:?}", status),
+            }
+        });
+    }
+}
@@ -0,0 +1,106 @@
+//! snapshot of the fragment pool and of the fragment logs, so the pending
+//! fragments survive a restart of the node.
+
+use jormungandr_lib::{
+    interfaces::{FragmentLog, FragmentOrigin},
+    time::SystemTime,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write as _},
+    path::Path,
+};
+use thiserror::Error;
+
+/// name of the snapshot file in the node's storage directory
+pub const SNAPSHOT_FILE_NAME: &str = "mempool.snapshot";
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("cannot access the mempool snapshot file")]
+    Io(#[from] io::Error),
+    #[error("invalid mempool snapshot encoding")]
+    Encoding(#[from] bincode::Error),
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// pending fragments, in the order they were received
+    pub fragments: Vec<PendingFragment>,
+    pub logs: Vec<FragmentLog>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PendingFragment {
+    pub origin: FragmentOrigin,
+    pub received_at: SystemTime,
+    /// the serialized fragment
+    pub raw: Vec<u8>,
+}
+
+impl Snapshot {
+    /// load the snapshot from the given file, returns `None` if there is no
+    /// snapshot to load.
+    pub fn load(path: &Path) -> Result<Option<Self>, SnapshotError> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        let snapshot = bincode::deserialize_from(BufReader::new(file))?;
+        Ok(Some(snapshot))
+    }
+
+    /// save the snapshot in the given file. The snapshot is written in a
+    /// temporary file first so a crash cannot leave a partially written file.
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            bincode::serialize_into(&mut writer, self)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fragment::testing::transaction;
+    use chain_core::property::{Fragment as _, Serialize as _};
+    use tempfile::tempdir;
+
+    #[test]
+    fn saved_snapshot_is_loaded() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(SNAPSHOT_FILE_NAME);
+        assert!(Snapshot::load(&path).unwrap().is_none());
+
+        let fragment = transaction(0, 10, 1);
+        let log = FragmentLog::new(fragment.id(), FragmentOrigin::Rest);
+        let received_at = SystemTime::now();
+        Snapshot {
+            fragments: vec![PendingFragment {
+                origin: FragmentOrigin::Rest,
+                received_at,
+                raw: fragment.serialize_as_vec().unwrap(),
+            }],
+            logs: vec![log.clone()],
+        }
+        .save(&path)
+        .unwrap();
+
+        let snapshot = Snapshot::load(&path).unwrap().expect("the saved snapshot");
+        assert_eq!(snapshot.fragments.len(), 1);
+        let pending = &snapshot.fragments[0];
+        assert_eq!(pending.origin, FragmentOrigin::Rest);
+        assert_eq!(pending.received_at, received_at);
+        assert_eq!(pending.raw, fragment.serialize_as_vec().unwrap());
+        assert_eq!(snapshot.logs, vec![log]);
+        assert!(!path.with_extension("tmp").exists());
+    }
+}
@@ -0,0 +1,63 @@
+//! fragments built for the tests of the mempool and of the fragment selection
+
+use crate::blockcfg::{Block, ConsensusVersion, Fragment, Ledger, LedgerParameters, Value};
+use chain_addr::Discrimination;
+use chain_crypto::{Ed25519, SecretKey};
+use chain_impl_mockchain::{
+    fee::LinearFee,
+    transaction::{Input, Output, TxBuilder, INPUT_PTR_SIZE},
+};
+use jormungandr_lib::{
+    crypto::hash::Hash,
+    interfaces::{Block0Configuration, BlockchainConfiguration},
+    wallet::utxo::Wallet,
+};
+
+/// a transaction spending the made up input `n` and paying the given fee,
+/// with the given number of outputs of 1 each.
+///
+/// The transactions with the same number of outputs have the same size,
+/// their fee per byte is in the order of their fee.
+pub fn transaction(n: u8, fee: u64, outputs: usize) -> Fragment {
+    let wallet = Wallet::from([n; 32]);
+    let output = Output {
+        address: wallet.address(Discrimination::Test).into(),
+        value: Value(1),
+    };
+    let outputs = vec![output; outputs];
+    let inputs = [Input::new(
+        0,
+        Value(fee + outputs.len() as u64),
+        [n; INPUT_PTR_SIZE],
+    )];
+    let builder = TxBuilder::new().set_nopayload().set_ios(&inputs, &outputs);
+    let sign_data = builder.get_auth_data_for_witness().hash();
+    let witness = wallet.mk_witness(&Hash::from([0; 32]), &sign_data, 0);
+    let tx = builder.set_witnesses(&[witness]).set_payload_auth(&());
+    Fragment::Transaction(tx)
+}
+
+/// a BFT block0 holding no funds: the transactions built by `transaction`
+/// spend inputs unknown to its ledger and are rejected
+pub fn empty_block0() -> Block {
+    let leader = SecretKey::<Ed25519>::generate(rand::thread_rng());
+    let mut blockchain_configuration = BlockchainConfiguration::new(
+        Discrimination::Test,
+        ConsensusVersion::Bft,
+        LinearFee::new(0, 0, 0),
+    );
+    blockchain_configuration.consensus_leader_ids = vec![leader.to_public().into()];
+    Block0Configuration {
+        blockchain_configuration,
+        initial: Vec::new(),
+    }
+    .to_block()
+}
+
+/// the ledger of `empty_block0`
+pub fn empty_ledger() -> (Ledger, LedgerParameters) {
+    let block0 = empty_block0();
+    let ledger = Ledger::new(block0.header.hash(), block0.contents.iter()).unwrap();
+    let parameters = ledger.get_ledger_parameters();
+    (ledger, parameters)
+}
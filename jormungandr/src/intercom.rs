@@ -1,17 +1,18 @@
 use crate::blockcfg::{Block, Fragment, FragmentId, Header, HeaderHash};
-use crate::blockchain::Checkpoints;
+use crate::blockchain::{Checkpoints, EpochTransitionRewards};
 use crate::network::p2p::comm::PeerInfo;
 use crate::network::p2p::Id as NodeId;
 use crate::utils::async_msg::{self, MessageBox, MessageQueue};
 use futures::prelude::*;
 use futures::sync::{mpsc, oneshot};
-use jormungandr_lib::interfaces::{FragmentOrigin, FragmentStatus};
+use jormungandr_lib::interfaces::{FragmentOrigin, FragmentStatus, FragmentSubmission};
 use network_core::error as core_error;
 use slog::Logger;
 use std::{
     error,
     fmt::{self, Debug, Display},
     marker::PhantomData,
+    sync::Arc,
 };
 
 /// The error values passed via intercom messages.
@@ -459,6 +460,13 @@ pub fn stream_request<T, R, E>(
 #[derive(Debug)]
 pub enum TransactionMsg {
     SendTransaction(FragmentOrigin, Vec<Fragment>),
+    /// same as `SendTransaction` but replies with the outcome of the
+    /// submission of every fragment
+    SubmitTransactions(
+        FragmentOrigin,
+        Vec<Fragment>,
+        ReplyHandle<Vec<FragmentSubmission>>,
+    ),
     RemoveTransactions(Vec<FragmentId>, FragmentStatus),
 }
 
@@ -542,7 +550,9 @@ pub enum NetworkMsg {
 
 /// Messages to the explorer task
 pub enum ExplorerMsg {
-    NewBlock(Block),
+    /// A block applied by the node, with the rewards distributed before it
+    /// if it is the first block of its epoch
+    NewBlock(Block, Option<Arc<EpochTransitionRewards>>),
 }
 
 #[cfg(test)]
@@ -1,19 +1,22 @@
-pub use crate::secure::enclave::LeaderEvent;
+pub use crate::secure::enclave::{LeaderEvent, LeaderEventOutput};
 use crate::{
     blockcfg::{
-        HeaderBft, HeaderBftBuilder, HeaderGenesisPraos, HeaderGenesisPraosBuilder,
-        HeaderSetConsensusSignature, Leadership,
+        BlockDate, ChainLength, Contents, Header, HeaderBft, HeaderBftBuilder, HeaderGenesisPraos,
+        HeaderGenesisPraosBuilder, HeaderHash, HeaderSetConsensusSignature, Leadership, Ledger,
     },
-    secure::enclave::Enclave as SecureEnclave,
+    secure::enclave::{Enclave as SecureEnclave, Error as SecureEnclaveError},
 };
 use jormungandr_lib::interfaces::EnclaveLeaderId as LeaderId;
 use std::sync::Arc;
+use std::time::SystemTime;
 use thiserror::Error;
 
-#[derive(Debug, Clone, Error)]
+#[derive(Debug, Error)]
 pub enum EnclaveError {
     #[error("This leader {id} is not in the enclave")]
     NotInEnclave { id: LeaderId },
+    #[error("The enclave failed to process the query")]
+    Secure(#[from] SecureEnclaveError),
 }
 
 /// represent the client side of an enclave. From there we will query the
@@ -21,16 +24,13 @@ pub enum EnclaveError {
 ///
 #[derive(Clone)]
 pub struct Enclave {
-    /// TODO: we will need to remove this to instead query data to an
-    /// external service (running in the secure enclave). For now we
-    /// hold on the `SecureEnclave` . But it will need to be separated
-    /// when we have the necessary crypto done.
+    /// the `SecureEnclave` either holds the leaders' secrets in the node's
+    /// memory or forwards the signing queries to a remote signer.
     inner: Arc<SecureEnclave>,
 }
 
 impl Enclave {
-    /// create a new enclave structure. This will need to change in the future
-    /// with a parameter to contact the secure enclave instead of the dummy type.
+    /// create a new enclave structure on top of the given secure enclave
     pub fn new(secure_enclave: SecureEnclave) -> Self {
         Enclave {
             inner: Arc::new(secure_enclave),
@@ -38,20 +38,22 @@ impl Enclave {
     }
 
     /// ask the enclave to attempt computing some leadership schedule for the
-    /// given settings
+    /// given settings, `leadership_state` is the ledger state the leadership
+    /// was computed from
     ///
     /// TODO: for now we are utilizing the Leadership object fully but on the long
     ///       run this might be limited to only the required data.
     pub async fn query_schedules(
         &self,
         leadership: Arc<Leadership>,
+        leadership_state: Arc<Ledger>,
         slot_start: u32,
         nb_slots: u32,
     ) -> Result<Vec<LeaderEvent>, EnclaveError> {
         Ok(self
             .inner
-            .leadership_evaluate(&leadership, slot_start, nb_slots)
-            .await)
+            .leadership_evaluate(&leadership, &leadership_state, slot_start, nb_slots)
+            .await?)
     }
 
     /// ask the leader associated to the `LeaderEvent` to finalize the given
@@ -63,8 +65,13 @@ impl Enclave {
         &self,
         block_builder: HeaderBftBuilder<HeaderSetConsensusSignature>,
         id: LeaderId,
+        date: BlockDate,
     ) -> Result<HeaderBft, EnclaveError> {
-        if let Some(block) = self.inner.create_header_bft(block_builder, id).await {
+        if let Some(block) = self
+            .inner
+            .create_header_bft(block_builder, id, date)
+            .await?
+        {
             Ok(block)
         } else {
             Err(EnclaveError::NotInEnclave { id })
@@ -80,15 +87,44 @@ impl Enclave {
         &self,
         block_builder: HeaderGenesisPraosBuilder<HeaderSetConsensusSignature>,
         id: LeaderId,
+        date: BlockDate,
     ) -> Result<HeaderGenesisPraos, EnclaveError> {
         if let Some(block) = self
             .inner
-            .create_header_genesis_praos(block_builder, id)
-            .await
+            .create_header_genesis_praos(block_builder, id, date)
+            .await?
         {
             Ok(block)
         } else {
             Err(EnclaveError::NotInEnclave { id })
         }
     }
+
+    /// ask the remote signer holding the leader associated to the
+    /// `LeaderEvent` to create the header of the given block
+    pub async fn query_header_remote(
+        &self,
+        id: LeaderId,
+        leadership_state: &Arc<Ledger>,
+        parent: HeaderHash,
+        chain_length: ChainLength,
+        date: BlockDate,
+        contents: &Contents,
+    ) -> Result<Header, EnclaveError> {
+        if let Some(header) = self
+            .inner
+            .create_header_remote(id, leadership_state, parent, chain_length, date, contents)
+            .await?
+        {
+            Ok(header)
+        } else {
+            Err(EnclaveError::NotInEnclave { id })
+        }
+    }
+
+    /// ask the enclave to evolve the KES keys of its leaders to the
+    /// current period
+    pub async fn evolve_kes(&self) -> Result<(), EnclaveError> {
+        Ok(self.inner.evolve_kes(SystemTime::now()).await?)
+    }
 }
@@ -0,0 +1,395 @@
+//! history of the slots won by the node's leaders.
+//!
+//! Unlike the leadership logs, the history is kept in the node's storage,
+//! one record per epoch, so it survives the node restarts. The
+//! reconciliation pass follows up on the outcome of every slot once the
+//! blockchain has moved on: whether the block created for the slot stayed
+//! on the main branch or whether no block was created at all.
+
+use crate::{
+    blockcfg::{BlockDate, Leadership, Ledger},
+    blockchain::{Ref, Storage},
+    leadership::enclave::{Enclave, EnclaveError},
+};
+use chain_storage::error::Error as StorageError;
+use chain_time::{
+    era::{EpochPosition, EpochSlotOffset, TimeEra},
+    Epoch, TimeFrame,
+};
+use futures03::compat::Future01CompatExt as _;
+use jormungandr_lib::interfaces::{
+    BlockDate as BlockDateDto, EnclaveLeaderId, LeadershipHistoryEntry, LeadershipHistoryStatus,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::SystemTime,
+};
+use thiserror::Error;
+use tokio02::sync::RwLock;
+
+/// prefix of the keys of the leadership history records in the node's
+/// storage, the key of the record of an epoch ends with the epoch
+const HISTORY_RECORD_PREFIX: &str = "leadership_history/";
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error("invalid leadership history record")]
+    Encoding(#[from] serde_json::Error),
+    #[error("cannot access the leadership history in the storage")]
+    Storage(#[from] StorageError),
+    #[error("cannot evaluate the leadership schedule")]
+    Enclave(#[from] EnclaveError),
+}
+
+type EntryKey = (BlockDateDto, EnclaveLeaderId);
+
+/// the leadership history, shared between the leadership module and the
+/// REST API
+#[derive(Clone)]
+pub struct History(Arc<RwLock<Inner>>);
+
+struct Inner {
+    storage: Storage,
+    epochs: BTreeMap<u32, BTreeMap<EntryKey, LeadershipHistoryEntry>>,
+    /// the last epoch whose whole schedule has been added to the history
+    evaluated_epoch: Option<u32>,
+}
+
+fn key(entry: &LeadershipHistoryEntry) -> EntryKey {
+    (*entry.scheduled_at_date(), *entry.enclave_leader_id())
+}
+
+fn epoch_of(date: &BlockDateDto) -> u32 {
+    date.as_ref().epoch
+}
+
+/// the key of the record of the epoch, the epoch is padded so the records
+/// are ordered by epoch
+fn record_key(epoch: u32) -> String {
+    format!("{}{:010}", HISTORY_RECORD_PREFIX, epoch)
+}
+
+impl History {
+    /// load the history from the node's storage
+    pub async fn load(storage: Storage) -> Result<Self, HistoryError> {
+        let mut epochs = BTreeMap::new();
+        let records = storage
+            .records(HISTORY_RECORD_PREFIX.to_owned())
+            .compat()
+            .await?;
+        for (_, record) in records {
+            let entries: Vec<LeadershipHistoryEntry> = serde_json::from_slice(&record)?;
+            for entry in entries {
+                epochs
+                    .entry(epoch_of(entry.scheduled_at_date()))
+                    .or_insert_with(BTreeMap::new)
+                    .insert(key(&entry), entry);
+            }
+        }
+        Ok(History(Arc::new(RwLock::new(Inner {
+            storage,
+            epochs,
+            evaluated_epoch: None,
+        }))))
+    }
+
+    /// the history of the given epoch, in chronological order
+    pub async fn epoch(&self, epoch: u32) -> Vec<LeadershipHistoryEntry> {
+        let inner = self.0.read().await;
+        inner
+            .epochs
+            .get(&epoch)
+            .map(|entries| entries.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// add the newly scheduled slots, the slots already in the history are
+    /// left untouched
+    pub async fn insert<I>(&self, entries: I) -> Result<(), HistoryError>
+    where
+        I: IntoIterator<Item = LeadershipHistoryEntry>,
+    {
+        let mut inner = self.0.write().await;
+        let mut modified = BTreeSet::new();
+        for entry in entries {
+            let epoch = epoch_of(entry.scheduled_at_date());
+            let epoch_entries = inner.epochs.entry(epoch).or_insert_with(BTreeMap::new);
+            if !epoch_entries.contains_key(&key(&entry)) {
+                epoch_entries.insert(key(&entry), entry);
+                modified.insert(epoch);
+            }
+        }
+        inner.save(modified).await
+    }
+
+    /// record the block created for the slot
+    pub async fn set_block(
+        &self,
+        id: EnclaveLeaderId,
+        date: BlockDate,
+        block: jormungandr_lib::crypto::hash::Hash,
+        chain_length: u32,
+    ) -> Result<(), HistoryError> {
+        self.set_statuses(vec![(
+            (date.into(), id),
+            LeadershipHistoryStatus::Block {
+                block,
+                chain_length,
+            },
+        )])
+        .await
+    }
+
+    async fn set_statuses(
+        &self,
+        statuses: Vec<(EntryKey, LeadershipHistoryStatus)>,
+    ) -> Result<(), HistoryError> {
+        let mut inner = self.0.write().await;
+        let mut modified = BTreeSet::new();
+        for (key, status) in statuses {
+            let epoch = epoch_of(&key.0);
+            if let Some(entry) = inner
+                .epochs
+                .get_mut(&epoch)
+                .and_then(|entries| entries.get_mut(&key))
+            {
+                entry.set_status(status);
+                modified.insert(epoch);
+            }
+        }
+        inner.save(modified).await
+    }
+
+    /// reconcile the slots of the epoch of the tip and of the previous
+    /// epoch with the blockchain. Older blocks are deeper than the
+    /// stability depth and cannot change branch anymore.
+    pub async fn reconcile(
+        &self,
+        tip: Arc<Ref>,
+        storage: &Storage,
+        enclave: &Enclave,
+    ) -> Result<(), HistoryError> {
+        let tip_epoch = tip.block_date().epoch;
+        let leadership = Arc::clone(tip.epoch_leadership_schedule());
+        let leadership_state = Arc::clone(tip.epoch_leadership_state());
+        let time_frame = Arc::clone(tip.time_frame());
+
+        // the slots of the epoch the node has not scheduled, because it was
+        // not running at the beginning of the epoch, are added as well
+        if self.0.read().await.evaluated_epoch != Some(tip_epoch) {
+            self.insert_schedule(&leadership, &leadership_state, &time_frame, enclave)
+                .await?;
+            self.0.write().await.evaluated_epoch = Some(tip_epoch);
+        }
+
+        let entries: Vec<LeadershipHistoryEntry> = {
+            let inner = self.0.read().await;
+            inner
+                .epochs
+                .range(tip_epoch.saturating_sub(1)..=tip_epoch)
+                .flat_map(|(_, entries)| entries.values().cloned())
+                .collect()
+        };
+
+        let now = SystemTime::now();
+        let era = leadership.era();
+        let mut statuses = Vec::new();
+        for entry in entries {
+            let status = match entry.status() {
+                LeadershipHistoryStatus::Pending => {
+                    // a block is recorded as soon as it is created, within
+                    // its slot
+                    match slot_end_time(era, &time_frame, (*entry.scheduled_at_date()).into()) {
+                        Some(end) if end <= now => LeadershipHistoryStatus::Missed,
+                        _ => continue,
+                    }
+                }
+                LeadershipHistoryStatus::Block {
+                    block,
+                    chain_length,
+                }
+                | LeadershipHistoryStatus::Orphaned {
+                    block,
+                    chain_length,
+                } => {
+                    let on_main_branch = match storage
+                        .is_ancestor(block.into_hash(), tip.hash())
+                        .compat()
+                        .await
+                    {
+                        Ok(distance) => distance.is_some(),
+                        Err(StorageError::BlockNotFound) => false,
+                        Err(error) => return Err(error.into()),
+                    };
+                    if on_main_branch {
+                        LeadershipHistoryStatus::Block {
+                            block: *block,
+                            chain_length: *chain_length,
+                        }
+                    } else {
+                        LeadershipHistoryStatus::Orphaned {
+                            block: *block,
+                            chain_length: *chain_length,
+                        }
+                    }
+                }
+                LeadershipHistoryStatus::Missed => continue,
+            };
+            if &status != entry.status() {
+                statuses.push((key(&entry), status));
+            }
+        }
+        self.set_statuses(statuses).await
+    }
+
+    async fn insert_schedule(
+        &self,
+        leadership: &Arc<Leadership>,
+        leadership_state: &Arc<Ledger>,
+        time_frame: &TimeFrame,
+        enclave: &Enclave,
+    ) -> Result<(), HistoryError> {
+        let era = leadership.era();
+        let events = enclave
+            .query_schedules(
+                Arc::clone(leadership),
+                Arc::clone(leadership_state),
+                0,
+                era.slots_per_epoch(),
+            )
+            .await?;
+        let entries = events.into_iter().filter_map(|event| {
+            let time = slot_time(era, time_frame, event.date.epoch, event.date.slot_id)?;
+            Some(LeadershipHistoryEntry::new(
+                event.id,
+                event.date.into(),
+                time.into(),
+            ))
+        });
+        self.insert(entries).await
+    }
+}
+
+impl Inner {
+    async fn save(&self, epochs: BTreeSet<u32>) -> Result<(), HistoryError> {
+        for epoch in epochs {
+            let entries: Vec<&LeadershipHistoryEntry> = self
+                .epochs
+                .get(&epoch)
+                .map(|entries| entries.values().collect())
+                .unwrap_or_default();
+            let record = serde_json::to_vec(&entries)?;
+            self.storage
+                .put_record(record_key(epoch), record)
+                .compat()
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+fn slot_time(
+    era: &TimeEra,
+    time_frame: &TimeFrame,
+    epoch: u32,
+    slot_id: u32,
+) -> Option<SystemTime> {
+    let slot = era.from_era_to_slot(EpochPosition {
+        epoch: Epoch(epoch),
+        slot: EpochSlotOffset(slot_id),
+    });
+    time_frame.slot_to_systemtime(slot)
+}
+
+/// the start time of the slot following the given date
+fn slot_end_time(era: &TimeEra, time_frame: &TimeFrame, date: BlockDate) -> Option<SystemTime> {
+    if date.slot_id + 1 >= era.slots_per_epoch() {
+        slot_time(era, time_frame, date.epoch + 1, 0)
+    } else {
+        slot_time(era, time_frame, date.epoch, date.slot_id + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::SledStore;
+    use jormungandr_lib::crypto::hash::Hash;
+
+    fn entry(id: EnclaveLeaderId, epoch: u32, slot_id: u32) -> LeadershipHistoryEntry {
+        LeadershipHistoryEntry::new(
+            id,
+            BlockDate { epoch, slot_id }.into(),
+            SystemTime::now().into(),
+        )
+    }
+
+    #[test]
+    fn record_keys_are_ordered_by_epoch() {
+        assert!(record_key(9) < record_key(10));
+        assert!(record_key(99) < record_key(100_000));
+        assert!(record_key(0).starts_with(HISTORY_RECORD_PREFIX));
+    }
+
+    #[test]
+    fn history_is_kept_in_the_storage() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            let id = EnclaveLeaderId::new();
+            let block = Hash::from([1; 32]);
+
+            let history = History::load(storage.clone()).await.unwrap();
+            history
+                .insert(vec![entry(id, 1, 3), entry(id, 1, 7), entry(id, 2, 0)])
+                .await
+                .unwrap();
+            history
+                .set_block(
+                    id,
+                    BlockDate {
+                        epoch: 1,
+                        slot_id: 7,
+                    },
+                    block,
+                    42,
+                )
+                .await
+                .unwrap();
+            // the slots already in the history are left untouched
+            history.insert(vec![entry(id, 1, 7)]).await.unwrap();
+
+            let history = History::load(storage).await.unwrap();
+            let epoch = history.epoch(1).await;
+            assert_eq!(epoch.len(), 2);
+            assert_eq!(epoch[0].status(), &LeadershipHistoryStatus::Pending);
+            assert_eq!(
+                epoch[1].status(),
+                &LeadershipHistoryStatus::Block {
+                    block,
+                    chain_length: 42
+                }
+            );
+            assert_eq!(history.epoch(2).await.len(), 1);
+            assert!(history.epoch(3).await.is_empty());
+        });
+    }
+
+    #[test]
+    fn invalid_record_is_rejected() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let storage = Storage::new(SledStore::temporary().unwrap());
+            storage
+                .put_record(record_key(1), b"[{\"truncated".to_vec())
+                .compat()
+                .await
+                .unwrap();
+            match History::load(storage).await {
+                Err(HistoryError::Encoding(_)) => (),
+                _ => panic!("the invalid record should be rejected"),
+            }
+        });
+    }
+}
@@ -106,6 +106,7 @@ impl Logs {
 
 pub(super) mod internal {
     use super::{LeadershipLog, LeadershipLogId, LeadershipLogStatus};
+    use crate::metrics;
     use futures03::{
         task::{Context, Poll},
         Stream,
@@ -148,6 +149,7 @@ pub(super) mod internal {
 
             let delay = self.expirations.insert(id.clone(), ttl);
 
+            metrics::leadership_event(log.status());
             self.entries.insert(id, (log, delay));
             id
         }
@@ -169,6 +171,7 @@ pub(super) mod internal {
             status: LeadershipLogStatus,
         ) {
             if let Some((ref mut log, ref key)) = self.entries.get_mut(leadership_log_id) {
+                metrics::leadership_event(&status);
                 log.set_status(status);
 
                 self.expirations
@@ -58,9 +58,11 @@
 //!
 
 mod enclave;
+mod history;
 mod logs;
 mod process;
 
 pub use self::enclave::{Enclave, EnclaveError, LeaderEvent};
+pub use self::history::{History, HistoryError};
 pub use self::logs::{LeadershipLogHandle, Logs};
 pub use self::process::Module;
@@ -1,14 +1,14 @@
 use crate::{
     blockcfg::{
         Block, BlockDate, BlockVersion, Contents, HeaderBuilderNew, LeaderOutput, Leadership,
-        Ledger, LedgerParameters,
+        Ledger, LedgerParameters, Value,
     },
-    blockchain::{new_epoch_leadership_from, Ref, Tip},
+    blockchain::{epoch_leadership_of, Ref, Storage, Tip},
     fragment,
     intercom::BlockMsg,
     leadership::{
-        enclave::{Enclave, EnclaveError, LeaderEvent},
-        LeadershipLogHandle, Logs,
+        enclave::{Enclave, EnclaveError, LeaderEvent, LeaderEventOutput},
+        History, LeadershipLogHandle, Logs,
     },
     utils::{async_msg::MessageBox, task::TokioServiceInfo},
 };
@@ -18,7 +18,7 @@ use chain_time::{
 };
 use futures03::{compat::*, future::TryFutureExt, sink::SinkExt};
 use jormungandr_lib::{
-    interfaces::{LeadershipLog, LeadershipLogStatus},
+    interfaces::{FragmentSelection, LeadershipHistoryEntry, LeadershipLog, LeadershipLogStatus},
     time::SystemTime,
 };
 use slog::Logger;
@@ -30,6 +30,13 @@ use std::{
 use thiserror::Error;
 use tokio02::time::{delay_until, timeout_at, Instant as TokioInstant};
 
+/// how often the enclave is asked to evolve the KES keys, the keys are also
+/// evolved when signing a block
+const KES_EVOLUTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// how often the leadership history is reconciled with the blockchain
+const HISTORY_RECONCILIATION_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Error, Debug)]
 pub enum LeadershipError {
     #[error("The blockchain Timeline hasn't started yet")]
@@ -45,6 +52,12 @@ pub enum LeadershipError {
         source: EnclaveError,
     },
 
+    #[error("Cannot compute the leadership of the current epoch")]
+    CannotComputeLeadership {
+        #[source]
+        source: crate::blockchain::Error,
+    },
+
     #[error("fragment selection failed")]
     FragmentSelectionFailed,
 
@@ -69,11 +82,15 @@ pub struct Module {
     schedule: Schedule,
     service_info: TokioServiceInfo,
     logs: Logs,
+    history: History,
     tip_ref: Arc<Ref>,
     tip: Tip,
     pool: fragment::Pool,
+    fragment_selection: FragmentSelection,
     enclave: Enclave,
     block_message: MessageBox<BlockMsg>,
+    /// assemble the blocks without signing nor sending them
+    dry_run: bool,
 }
 
 impl Module {
@@ -81,10 +98,14 @@ impl Module {
         service_info: TokioServiceInfo,
         logs: Logs,
         garbage_collection_interval: Duration,
+        history: History,
+        storage: Storage,
         tip: Tip,
         pool: fragment::Pool,
+        fragment_selection: FragmentSelection,
         enclave: Enclave,
         block_message: MessageBox<BlockMsg>,
+        dry_run: bool,
     ) -> Result<Self, LeadershipError> {
         let logs_to_purge = logs.clone();
 
@@ -97,15 +118,46 @@ impl Module {
             },
         );
 
+        let kes_enclave = enclave.clone();
+        service_info.run_periodic_std("kes evolution", KES_EVOLUTION_INTERVAL, move || {
+            let enclave = kes_enclave.clone();
+            async move { enclave.evolve_kes().await }
+        });
+
+        // in dry-run mode no block is created, there is nothing to follow
+        // up on in the history
+        if !dry_run {
+            let history_to_reconcile = history.clone();
+            let history_tip = tip.clone();
+            let history_enclave = enclave.clone();
+            service_info.run_periodic_std(
+                "leadership history reconciliation",
+                HISTORY_RECONCILIATION_INTERVAL,
+                move || {
+                    let history = history_to_reconcile.clone();
+                    let tip = history_tip.clone();
+                    let storage = storage.clone();
+                    let enclave = history_enclave.clone();
+                    async move {
+                        let tip_ref = tip.get_ref_std().await;
+                        history.reconcile(tip_ref, &storage, &enclave).await
+                    }
+                },
+            );
+        }
+
         tip.get_ref().compat().await.map(move |tip_ref| Self {
             schedule: Schedule::default(),
             service_info,
             logs,
+            history,
             tip_ref,
             tip,
             pool,
+            fragment_selection,
             enclave,
             block_message,
+            dry_run,
         })
     }
 
@@ -398,8 +450,10 @@ impl Module {
         let event_logs = entry.log;
 
         let enclave = self.enclave.clone();
+        let history = self.history.clone();
         let sender = self.block_message.clone();
         let pool = self.pool.clone();
+        let fragment_selection = self.fragment_selection;
 
         let (parent_id, chain_length, ledger, ledger_parameters) = if self.tip_ref.block_date()
             < event.date
@@ -434,73 +488,120 @@ impl Module {
             return Ok(());
         };
 
-        let contents = prepare_block(pool, event.date, ledger, ledger_parameters).await?;
+        let contents = prepare_block(
+            pool,
+            fragment_selection,
+            event.date,
+            ledger,
+            ledger_parameters,
+            self.dry_run,
+        )
+        .await?;
+
+        if self.dry_run {
+            let status = dry_run_status(&contents);
+            info!(logger, "dry run, the block is not signed"; "status" => ?status);
+            event_logs.set_status(status).await;
+            return Ok(());
+        }
 
         let event_logs_error = event_logs.clone();
-        let signing = {
-            let ver = match event.output {
-                LeaderOutput::None => BlockVersion::Genesis,
-                LeaderOutput::Bft(_) => BlockVersion::Ed25519Signed,
-                LeaderOutput::GenesisPraos(..) => BlockVersion::KesVrfproof,
-            };
-
-            let hdr_builder = HeaderBuilderNew::new(ver, &contents)
-                .set_parent(&parent_id, chain_length)
-                .set_date(event.date);
-
-            match event.output {
-                LeaderOutput::None => {
-                    let header = hdr_builder
-                        .to_unsigned_header()
-                        .expect("Valid Header Builder")
-                        .generalize();
-                    Ok(Some(Block { header, contents }))
-                }
-                LeaderOutput::Bft(leader_id) => {
-                    let final_builder = hdr_builder
-                        .to_bft_builder()
-                        .expect("Valid Header Builder")
-                        .set_consensus_data(&leader_id);
-                    enclave
-                        .query_header_bft_finalize(final_builder, event.id)
-                        .map_ok(|h| {
-                            Some(Block {
-                                header: h.generalize(),
-                                contents,
+        let signing = match event.output {
+            LeaderEventOutput::Leader(output) => {
+                let ver = match output {
+                    LeaderOutput::None => BlockVersion::Genesis,
+                    LeaderOutput::Bft(_) => BlockVersion::Ed25519Signed,
+                    LeaderOutput::GenesisPraos(..) => BlockVersion::KesVrfproof,
+                };
+
+                let hdr_builder = HeaderBuilderNew::new(ver, &contents)
+                    .set_parent(&parent_id, chain_length)
+                    .set_date(event.date);
+
+                match output {
+                    LeaderOutput::None => {
+                        let header = hdr_builder
+                            .to_unsigned_header()
+                            .expect("Valid Header Builder")
+                            .generalize();
+                        Ok(Some(Block { header, contents }))
+                    }
+                    LeaderOutput::Bft(leader_id) => {
+                        let final_builder = hdr_builder
+                            .to_bft_builder()
+                            .expect("Valid Header Builder")
+                            .set_consensus_data(&leader_id);
+                        enclave
+                            .query_header_bft_finalize(final_builder, event.id, event.date)
+                            .map_ok(|h| {
+                                Some(Block {
+                                    header: h.generalize(),
+                                    contents,
+                                })
                             })
-                        })
-                        .or_else(|e| async move {
-                            event_logs_error
-                                .set_status(LeadershipLogStatus::Rejected {
-                                    reason: format!("Cannot sign the block: {}", e),
+                            .or_else(|e| async move {
+                                event_logs_error
+                                    .set_status(LeadershipLogStatus::Rejected {
+                                        reason: format!("Cannot sign the block: {}", e),
+                                    })
+                                    .await;
+                                Ok(None)
+                            })
+                            .await
+                    }
+                    LeaderOutput::GenesisPraos(node_id, vrfproof) => {
+                        let final_builder = hdr_builder
+                            .to_genesis_praos_builder()
+                            .expect("Valid Header Builder")
+                            .set_consensus_data(&node_id, &vrfproof.into());
+                        enclave
+                            .query_header_genesis_praos_finalize(
+                                final_builder,
+                                event.id,
+                                event.date,
+                            )
+                            .map_ok(|h| {
+                                Some(Block {
+                                    header: h.generalize(),
+                                    contents,
                                 })
-                                .await;
-                            Ok(None)
-                        })
-                        .await
+                            })
+                            .or_else(|e| async move {
+                                event_logs_error
+                                    .set_status(LeadershipLogStatus::Rejected {
+                                        reason: format!("Cannot sign the block: {}", e),
+                                    })
+                                    .await;
+                                Ok(None)
+                            })
+                            .await
+                    }
                 }
-                LeaderOutput::GenesisPraos(node_id, vrfproof) => {
-                    let final_builder = hdr_builder
-                        .to_genesis_praos_builder()
-                        .expect("Valid Header Builder")
-                        .set_consensus_data(&node_id, &vrfproof.into());
-                    enclave
-                        .query_header_genesis_praos_finalize(final_builder, event.id)
-                        .map_ok(|h| {
-                            Some(Block {
-                                header: h.generalize(),
-                                contents,
+            }
+            LeaderEventOutput::Remote { leadership_state } => {
+                // the remote signer checks the leadership of the slot and
+                // creates the whole header, with the VRF proof of the slot
+                // for Genesis Praos
+                let signing = enclave
+                    .query_header_remote(
+                        event.id,
+                        &leadership_state,
+                        parent_id,
+                        chain_length,
+                        event.date,
+                        &contents,
+                    )
+                    .await;
+                match signing {
+                    Ok(header) => Ok(Some(Block { header, contents })),
+                    Err(e) => {
+                        event_logs_error
+                            .set_status(LeadershipLogStatus::Rejected {
+                                reason: format!("Cannot sign the block: {}", e),
                             })
-                        })
-                        .or_else(|e| async move {
-                            event_logs_error
-                                .set_status(LeadershipLogStatus::Rejected {
-                                    reason: format!("Cannot sign the block: {}", e),
-                                })
-                                .await;
-                            Ok(None)
-                        })
-                        .await
+                            .await;
+                        Ok(None)
+                    }
                 }
             }
         };
@@ -521,6 +622,14 @@ impl Module {
                             chain_length,
                         })
                         .await;
+                    // the block is still produced if the history cannot be
+                    // updated, the failure is only reported
+                    if let Err(error) = history
+                        .set_block(event.id, event.date, id.into(), chain_length)
+                        .await
+                    {
+                        error!(logger, "cannot update the leadership history"; "reason" => %error);
+                    }
                 };
                 Ok(())
             }
@@ -540,8 +649,9 @@ impl Module {
         ));
 
         if epoch_tip < current_slot_position.epoch {
-            let (_, leadership, _, _, _) =
-                new_epoch_leadership_from(current_slot_position.epoch.0, Arc::clone(&self.tip_ref));
+            let (leadership, leadership_state) =
+                epoch_leadership_of(current_slot_position.epoch.0, &self.tip_ref)
+                    .map_err(|source| LeadershipError::CannotComputeLeadership { source })?;
 
             let slot_start = current_slot_position.slot.0 + 1;
             let nb_slots = leadership.era().slots_per_epoch() - slot_start;
@@ -552,7 +662,7 @@ impl Module {
                 "nb_slots" => nb_slots,
             );
 
-            self.action_run_schedule(running_ref, slot_start, nb_slots)
+            self.action_run_schedule(running_ref, leadership_state, slot_start, nb_slots)
                 .await
         } else if epoch_tip == current_slot_position.epoch {
             // check for current epoch
@@ -564,13 +674,14 @@ impl Module {
                 .slots_per_epoch()
                 - slot_start;
             let running_ref = Arc::clone(self.tip_ref.epoch_leadership_schedule());
+            let leadership_state = Arc::clone(self.tip_ref.epoch_leadership_state());
 
             debug!(logger, "scheduling events" ;
                 "slot_start" => slot_start,
                 "nb_slots" => nb_slots,
             );
 
-            self.action_run_schedule(running_ref, slot_start, nb_slots)
+            self.action_run_schedule(running_ref, leadership_state, slot_start, nb_slots)
                 .await
         } else {
             // The only reason this would happen is if we had accepted a block
@@ -587,16 +698,42 @@ impl Module {
     async fn action_run_schedule(
         self,
         leadership: Arc<Leadership>,
+        leadership_state: Arc<Ledger>,
         slot_start: u32,
         nb_slots: u32,
     ) -> Result<Self, LeadershipError> {
         let schedules = self
             .enclave
-            .query_schedules(leadership, slot_start, nb_slots)
+            .query_schedules(leadership, leadership_state, slot_start, nb_slots)
             .map_err(|e| LeadershipError::CannotScheduleWithEnclave { source: e })
             .await?;
 
         let mut module = self;
+        let history_entries = if module.dry_run {
+            Vec::new()
+        } else {
+            schedules
+                .iter()
+                .map(|schedule| {
+                    let epoch = Epoch(schedule.date.epoch);
+                    let slot = EpochSlotOffset(schedule.date.slot_id);
+                    let scheduled_at_time = module.slot_time(epoch, slot);
+                    LeadershipHistoryEntry::new(
+                        schedule.id,
+                        schedule.date.into(),
+                        scheduled_at_time,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        if let Err(error) = module.history.insert(history_entries).await {
+            error!(
+                module.service_info.logger(),
+                "cannot update the leadership history";
+                "reason" => %error
+            );
+        }
+
         for schedule in schedules.into_iter() {
             let epoch = Epoch(schedule.date.epoch);
             let slot = EpochSlotOffset(schedule.date.slot_id);
@@ -639,14 +776,66 @@ impl Schedule {
 }
 
 async fn prepare_block(
-    mut fragment_pool: fragment::Pool,
+    fragment_pool: fragment::Pool,
+    fragment_selection: FragmentSelection,
     block_date: BlockDate,
     ledger: Arc<Ledger>,
     epoch_parameters: Arc<LedgerParameters>,
+    dry_run: bool,
 ) -> Result<Contents, LeadershipError> {
-    use crate::fragment::selection::{FragmentSelectionAlgorithm as _, OldestFirst};
+    use crate::fragment::selection::{HighestFeeFirst, OldestFirst};
+
+    match fragment_selection {
+        FragmentSelection::OldestFirst => {
+            select_fragments(
+                fragment_pool,
+                block_date,
+                ledger,
+                epoch_parameters,
+                OldestFirst::new(),
+                dry_run,
+            )
+            .await
+        }
+        FragmentSelection::HighestFeeFirst => {
+            select_fragments(
+                fragment_pool,
+                block_date,
+                ledger,
+                epoch_parameters,
+                HighestFeeFirst::new(),
+                dry_run,
+            )
+            .await
+        }
+    }
+}
 
-    let selection_algorithm = OldestFirst::new();
+async fn select_fragments<SelectAlg>(
+    mut fragment_pool: fragment::Pool,
+    block_date: BlockDate,
+    ledger: Arc<Ledger>,
+    epoch_parameters: Arc<LedgerParameters>,
+    selection_algorithm: SelectAlg,
+    dry_run: bool,
+) -> Result<Contents, LeadershipError>
+where
+    SelectAlg: fragment::selection::FragmentSelectionAlgorithm,
+{
+    if dry_run {
+        // the fragments are left in the pool for the blocks of the
+        // other nodes
+        return fragment_pool
+            .select_dry_run(
+                ledger.as_ref().clone(),
+                block_date,
+                epoch_parameters.as_ref().clone(),
+                selection_algorithm,
+            )
+            .map_ok(|selection_algorithm| selection_algorithm.finalize())
+            .map_err(|()| LeadershipError::FragmentSelectionFailed)
+            .await;
+    }
     fragment_pool
         .select(
             ledger.as_ref().clone(),
@@ -659,6 +848,48 @@ async fn prepare_block(
         .await
 }
 
+/// the status of the leadership log of a block assembled in dry-run mode:
+/// the number of fragments, the size and the fees the block would have had
+fn dry_run_status(contents: &Contents) -> LeadershipLogStatus {
+    let (fragments, fees) =
+        contents
+            .iter()
+            .fold((0u32, 0u64), |(fragments, total_fees), fragment| {
+                let fee = fragment::fragment_fee(fragment);
+                (fragments + 1, total_fees.saturating_add(fee.0))
+            });
+    let (_, size) = contents.compute_hash_size();
+    LeadershipLogStatus::DryRun {
+        fragments,
+        size,
+        fees: Value(fees).into(),
+    }
+}
+
 fn too_late(now: SystemTime, event_end: SystemTime) -> bool {
     event_end <= now
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blockcfg::ContentsBuilder, fragment::testing::transaction};
+
+    #[test]
+    fn dry_run_status_sums_the_fees_of_the_block() {
+        let mut builder = ContentsBuilder::new();
+        builder.push(transaction(1, 10, 1));
+        builder.push(transaction(2, 20, 2));
+        let contents: Contents = builder.into();
+        let (_, size) = contents.compute_hash_size();
+
+        assert_eq!(
+            dry_run_status(&contents),
+            LeadershipLogStatus::DryRun {
+                fragments: 2,
+                size,
+                fees: Value(30).into(),
+            }
+        );
+    }
+}
@@ -26,12 +26,19 @@ use crate::{
     blockchain::Blockchain,
     diagnostic::Diagnostic,
     network::p2p::P2pTopology,
-    secure::enclave::Enclave,
+    notifier::Notifier,
+    secure::{
+        enclave::Enclave,
+        rotation::{LeaderRotations, RotationError, RotationsFile, LEADER_ROTATIONS_FILE_NAME},
+    },
     settings::start::Settings,
     utils::{async_msg, task::Services},
 };
 use futures03::{executor::block_on, future::TryFutureExt};
-use jormungandr_lib::interfaces::NodeState;
+use jormungandr_lib::{
+    crypto::kes::{KesPeriods, KesSchedule, KES_PERIODS_FILE_NAME},
+    interfaces::NodeState,
+};
 use settings::{start::RawSettings, CommandLine};
 use slog::Logger;
 use std::time::Duration;
@@ -45,7 +52,9 @@ pub mod fragment;
 pub mod intercom;
 pub mod leadership;
 pub mod log;
+pub mod metrics;
 pub mod network;
+pub mod notifier;
 pub mod rest;
 pub mod secure;
 pub mod settings;
@@ -58,7 +67,11 @@ pub mod utils;
 use stats_counter::StatsCounter;
 
 fn start() -> Result<(), start_up::Error> {
-    let initialized_node = initialize_node()?;
+    let initialized_node = match initialize_node()? {
+        Some(initialized_node) => initialized_node,
+        // an offline command has been run instead of starting the node
+        None => return Ok(()),
+    };
 
     let bootstrapped_node = bootstrap(initialized_node)?;
 
@@ -70,6 +83,7 @@ pub struct BootstrappedNode {
     blockchain: Blockchain,
     blockchain_tip: blockchain::Tip,
     block0_hash: HeaderHash,
+    kes_schedule: Option<KesSchedule>,
     logger: Logger,
     explorer_db: Option<explorer::ExplorerDB>,
     rest_context: Option<rest::Context>,
@@ -81,6 +95,8 @@ const BLOCK_TASK_QUEUE_LEN: usize = 32;
 const FRAGMENT_TASK_QUEUE_LEN: usize = 1024;
 const NETWORK_TASK_QUEUE_LEN: usize = 32;
 const BOOTSTRAP_RETRY_WAIT: Duration = Duration::from_secs(5);
+// TODO: we should get this value from the configuration
+const BLOCK_CACHE_TTL: Duration = Duration::from_secs(5 * 24 * 3600);
 
 fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::Error> {
     if let Some(context) = bootstrapped_node.rest_context.as_ref() {
@@ -99,6 +115,10 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         leadership::Logs::new(bootstrapped_node.settings.leadership.log_ttl.into());
     let leadership_garbage_collection_interval =
         bootstrapped_node.settings.leadership.log_ttl.into();
+    // the storage runs its queries on the blocking threads of a runtime
+    let leadership_history = tokio02::runtime::Runtime::new()
+        .unwrap()
+        .block_on(leadership::History::load(blockchain.storage().clone()))?;
 
     let topology = P2pTopology::new(
         &bootstrapped_node.settings.network,
@@ -108,12 +128,21 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
     );
 
     let stats_counter = StatsCounter::default();
+    let notifier = Notifier::new();
+
+    let mempool_snapshot_path = bootstrapped_node
+        .settings
+        .storage
+        .as_ref()
+        .map(|dir| dir.join(fragment::SNAPSHOT_FILE_NAME));
 
     let (fragment_pool, pool_logs) = {
         let stats_counter = stats_counter.clone();
         let process = fragment::Process::new(
             bootstrapped_node.settings.mempool.pool_max_entries.into(),
             bootstrapped_node.settings.mempool.fragment_ttl.into(),
+            bootstrapped_node.settings.mempool.network_limits.clone(),
+            bootstrapped_node.settings.mempool.rest_limits.clone(),
             bootstrapped_node.settings.mempool.log_max_entries.into(),
             bootstrapped_node.settings.mempool.log_ttl.into(),
             bootstrapped_node
@@ -121,14 +150,17 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
                 .mempool
                 .garbage_collection_interval
                 .into(),
+            mempool_snapshot_path.clone(),
             network_msgbox.clone(),
+            notifier.clone(),
         );
 
         let pool = process.pool().clone();
         let logs = process.logs().clone();
+        let blockchain_tip = blockchain_tip.clone();
 
         services.spawn_future("fragment", move |info| {
-            let fut = process.start(info, stats_counter, fragment_queue);
+            let fut = process.start(info, stats_counter, blockchain_tip, fragment_queue);
             Box::pin(fut).compat()
         });
         (pool, logs)
@@ -164,6 +196,16 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         // TODO: we should get this value from the configuration
         let block_cache_ttl: Duration = Duration::from_secs(120);
         let stats_counter = stats_counter.clone();
+        let notifier = notifier.clone();
+        let pruning = bootstrapped_node.settings.pruning.map(|kept_epochs| {
+            let snapshot_path = bootstrapped_node
+                .settings
+                .storage
+                .as_ref()
+                .expect("the pruned blocks to be stored in a directory")
+                .join(blockchain::SNAPSHOT_FILE_NAME);
+            blockchain::Pruning::new(kept_epochs, bootstrapped_node.block0_hash, snapshot_path)
+        });
         services.spawn_future("block", move |info| {
             let process = blockchain::Process {
                 blockchain,
@@ -172,7 +214,9 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
                 network_msgbox,
                 fragment_msgbox,
                 explorer_msgbox,
+                notifier,
                 garbage_collection_interval: block_cache_ttl,
+                pruning,
             };
             process.start(info, block_queue)
         });
@@ -213,12 +257,15 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         });
     }
 
+    let mut passphrase =
+        secure::passphrase::Passphrase::new(bootstrapped_node.settings.secret_passphrase_fd);
     let leader_secrets: Result<Vec<Leader>, start_up::Error> = bootstrapped_node
         .settings
         .secrets
         .iter()
         .map(|secret_path| {
-            let secret = secure::NodeSecret::load_from_file(secret_path.as_path())?;
+            let secret =
+                secure::NodeSecret::load_from_file(secret_path.as_path(), &mut passphrase)?;
             Ok(Leader {
                 bft_leader: secret.bft(),
                 genesis_leader: secret.genesis(),
@@ -226,24 +273,95 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         })
         .collect();
     let leader_secrets = leader_secrets?;
+    #[cfg(unix)]
+    let enclave = match &bootstrapped_node.settings.leadership.remote_signer {
+        Some(path) => {
+            info!(
+                bootstrapped_node.logger,
+                "leaders' secrets are held by the remote signer at {}",
+                path.display()
+            );
+            Enclave::remote(secure::remote::RemoteSigner::new(path))
+        }
+        None => block_on(Enclave::from_vec(leader_secrets)),
+    };
+    #[cfg(not(unix))]
     let enclave = block_on(Enclave::from_vec(leader_secrets));
 
+    let leader_rotations_file = match &bootstrapped_node.settings.storage {
+        Some(dir) => match passphrase
+            .given()
+            .map_err(RotationError::from)
+            .map_err(secure::enclave::Error::from)?
+        {
+            Some(passphrase) => Some(RotationsFile {
+                path: dir.join(LEADER_ROTATIONS_FILE_NAME),
+                passphrase,
+            }),
+            None => {
+                if !bootstrapped_node.settings.secrets.is_empty() {
+                    warn!(
+                        bootstrapped_node.logger,
+                        "no passphrase given, the leader rotations will not be kept after a restart"
+                    );
+                }
+                None
+            }
+        },
+        None => None,
+    };
+    let leader_rotations =
+        LeaderRotations::load(leader_rotations_file).map_err(secure::enclave::Error::from)?;
+    block_on(enclave.enable_leader_rotations(leader_rotations))?;
+
+    if let Some(kes_schedule) = bootstrapped_node.kes_schedule {
+        let kes_periods_path = bootstrapped_node
+            .settings
+            .storage
+            .as_ref()
+            .map(|dir| dir.join(KES_PERIODS_FILE_NAME));
+        if kes_periods_path.is_none() {
+            warn!(
+                bootstrapped_node.logger,
+                "no storage set, the periods of the KES keys will not be kept after a restart"
+            );
+        }
+        let kes_periods =
+            KesPeriods::load(kes_periods_path).map_err(secure::enclave::Error::from)?;
+        block_on(enclave.enable_kes_evolution(kes_schedule, kes_periods))?;
+    }
+
     {
         let leadership_logs = leadership_logs.clone();
+        let leadership_history = leadership_history.clone();
+        let storage = blockchain.storage().clone();
         let fragment_pool = fragment_pool.clone();
+        let fragment_selection = bootstrapped_node.settings.mempool.fragment_selection;
         let block_msgbox = block_msgbox.clone();
         let blockchain_tip = blockchain_tip.clone();
         let enclave = leadership::Enclave::new(enclave.clone());
+        let dry_run = bootstrapped_node.settings.leadership.dry_run;
+
+        if dry_run {
+            info!(
+                bootstrapped_node.logger,
+                "leadership dry run enabled, the blocks of the leaders will not be signed nor sent"
+            );
+        }
 
         services.spawn_future("leadership", move |info| {
             let fut = leadership::Module::new(
                 info,
                 leadership_logs,
                 leadership_garbage_collection_interval,
+                leadership_history,
+                storage,
                 blockchain_tip,
                 fragment_pool,
+                fragment_selection,
                 enclave,
                 block_msgbox,
+                dry_run,
             )
             .and_then(|module| module.run())
             .map_err(|e| unimplemented!("error in leadership {}", e));
@@ -261,10 +379,12 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
             transaction_task: fragment_msgbox,
             logs: pool_logs,
             leadership_logs,
+            leadership_history,
             enclave,
             p2p: topology,
             explorer: explorer.as_ref().map(|(_msg_box, context)| context.clone()),
             diagnostic: bootstrapped_node.diagnostic,
+            notifier,
         };
         block_on(async {
             rest_context.set_full(full_context).await;
@@ -299,6 +419,15 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         }
         Ok(true) => {
             info!(bootstrapped_node.logger, "Shutting down node");
+            if let Some(path) = mempool_snapshot_path {
+                if let Err(error) = block_on(fragment_pool.save_snapshot(&path)) {
+                    warn!(
+                        bootstrapped_node.logger,
+                        "cannot save the mempool snapshot" ;
+                        "reason" => error.to_string()
+                    );
+                }
+            }
             Ok(())
         }
         Ok(false) => {
@@ -342,13 +471,37 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
 
     let block0_hash = block0.header.hash();
 
+    let kes_schedule = {
+        use crate::blockcfg::Block0DataSource as _;
+        match block0.kes_update_speed() {
+            Ok(update_speed) => Some(KesSchedule::new(block0.start_time()?, update_speed)),
+            Err(error) => {
+                warn!(
+                    &bootstrap_logger,
+                    "the KES keys will not be evolved";
+                    "reason" => %error
+                );
+                None
+            }
+        }
+    };
+
     let block0_explorer = block0.clone();
 
-    // TODO: we should get this value from the configuration
-    let block_cache_ttl: Duration = Duration::from_secs(5 * 24 * 3600);
+    let snapshot = start_up::prepare_snapshot(&settings, &storage, block0_hash, &bootstrap_logger)?;
+    // the explorer indexes the blockchain from the block0
+    if snapshot.is_some() && settings.explorer {
+        return Err(start_up::Error::ExplorerWithSnapshot);
+    }
 
-    let (blockchain, blockchain_tip) =
-        start_up::load_blockchain(block0, storage, block_cache_ttl, &bootstrap_logger)?;
+    let (blockchain, blockchain_tip) = start_up::load_blockchain(
+        block0,
+        storage,
+        snapshot,
+        BLOCK_CACHE_TTL,
+        settings.explorer,
+        &bootstrap_logger,
+    )?;
 
     let mut bootstrap_attempt: usize = 0;
     loop {
@@ -387,9 +540,15 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
     }
 
     let explorer_db = if settings.explorer {
+        let index_path = settings
+            .storage
+            .as_ref()
+            .map(|dir| dir.join(explorer::INDEX_FILE_NAME));
         Some(explorer::ExplorerDB::bootstrap(
             block0_explorer,
             &blockchain,
+            index_path,
+            &bootstrap_logger,
         )?)
     } else {
         None
@@ -398,6 +557,7 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
     Ok(BootstrappedNode {
         settings,
         block0_hash,
+        kes_schedule,
         blockchain,
         blockchain_tip,
         logger,
@@ -418,8 +578,10 @@ pub struct InitializedNode {
     pub diagnostic: Diagnostic,
 }
 
-fn initialize_node() -> Result<InitializedNode, start_up::Error> {
-    let command_line = CommandLine::load();
+/// returns `None` if an offline command has been run instead of
+/// initializing the node
+fn initialize_node() -> Result<Option<InitializedNode>, start_up::Error> {
+    let mut command_line = CommandLine::load();
 
     if command_line.full_version {
         println!("{}", env!("FULL_VERSION"));
@@ -429,6 +591,7 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
         std::process::exit(0);
     }
 
+    let command = command_line.command.take();
     let raw_settings = RawSettings::load(command_line)?;
 
     let log_settings = raw_settings.log_settings();
@@ -441,6 +604,12 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
     debug!(init_logger, "system settings are: {}", diagnostic);
 
     let settings = raw_settings.try_into_settings(&init_logger)?;
+
+    if let Some(command) = command {
+        start_up::run_command(command, &settings, BLOCK_CACHE_TTL, &init_logger)?;
+        return Ok(None);
+    }
+
     let mut services = Services::new(logger.clone());
 
     let rest_context = match settings.rest.clone() {
@@ -474,7 +643,7 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
         &init_logger, /* add network to fetch block0 */
     )?;
 
-    Ok(InitializedNode {
+    Ok(Some(InitializedNode {
         settings,
         block0,
         storage,
@@ -482,7 +651,7 @@ fn initialize_node() -> Result<InitializedNode, start_up::Error> {
         rest_context,
         services,
         diagnostic,
-    })
+    }))
 }
 
 fn main() {
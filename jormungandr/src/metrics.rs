@@ -0,0 +1,188 @@
+//! metrics of the node, exposed in the Prometheus text format by the
+//! `/metrics` endpoint of the REST API.
+//!
+//! The metrics are registered once in a process wide registry so every
+//! task of the node can update them without having to carry a handle.
+
+use jormungandr_lib::interfaces::LeadershipLogStatus;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+const NAMESPACE: &str = "jormungandr";
+
+/// the reasons for the rejection of a fragment, kept as a closed set so
+/// the number of time series stays bounded.
+#[derive(Debug, Clone, Copy)]
+pub enum RejectionReason {
+    /// the fragment is malformed or not acceptable in the mempool
+    Invalid,
+    /// the pool is full and the fragment does not pay enough fee
+    PoolFull,
+    QuotaExceeded,
+    RateLimited,
+    /// the fragment cannot be applied to the ledger
+    Ledger,
+}
+
+impl RejectionReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            RejectionReason::Invalid => "invalid",
+            RejectionReason::PoolFull => "pool_full",
+            RejectionReason::QuotaExceeded => "quota_exceeded",
+            RejectionReason::RateLimited => "rate_limited",
+            RejectionReason::Ledger => "ledger",
+        }
+    }
+}
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+    pub static ref MEMPOOL_FRAGMENTS: IntGauge = register(
+        IntGauge::with_opts(opts(
+            "mempool_fragments",
+            "Number of fragments pending in the mempool"
+        ))
+        .unwrap()
+    );
+    pub static ref MEMPOOL_EVICTIONS: IntCounter = register(
+        IntCounter::with_opts(opts(
+            "mempool_evictions_total",
+            "Number of fragments evicted from the full mempool by a higher fee fragment"
+        ))
+        .unwrap()
+    );
+    static ref FRAGMENT_REJECTIONS: IntCounterVec = register(
+        IntCounterVec::new(
+            opts(
+                "fragment_rejections_total",
+                "Number of rejected fragments, by reason"
+            ),
+            &["reason"]
+        )
+        .unwrap()
+    );
+    pub static ref BLOCK_APPLY_SECONDS: HistogramVec = register(
+        HistogramVec::new(
+            HistogramOpts::from(opts(
+                "block_apply_seconds",
+                "Time to validate, apply and store a block, by origin of the block"
+            )),
+            &["origin"]
+        )
+        .unwrap()
+    );
+    pub static ref CHAIN_REORG_DEPTH: Histogram = register(
+        Histogram::with_opts(
+            HistogramOpts::from(opts(
+                "chain_reorg_depth",
+                "Number of blocks rolled back by the branch switches of the node"
+            ))
+            .buckets(vec![
+                1.0, 2.0, 3.0, 5.0, 10.0, 20.0, 50.0, 100.0, 500.0, 1000.0
+            ])
+        )
+        .unwrap()
+    );
+    pub static ref DEEP_CHAIN_REORGS: IntCounter = register(
+        IntCounter::with_opts(opts(
+            "deep_chain_reorgs_total",
+            "Number of branch switches rolling back close to the epoch stability depth"
+        ))
+        .unwrap()
+    );
+    static ref LEADERSHIP_EVENTS: IntCounterVec = register(
+        IntCounterVec::new(
+            opts(
+                "leadership_events_total",
+                "Number of leadership events, by status"
+            ),
+            &["status"]
+        )
+        .unwrap()
+    );
+    pub static ref PEERS: IntGaugeVec = register(
+        IntGaugeVec::new(
+            opts("peers", "Number of peers known to the node, by state"),
+            &["state"]
+        )
+        .unwrap()
+    );
+    pub static ref SUBSCRIPTION_MESSAGES: IntCounterVec = register(
+        IntCounterVec::new(
+            opts(
+                "subscription_messages_total",
+                "Number of messages exchanged with the peers, by subscription and direction"
+            ),
+            &["subscription", "direction"]
+        )
+        .unwrap()
+    );
+    pub static ref STORAGE_QUERY_SECONDS: HistogramVec = register(
+        HistogramVec::new(
+            HistogramOpts::from(opts(
+                "storage_query_seconds",
+                "Time to run a query on the block storage, by query"
+            )),
+            &["query"]
+        )
+        .unwrap()
+    );
+}
+
+fn opts(name: &str, help: &str) -> Opts {
+    Opts::new(name, help).namespace(NAMESPACE)
+}
+
+fn register<M>(metric: M) -> M
+where
+    M: prometheus::core::Collector + Clone + 'static,
+{
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("metrics must be registered only once");
+    metric
+}
+
+pub fn fragment_rejected(reason: RejectionReason) {
+    FRAGMENT_REJECTIONS
+        .with_label_values(&[reason.as_str()])
+        .inc()
+}
+
+pub fn leadership_event(status: &LeadershipLogStatus) {
+    let status = match status {
+        LeadershipLogStatus::Pending => "pending",
+        LeadershipLogStatus::Rejected { .. } => "rejected",
+        LeadershipLogStatus::Block { .. } => "block",
+        LeadershipLogStatus::DryRun { .. } => "dry_run",
+    };
+    LEADERSHIP_EVENTS.with_label_values(&[status]).inc()
+}
+
+/// the metrics are registered on first use, make sure the ones that were
+/// not updated yet are still exposed
+fn register_all() {
+    lazy_static::initialize(&MEMPOOL_FRAGMENTS);
+    lazy_static::initialize(&MEMPOOL_EVICTIONS);
+    lazy_static::initialize(&FRAGMENT_REJECTIONS);
+    lazy_static::initialize(&BLOCK_APPLY_SECONDS);
+    lazy_static::initialize(&CHAIN_REORG_DEPTH);
+    lazy_static::initialize(&DEEP_CHAIN_REORGS);
+    lazy_static::initialize(&LEADERSHIP_EVENTS);
+    lazy_static::initialize(&PEERS);
+    lazy_static::initialize(&SUBSCRIPTION_MESSAGES);
+    lazy_static::initialize(&STORAGE_QUERY_SECONDS);
+}
+
+/// encode all the metrics of the node in the Prometheus text format,
+/// returns the content type of the encoding along with the encoded metrics
+pub fn encode() -> Result<(String, Vec<u8>), prometheus::Error> {
+    register_all();
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder.encode(&REGISTRY.gather(), &mut buffer)?;
+    Ok((encoder.format_type().to_owned(), buffer))
+}
@@ -3,6 +3,7 @@ mod peer_map;
 use peer_map::{CommStatus, PeerMap};
 
 use crate::blockcfg::{Block, Fragment, Header, HeaderHash};
+use crate::metrics;
 use crate::network::{
     client::ConnectHandle,
     p2p::{Gossip as NodeData, Id, Node as NodeRef},
@@ -29,6 +30,19 @@ use std::time::SystemTime;
 // a gRPC subscription stream.
 const BUFFER_LEN: usize = 8;
 
+// Labels of the subscription message metrics.
+const BLOCK_EVENTS: &str = "block_events";
+const FRAGMENTS: &str = "fragments";
+const GOSSIP: &str = "gossip";
+const INBOUND: &str = "inbound";
+const OUTBOUND: &str = "outbound";
+
+fn count_message(subscription: &str, direction: &str) {
+    metrics::SUBSCRIPTION_MESSAGES
+        .with_label_values(&[subscription, direction])
+        .inc();
+}
+
 #[derive(Debug)]
 pub struct PropagateError<T> {
     kind: ErrorKind,
@@ -298,21 +312,27 @@ impl PeerComms {
         &mut self,
         header: Header,
     ) -> Result<(), PropagateError<Header>> {
-        self.block_announcements.try_send(header)
+        self.block_announcements
+            .try_send(header)
+            .map(|()| count_message(BLOCK_EVENTS, OUTBOUND))
     }
 
     pub fn try_send_fragment(
         &mut self,
         fragment: Fragment,
     ) -> Result<(), PropagateError<Fragment>> {
-        self.fragments.try_send(fragment)
+        self.fragments
+            .try_send(fragment)
+            .map(|()| count_message(FRAGMENTS, OUTBOUND))
     }
 
     pub fn try_send_gossip(
         &mut self,
         gossip: Gossip<NodeData>,
     ) -> Result<(), PropagateError<Gossip<NodeData>>> {
-        self.gossip.try_send(gossip)
+        self.gossip
+            .try_send(gossip)
+            .map(|()| count_message(GOSSIP, OUTBOUND))
     }
 
     pub fn subscribe_to_block_announcements(&mut self) -> OutboundSubscription<Header> {
@@ -648,6 +668,7 @@ impl Peers {
     }
 
     pub fn refresh_peer_on_block<E>(&self, node_id: Id) -> impl Future<Item = bool, Error = E> {
+        count_message(BLOCK_EVENTS, INBOUND);
         let timestamp = SystemTime::now();
         self.inner()
             .map(move |mut map| match map.refresh_peer(&node_id) {
@@ -660,6 +681,7 @@ impl Peers {
     }
 
     pub fn refresh_peer_on_fragment<E>(&self, node_id: Id) -> impl Future<Item = bool, Error = E> {
+        count_message(FRAGMENTS, INBOUND);
         let timestamp = SystemTime::now();
         self.inner()
             .map(move |mut map| match map.refresh_peer(&node_id) {
@@ -672,6 +694,7 @@ impl Peers {
     }
 
     pub fn refresh_peer_on_gossip<E>(&self, node_id: Id) -> impl Future<Item = bool, Error = E> {
+        count_message(GOSSIP, INBOUND);
         let timestamp = SystemTime::now();
         self.inner()
             .map(move |mut map| match map.refresh_peer(&node_id) {
@@ -28,7 +28,7 @@ pub mod limits {
     /// limit the gossip size to 512 bytes (limit per gossip).
     ///
     /// a gossip only contains the Id, the address and an array of subscriptions
-    /// which should not go beyond 2 2-tuples of 64bits.
+    /// which should not go beyond 3 2-tuples of 64bits.
     pub const MAX_GOSSIP_SIZE: u64 = 512;
 
     /// limit the ID size to 32 bytes. Right now the Node ID are 24 bytes but
@@ -502,7 +502,9 @@ impl FragmentProcessor {
         let polled = self
             .mbox
             .start_send(TransactionMsg::SendTransaction(
-                FragmentOrigin::Network,
+                FragmentOrigin::Network {
+                    id: Some(self.node_id.into()),
+                },
                 fragments,
             ))
             .map_err(|e| {
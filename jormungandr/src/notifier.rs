@@ -0,0 +1,41 @@
+//! broadcast of the node's events (tip updates, rollbacks and fragment
+//! status changes) to the clients subscribed to the REST event stream.
+
+use jormungandr_lib::interfaces::{ChainTip, NodeEvent};
+use tokio02::sync::broadcast;
+
+use crate::blockchain::Ref;
+
+/// number of events kept for the subscribers that are late to
+/// consume them, once exceeded the oldest events are lost for them
+const EVENTS_CAPACITY: usize = 1024;
+
+#[derive(Clone)]
+pub struct Notifier {
+    sender: broadcast::Sender<NodeEvent>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENTS_CAPACITY);
+        Notifier { sender }
+    }
+
+    /// send the event to every current subscriber. It is not an error
+    /// to have no subscriber, the event is simply dropped.
+    pub fn notify(&self, event: NodeEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.sender.subscribe()
+    }
+}
+
+pub fn chain_tip(reference: &Ref) -> ChainTip {
+    ChainTip {
+        hash: reference.hash().into(),
+        chain_length: reference.chain_length().into(),
+        date: reference.block_date().into(),
+    }
+}
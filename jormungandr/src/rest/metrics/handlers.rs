@@ -0,0 +1,29 @@
+use actix_web::error::ErrorInternalServerError;
+use actix_web::web::Data;
+use actix_web::{Error, HttpResponse, Responder};
+use futures03::compat::Future01CompatExt;
+
+use crate::metrics;
+pub use crate::rest::Context;
+
+pub async fn get_metrics(context: Data<Context>) -> Result<impl Responder, Error> {
+    // the peer counts are only known once the node is fully started
+    if let Ok(full_context) = context.try_full().await {
+        let nodes_count = full_context.p2p.nodes_count::<Error>().compat().await?;
+        let peers = &metrics::PEERS;
+        peers
+            .with_label_values(&["total"])
+            .set(nodes_count.all_count as i64);
+        peers
+            .with_label_values(&["available"])
+            .set(nodes_count.available_count as i64);
+        peers
+            .with_label_values(&["unreachable"])
+            .set(nodes_count.not_reachable_count as i64);
+        peers
+            .with_label_values(&["quarantined"])
+            .set(nodes_count.quarantined_count as i64);
+    }
+    let (content_type, body) = metrics::encode().map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type(content_type).body(body))
+}
@@ -0,0 +1,10 @@
+mod handlers;
+
+use actix_web::{
+    dev::HttpServiceFactory,
+    web::{get, resource},
+};
+
+pub fn service(path: &str) -> impl HttpServiceFactory {
+    resource(path).route(get().to(handlers::get_metrics))
+}
@@ -3,6 +3,7 @@
 mod server;
 
 pub mod explorer;
+pub mod metrics;
 pub mod v0;
 
 pub use self::server::{Error, Server, ServerStopper};
@@ -15,8 +16,9 @@ use std::sync::Arc;
 
 use crate::blockchain::{Blockchain, Tip};
 use crate::fragment::Logs;
-use crate::leadership::Logs as LeadershipLogs;
+use crate::leadership::{History as LeadershipHistory, Logs as LeadershipLogs};
 use crate::network::p2p::P2pTopology;
+use crate::notifier::Notifier;
 use crate::secure::enclave::Enclave;
 use crate::settings::start::{Error as ConfigError, Rest};
 use crate::stats_counter::StatsCounter;
@@ -100,10 +102,12 @@ pub struct FullContext {
     pub transaction_task: MessageBox<TransactionMsg>,
     pub logs: Logs,
     pub leadership_logs: LeadershipLogs,
+    pub leadership_history: LeadershipHistory,
     pub enclave: Enclave,
     pub p2p: P2pTopology,
     pub explorer: Option<crate::explorer::Explorer>,
     pub diagnostic: crate::diagnostic::Diagnostic,
+    pub notifier: Notifier,
 }
 
 pub fn start_rest_server(
@@ -125,7 +129,10 @@ fn app_config_factory(
 }
 
 fn app_config(config: &mut ServiceConfig, explorer_enabled: bool, context: Context) {
-    config.data(context).service(v0::service("/api/v0"));
+    config
+        .data(context)
+        .service(v0::service("/api/v0"))
+        .service(metrics::service("/metrics"));
     if explorer_enabled {
         config.service(explorer::service("/explorer"));
     }
@@ -1,10 +1,10 @@
 use jormungandr_lib::interfaces::{
-    AccountState, Address, EnclaveLeaderId, FragmentOrigin, Rewards as StakePoolRewards,
-    StakePoolStats, TaxTypeSerde,
+    AccountState, Address, EnclaveLeaderId, FragmentLogLookup, FragmentOrigin,
+    LeadershipScheduleEntry, NodeEvent, Rewards as StakePoolRewards, StakePoolStats, TaxTypeSerde,
 };
 use jormungandr_lib::time::SystemTime;
 
-use actix_web::error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound};
+use actix_web::error::{ErrorBadRequest, ErrorGone, ErrorInternalServerError, ErrorNotFound};
 use actix_web::web::{Bytes, BytesMut, Data, Json, Path, Query};
 use actix_web::{Error, HttpResponse, Responder};
 use chain_core::property::{Block, Deserialize, Serialize as _};
@@ -17,15 +17,22 @@ use chain_impl_mockchain::stake::StakeDistribution;
 use chain_impl_mockchain::transaction::Transaction;
 use chain_impl_mockchain::value::{Value, ValueError};
 use chain_storage::error::Error as StorageError;
+use chain_time::era::{EpochPosition, EpochSlotOffset};
+use chain_time::Epoch;
 
-use crate::blockchain::Ref;
+use crate::blockchain::{
+    epoch_leadership_of, pruned_chain_length, snapshot_digest, Ref, Snapshot, SnapshotError,
+};
+use crate::fragment::Logs;
 use crate::intercom::{self, NetworkMsg, TransactionMsg};
 use crate::secure::NodeSecret;
 use futures::Stream;
-use futures03::compat::Future01CompatExt;
+use futures03::{compat::Future01CompatExt, stream as stream03};
 use jormungandr_lib::interfaces::NodeState;
+use std::collections::BTreeMap;
 use std::str::FromStr;
 use std::sync::Arc;
+use tokio02::{sync::broadcast::RecvError, task::spawn_blocking};
 
 pub use crate::rest::{Context, FullContext};
 
@@ -78,6 +85,56 @@ pub async fn get_message_logs(context: Data<Context>) -> Result<impl Responder,
         .map(Json)
 }
 
+const MAX_FRAGMENT_IDS: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct FragmentStatusQuery {
+    /// comma separated list of hex-encoded fragment IDs
+    ids: String,
+}
+
+pub async fn get_fragment_statuses(
+    context: Data<Context>,
+    query: Query<FragmentStatusQuery>,
+) -> Result<impl Responder, Error> {
+    let fragment_ids = query
+        .ids
+        .split(',')
+        .filter(|id| !id.is_empty())
+        .map(parse_fragment_id)
+        .collect::<Result<Vec<_>, _>>()?;
+    if fragment_ids.len() > MAX_FRAGMENT_IDS {
+        return Err(ErrorBadRequest(format!(
+            "too many fragment IDs, at most {} can be queried at once",
+            MAX_FRAGMENT_IDS
+        )));
+    }
+    let statuses = fragment_statuses(&context.try_full().await?.logs, fragment_ids).await?;
+    Ok(Json(statuses))
+}
+
+/// the logs of the given fragments, by hex-encoded fragment ID
+async fn fragment_statuses(
+    logs: &Logs,
+    fragment_ids: Vec<FragmentId>,
+) -> Result<BTreeMap<String, FragmentLogLookup>, Error> {
+    let fragment_logs = logs
+        .get_all(fragment_ids.clone())
+        .await
+        .map_err(|_| ErrorInternalServerError("Failed to get logs"))?;
+    Ok(fragment_ids
+        .into_iter()
+        .zip(fragment_logs)
+        .map(|(fragment_id, log)| {
+            let lookup = match log {
+                Some(log) => FragmentLogLookup::Found(log),
+                None => FragmentLogLookup::NotFound,
+            };
+            (fragment_id.to_string(), lookup)
+        })
+        .collect())
+}
+
 pub async fn post_message(context: Data<Context>, message: Bytes) -> Result<impl Responder, Error> {
     let fragment = Fragment::deserialize(&*message).map_err(ErrorBadRequest)?;
     let msg = TransactionMsg::SendTransaction(FragmentOrigin::Rest, vec![fragment]);
@@ -91,10 +148,95 @@ pub async fn post_message(context: Data<Context>, message: Bytes) -> Result<impl
     Ok(HttpResponse::Ok().finish())
 }
 
+/// accepts a stream of serialized fragments, replies with the outcome of
+/// the submission of every fragment
+pub async fn post_fragments(
+    context: Data<Context>,
+    messages: Bytes,
+) -> Result<impl Responder, Error> {
+    let fragments = deserialize_fragments(&messages)?;
+    let full_context = context.try_full().await?;
+    let submissions = intercom::unary_future(
+        full_context.transaction_task.clone(),
+        context.logger().await?,
+        |reply_handle| {
+            TransactionMsg::SubmitTransactions(FragmentOrigin::Rest, fragments, reply_handle)
+        },
+    )
+    .compat()
+    .await
+    .map_err(|e: intercom::Error| ErrorInternalServerError(e))?;
+    Ok(Json(submissions))
+}
+
+/// the fragments serialized one after the other in the body of a request
+fn deserialize_fragments(mut bytes: &[u8]) -> Result<Vec<Fragment>, Error> {
+    let mut fragments = Vec::new();
+    while !bytes.is_empty() {
+        let fragment = Fragment::deserialize(&mut bytes).map_err(ErrorBadRequest)?;
+        fragments.push(fragment);
+    }
+    Ok(fragments)
+}
+
+/// stream of the node's events, as server-sent events. A subscriber too slow
+/// to consume the events receives a `lagged` event with the number of
+/// events it missed.
+pub async fn get_events(context: Data<Context>) -> Result<impl Responder, Error> {
+    let receiver = context.try_full().await?.notifier.subscribe();
+    let events = stream03::unfold(receiver, |mut receiver| async move {
+        let event = match receiver.recv().await {
+            Ok(event) => server_sent_event(&event),
+            Err(RecvError::Lagged(missed)) => Ok(format!("event: lagged\ndata: {}\n\n", missed)),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((event.map(Bytes::from), receiver))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(Box::pin(events)))
+}
+
+fn server_sent_event(event: &NodeEvent) -> Result<String, Error> {
+    let data = match event {
+        NodeEvent::Tip(tip) => serde_json::to_string(tip),
+        NodeEvent::Rollback(rollback) => serde_json::to_string(rollback),
+        NodeEvent::FragmentStatus(update) => serde_json::to_string(update),
+    }
+    .map_err(ErrorInternalServerError)?;
+    Ok(format!("event: {}\ndata: {}\n\n", event.name(), data))
+}
+
 pub async fn get_tip(context: Data<Context>) -> Result<impl Responder, Error> {
     chain_tip(&context).await.map(|tip| tip.hash().to_string())
 }
 
+/// response header with the hash of the block the ledger snapshot is taken at
+const SNAPSHOT_BLOCK_HEADER: &str = "x-snapshot-block";
+/// response header with the digest of the ledger snapshot
+const SNAPSHOT_DIGEST_HEADER: &str = "x-snapshot-digest";
+
+pub async fn get_snapshot(context: Data<Context>) -> Result<impl Responder, Error> {
+    let full_context = context.try_full().await?;
+    let tip = chain_tip_from_full(&full_context).await?;
+    let snapshot = Snapshot::from_tip(tip, full_context.blockchain.storage())
+        .await
+        .map_err(|error| match error {
+            SnapshotError::NoStableEpoch => ErrorNotFound(error),
+            error => ErrorInternalServerError(error),
+        })?;
+    let mut bytes = Vec::new();
+    snapshot
+        .write(&mut bytes)
+        .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .header(SNAPSHOT_BLOCK_HEADER, snapshot.hash().to_string())
+        .header(SNAPSHOT_DIGEST_HEADER, snapshot_digest(&bytes).to_string())
+        .body(bytes))
+}
+
 #[derive(Serialize)]
 struct NodeStatsDto {
     version: &'static str,
@@ -156,6 +298,9 @@ async fn create_stats(context: &FullContext) -> Result<serde_json::Value, Error>
         .collect::<Result<(), ValueError>>()
         .map_err(|e| ErrorInternalServerError(format!("Block value calculation error: {}", e)))?;
     let nodes_count = &context.p2p.nodes_count::<Error>().compat().await?;
+    let pruned_chain_length = pruned_chain_length(context.blockchain.storage().clone())
+        .await
+        .map_err(ErrorInternalServerError)?;
     let tip_header = tip.header();
     let stats = &context.stats_counter;
     let node_id = &context.p2p.node_id().to_string();
@@ -172,6 +317,7 @@ async fn create_stats(context: &FullContext) -> Result<serde_json::Value, Error>
         "lastBlockContentSize": tip_header.block_content_size(),
         "lastBlockSum": block_input_sum.0,
         "lastBlockFees": block_fee_sum.0,
+        "prunedBlockHeight": pruned_chain_length.map(|length| length.to_string()),
         "peerTotalCnt": nodes_count.all_count,
         "peerAvailableCnt": nodes_count.available_count,
         "peerUnreachableCnt": nodes_count.not_reachable_count,
@@ -180,20 +326,42 @@ async fn create_stats(context: &FullContext) -> Result<serde_json::Value, Error>
     }))
 }
 
+pub async fn get_chain_reorgs(context: Data<Context>) -> Result<impl Responder, Error> {
+    let full_context = context.try_full().await?;
+    Ok(Json(full_context.blockchain.reorgs().all().await))
+}
+
 pub async fn get_block_id(
     context: Data<Context>,
     block_id_hex: Path<String>,
 ) -> Result<impl Responder, Error> {
-    context
-        .try_full()
-        .await?
-        .blockchain
-        .storage()
-        .get(parse_block_hash(&block_id_hex)?)
+    let full_context = context.try_full().await?;
+    let block_id = parse_block_hash(&block_id_hex)?;
+    let storage = full_context.blockchain.storage();
+    let block = storage
+        .get(block_id)
         .compat()
         .await
-        .map_err(ErrorInternalServerError)?
-        .ok_or(ErrorNotFound("Block not found"))?
+        .map_err(ErrorInternalServerError)?;
+    let block = match block {
+        Some(block) => block,
+        None => {
+            // the header is still stored if the contents of the block have
+            // been pruned
+            let pruned = storage
+                .get_header(block_id)
+                .compat()
+                .await
+                .map_err(ErrorInternalServerError)?
+                .is_some();
+            return Err(if pruned {
+                ErrorGone("Block has been pruned")
+            } else {
+                ErrorNotFound("Block not found")
+            });
+        }
+    };
+    block
         .serialize_as_vec()
         .map_err(ErrorInternalServerError)
         .map(Bytes::from)
@@ -315,7 +483,13 @@ pub async fn get_shutdown(context: Data<Context>) -> Result<impl Responder, Erro
 
 pub async fn get_leaders(context: Data<Context>) -> Result<impl Responder, Error> {
     Ok(Json(json! {
-        context.try_full().await?.enclave.get_leaderids().await
+        context
+            .try_full()
+            .await?
+            .enclave
+            .get_leaderids()
+            .await
+            .map_err(ErrorInternalServerError)?
     }))
 }
 
@@ -327,7 +501,13 @@ pub async fn post_leaders(
         bft_leader: secret.bft(),
         genesis_leader: secret.genesis(),
     };
-    let leader_id = context.try_full().await?.enclave.add_leader(leader).await;
+    let leader_id = context
+        .try_full()
+        .await?
+        .enclave
+        .add_leader(leader)
+        .await
+        .map_err(ErrorBadRequest)?;
     Ok(Json(leader_id))
 }
 
@@ -341,12 +521,132 @@ pub async fn delete_leaders(
         .enclave
         .remove_leader(*leader_id)
         .await
+        .map_err(ErrorBadRequest)?
+    {
+        true => Ok(HttpResponse::Ok().finish()),
+        false => Err(ErrorNotFound("Leader with given ID not found")),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LeaderRotation {
+    epoch: u32,
+    secret: NodeSecret,
+}
+
+pub async fn post_leaders_rotate(
+    rotation: Json<LeaderRotation>,
+    context: Data<Context>,
+    leader_id: Path<EnclaveLeaderId>,
+) -> Result<impl Responder, Error> {
+    let full_context = context.try_full().await?;
+    let tip_epoch = chain_tip_from_full(&full_context).await?.block_date().epoch;
+    if rotation.epoch < tip_epoch {
+        return Err(ErrorBadRequest(format!(
+            "cannot rotate the leader's keys at epoch {}, the blockchain is already at epoch {}",
+            rotation.epoch, tip_epoch
+        )));
+    }
+    let leader = Leader {
+        bft_leader: rotation.secret.bft(),
+        genesis_leader: rotation.secret.genesis(),
+    };
+    match full_context
+        .enclave
+        .rotate_leader(*leader_id, leader, rotation.epoch)
+        .await
+        .map_err(ErrorBadRequest)?
     {
         true => Ok(HttpResponse::Ok().finish()),
         false => Err(ErrorNotFound("Leader with given ID not found")),
     }
 }
 
+#[derive(Deserialize)]
+pub struct LeadersScheduleQuery {
+    epoch: Option<u32>,
+}
+
+pub async fn get_leaders_schedule(
+    context: Data<Context>,
+    query: Query<LeadersScheduleQuery>,
+) -> Result<impl Responder, Error> {
+    let full_context = context.try_full().await?;
+    let tip = chain_tip_from_full(&full_context).await?;
+    let tip_epoch = tip.block_date().epoch;
+    let epoch = query.epoch.unwrap_or(tip_epoch);
+    let (leadership, leadership_state) = if epoch == tip_epoch {
+        (
+            Arc::clone(tip.epoch_leadership_schedule()),
+            Arc::clone(tip.epoch_leadership_state()),
+        )
+    } else if epoch == tip_epoch + 1 && next_epoch_leadership_is_known(&tip) {
+        let tip = Arc::clone(&tip);
+        spawn_blocking(move || epoch_leadership_of(epoch, &tip))
+            .await
+            .map_err(ErrorInternalServerError)?
+            .map_err(|error| ErrorInternalServerError(error.to_string()))?
+    } else {
+        return Err(ErrorBadRequest(format!(
+            "the schedule of the epoch {} is not known yet",
+            epoch
+        )));
+    };
+
+    let era = leadership.era();
+    let events = full_context
+        .enclave
+        .leadership_evaluate(&leadership, &leadership_state, 0, era.slots_per_epoch())
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let schedule = events
+        .into_iter()
+        .map(|event| {
+            let slot = era.from_era_to_slot(EpochPosition {
+                epoch: Epoch(event.date.epoch),
+                slot: EpochSlotOffset(event.date.slot_id),
+            });
+            let time = tip.time_frame().slot_to_systemtime(slot).ok_or_else(|| {
+                ErrorInternalServerError("slot before the start of the blockchain")
+            })?;
+            Ok(LeadershipScheduleEntry::new(
+                event.id,
+                event.date.into(),
+                time.into(),
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(Json(schedule))
+}
+
+/// with Genesis Praos, the leadership of the next epoch is computed from the
+/// state of the blockchain at the end of the epoch before the tip's, the
+/// blocks still to come in the tip's epoch do not change it. With BFT, it
+/// depends on the state at the end of the tip's epoch.
+fn next_epoch_leadership_is_known(tip: &Ref) -> bool {
+    match tip.epoch_leadership_schedule().consensus() {
+        LeadershipConsensus::GenesisPraos(_) => tip.last_ref_previous_epoch().is_some(),
+        _ => false,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LeadersHistoryQuery {
+    epoch: Option<u32>,
+}
+
+pub async fn get_leaders_history(
+    context: Data<Context>,
+    query: Query<LeadersHistoryQuery>,
+) -> Result<impl Responder, Error> {
+    let full_context = context.try_full().await?;
+    let epoch = match query.epoch {
+        Some(epoch) => epoch,
+        None => chain_tip_from_full(&full_context).await?.block_date().epoch,
+    };
+    Ok(Json(full_context.leadership_history.epoch(epoch).await))
+}
+
 pub async fn get_leaders_logs(context: Data<Context>) -> Result<impl Responder, Error> {
     Ok(Json(context.try_full().await?.leadership_logs.logs().await))
 }
@@ -502,3 +802,84 @@ pub async fn get_network_p2p_view_topic(
     let node_infos: Vec<poldercast::NodeInfo> = view.peers.into_iter().map(Into::into).collect();
     Ok(Json(json!(node_infos)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fragment::testing::transaction;
+    use crate::notifier::Notifier;
+    use jormungandr_lib::interfaces::{FragmentLog, FragmentStatus, FragmentStatusUpdate};
+    use std::time::Duration;
+
+    #[test]
+    fn batch_of_fragments_is_deserialized_in_order() {
+        let fragments = vec![transaction(0, 10, 1), transaction(1, 20, 2)];
+        let mut bytes = Vec::new();
+        for fragment in &fragments {
+            bytes.extend(fragment.serialize_as_vec().unwrap());
+        }
+
+        let deserialized = deserialize_fragments(&bytes).unwrap();
+        assert_eq!(
+            deserialized.iter().map(Fragment::id).collect::<Vec<_>>(),
+            fragments.iter().map(Fragment::id).collect::<Vec<_>>()
+        );
+        assert!(deserialize_fragments(&[]).unwrap().is_empty());
+        // a truncated fragment fails the whole batch
+        assert!(deserialize_fragments(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn statuses_of_known_and_unknown_fragments() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut logs = Logs::new(10, Duration::from_secs(3600), Notifier::new());
+            let known = FragmentId::hash_bytes(&[1]);
+            let unknown = FragmentId::hash_bytes(&[2]);
+            let log = FragmentLog::new(known, FragmentOrigin::Rest);
+            logs.insert(log.clone()).await.unwrap();
+
+            let statuses = fragment_statuses(&logs, vec![known, unknown])
+                .await
+                .unwrap();
+
+            assert_eq!(statuses.len(), 2);
+            assert_eq!(statuses[&known.to_string()], FragmentLogLookup::Found(log));
+            assert_eq!(statuses[&unknown.to_string()], FragmentLogLookup::NotFound);
+        });
+    }
+
+    #[test]
+    fn fragment_status_changes_are_sent_as_events() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let notifier = Notifier::new();
+            let mut receiver = notifier.subscribe();
+            let mut logs = Logs::new(10, Duration::from_secs(3600), notifier);
+            let fragment_id = FragmentId::hash_bytes(&[1]);
+            let rejected = FragmentStatus::Rejected {
+                reason: "rejected by the ledger".to_owned(),
+            };
+            logs.insert(FragmentLog::new(fragment_id, FragmentOrigin::Rest))
+                .await
+                .unwrap();
+            logs.modify(fragment_id, rejected.clone()).await.unwrap();
+
+            for status in vec![FragmentStatus::Pending, rejected] {
+                let event = receiver.recv().await.unwrap();
+                let update = FragmentStatusUpdate {
+                    fragment_id: fragment_id.into(),
+                    status,
+                };
+                assert_eq!(event, NodeEvent::FragmentStatus(update.clone()));
+                assert_eq!(
+                    server_sent_event(&event).unwrap(),
+                    format!(
+                        "event: fragment_status\ndata: {}\n\n",
+                        serde_json::to_string(&update).unwrap()
+                    )
+                );
+            }
+        });
+    }
+}
@@ -2,9 +2,12 @@ mod handlers;
 
 use actix_web::{
     dev::HttpServiceFactory,
-    web::{delete, get, post, resource, scope},
+    web::{delete, get, post, resource, scope, PayloadConfig},
 };
 
+/// maximum size of the body of a fragments batch submission
+const FRAGMENTS_PAYLOAD_LIMIT: usize = 4 * 1024 * 1024;
+
 pub fn service(root_path: &str) -> impl HttpServiceFactory {
     scope(root_path)
         .route(
@@ -12,21 +15,35 @@ pub fn service(root_path: &str) -> impl HttpServiceFactory {
             get().to(handlers::get_account_state),
         )
         .route("/block/{block_id}", get().to(handlers::get_block_id))
+        .route("/chain/reorgs", get().to(handlers::get_chain_reorgs))
         .route(
             "/block/{block_id}/next_id",
             get().to(handlers::get_block_next_id),
         )
         .route("/fragment/logs", get().to(handlers::get_message_logs))
+        .route(
+            "/fragment/status",
+            get().to(handlers::get_fragment_statuses),
+        )
         .service(
             resource("/leaders")
                 .route(get().to(handlers::get_leaders))
                 .route(post().to(handlers::post_leaders)),
         )
         .route("/leaders/logs", get().to(handlers::get_leaders_logs))
+        .route(
+            "/leaders/schedule",
+            get().to(handlers::get_leaders_schedule),
+        )
+        .route("/leaders/history", get().to(handlers::get_leaders_history))
         .route(
             "/leaders/{leader_id}",
             delete().to(handlers::delete_leaders),
         )
+        .route(
+            "/leaders/{leader_id}/rotate",
+            post().to(handlers::post_leaders_rotate),
+        )
         .route("/network/stats", get().to(handlers::get_network_stats))
         .route(
             "/network/p2p/quarantined",
@@ -49,13 +66,20 @@ pub fn service(root_path: &str) -> impl HttpServiceFactory {
             get().to(handlers::get_network_p2p_view_topic),
         )
         .route("/settings", get().to(handlers::get_settings))
+        .route("/snapshot", get().to(handlers::get_snapshot))
         .route("/stake", get().to(handlers::get_stake_distribution))
         .route("/stake_pools", get().to(handlers::get_stake_pools))
         .route("/stake_pool/{pool_id}", get().to(handlers::get_stake_pool))
         .route("/shutdown", get().to(handlers::get_shutdown))
         .route("/message", post().to(handlers::post_message))
+        .service(
+            resource("/fragments")
+                .data(PayloadConfig::new(FRAGMENTS_PAYLOAD_LIMIT))
+                .route(post().to(handlers::post_fragments)),
+        )
         .route("/node/stats", get().to(handlers::get_stats_counter))
         .route("/tip", get().to(handlers::get_tip))
+        .route("/events", get().to(handlers::get_events))
         .route(
             "/utxo/{fragment_id}/{output_index}",
             get().to(handlers::get_utxo),
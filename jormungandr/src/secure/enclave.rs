@@ -1,138 +1,520 @@
+#[cfg(unix)]
+use super::remote::{RemoteSigner, RemoteSignerError};
+use super::rotation::{LeaderRotations, RotationError};
 use crate::blockcfg::{
-    BlockDate, HeaderBft, HeaderBftBuilder, HeaderGenesisPraos, HeaderGenesisPraosBuilder,
-    HeaderSetConsensusSignature, SlotId,
+    BlockDate, ChainLength, Contents, Header, HeaderBft, HeaderBftBuilder, HeaderGenesisPraos,
+    HeaderGenesisPraosBuilder, HeaderHash, HeaderSetConsensusSignature, Ledger, SlotId,
 };
+#[cfg(unix)]
+use chain_core::property::{Deserialize as _, Serialize as _};
+#[cfg(unix)]
+use chain_impl_mockchain::leadership::{bft, LeadershipConsensus};
 use chain_impl_mockchain::leadership::{Leader, LeaderOutput, Leadership};
-use jormungandr_lib::interfaces::EnclaveLeaderId as LeaderId;
+#[cfg(unix)]
+use jormungandr_lib::interfaces::{SignerLeader, SignerLeaderEvent, SignerRequest, SignerResponse};
+use jormungandr_lib::{
+    crypto::kes::{KesError, KesPeriods, KesSchedule},
+    interfaces::EnclaveLeaderId as LeaderId,
+};
 use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::SystemTime;
+use thiserror::Error;
 use tokio02::sync::RwLock;
 
 #[derive(Clone)]
 pub struct Enclave {
-    leaders: Arc<RwLock<BTreeMap<LeaderId, Leader>>>,
+    backend: Backend,
+}
+
+/// where the leaders' secrets are held
+#[derive(Clone)]
+enum Backend {
+    /// the secrets are in the memory of the node
+    Local(Arc<RwLock<LocalLeaders>>),
+    /// the secrets are held by an external signer process, the node only
+    /// receives the public keys of the BFT leaders. The Genesis Praos
+    /// leadership is evaluated by the signer, which creates the headers.
+    #[cfg(unix)]
+    Remote(Arc<RemoteSigner>),
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("the leaders are managed by the remote signer")]
+    ManagedRemotely,
+    #[error("cannot evolve the KES key")]
+    Kes(#[from] KesError),
+    #[error("cannot save the leader rotation")]
+    Rotation(#[from] RotationError),
+    #[cfg(unix)]
+    #[error("remote signer failure")]
+    Remote(#[from] RemoteSignerError),
 }
 
 pub struct LeaderEvent {
     pub id: LeaderId,
     pub date: BlockDate,
-    pub output: LeaderOutput,
+    pub output: LeaderEventOutput,
+}
+
+pub enum LeaderEventOutput {
+    /// the leadership evaluated with the leader's keys
+    Leader(LeaderOutput),
+    /// the leader held by the remote signer is leader of the slot: the
+    /// signer creates the whole header after checking the leadership of
+    /// the slot against the leadership state
+    Remote { leadership_state: Arc<Ledger> },
+}
+
+#[derive(Default)]
+struct LocalLeaders {
+    leaders: BTreeMap<LeaderId, LocalLeader>,
+    /// evolution of the KES keys, not enabled if the blockchain does not
+    /// define the KES update speed
+    kes: Option<Kes>,
+    /// the saved rotations of the leaders
+    rotations: Option<LeaderRotations>,
+}
+
+struct LocalLeader {
+    leader: Leader,
+    /// the period the KES key of the Genesis Praos leader has reached
+    kes_period: u32,
+    /// the new secrets of the leader, replacing the current ones from the
+    /// given epoch
+    rotation: Option<Rotation>,
+}
+
+struct Rotation {
+    epoch: u32,
+    leader: Leader,
+}
+
+struct Kes {
+    schedule: KesSchedule,
+    periods: KesPeriods,
+}
+
+impl LocalLeader {
+    fn new(leader: Leader) -> Self {
+        LocalLeader {
+            leader,
+            kes_period: 0,
+            rotation: None,
+        }
+    }
+
+    /// the secrets of the leader in use at the given epoch
+    fn for_epoch(&self, epoch: u32) -> &Leader {
+        match &self.rotation {
+            Some(rotation) if epoch >= rotation.epoch => &rotation.leader,
+            _ => &self.leader,
+        }
+    }
+
+    /// the rotation of the leader is due at the given epoch
+    fn rotation_is_due(&self, epoch: u32) -> bool {
+        self.rotation
+            .as_ref()
+            .map_or(false, |rotation| epoch >= rotation.epoch)
+    }
+
+    /// replace the secrets of the leader if its rotation is due at the
+    /// given epoch
+    fn apply_rotation(&mut self, epoch: u32) {
+        if self.rotation_is_due(epoch) {
+            let rotation = self.rotation.take().unwrap();
+            self.leader = rotation.leader;
+            self.kes_period = 0;
+        }
+    }
+
+    /// evolve the KES key up to the period of the given time, or to the
+    /// period saved for this key if it is already further
+    fn evolve_kes(&mut self, kes: &mut Kes, time: SystemTime) -> Result<(), KesError> {
+        self.kes_period = kes.periods.evolve_leader(
+            &mut self.leader,
+            self.kes_period,
+            kes.schedule.period_at(time),
+        )?;
+        Ok(())
+    }
+}
+
+impl LocalLeaders {
+    /// get the leader ready to sign a block of the given epoch
+    fn get_for_signing(
+        &mut self,
+        id: &LeaderId,
+        epoch: u32,
+        time: SystemTime,
+    ) -> Result<Option<&Leader>, Error> {
+        let leader = match self.leaders.get_mut(id) {
+            Some(leader) => leader,
+            None => return Ok(None),
+        };
+        if leader.rotation_is_due(epoch) {
+            // the saved rotation is forgotten once it takes effect
+            if let Some(rotations) = &mut self.rotations {
+                rotations.remove(&leader.leader)?;
+            }
+            leader.apply_rotation(epoch);
+        }
+        if let Some(kes) = &mut self.kes {
+            leader.evolve_kes(kes, time)?;
+        }
+        Ok(Some(&leader.leader))
+    }
+}
+
+/// the BFT leadership evaluation data of a leader held by the remote signer
+#[cfg(unix)]
+struct RemoteLeader {
+    bft: Option<bft::LeaderId>,
 }
 
 fn get_maximum_id<A>(leaders: &BTreeMap<LeaderId, A>) -> LeaderId {
     leaders.keys().last().copied().unwrap_or(LeaderId::new())
 }
 
+fn evaluate<L, E, F>(
+    leaders: &BTreeMap<LeaderId, L>,
+    leadership: &Leadership,
+    slot_start: u32,
+    nb_slots: u32,
+    is_leader_for_date: F,
+) -> Vec<LeaderEvent>
+where
+    F: Fn(&L, BlockDate) -> Result<LeaderOutput, E>,
+{
+    let mut output = Vec::new();
+    for slot_idx in slot_start..slot_start + nb_slots {
+        let date = leadership.date_at_slot(slot_idx);
+        for (id, leader) in leaders.iter() {
+            match is_leader_for_date(leader, date) {
+                Ok(LeaderOutput::None) => (),
+                Ok(leader_output) => output.push(LeaderEvent {
+                    id: *id,
+                    date: date,
+                    output: LeaderEventOutput::Leader(leader_output),
+                }),
+                Err(_) => {
+                    // For now silently ignore error
+                }
+            }
+        }
+    }
+    output
+}
+
 impl Enclave {
     pub fn new() -> Self {
         Enclave {
-            leaders: Arc::new(RwLock::new(BTreeMap::new())),
+            backend: Backend::Local(Arc::new(RwLock::new(LocalLeaders::default()))),
         }
     }
 
     pub async fn from_vec(leaders: Vec<Leader>) -> Self {
         let e = Self::new();
         for leader in leaders {
-            e.add_leader(leader).await;
+            e.add_leader(leader)
+                .await
+                .expect("a local enclave always accepts new leaders");
         }
         e
     }
 
-    pub async fn get_leaderids(&self) -> Vec<LeaderId> {
-        let leaders = self.leaders.read().await;
-        leaders.keys().map(|v| v.clone()).collect()
+    /// create an enclave whose leaders are held by the given remote signer
+    #[cfg(unix)]
+    pub fn remote(signer: RemoteSigner) -> Self {
+        Enclave {
+            backend: Backend::Remote(Arc::new(signer)),
+        }
     }
 
-    pub async fn add_leader(&self, leader: Leader) -> LeaderId {
-        let mut leaders = self.leaders.write().await;
-        let next_leader_id = get_maximum_id(&leaders).next();
+    pub async fn get_leaderids(&self) -> Result<Vec<LeaderId>, Error> {
+        match &self.backend {
+            Backend::Local(leaders) => {
+                let leaders = leaders.read().await;
+                Ok(leaders.leaders.keys().map(|v| v.clone()).collect())
+            }
+            #[cfg(unix)]
+            Backend::Remote(signer) => Ok(remote_leaders(signer).await?.keys().copied().collect()),
+        }
+    }
+
+    pub async fn add_leader(&self, leader: Leader) -> Result<LeaderId, Error> {
+        let leaders = match &self.backend {
+            Backend::Local(leaders) => leaders,
+            #[cfg(unix)]
+            Backend::Remote(_) => return Err(Error::ManagedRemotely),
+        };
+        let mut leaders = leaders.write().await;
+        let LocalLeaders { leaders, kes, .. } = &mut *leaders;
+        let next_leader_id = get_maximum_id(leaders).next();
+        let mut leader = LocalLeader::new(leader);
+        if let Some(kes) = kes {
+            leader.evolve_kes(kes, SystemTime::now())?;
+        }
         // This panic case should never happens in practice, as this structure is
         // not supposed to be shared between thread.
         match leaders.insert(next_leader_id, leader) {
             None => (),
             Some(_) => panic!("enclave leader failed : duplicated value race"),
         };
-        next_leader_id
+        Ok(next_leader_id)
     }
 
-    pub async fn remove_leader(&self, leader_id: LeaderId) -> bool {
-        let mut leaders = self.leaders.write().await;
-        leaders.remove(&leader_id).is_some()
+    pub async fn remove_leader(&self, leader_id: LeaderId) -> Result<bool, Error> {
+        match &self.backend {
+            Backend::Local(leaders) => {
+                let mut leaders = leaders.write().await;
+                let LocalLeaders {
+                    leaders, rotations, ..
+                } = &mut *leaders;
+                let local_leader = match leaders.get(&leader_id) {
+                    Some(local_leader) => local_leader,
+                    None => return Ok(false),
+                };
+                if let Some(rotations) = rotations {
+                    rotations.remove(&local_leader.leader)?;
+                }
+                leaders.remove(&leader_id);
+                Ok(true)
+            }
+            #[cfg(unix)]
+            Backend::Remote(_) => Err(Error::ManagedRemotely),
+        }
+    }
+
+    /// replace the secrets of the leader from the given epoch, returns
+    /// `false` if there is no such leader. The rotation is saved before it
+    /// is scheduled, if the leader rotations are saved.
+    pub async fn rotate_leader(
+        &self,
+        leader_id: LeaderId,
+        leader: Leader,
+        epoch: u32,
+    ) -> Result<bool, Error> {
+        match &self.backend {
+            Backend::Local(leaders) => {
+                let mut leaders = leaders.write().await;
+                let LocalLeaders {
+                    leaders, rotations, ..
+                } = &mut *leaders;
+                match leaders.get_mut(&leader_id) {
+                    Some(local_leader) => {
+                        if let Some(rotations) = rotations {
+                            rotations.set(&local_leader.leader, epoch, &leader)?;
+                        }
+                        local_leader.rotation = Some(Rotation { epoch, leader });
+                        Ok(true)
+                    }
+                    None => Ok(false),
+                }
+            }
+            #[cfg(unix)]
+            Backend::Remote(_) => Err(Error::ManagedRemotely),
+        }
+    }
+
+    /// schedule again the rotations saved in `rotations`, and save the
+    /// following ones there
+    pub async fn enable_leader_rotations(&self, rotations: LeaderRotations) -> Result<(), Error> {
+        if let Backend::Local(leaders) = &self.backend {
+            let mut leaders = leaders.write().await;
+            for local_leader in leaders.leaders.values_mut() {
+                if let Some((epoch, leader)) = rotations.get(&local_leader.leader) {
+                    local_leader.rotation = Some(Rotation { epoch, leader });
+                }
+            }
+            leaders.rotations = Some(rotations);
+        }
+        Ok(())
+    }
+
+    /// evolve the KES keys of the leaders following the given schedule,
+    /// the periods reached by the keys are kept in `periods`. The remote
+    /// signer keeps the periods of its keys itself, it is only told the
+    /// period to evolve the keys to.
+    pub async fn enable_kes_evolution(
+        &self,
+        schedule: KesSchedule,
+        periods: KesPeriods,
+    ) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Local(leaders) => {
+                let mut leaders = leaders.write().await;
+                let LocalLeaders { leaders, kes, .. } = &mut *leaders;
+                let kes = kes.get_or_insert(Kes { schedule, periods });
+                for leader in leaders.values_mut() {
+                    leader.evolve_kes(kes, SystemTime::now())?;
+                }
+            }
+            #[cfg(unix)]
+            Backend::Remote(signer) => {
+                signer.set_kes_schedule(schedule);
+                remote_evolve_kes(signer, SystemTime::now()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// evolve the KES keys of the leaders to the period of the given time
+    pub async fn evolve_kes(&self, time: SystemTime) -> Result<(), Error> {
+        match &self.backend {
+            Backend::Local(leaders) => {
+                let mut leaders = leaders.write().await;
+                let LocalLeaders { leaders, kes, .. } = &mut *leaders;
+                if let Some(kes) = kes {
+                    for leader in leaders.values_mut() {
+                        leader.evolve_kes(kes, time)?;
+                    }
+                }
+            }
+            #[cfg(unix)]
+            Backend::Remote(signer) => remote_evolve_kes(signer, time).await?,
+        }
+        Ok(())
     }
 
     // temporary method
     pub async fn leadership_evaluate1(
         &self,
         leadership: &Leadership,
+        leadership_state: &Arc<Ledger>,
         leader_id: &LeaderId,
         slot: SlotId,
-    ) -> Option<LeaderEvent> {
-        let leaders = self.leaders.read().await;
-        if leaders.len() == 0 {
-            return None;
-        }
-
-        leaders.get(leader_id).and_then(|leader| {
-            let date = leadership.date_at_slot(slot);
-            match leadership.is_leader_for_date(&leader, date) {
-                Ok(LeaderOutput::None) => None,
-                Ok(leader_output) => Some(LeaderEvent {
-                    id: *leader_id,
-                    date: date,
-                    output: leader_output,
-                }),
-                Err(_) => {
-                    // For now silently ignore error
-                    None
-                }
-            }
-        })
+    ) -> Result<Option<LeaderEvent>, Error> {
+        Ok(self
+            .leadership_evaluate(leadership, leadership_state, slot, 1)
+            .await?
+            .into_iter()
+            .find(|event| event.id == *leader_id))
     }
 
+    /// evaluate the leadership of the leaders on the given slots,
+    /// `leadership_state` is the ledger state `leadership` was computed
+    /// from
     pub async fn leadership_evaluate(
         &self,
         leadership: &Leadership,
+        leadership_state: &Arc<Ledger>,
         slot_start: u32,
         nb_slots: u32,
-    ) -> Vec<LeaderEvent> {
-        let leaders = self.leaders.read().await;
-        if leaders.len() == 0 {
-            return vec![];
-        }
-
-        let mut output = Vec::new();
-        for slot_idx in slot_start..slot_start + nb_slots {
-            let date = leadership.date_at_slot(slot_idx);
-            for (id, leader) in leaders.iter() {
-                match leadership.is_leader_for_date(&leader, date) {
-                    Ok(LeaderOutput::None) => (),
-                    Ok(leader_output) => output.push(LeaderEvent {
-                        id: *id,
-                        date: date,
-                        output: leader_output,
-                    }),
-                    Err(_) => {
-                        // For now silently ignore error
+    ) -> Result<Vec<LeaderEvent>, Error> {
+        match &self.backend {
+            Backend::Local(leaders) => {
+                let leaders = leaders.read().await;
+                Ok(evaluate(
+                    &leaders.leaders,
+                    leadership,
+                    slot_start,
+                    nb_slots,
+                    |leader, date| {
+                        leadership.is_leader_for_date(leader.for_epoch(date.epoch), date)
+                    },
+                ))
+            }
+            #[cfg(unix)]
+            Backend::Remote(signer) => match leadership.consensus() {
+                LeadershipConsensus::Bft(_) => {
+                    let leaders = remote_leaders(signer).await?;
+                    Ok(evaluate(
+                        &leaders,
+                        leadership,
+                        slot_start,
+                        nb_slots,
+                        |leader, date| remote_is_bft_leader_for_date(leadership, leader, date),
+                    )
+                    .into_iter()
+                    .map(|event| LeaderEvent {
+                        output: LeaderEventOutput::Remote {
+                            leadership_state: Arc::clone(leadership_state),
+                        },
+                        ..event
+                    })
+                    .collect())
+                }
+                LeadershipConsensus::GenesisPraos(_) => {
+                    let epoch = leadership.date_at_slot(slot_start).epoch;
+                    let request = |state| SignerRequest::LeadershipEvaluate {
+                        state,
+                        slot_start,
+                        nb_slots,
+                    };
+                    match remote_query_with_state(signer, leadership_state, epoch, request).await? {
+                        SignerResponse::LeaderEvents(events) => Ok(events
+                            .into_iter()
+                            .map(|SignerLeaderEvent { id, date }| LeaderEvent {
+                                id,
+                                date: date.into(),
+                                output: LeaderEventOutput::Remote {
+                                    leadership_state: Arc::clone(leadership_state),
+                                },
+                            })
+                            .collect()),
+                        _ => Err(RemoteSignerError::UnexpectedResponse.into()),
                     }
                 }
-            }
+            },
+        }
+    }
+
+    /// ask the remote signer to create the header of a block of the
+    /// leader, `None` if the signer does not hold the leader or is not
+    /// leader of the slot
+    pub async fn create_header_remote(
+        &self,
+        id: LeaderId,
+        leadership_state: &Arc<Ledger>,
+        parent: HeaderHash,
+        chain_length: ChainLength,
+        date: BlockDate,
+        contents: &Contents,
+    ) -> Result<Option<Header>, Error> {
+        match &self.backend {
+            Backend::Local(_) => Ok(None),
+            #[cfg(unix)]
+            Backend::Remote(signer) => Ok(remote_create_header(
+                signer,
+                id,
+                leadership_state,
+                parent,
+                chain_length,
+                date,
+                contents,
+            )
+            .await?),
         }
-        output
     }
 
     pub async fn create_header_genesis_praos(
         &self,
         header_builder: HeaderGenesisPraosBuilder<HeaderSetConsensusSignature>,
         id: LeaderId,
-    ) -> Option<HeaderGenesisPraos> {
-        let leaders = self.leaders.read().await;
-        let leader = leaders.get(&id)?;
-        if let Some(genesis_leader) = &leader.genesis_leader {
-            let data = header_builder.get_authenticated_data();
-            let signature = genesis_leader.sig_key.sign_slice(data);
-            Some(header_builder.set_signature(signature.into()))
-        } else {
-            None
+        date: BlockDate,
+    ) -> Result<Option<HeaderGenesisPraos>, Error> {
+        match &self.backend {
+            Backend::Local(leaders) => {
+                let mut leaders = leaders.write().await;
+                let leader = match leaders.get_for_signing(&id, date.epoch, SystemTime::now())? {
+                    Some(leader) => leader,
+                    None => return Ok(None),
+                };
+                if let Some(genesis_leader) = &leader.genesis_leader {
+                    let data = header_builder.get_authenticated_data();
+                    let signature = genesis_leader.sig_key.sign_slice(data);
+                    Ok(Some(header_builder.set_signature(signature.into())))
+                } else {
+                    Ok(None)
+                }
+            }
+            // the VRF proof is not known to the node, the header is
+            // created by the signer
+            #[cfg(unix)]
+            Backend::Remote(_) => Err(Error::ManagedRemotely),
         }
     }
 
@@ -140,15 +522,236 @@ impl Enclave {
         &self,
         header_builder: HeaderBftBuilder<HeaderSetConsensusSignature>,
         id: LeaderId,
-    ) -> Option<HeaderBft> {
-        let leaders = self.leaders.read().await;
-        let leader = leaders.get(&id)?;
-        if let Some(ref leader) = &leader.bft_leader {
-            let data = header_builder.get_authenticated_data();
-            let signature = leader.sig_key.sign_slice(data);
-            Some(header_builder.set_signature(signature.into()))
-        } else {
-            None
+        date: BlockDate,
+    ) -> Result<Option<HeaderBft>, Error> {
+        match &self.backend {
+            Backend::Local(leaders) => {
+                let mut leaders = leaders.write().await;
+                let leader = match leaders.get_for_signing(&id, date.epoch, SystemTime::now())? {
+                    Some(leader) => leader,
+                    None => return Ok(None),
+                };
+                if let Some(ref leader) = &leader.bft_leader {
+                    let data = header_builder.get_authenticated_data();
+                    let signature = leader.sig_key.sign_slice(data);
+                    Ok(Some(header_builder.set_signature(signature.into())))
+                } else {
+                    Ok(None)
+                }
+            }
+            // the signer does not sign data it has not checked, the header
+            // is created by the signer
+            #[cfg(unix)]
+            Backend::Remote(_) => Err(Error::ManagedRemotely),
         }
     }
 }
+
+#[cfg(unix)]
+async fn remote_leaders(
+    signer: &Arc<RemoteSigner>,
+) -> Result<BTreeMap<LeaderId, RemoteLeader>, RemoteSignerError> {
+    match signer.query(SignerRequest::Leaders).await? {
+        SignerResponse::Leaders(leaders) => Ok(leaders
+            .into_iter()
+            .map(|SignerLeader { id, bft, .. }| {
+                let leader = RemoteLeader {
+                    bft: bft.map(|key| bft::LeaderId::from(key.into_public_key())),
+                };
+                (id, leader)
+            })
+            .collect()),
+        _ => Err(RemoteSignerError::UnexpectedResponse),
+    }
+}
+
+/// the header of the block created by the remote signer, `None` if the
+/// signer refused to create it
+#[cfg(unix)]
+async fn remote_create_header(
+    signer: &Arc<RemoteSigner>,
+    id: LeaderId,
+    leadership_state: &Arc<Ledger>,
+    parent: HeaderHash,
+    chain_length: ChainLength,
+    date: BlockDate,
+    contents: &Contents,
+) -> Result<Option<Header>, RemoteSignerError> {
+    let fragments: Vec<Vec<u8>> = contents
+        .iter()
+        .map(|fragment| fragment.serialize_as_vec())
+        .collect::<Result<_, _>>()
+        .map_err(|_| RemoteSignerError::Encoding)?;
+    let kes_period = signer.kes_period_at(SystemTime::now());
+    let request = |state| SignerRequest::CreateHeader {
+        id,
+        state,
+        parent: parent.into(),
+        chain_length: chain_length.into(),
+        date: date.into(),
+        kes_period,
+        fragments: fragments.clone(),
+    };
+    let header = match remote_query_with_state(signer, leadership_state, date.epoch, request).await
+    {
+        Ok(SignerResponse::Header(header)) => header,
+        Ok(_) => return Err(RemoteSignerError::UnexpectedResponse),
+        Err(RemoteSignerError::Refused(_)) => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let header = Header::deserialize(header.as_slice())
+        .map_err(|_| RemoteSignerError::UnexpectedResponse)?;
+    // the block is checked against the leadership when it is applied,
+    // the header must still be the one of the requested block
+    if header.block_parent_hash() != parent
+        || header.chain_length() != chain_length
+        || header.block_date() != date
+    {
+        return Err(RemoteSignerError::UnexpectedResponse);
+    }
+    Ok(Some(header))
+}
+
+/// ask the remote signer to evolve the KES keys to the period of the given
+/// time, if the KES keys are evolved
+#[cfg(unix)]
+async fn remote_evolve_kes(
+    signer: &Arc<RemoteSigner>,
+    time: SystemTime,
+) -> Result<(), RemoteSignerError> {
+    let period = match signer.kes_period_at(time) {
+        Some(period) => period,
+        None => return Ok(()),
+    };
+    match signer.query(SignerRequest::EvolveKes { period }).await? {
+        SignerResponse::Done => Ok(()),
+        _ => Err(RemoteSignerError::UnexpectedResponse),
+    }
+}
+
+/// send the request built with the identifier of the leadership state to
+/// the remote signer. The state is sent first if the signer does not hold
+/// it yet.
+#[cfg(unix)]
+async fn remote_query_with_state<F>(
+    signer: &Arc<RemoteSigner>,
+    leadership_state: &Arc<Ledger>,
+    epoch: u32,
+    request: F,
+) -> Result<SignerResponse, RemoteSignerError>
+where
+    F: Fn(u64) -> SignerRequest,
+{
+    let (state, sent) = signer.leadership_state_id(leadership_state, epoch);
+    if sent {
+        match signer.query(request(state)).await? {
+            SignerResponse::UnknownLeadershipState => (),
+            response => return Ok(response),
+        }
+    }
+
+    let ledger = leadership_state
+        .serialize_as_vec()
+        .map_err(|_| RemoteSignerError::Encoding)?;
+    match signer
+        .query(SignerRequest::LeadershipState {
+            state,
+            epoch,
+            ledger,
+        })
+        .await?
+    {
+        SignerResponse::Done => signer.set_leadership_state_sent(state),
+        _ => return Err(RemoteSignerError::UnexpectedResponse),
+    }
+    match signer.query(request(state)).await? {
+        SignerResponse::UnknownLeadershipState => Err(RemoteSignerError::UnexpectedResponse),
+        response => Ok(response),
+    }
+}
+
+/// same as `Leadership::is_leader_for_date` for the BFT leadership, only
+/// with the public key of the leader
+#[cfg(unix)]
+fn remote_is_bft_leader_for_date(
+    leadership: &Leadership,
+    leader: &RemoteLeader,
+    date: BlockDate,
+) -> Result<LeaderOutput, chain_impl_mockchain::leadership::Error> {
+    match (leadership.consensus(), leader) {
+        (
+            LeadershipConsensus::Bft(bft),
+            RemoteLeader {
+                bft: Some(leader_id),
+            },
+        ) => {
+            let slot_leader = bft.get_leader_at(date)?;
+            if &slot_leader == leader_id {
+                Ok(LeaderOutput::Bft(slot_leader))
+            } else {
+                Ok(LeaderOutput::None)
+            }
+        }
+        _ => Ok(LeaderOutput::None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::BftLeader;
+    use chain_crypto::{Ed25519, PublicKey, SecretKey};
+    use rand::{rngs::StdRng, SeedableRng as _};
+
+    /// BFT leaders of the same seed hold the same key
+    fn leader(seed: u64) -> Leader {
+        Leader {
+            bft_leader: Some(BftLeader {
+                sig_key: SecretKey::<Ed25519>::generate(StdRng::seed_from_u64(seed)),
+            }),
+            genesis_leader: None,
+        }
+    }
+
+    fn public_key(leader: &Leader) -> PublicKey<Ed25519> {
+        leader.bft_leader.as_ref().unwrap().sig_key.to_public()
+    }
+
+    fn signing_key(leaders: &mut LocalLeaders, id: &LeaderId, epoch: u32) -> PublicKey<Ed25519> {
+        let leader = leaders
+            .get_for_signing(id, epoch, SystemTime::now())
+            .unwrap()
+            .expect("the leader is in the enclave");
+        public_key(leader)
+    }
+
+    #[test]
+    fn rotation_is_forgotten_once_it_takes_effect() {
+        let mut rt = tokio02::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let enclave = Enclave::from_vec(vec![leader(1)]).await;
+            enclave
+                .enable_leader_rotations(LeaderRotations::load(None).unwrap())
+                .await
+                .unwrap();
+            let id = enclave.get_leaderids().await.unwrap()[0];
+            assert!(enclave.rotate_leader(id, leader(2), 2).await.unwrap());
+
+            let leaders = match &enclave.backend {
+                Backend::Local(leaders) => leaders,
+                #[cfg(unix)]
+                Backend::Remote(_) => unreachable!(),
+            };
+            let mut leaders = leaders.write().await;
+            let rotation_of = |leaders: &LocalLeaders, leader: &Leader| {
+                let rotations = leaders.rotations.as_ref().unwrap();
+                rotations.get(leader).map(|(epoch, _)| epoch)
+            };
+            assert!(signing_key(&mut leaders, &id, 1) == public_key(&leader(1)));
+            assert_eq!(rotation_of(&*leaders, &leader(1)), Some(2));
+
+            assert!(signing_key(&mut leaders, &id, 2) == public_key(&leader(2)));
+            assert_eq!(rotation_of(&*leaders, &leader(1)), None);
+        });
+    }
+}
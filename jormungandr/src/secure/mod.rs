@@ -3,12 +3,18 @@ use chain_impl_mockchain::leadership::{BftLeader, GenesisLeader};
 use jormungandr_lib::crypto::{
     hash::Hash,
     key::{Identifier, SigningKey},
+    password_encryption::{self, DecryptionError},
 };
+use passphrase::Passphrase;
 use serde::Deserialize;
 use std::path::Path;
 use thiserror::Error;
 
 pub mod enclave;
+pub mod passphrase;
+#[cfg(unix)]
+pub mod remote;
+pub mod rotation;
 
 /// hold the node's bft secret setting
 #[derive(Clone, Deserialize)]
@@ -62,12 +68,30 @@ pub enum NodeSecretFromFileError {
     Io(#[from] std::io::Error),
     #[error("Invalid Node secret file: {0}")]
     Format(#[from] serde_yaml::Error),
+    #[error("Cannot read the passphrase of the node's secrets: {0}")]
+    Passphrase(#[source] std::io::Error),
+    #[error("Cannot decrypt the node's secrets: {0}")]
+    Decryption(#[from] DecryptionError),
 }
 
 impl NodeSecret {
-    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<NodeSecret, NodeSecretFromFileError> {
-        let file = std::fs::File::open(path)?;
-        Ok(serde_yaml::from_reader(file)?)
+    /// load the secret from the given file, the passphrase is only
+    /// queried if the file is encrypted
+    pub fn load_from_file<P: AsRef<Path>>(
+        path: P,
+        passphrase: &mut Passphrase,
+    ) -> Result<NodeSecret, NodeSecretFromFileError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)?;
+        if password_encryption::is_encrypted(&data) {
+            let passphrase = passphrase
+                .get(path)
+                .map_err(NodeSecretFromFileError::Passphrase)?;
+            let data = password_encryption::decrypt(passphrase.as_bytes(), &data)?;
+            Ok(serde_yaml::from_slice(&data)?)
+        } else {
+            Ok(serde_yaml::from_slice(&data)?)
+        }
     }
 
     pub fn bft(&self) -> Option<BftLeader> {
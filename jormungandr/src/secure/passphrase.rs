@@ -0,0 +1,73 @@
+//! passphrase of the encrypted node secret files.
+//!
+//! The passphrase is read, in order of preference, from the file descriptor
+//! given on the command line, from the `JORMUNGANDR_SECRET_PASSPHRASE`
+//! environment variable or from an interactive prompt. The passphrases are
+//! zeroed in memory once dropped.
+
+use jormungandr_lib::crypto::password_encryption::PASSPHRASE_ENV_VAR;
+use std::{env, io, path::Path};
+use zeroize::Zeroizing;
+
+pub struct Passphrase {
+    fd: Option<i32>,
+    /// the passphrase read from the file descriptor, kept as the file
+    /// descriptor can only be read once
+    from_fd: Option<Zeroizing<String>>,
+}
+
+impl Passphrase {
+    pub fn new(fd: Option<i32>) -> Self {
+        Passphrase { fd, from_fd: None }
+    }
+
+    /// get the passphrase to decrypt the secret file at the given path
+    pub fn get(&mut self, path: &Path) -> io::Result<Zeroizing<String>> {
+        if let Some(passphrase) = self.given()? {
+            return Ok(passphrase);
+        }
+        rpassword::read_password_from_tty(Some(&format!("passphrase of {}: ", path.display())))
+            .map(Zeroizing::new)
+    }
+
+    /// get the passphrase given to the node on the command line or in the
+    /// environment, without prompting for it
+    pub fn given(&mut self) -> io::Result<Option<Zeroizing<String>>> {
+        if let Some(fd) = self.fd.take() {
+            self.from_fd = Some(read_fd(fd)?);
+        }
+        if let Some(passphrase) = &self.from_fd {
+            return Ok(Some(passphrase.clone()));
+        }
+        Ok(env::var(PASSPHRASE_ENV_VAR).ok().map(Zeroizing::new))
+    }
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> io::Result<Zeroizing<String>> {
+    use std::{fs::File, io::Read as _, mem::ManuallyDrop, os::unix::io::FromRawFd as _};
+
+    // the standard streams are in use by the node
+    if (0..=2).contains(&fd) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the passphrase file descriptor cannot be a standard input or output",
+        ));
+    }
+    // the file descriptor is owned by the process that started the node,
+    // it is left open once read
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    let mut passphrase = Zeroizing::new(String::new());
+    file.read_to_string(&mut passphrase)?;
+    let len = passphrase.trim_end_matches(&['\r', '\n'][..]).len();
+    passphrase.truncate(len);
+    Ok(passphrase)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> io::Result<Zeroizing<String>> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "reading the passphrase from a file descriptor is only supported on Unix",
+    ))
+}
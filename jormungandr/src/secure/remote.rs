@@ -0,0 +1,169 @@
+//! client of a remote signer, the signing keys of the leaders are held by
+//! an external process the node talks to over a Unix socket.
+
+use crate::blockcfg::Ledger;
+use jormungandr_lib::{
+    crypto::kes::KesSchedule,
+    interfaces::{
+        read_signer_message, write_signer_message, SignerProtocolError, SignerRequest,
+        SignerResponse,
+    },
+};
+use std::{
+    io,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+use tokio02::task::spawn_blocking;
+
+/// time after which a request without answer from the signer fails
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// number of leadership states the node remembers having sent to the
+/// signer, enough for the current and the next epoch
+const MAX_LEADERSHIP_STATES: usize = 4;
+
+#[derive(Debug, Error)]
+pub enum RemoteSignerError {
+    #[error("cannot connect to the remote signer at {path}")]
+    Connect {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("communication with the remote signer failed")]
+    Protocol(#[from] SignerProtocolError),
+    #[error("the remote signer refused the request: {0}")]
+    Refused(String),
+    #[error("unexpected response from the remote signer")]
+    UnexpectedResponse,
+    #[error("cannot encode the request to the remote signer")]
+    Encoding,
+    #[error("the remote signer request was interrupted")]
+    Interrupted(#[from] tokio02::task::JoinError),
+}
+
+/// the connection to the signer is opened on the first request and kept
+/// open for the following ones. It is reopened on the next request when
+/// an error occurred, so the signer can be restarted without the node.
+pub struct RemoteSigner {
+    path: PathBuf,
+    connection: Mutex<Option<UnixStream>>,
+    /// the leadership states sent to the signer, the most recent last
+    leadership_states: Mutex<Vec<LeadershipState>>,
+    /// the signer is told the period to evolve its KES keys to
+    kes_schedule: Mutex<Option<KesSchedule>>,
+}
+
+struct LeadershipState {
+    id: u64,
+    epoch: u32,
+    ledger: Arc<Ledger>,
+    /// the state was accepted by the signer
+    sent: bool,
+}
+
+impl RemoteSigner {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        RemoteSigner {
+            path: path.as_ref().to_path_buf(),
+            connection: Mutex::new(None),
+            leadership_states: Mutex::new(Vec::new()),
+            kes_schedule: Mutex::new(None),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// send the request to the signer and wait for its response, the
+    /// errors reported by the signer are returned as `Refused`
+    pub async fn query(
+        self: &Arc<Self>,
+        request: SignerRequest,
+    ) -> Result<SignerResponse, RemoteSignerError> {
+        let signer = Arc::clone(self);
+        match spawn_blocking(move || signer.query_blocking(&request)).await?? {
+            SignerResponse::Error(reason) => Err(RemoteSignerError::Refused(reason)),
+            response => Ok(response),
+        }
+    }
+
+    pub fn set_kes_schedule(&self, schedule: KesSchedule) {
+        *self.kes_schedule.lock().unwrap() = Some(schedule);
+    }
+
+    /// the KES period of the given time, `None` if the KES keys are not
+    /// evolved
+    pub fn kes_period_at(&self, time: SystemTime) -> Option<u32> {
+        self.kes_schedule
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|schedule| schedule.period_at(time))
+    }
+
+    /// the identifier of the leadership state of the epoch, and whether the
+    /// signer already received it. The identifiers are random so a signer
+    /// kept running across restarts of the node never mixes up the states.
+    pub fn leadership_state_id(&self, ledger: &Arc<Ledger>, epoch: u32) -> (u64, bool) {
+        let mut states = self.leadership_states.lock().unwrap();
+        if let Some(state) = states
+            .iter()
+            .find(|state| state.epoch == epoch && Arc::ptr_eq(&state.ledger, ledger))
+        {
+            return (state.id, state.sent);
+        }
+        if states.len() >= MAX_LEADERSHIP_STATES {
+            states.remove(0);
+        }
+        let id = rand::random();
+        states.push(LeadershipState {
+            id,
+            epoch,
+            ledger: Arc::clone(ledger),
+            sent: false,
+        });
+        (id, false)
+    }
+
+    pub fn set_leadership_state_sent(&self, id: u64) {
+        let mut states = self.leadership_states.lock().unwrap();
+        if let Some(state) = states.iter_mut().find(|state| state.id == id) {
+            state.sent = true;
+        }
+    }
+
+    fn query_blocking(&self, request: &SignerRequest) -> Result<SignerResponse, RemoteSignerError> {
+        let mut connection = self.connection.lock().unwrap();
+        let mut stream = match connection.take() {
+            Some(stream) => stream,
+            None => self.connect()?,
+        };
+
+        let result = write_signer_message(&mut stream, request)
+            .and_then(|()| read_signer_message(&mut stream));
+        // a stream left in an unknown state by an error is dropped
+        if result.is_ok() {
+            *connection = Some(stream);
+        }
+        Ok(result?)
+    }
+
+    fn connect(&self) -> Result<UnixStream, RemoteSignerError> {
+        let connect = || -> io::Result<UnixStream> {
+            let stream = UnixStream::connect(&self.path)?;
+            stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+            stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+            Ok(stream)
+        };
+        connect().map_err(|source| RemoteSignerError::Connect {
+            path: self.path.clone(),
+            source,
+        })
+    }
+}
@@ -0,0 +1,320 @@
+//! leader rotations scheduled from the REST API.
+//!
+//! A rotation replaces the secrets of a leader from a given epoch. The
+//! rotations are kept by the public keys of the leader they replace, which
+//! unlike the leader ID do not depend on the order the secret files are
+//! loaded in. They are saved in the node's storage, encrypted with the
+//! passphrase given to the node, so they are scheduled again after a
+//! restart. A rotation is forgotten once it takes effect: the secret files
+//! of the leader have to be replaced by the new secrets before the node
+//! is restarted.
+
+use crate::blockcfg::{BftLeader, GenesisLeader, Leader};
+use chain_crypto::{Blake2b256, Ed25519, SumEd25519_12};
+use jormungandr_lib::{
+    crypto::{
+        hash::Hash,
+        key::{Identifier, SigningKey},
+        password_encryption::{self, DecryptionError},
+    },
+    interfaces::{Bft as NodeSecretBft, GenesisPraos as NodeSecretGenesisPraos, NodeSecret},
+};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// name of the file of the leader rotations in the node's storage directory
+pub const LEADER_ROTATIONS_FILE_NAME: &str = "leader_rotations.json";
+
+#[derive(Debug, Error)]
+pub enum RotationError {
+    #[error("cannot access the leader rotations file")]
+    Io(#[from] io::Error),
+    #[error("cannot decrypt the leader rotations file")]
+    Decryption(#[from] DecryptionError),
+    #[error("invalid leader rotations file")]
+    Encoding(#[from] serde_json::Error),
+}
+
+/// the public keys of a leader
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct LeaderKey {
+    bft: Option<Identifier<Ed25519>>,
+    genesis: Option<Identifier<SumEd25519_12>>,
+}
+
+impl LeaderKey {
+    fn of(leader: &Leader) -> Self {
+        LeaderKey {
+            bft: leader
+                .bft_leader
+                .as_ref()
+                .map(|bft| bft.sig_key.to_public().into()),
+            genesis: leader
+                .genesis_leader
+                .as_ref()
+                .map(|genesis| genesis.sig_key.to_public().into()),
+        }
+    }
+}
+
+/// the file the rotations are saved in, encrypted with the passphrase
+pub struct RotationsFile {
+    pub path: PathBuf,
+    pub passphrase: Zeroizing<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredRotation {
+    leader: LeaderKey,
+    epoch: u32,
+    secret: NodeSecret,
+}
+
+/// the rotations scheduled for the leaders of the node
+pub struct LeaderRotations {
+    file: Option<RotationsFile>,
+    rotations: BTreeMap<LeaderKey, (u32, Leader)>,
+}
+
+impl LeaderRotations {
+    /// load the rotations from the given file, without file the rotations
+    /// are only kept in memory
+    pub fn load(file: Option<RotationsFile>) -> Result<Self, RotationError> {
+        let stored: Vec<StoredRotation> = match &file {
+            None => Vec::new(),
+            Some(file) => match fs::read(&file.path) {
+                Ok(data) => {
+                    let data = password_encryption::decrypt(file.passphrase.as_bytes(), &data)?;
+                    serde_json::from_slice(&data)?
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Err(error) => return Err(error.into()),
+            },
+        };
+        let rotations = stored
+            .into_iter()
+            .map(|rotation| {
+                (
+                    rotation.leader,
+                    (rotation.epoch, leader_from_secret(rotation.secret)),
+                )
+            })
+            .collect();
+        Ok(LeaderRotations { file, rotations })
+    }
+
+    /// the rotation scheduled for the leader holding the given secrets:
+    /// the epoch of the rotation and the new secrets of the leader
+    pub fn get(&self, current: &Leader) -> Option<(u32, Leader)> {
+        self.rotations
+            .get(&LeaderKey::of(current))
+            .map(|(epoch, leader)| (*epoch, clone_leader(leader)))
+    }
+
+    /// record the rotation of the leader holding the `current` secrets,
+    /// replacing the one already scheduled
+    pub fn set(
+        &mut self,
+        current: &Leader,
+        epoch: u32,
+        leader: &Leader,
+    ) -> Result<(), RotationError> {
+        self.rotations
+            .insert(LeaderKey::of(current), (epoch, clone_leader(leader)));
+        self.save()
+    }
+
+    /// forget the rotation of the leader holding the given secrets, once
+    /// the rotation takes effect or the leader is removed
+    pub fn remove(&mut self, current: &Leader) -> Result<(), RotationError> {
+        if self.rotations.remove(&LeaderKey::of(current)).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), RotationError> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let stored: Vec<StoredRotation> = self
+            .rotations
+            .iter()
+            .map(|(key, (epoch, leader))| StoredRotation {
+                leader: key.clone(),
+                epoch: *epoch,
+                secret: secret_from_leader(leader),
+            })
+            .collect();
+        let data = password_encryption::encrypt(
+            file.passphrase.as_bytes(),
+            password_encryption::DEFAULT_ITERATIONS,
+            &serde_json::to_vec(&stored)?,
+            &mut OsRng,
+        );
+        let tmp_path = file.path.with_extension("tmp");
+        {
+            let mut tmp_file = create_private(&tmp_path)?;
+            tmp_file.write_all(&data)?;
+            tmp_file.sync_all()?;
+        }
+        fs::rename(tmp_path, &file.path)?;
+        Ok(())
+    }
+}
+
+/// the file holds secret keys, it is only readable by the node's user
+fn create_private(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+fn clone_leader(leader: &Leader) -> Leader {
+    leader_from_secret(secret_from_leader(leader))
+}
+
+fn secret_from_leader(leader: &Leader) -> NodeSecret {
+    NodeSecret {
+        bft: leader.bft_leader.as_ref().map(|bft| NodeSecretBft {
+            signing_key: SigningKey::from(bft.sig_key.clone()),
+        }),
+        genesis: leader
+            .genesis_leader
+            .as_ref()
+            .map(|genesis| NodeSecretGenesisPraos {
+                node_id: Hash::from(genesis.node_id.clone()),
+                sig_key: SigningKey::from(genesis.sig_key.clone()),
+                vrf_key: SigningKey::from(genesis.vrf_key.clone()),
+            }),
+    }
+}
+
+fn leader_from_secret(secret: NodeSecret) -> Leader {
+    Leader {
+        bft_leader: secret.bft.map(|bft| BftLeader {
+            sig_key: bft.signing_key.into_secret_key(),
+        }),
+        genesis_leader: secret.genesis.map(|genesis| GenesisLeader {
+            node_id: Blake2b256::from(genesis.node_id).into(),
+            sig_key: genesis.sig_key.into_secret_key(),
+            vrf_key: genesis.vrf_key.into_secret_key(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chain_crypto::{Curve25519_2HashDH, SecretKey};
+    use tempfile::tempdir;
+
+    fn file(path: &Path, passphrase: &str) -> Option<RotationsFile> {
+        Some(RotationsFile {
+            path: path.to_path_buf(),
+            passphrase: Zeroizing::new(passphrase.to_owned()),
+        })
+    }
+
+    fn leader() -> Leader {
+        Leader {
+            bft_leader: Some(BftLeader {
+                sig_key: SecretKey::<Ed25519>::generate(rand::thread_rng()),
+            }),
+            genesis_leader: Some(GenesisLeader {
+                node_id: Blake2b256::from(Hash::from(rand::random::<[u8; 32]>())).into(),
+                sig_key: SecretKey::<SumEd25519_12>::generate(rand::thread_rng()),
+                vrf_key: SecretKey::<Curve25519_2HashDH>::generate(rand::thread_rng()),
+            }),
+        }
+    }
+
+    fn same_leader(a: &Leader, b: &Leader) -> bool {
+        let vrf_key = |leader: &Leader| {
+            leader
+                .genesis_leader
+                .as_ref()
+                .map(|genesis| (genesis.node_id.clone(), genesis.vrf_key.to_public()))
+        };
+        LeaderKey::of(a) == LeaderKey::of(b) && vrf_key(a) == vrf_key(b)
+    }
+
+    #[test]
+    fn rotations_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(LEADER_ROTATIONS_FILE_NAME);
+        let (first, second) = (leader(), leader());
+        let (first_rotation, second_rotation) = (leader(), leader());
+
+        let mut rotations = LeaderRotations::load(file(&path, "passphrase")).unwrap();
+        assert!(rotations.get(&first).is_none());
+        rotations.set(&first, 10, &first_rotation).unwrap();
+        rotations.set(&second, 11, &second_rotation).unwrap();
+        // a new rotation of the leader replaces the scheduled one
+        rotations.set(&second, 12, &first_rotation).unwrap();
+
+        let mut rotations = LeaderRotations::load(file(&path, "passphrase")).unwrap();
+        let (epoch, leader) = rotations.get(&first).unwrap();
+        assert_eq!(epoch, 10);
+        assert!(same_leader(&leader, &first_rotation));
+        let (epoch, leader) = rotations.get(&second).unwrap();
+        assert_eq!(epoch, 12);
+        assert!(same_leader(&leader, &first_rotation));
+        assert!(rotations.get(&first_rotation).is_none());
+
+        rotations.remove(&first).unwrap();
+        let rotations = LeaderRotations::load(file(&path, "passphrase")).unwrap();
+        assert!(rotations.get(&first).is_none());
+        assert!(rotations.get(&second).is_some());
+    }
+
+    #[test]
+    fn rotations_are_encrypted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(LEADER_ROTATIONS_FILE_NAME);
+        let current = leader();
+        let mut rotations = LeaderRotations::load(file(&path, "passphrase")).unwrap();
+        rotations.set(&current, 1, &leader()).unwrap();
+
+        let data = fs::read(&path).unwrap();
+        assert!(password_encryption::is_encrypted(&data));
+        match LeaderRotations::load(file(&path, "another passphrase")) {
+            Err(RotationError::Decryption(DecryptionError::Authentication)) => (),
+            _ => panic!("the rotations should not be decrypted with another passphrase"),
+        }
+    }
+
+    #[test]
+    fn rotations_in_memory() {
+        let current = leader();
+        let mut rotations = LeaderRotations::load(None).unwrap();
+        rotations.set(&current, 1, &leader()).unwrap();
+        assert_eq!(rotations.get(&current).map(|(epoch, _)| epoch), Some(1));
+    }
+
+    #[test]
+    fn rotations_invalid_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(LEADER_ROTATIONS_FILE_NAME);
+        let data = password_encryption::encrypt(b"passphrase", 1, b"[{\"leader\": 1", &mut OsRng);
+        fs::write(&path, data).unwrap();
+        match LeaderRotations::load(file(&path, "passphrase")) {
+            Err(RotationError::Encoding(_)) => (),
+            _ => panic!("the invalid file should be rejected"),
+        }
+    }
+}
@@ -24,6 +24,13 @@ pub struct StartArguments {
     #[structopt(long = "secret", parse(from_os_str))]
     pub secret: Vec<PathBuf>,
 
+    /// Read the passphrase of the encrypted secret files from the given file
+    /// descriptor (Unix only), which is read once and cannot be 0, 1 or 2.
+    /// Otherwise the passphrase is taken from the
+    /// JORMUNGANDR_SECRET_PASSPHRASE environment variable or prompted for.
+    #[structopt(long = "secret-passphrase-fd")]
+    pub secret_passphrase_fd: Option<i32>,
+
     /// Path to the genesis block (the block0) of the blockchain
     #[structopt(long = "genesis-block", parse(try_from_str))]
     pub block_0_path: Option<PathBuf>,
@@ -44,6 +51,49 @@ pub struct StartArguments {
     /// Start the explorer task and enable associated query endpoints.
     #[structopt(long = "enable-explorer")]
     pub explorer_enabled: bool,
+
+    /// Path to a ledger snapshot to start the blockchain from instead of the
+    /// genesis block, only the blocks after the snapshot are downloaded. The
+    /// digest of the snapshot must match the `trusted_snapshot` of the node
+    /// configuration.
+    #[structopt(long = "snapshot", parse(from_os_str))]
+    pub snapshot: Option<PathBuf>,
+}
+
+/// offline commands run on the storage instead of starting the node
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// write the blocks of the main branch of the storage to a file, from
+    /// the genesis block up to the tip. The node must not be running.
+    #[structopt(name = "export-blocks")]
+    ExportBlocks {
+        /// the file to write the blocks to
+        #[structopt(long = "output", short = "o", parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// validate the blocks of a file written by `export-blocks` and add
+    /// them to the storage. The node must not be running.
+    #[structopt(name = "import-blocks")]
+    ImportBlocks {
+        /// the file to read the blocks from
+        #[structopt(long = "input", short = "i", parse(from_os_str))]
+        input: PathBuf,
+    },
+    /// walk the main branch of the storage from the tip back to the genesis
+    /// block and report the missing or damaged blocks. The node must not be
+    /// running.
+    #[structopt(name = "verify-storage")]
+    VerifyStorage {
+        /// rewind the tip to the most recent block whose whole branch is
+        /// valid
+        #[structopt(long = "repair")]
+        repair: bool,
+    },
+    /// copy the blocks of the SQLite file of the storage to the embedded
+    /// key-value store, to use with `storage_backend: sled`. The node must
+    /// not be running.
+    #[structopt(name = "migrate-storage")]
+    MigrateStorage,
 }
 
 #[derive(StructOpt, Debug)]
@@ -94,6 +144,9 @@ pub struct CommandLine {
     /// this option is useful for scripting retrieving the logs of the version of this application.
     #[structopt(long = "source-version")]
     pub source_version: bool,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
 }
 
 impl CommandLine {
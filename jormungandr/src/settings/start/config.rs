@@ -3,7 +3,7 @@ use crate::{
     settings::logging::{LogFormat, LogOutput},
     settings::LOG_FILTER_LEVEL_POSSIBLE_VALUES,
 };
-use jormungandr_lib::{interfaces::Mempool, time::Duration};
+use jormungandr_lib::{crypto::hash::Hash, interfaces::Mempool, time::Duration};
 use poldercast;
 use serde::{de::Error as _, de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
 use slog::FilterLevel;
@@ -15,6 +15,11 @@ pub struct Config {
     #[serde(default)]
     pub secret_files: Vec<PathBuf>,
     pub storage: Option<PathBuf>,
+
+    /// the engine the blocks are stored with in the `storage` directory
+    #[serde(default)]
+    pub storage_backend: StorageBackend,
+
     pub log: Option<ConfigLogSettings>,
 
     /// setting of the mempool, fragment logs and related data
@@ -34,6 +39,15 @@ pub struct Config {
     /// the time interval with no blockchain updates after which alerts are thrown
     #[serde(default)]
     pub no_blockchain_updates_warning_interval: Option<Duration>,
+
+    /// the digest of the ledger snapshot the node can be started from with
+    /// `--snapshot`, a Blake2b-256 hash of the contents of the file
+    #[serde(default)]
+    pub trusted_snapshot: Option<Hash>,
+
+    /// prune the contents of the old blocks from the storage
+    #[serde(default)]
+    pub pruning: Option<Pruning>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -173,6 +187,39 @@ pub struct Leadership {
     pub log_ttl: Duration,
     /// interval between 2 garbage collection check logs
     pub garbage_collection_interval: Duration,
+    /// path to the Unix socket of a remote signer holding the leaders'
+    /// secrets, to use instead of the node's secret files
+    #[cfg(unix)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_signer: Option<PathBuf>,
+    /// assemble the blocks of the leaders' slots without signing nor
+    /// sending them, to check the setup of a pool on a live network
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pruning {
+    /// number of epochs before the current epoch whose blocks are kept
+    /// in full, the blocks deeper than the epoch stability depth are kept
+    /// as well
+    pub epochs: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// the SQLite file `blocks.sqlite`
+    Sqlite,
+    /// the embedded key-value store in the `blocks.sled` directory
+    Sled,
+}
+
+impl Default for StorageBackend {
+    fn default() -> Self {
+        StorageBackend::Sqlite
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -231,6 +278,9 @@ impl Default for Leadership {
         Leadership {
             log_ttl: Duration::new(3600, 0),
             garbage_collection_interval: Duration::new(3600 / 4, 0),
+            #[cfg(unix)]
+            remote_signer: None,
+            dry_run: false,
         }
     }
 }
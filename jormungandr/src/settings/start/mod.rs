@@ -2,7 +2,7 @@ pub mod config;
 pub mod network;
 
 use self::config::{Config, Leadership};
-pub use self::config::{Cors, Rest, Tls};
+pub use self::config::{Cors, Rest, StorageBackend, Tls};
 use self::network::Protocol;
 use crate::rest::Error as RestError;
 use crate::settings::logging::{LogFormat, LogOutput, LogSettings, LogSettingsEntry};
@@ -31,19 +31,37 @@ pub enum Error {
     TooMuchBlock0Info,
     #[error("In the node configuration file, the `p2p.listen_address` value is not a valid address. Use format `/ip4/x.x.x.x/tcp/4920")]
     ListenAddressNotValid,
+    #[error("The node's secret files cannot be used along with a remote signer")]
+    SecretsWithRemoteSigner,
+    #[error("Cannot start from a ledger snapshot without the `trusted_snapshot` digest in the node configuration")]
+    SnapshotNotTrusted,
+    #[error("The pruning of the blocks needs the blocks to be stored in the `storage` directory")]
+    PruningWithoutStorage,
+    #[error("The pruning of the blocks needs the `sled` storage backend")]
+    PruningWithSqliteStorage,
+    #[error("The explorer cannot be enabled along with the pruning of the blocks")]
+    ExplorerWithPruning,
 }
 
 /// Overall Settings for node
 pub struct Settings {
     pub network: network::Configuration,
     pub storage: Option<PathBuf>,
+    pub storage_backend: StorageBackend,
     pub block_0: Block0Info,
     pub secrets: Vec<PathBuf>,
+    pub secret_passphrase_fd: Option<i32>,
     pub rest: Option<Rest>,
     pub mempool: Mempool,
     pub leadership: Leadership,
     pub explorer: bool,
     pub no_blockchain_updates_warning_interval: std::time::Duration,
+    pub snapshot: Option<PathBuf>,
+    /// the digest of the ledger snapshot given with `--snapshot`
+    pub trusted_snapshot: Option<jormungandr_lib::crypto::hash::Hash>,
+    /// number of epochs before the current epoch whose blocks are kept in
+    /// full, if the storage is pruned
+    pub pruning: Option<u32>,
 }
 
 pub struct RawSettings {
@@ -130,7 +148,6 @@ impl RawSettings {
             config,
         } = self;
         let command_arguments = &command_line.start_arguments;
-        let network = generate_network(&command_arguments, &config, &logger)?;
 
         let storage = match (
             command_arguments.storage.as_ref(),
@@ -146,7 +163,20 @@ impl RawSettings {
             secrets.extend(secret_files);
         }
 
-        if secrets.is_empty() {
+        let leadership = config
+            .as_ref()
+            .map_or(Leadership::default(), |cfg| cfg.leadership.clone());
+
+        #[cfg(unix)]
+        let remote_signer = leadership.remote_signer.is_some();
+        #[cfg(not(unix))]
+        let remote_signer = false;
+
+        if remote_signer && !secrets.is_empty() {
+            return Err(Error::SecretsWithRemoteSigner);
+        }
+
+        if secrets.is_empty() && !remote_signer {
             warn!(
                 logger,
                 "Node started without path to the stored secret keys (not a stake pool or a BFT leader)"
@@ -163,6 +193,11 @@ impl RawSettings {
             (None, Some(hash)) => Block0Info::Hash(hash.clone()),
         };
 
+        let trusted_snapshot = config.as_ref().and_then(|cfg| cfg.trusted_snapshot);
+        if command_arguments.snapshot.is_some() && trusted_snapshot.is_none() {
+            return Err(Error::SnapshotNotTrusted);
+        }
+
         let explorer = command_arguments.explorer_enabled
             || config.as_ref().map_or(false, |cfg| {
                 cfg.explorer
@@ -170,18 +205,39 @@ impl RawSettings {
                     .map_or(false, |settings| settings.enabled)
             });
 
+        let pruning = config
+            .as_ref()
+            .and_then(|cfg| cfg.pruning.as_ref())
+            .map(|pruning| pruning.epochs);
+        let storage_backend = config
+            .as_ref()
+            .map_or(StorageBackend::default(), |cfg| cfg.storage_backend);
+        if pruning.is_some() {
+            if storage.is_none() {
+                return Err(Error::PruningWithoutStorage);
+            }
+            if storage_backend != StorageBackend::Sled {
+                return Err(Error::PruningWithSqliteStorage);
+            }
+            if explorer {
+                return Err(Error::ExplorerWithPruning);
+            }
+        }
+
+        let network = generate_network(&command_arguments, &config, &logger)?;
+
         Ok(Settings {
             storage,
+            storage_backend,
             block_0,
             network,
             secrets,
+            secret_passphrase_fd: command_arguments.secret_passphrase_fd,
             rest,
             mempool: config
                 .as_ref()
                 .map_or(Mempool::default(), |cfg| cfg.mempool.clone()),
-            leadership: config
-                .as_ref()
-                .map_or(Leadership::default(), |cfg| cfg.leadership.clone()),
+            leadership,
             explorer,
             no_blockchain_updates_warning_interval: config
                 .as_ref()
@@ -190,6 +246,9 @@ impl RawSettings {
                 .unwrap_or(std::time::Duration::from_secs(
                     DEFAULT_NO_BLOCKCHAIN_UPDATES_WARNING_INTERVAL,
                 )),
+            snapshot: command_arguments.snapshot.clone(),
+            trusted_snapshot,
+            pruning,
         })
     }
 }
@@ -1,11 +1,11 @@
 use crate::{
     blockcfg, blockchain,
     diagnostic::DiagnosticError,
-    explorer, network, secure,
+    explorer, leadership, network, secure,
     settings::{self, logging},
 };
 use chain_storage::error::Error as StorageError;
-use std::io;
+use std::{io, path::PathBuf};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -54,6 +54,24 @@ pub enum Error {
     ServiceTerminatedWithError,
     #[error("Unable to get system limits: {0}")]
     DiagnosticError(#[from] DiagnosticError),
+    #[error("Error while setting up the leaders' enclave")]
+    Enclave(#[from] secure::enclave::Error),
+    #[error("Error while loading the leadership history")]
+    LeadershipHistory(#[from] leadership::HistoryError),
+    #[error("Error while loading the ledger snapshot")]
+    Snapshot(#[from] blockchain::SnapshotError),
+    #[error("The explorer cannot be enabled on a node started from a ledger snapshot")]
+    ExplorerWithSnapshot,
+    #[error("Error while exporting or importing the blocks")]
+    BlocksFile(#[from] blockchain::BlocksFileError),
+    #[error("The offline commands can only be run on the `storage` directory")]
+    CommandWithoutStorage,
+    #[error("The storage holds {0} damaged or missing blocks, run `verify-storage --repair` to rewind the tip")]
+    StorageDamaged(usize),
+    #[error("There is no SQLite storage to migrate at '{0}'")]
+    NoStorageToMigrate(PathBuf),
+    #[error("The storage at '{0}' already holds blocks, the blocks cannot be migrated to it")]
+    MigratedStorageNotEmpty(PathBuf),
 }
 
 impl Error {
@@ -74,6 +92,15 @@ impl Error {
             Error::ExplorerBootstrapError { .. } => 11,
             Error::ServiceTerminatedWithError => 12,
             Error::DiagnosticError { .. } => 13,
+            Error::Enclave { .. } => 14,
+            Error::LeadershipHistory { .. } => 15,
+            Error::Snapshot { .. } => 16,
+            Error::ExplorerWithSnapshot => 16,
+            Error::BlocksFile { .. } => 17,
+            Error::CommandWithoutStorage => 17,
+            Error::StorageDamaged { .. } => 18,
+            Error::NoStorageToMigrate { .. } => 19,
+            Error::MigratedStorageNotEmpty { .. } => 19,
         }
     }
 }
@@ -2,40 +2,85 @@ mod error;
 
 pub use self::error::{Error, ErrorKind};
 use crate::{
-    blockcfg::Block,
-    blockchain::{Blockchain, ErrorKind as BlockchainError, Storage, Tip},
+    blockcfg::{Block, HeaderHash},
+    blockchain::{
+        check_storage, export_blocks, import_blocks, migrate_blocks, Blockchain, BlocksFileError,
+        ErrorKind as BlockchainError, SledStore, Snapshot, SqliteStore, Storage, Tip,
+        SNAPSHOT_FILE_NAME,
+    },
     network,
-    settings::start::Settings,
+    settings::{
+        start::{Settings, StorageBackend},
+        Command,
+    },
 };
 use chain_storage_sqlite_old::{SQLiteBlockStore, SQLiteBlockStoreConnection};
 use slog::Logger;
-use std::time::Duration;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    time::Duration,
+};
 use tokio_compat::runtime;
 
 pub type NodeStorage = SQLiteBlockStore;
 pub type NodeStorageConnection = SQLiteBlockStoreConnection<Block>;
 
+/// name of the SQLite file of the blocks in the storage directory
+const SQLITE_FILE_NAME: &str = "blocks.sqlite";
+
+/// name of the directory of the sled store in the storage directory
+const SLED_DIRECTORY_NAME: &str = "blocks.sled";
+
 /// prepare the block storage from the given settings
 ///
 pub fn prepare_storage(setting: &Settings, logger: &Logger) -> Result<Storage, Error> {
-    let raw_block_store = match &setting.storage {
+    match setting.storage_backend {
+        StorageBackend::Sqlite => prepare_sqlite_storage(setting.storage.as_deref(), logger),
+        StorageBackend::Sled => prepare_sled_storage(setting.storage.as_deref(), logger),
+    }
+}
+
+fn create_storage_dir(dir: &Path) -> Result<(), Error> {
+    std::fs::create_dir_all(dir).map_err(|err| Error::IO {
+        source: err,
+        reason: ErrorKind::SQLite,
+    })
+}
+
+fn prepare_sqlite_storage(dir: Option<&Path>, logger: &Logger) -> Result<Storage, Error> {
+    let (raw_block_store, sqlite_file) = match dir {
         None => {
             info!(logger, "storing blockchain in memory");
-            SQLiteBlockStore::memory()
+            (SQLiteBlockStore::memory(), None)
         }
         Some(dir) => {
-            std::fs::create_dir_all(dir).map_err(|err| Error::IO {
-                source: err,
-                reason: ErrorKind::SQLite,
-            })?;
-            let mut sqlite = dir.clone();
-            sqlite.push("blocks.sqlite");
+            create_storage_dir(dir)?;
+            let sqlite = dir.join(SQLITE_FILE_NAME);
             info!(logger, "storing blockchain in '{:?}'", sqlite);
-            SQLiteBlockStore::file(sqlite)
+            (SQLiteBlockStore::file(sqlite.clone()), Some(sqlite))
+        }
+    };
+
+    Ok(Storage::new(SqliteStore::new(raw_block_store, sqlite_file)))
+}
+
+fn prepare_sled_storage(dir: Option<&Path>, logger: &Logger) -> Result<Storage, Error> {
+    let store = match dir {
+        None => {
+            info!(logger, "storing blockchain in memory");
+            SledStore::temporary()?
+        }
+        Some(dir) => {
+            create_storage_dir(dir)?;
+            let sled = dir.join(SLED_DIRECTORY_NAME);
+            info!(logger, "storing blockchain in '{:?}'", sled);
+            SledStore::open(sled)?
         }
     };
 
-    Ok(Storage::new(raw_block_store))
+    Ok(Storage::new(store))
 }
 
 /// loading the block 0 is not as trivial as it seems,
@@ -90,24 +135,111 @@ pub fn prepare_block_0(
     }
 }
 
+/// load the ledger snapshot the node starts from, if any:
+///
+/// 1. the copy of the storage directory, if the node was started from a
+///    snapshot before or if the storage has been pruned: the node cannot
+///    apply the blocks from the block0 anymore. The copy is trusted by the
+///    digest recorded in the storage when it was saved;
+/// 2. the snapshot given on the command line, it is copied in the storage
+///    directory so the node can restart from it. It is not used if the
+///    storage already holds the blockchain from the block0.
+pub fn prepare_snapshot(
+    settings: &Settings,
+    storage: &Storage,
+    block0_hash: HeaderHash,
+    logger: &Logger,
+) -> Result<Option<Snapshot>, Error> {
+    let stored_path = settings
+        .storage
+        .as_ref()
+        .map(|dir| dir.join(SNAPSHOT_FILE_NAME));
+    let mut rt = runtime::Builder::new()
+        .name_prefix("prepare-snapshot-worker-")
+        .core_threads(1)
+        .build()
+        .unwrap();
+
+    if let Some(path) = stored_path.as_ref().filter(|path| path.exists()) {
+        if settings.snapshot.is_some() {
+            warn!(
+                logger,
+                "the storage already holds a ledger snapshot, the snapshot given on the command line is not used"
+            );
+        }
+        info!(logger, "loading the ledger snapshot from '{:?}'", path);
+        let snapshot = rt.block_on_std(Snapshot::load_stored(path, storage))?;
+        info!(
+            logger,
+            "starting from the ledger snapshot at block {}",
+            snapshot.hash()
+        );
+        return Ok(Some(snapshot));
+    }
+
+    let path = match &settings.snapshot {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    if rt.block_on(storage.block_exists(block0_hash))? {
+        warn!(
+            logger,
+            "the storage already holds the blockchain from the block0, the ledger snapshot is not used"
+        );
+        return Ok(None);
+    }
+
+    info!(logger, "loading the ledger snapshot from '{:?}'", path);
+    // the settings only accept `--snapshot` along with a trusted digest
+    let snapshot = match &settings.trusted_snapshot {
+        Some(trusted) => Snapshot::load_trusted(path, trusted)?,
+        None => Snapshot::load(path)?,
+    };
+    info!(
+        logger,
+        "starting from the ledger snapshot at block {}",
+        snapshot.hash()
+    );
+
+    if let Some(stored_path) = &stored_path {
+        rt.block_on_std(snapshot.save_stored(stored_path, storage))?;
+    }
+    Ok(Some(snapshot))
+}
+
 pub fn load_blockchain(
     block0: Block,
     storage: Storage,
+    snapshot: Option<Snapshot>,
     block_cache_ttl: Duration,
+    report_account_rewards: bool,
     logger: &Logger,
 ) -> Result<(Blockchain, Tip), Error> {
-    let blockchain = Blockchain::new(block0.header.hash(), storage, block_cache_ttl);
+    let blockchain = Blockchain::new(
+        block0.header.hash(),
+        storage,
+        block_cache_ttl,
+        report_account_rewards,
+    );
 
     let mut rt = tokio02::runtime::Runtime::new().unwrap();
     rt.block_on(async {
-        let main_branch = match blockchain.load_from_block0(block0.clone()).await {
-            Err(error) => match error.kind() {
-                BlockchainError::Block0AlreadyInStorage => {
-                    blockchain.load_from_storage(block0, logger).await
-                }
-                _ => Err(error),
+        let main_branch = match snapshot {
+            Some(snapshot) => {
+                blockchain
+                    .load_from_snapshot(&block0, snapshot, logger)
+                    .await
+            }
+            None => match blockchain.load_from_block0(block0.clone()).await {
+                Err(error) => match error.kind() {
+                    BlockchainError::Block0AlreadyInStorage => {
+                        blockchain.load_from_storage(block0, logger).await
+                    }
+                    _ => Err(error),
+                },
+                Ok(branch) => Ok(branch),
             },
-            Ok(branch) => Ok(branch),
         }?;
         let tip = Tip::new(main_branch);
         let tip_ref = tip.get_ref_std().await;
@@ -119,3 +251,114 @@ pub fn load_blockchain(
         Ok((blockchain, tip))
     })
 }
+
+/// run the offline command on the storage instead of starting the node
+pub fn run_command(
+    command: Command,
+    settings: &Settings,
+    block_cache_ttl: Duration,
+    logger: &Logger,
+) -> Result<(), Error> {
+    let dir = match &settings.storage {
+        Some(dir) => dir,
+        None => return Err(Error::CommandWithoutStorage),
+    };
+    if let Command::MigrateStorage = command {
+        return migrate_storage(settings, dir, logger);
+    }
+    let storage = prepare_storage(settings, logger)?;
+    let block0 = prepare_block_0(settings, &storage, logger)?;
+
+    match command {
+        Command::ExportBlocks { output } => {
+            let writer = BufWriter::new(File::create(&output).map_err(BlocksFileError::from)?);
+            let mut rt = tokio02::runtime::Runtime::new().unwrap();
+            let written = rt.block_on(export_blocks(&storage, &block0, writer))?;
+            info!(logger, "exported {} blocks to '{:?}'", written, output);
+        }
+        Command::ImportBlocks { input } => {
+            let reader = BufReader::new(File::open(&input).map_err(BlocksFileError::from)?);
+            let snapshot = prepare_snapshot(settings, &storage, block0.header.hash(), logger)?;
+            let (blockchain, tip) =
+                load_blockchain(block0, storage, snapshot, block_cache_ttl, false, logger)?;
+            let mut rt = runtime::Runtime::new().unwrap();
+            let imported = rt.block_on_std(import_blocks(&blockchain, &tip, reader, logger))?;
+            info!(
+                logger,
+                "imported {} new blocks from '{:?}'", imported, input
+            );
+        }
+        Command::VerifyStorage { repair } => {
+            let mut rt = tokio02::runtime::Runtime::new().unwrap();
+            let check = rt.block_on(check_storage(&storage, block0.header.hash()))?;
+            for issue in check.issues() {
+                match std::error::Error::source(issue) {
+                    Some(source) => warn!(logger, "{}", issue; "reason" => %source),
+                    None => warn!(logger, "{}", issue),
+                }
+            }
+            info!(
+                logger,
+                "checked {} blocks, found {} issues",
+                check.checked(),
+                check.issues().len()
+            );
+            if check.issues().is_empty() {
+                return Ok(());
+            }
+            if !repair {
+                return Err(Error::StorageDamaged(check.issues().len()));
+            }
+            rt.block_on(check.repair(&storage))?;
+            info!(logger, "rewound the tip to {}", check.rewind_target());
+        }
+    }
+    Ok(())
+}
+
+/// copy the blocks of the SQLite file of the storage directory to the sled
+/// store, the SQLite file is left untouched
+fn migrate_storage(settings: &Settings, dir: &Path, logger: &Logger) -> Result<(), Error> {
+    let sqlite = dir.join(SQLITE_FILE_NAME);
+    if !sqlite.exists() {
+        return Err(Error::NoStorageToMigrate(sqlite));
+    }
+    let from = prepare_sqlite_storage(Some(dir), logger)?;
+    let block0 = prepare_block_0(settings, &from, logger)?;
+
+    let sled = dir.join(SLED_DIRECTORY_NAME);
+    // the block0 is not copied from a pruned storage, any stored block or
+    // tag of the target is found instead
+    let store = SledStore::open(&sled)?;
+    if !store.is_empty() {
+        return Err(Error::MigratedStorageNotEmpty(sled));
+    }
+    let to = Storage::new(store);
+    let mut rt = runtime::Runtime::new().unwrap();
+
+    // the block the node restarts from once the storage has been pruned
+    let snapshot_path = dir.join(SNAPSHOT_FILE_NAME);
+    let snapshot_block = if snapshot_path.exists() {
+        Some(
+            rt.block_on_std(Snapshot::load_stored(&snapshot_path, &from))?
+                .block()
+                .clone(),
+        )
+    } else {
+        None
+    };
+
+    let copied = rt.block_on_std(migrate_blocks(
+        &from,
+        &to,
+        block0.header.hash(),
+        snapshot_block,
+    ))?;
+    info!(
+        logger,
+        "copied {} blocks to '{:?}', set `storage_backend: sled` in the node configuration to use them",
+        copied,
+        sled
+    );
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! length-prefixed frames of the files written by the node: the blocks
+//! files, the ledger snapshots and the saved explorer indexes. Every frame
+//! is prefixed with its size (4 bytes, big endian).
+
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FrameError {
+    #[error("cannot read the frame")]
+    Io(#[from] io::Error),
+    #[error("the frame is truncated")]
+    Truncated,
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// read the next frame, `None` if the reader is at its end
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, FrameError> {
+    let mut size = [0; 4];
+    if reader.read(&mut size[..1])? == 0 {
+        return Ok(None);
+    }
+    reader
+        .read_exact(&mut size[1..])
+        .map_err(|error| match error.kind() {
+            io::ErrorKind::UnexpectedEof => FrameError::Truncated,
+            _ => FrameError::Io(error),
+        })?;
+    let size = u32::from_be_bytes(size) as usize;
+    // the size is not trusted to preallocate the frame
+    let mut bytes = Vec::new();
+    reader.take(size as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != size {
+        return Err(FrameError::Truncated);
+    }
+    Ok(Some(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, b"first").unwrap();
+        write_frame(&mut bytes, b"").unwrap();
+        let mut reader = bytes.as_slice();
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"first");
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"");
+        assert!(read_frame(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_frames_are_refused() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, b"frame").unwrap();
+        for len in 1..bytes.len() {
+            match read_frame(&mut &bytes[..len]) {
+                Err(FrameError::Truncated) => (),
+                _ => panic!("the frame truncated to {} bytes was read", len),
+            }
+        }
+    }
+}